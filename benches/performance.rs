@@ -5,10 +5,12 @@
 //! processing times.
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use diff_gitignore_filter::matcher::Gitignore;
 use diff_gitignore_filter::Filter;
 use std::fs;
 use std::hint::black_box;
 use std::io::Cursor;
+use std::sync::Arc;
 use tempfile::TempDir;
 
 /// Create a test repository with specified .gitignore content
@@ -134,6 +136,22 @@ fn bench_filter_creation(c: &mut Criterion) {
         });
     });
 
+    // Parse the .gitignore once and reuse the matcher for each diff
+    let temp_dir = create_benchmark_repo(complex_gitignore);
+    let (matcher, _) = Gitignore::new(temp_dir.path().join(".gitignore"));
+    let matcher = Arc::new(matcher);
+    let diff = generate_diff(10, 0.3);
+    group.bench_function("shared_matcher", |b| {
+        b.iter(|| {
+            let filter = Filter::from_matcher(temp_dir.path(), Arc::clone(&matcher));
+            let mut output = Vec::new();
+            filter
+                .process_diff(Cursor::new(black_box(&diff)), &mut output)
+                .expect("Failed to process diff");
+            black_box(output);
+        });
+    });
+
     group.finish();
 }
 
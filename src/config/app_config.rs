@@ -3,19 +3,138 @@
 //! This module provides the main application configuration structure that combines
 //! CLI arguments with Git configuration values using a clear priority system.
 
-use super::{ConfigError, GitConfig};
+use super::env_config::ENV_CONFIG_KEY_PREFIX;
+use super::{
+    ConfigError, ConfigKeys, EnvConfig, GitConfig, GitConfigReader, SystemGitConfigReader,
+};
+use crate::diff::DiffPrefixes;
+use crate::filter::{OutputFormat, UnparsableAction};
+use crate::matcher::GitignoreBuilder;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// CLI arguments structure
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CliArgs {
     /// Enable VCS ignore filtering
     pub vcs: bool,
     /// Disable VCS ignore filtering
     pub no_vcs: bool,
-    /// Downstream command for piping output
-    pub downstream: Option<String>,
+    /// Downstream commands for piping output, chained in order
+    pub downstream: Vec<String>,
+    /// Run downstream commands through `sh -c` instead of spawning them directly
+    pub downstream_shell: bool,
+    /// Downstream timeout in seconds (0 = no timeout)
+    pub downstream_timeout: Option<u64>,
+    /// Number of times a failed downstream pipeline is re-run
+    pub downstream_retries: u32,
+    /// Line count of the filtered diff above which the downstream commands are bypassed
+    pub downstream_max_lines: Option<usize>,
+    /// Working directory of the downstream commands
+    pub downstream_cwd: Option<PathBuf>,
+    /// Environment variables for the downstream commands, as `NAME=VALUE`
+    pub downstream_env: Vec<String>,
     /// Custom VCS patterns (overrides git config)
     pub vcs_pattern: Option<String>,
+    /// VCS patterns appended to the effective patterns (CLI, environment, git config or defaults)
+    pub vcs_pattern_add: Option<String>,
+    /// Accept a VCS pattern list that is empty after trimming (VCS filtering becomes a no-op)
+    pub allow_empty_vcs: bool,
+    /// Namespace of the filter's Git config keys (overrides the environment)
+    pub config_key_prefix: Option<String>,
+    /// File with review-only ignore patterns (default: `.diffignore` in the root)
+    pub diffignore: Option<PathBuf>,
+    /// File with patterns of paths that are always kept
+    pub force_include: Option<PathBuf>,
+    /// File read instead of the root .gitignore, also outside a repository
+    pub gitignore_file: Option<PathBuf>,
+    /// Ignore patterns added after those of the root .gitignore
+    pub ignore: Vec<String>,
+    /// Leading path prefix to strip from diff paths before matching
+    pub strip_path_prefix: Option<String>,
+    /// Directory the diff paths are relative to, prepended before .gitignore matching
+    pub path_prefix: Option<String>,
+    /// Source path prefix of diff headers (overrides git config)
+    pub src_prefix: Option<String>,
+    /// Destination path prefix of diff headers (overrides git config)
+    pub dst_prefix: Option<String>,
+    /// Split plain unified diffs (`---`/`+++` pairs without `diff --git`) into file sections
+    pub unified: bool,
+    /// Remove ANSI color sequences from the input before parsing
+    pub strip_ansi: bool,
+    /// Report per-section decisions instead of writing the filtered diff
+    pub dry_run: bool,
+    /// Terminate report lines (dry-run, stats, directory list) with NUL instead of newline
+    pub null_terminated: bool,
+    /// Format of the output written for the processed diff
+    pub output_format: OutputFormat,
+    /// What happens to sections whose path cannot be determined
+    pub on_unparsable: UnparsableAction,
+    /// Number of threads deciding about diff sections (0 or 1 = serial)
+    pub jobs: usize,
+    /// Body size in bytes after which a section is streamed instead of buffered
+    pub max_section_bytes: Option<usize>,
+    /// Replace dropped sections by their header and a `# filtered by <reason>` line
+    pub keep_empty_diff_headers: bool,
+    /// Precede kept sections with a `# kept: <reason>` comment line
+    pub annotate: bool,
+    /// Drop sections that only change the file mode
+    pub no_mode_only: bool,
+    /// Drop sections that delete a file
+    pub no_deletions: bool,
+    /// Apply .gitignore files added or modified by the diff to later sections
+    pub apply_diff_gitignore: bool,
+    /// Keep only sections matching the VCS patterns
+    pub only_vcs: bool,
+    /// Decide a deletion and an addition of the same content together
+    pub pair_renames: bool,
+    /// Level of diagnostics written to stderr (number of `-v` flags)
+    pub verbose: u8,
+    /// Comma-separated file extensions to keep (`--only-ext`)
+    pub only_ext: Option<String>,
+    /// Comma-separated file extensions to drop (`--exclude-ext`)
+    pub exclude_ext: Option<String>,
+}
+
+/// Layer a resolved setting was taken from, see [`AppConfig::sources`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// A command line option
+    Cli,
+    /// An environment variable (see [`EnvConfig`])
+    Env,
+    /// A Git configuration value
+    GitConfig,
+    /// The built-in default
+    #[default]
+    Default,
+}
+
+impl ConfigSource {
+    /// Name of the source as printed by [`AppConfig::to_toml`]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cli => "cli",
+            Self::Env => "env",
+            Self::GitConfig => "git-config",
+            Self::Default => "default",
+        }
+    }
+}
+
+/// Sources of the settings that the command line, the environment and Git config can all set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConfigSources {
+    /// Source of the VCS enabled state
+    pub vcs_enabled: ConfigSource,
+    /// Source of the VCS pattern list (before `--vcs-pattern-add` additions)
+    pub vcs_patterns: ConfigSource,
+    /// Source of the downstream filter
+    pub downstream_filter: ConfigSource,
+    /// Whether the downstream filter was read from the deprecated Git config key
+    /// [`ConfigKeys::legacy_downstream_filter`]
+    pub legacy_downstream_filter: bool,
 }
 
 /// Main application configuration
@@ -25,8 +144,76 @@ pub struct AppConfig {
     vcs_enabled: bool,
     /// VCS patterns to use for filtering
     vcs_patterns: Vec<String>,
-    /// Optional downstream filter command
-    downstream_filter: Option<String>,
+    /// Downstream commands, each stage piped into the next (empty = none)
+    downstream_pipeline: Vec<String>,
+    /// Layers the VCS and downstream settings were resolved from
+    sources: ConfigSources,
+    /// Whether downstream commands are run through `sh -c`
+    downstream_shell: bool,
+    /// Deadline for the downstream pipeline (None = no limit)
+    downstream_timeout: Option<Duration>,
+    /// Number of times a downstream pipeline that failed to spawn or exited non-zero is re-run
+    downstream_retries: u32,
+    /// Line count of the filtered diff above which the downstream pipeline is bypassed (None = no limit)
+    downstream_max_lines: Option<usize>,
+    /// Working directory of the downstream commands (None = inherited)
+    downstream_cwd: Option<PathBuf>,
+    /// Environment variables set for the downstream commands
+    downstream_env: Vec<(String, String)>,
+    /// File with review-only ignore patterns (None = `.diffignore` in the root)
+    diffignore: Option<PathBuf>,
+    /// File with patterns of paths that are always kept (None = no override)
+    force_include: Option<PathBuf>,
+    /// File read instead of the root .gitignore (None = `.gitignore` in the root)
+    gitignore_file: Option<PathBuf>,
+    /// Ignore patterns added after those of the root .gitignore
+    extra_ignore_patterns: Vec<String>,
+    /// Optional path prefix stripped from diff paths before matching
+    strip_path_prefix: Option<String>,
+    /// Optional directory prepended to diff paths before .gitignore matching
+    path_prefix: Option<String>,
+    /// Whether .gitignore patterns match case-insensitively (`core.ignorecase`)
+    ignore_case: bool,
+    /// Global ignore file read beneath the repository's patterns (`core.excludesFile`)
+    global_excludes_file: Option<PathBuf>,
+    /// Path prefixes of the `diff --git` headers
+    diff_prefixes: DiffPrefixes,
+    /// Whether plain unified diffs are split into file sections
+    unified: bool,
+    /// Whether ANSI color sequences are removed from the input before parsing
+    strip_ansi: bool,
+    /// Whether decisions are reported instead of writing the filtered diff
+    dry_run: bool,
+    /// Whether report lines are terminated with NUL instead of newline
+    null_terminated: bool,
+    /// Format of the output written for the processed diff
+    output_format: OutputFormat,
+    /// What happens to sections whose path cannot be determined
+    on_unparsable: UnparsableAction,
+    /// Number of threads deciding about diff sections (1 = serial)
+    jobs: usize,
+    /// Body size in bytes after which a section is streamed instead of buffered (None = no limit)
+    max_section_bytes: Option<usize>,
+    /// Whether dropped sections are replaced by their header and a comment line
+    keep_empty_diff_headers: bool,
+    /// Whether kept sections are preceded by a comment line saying why they were kept
+    annotate: bool,
+    /// Whether sections that only change the file mode are dropped
+    no_mode_only: bool,
+    /// Whether sections deleting a file are dropped
+    no_deletions: bool,
+    /// Whether .gitignore files added or modified by the diff apply to later sections
+    apply_diff_gitignore: bool,
+    /// Whether only sections matching the VCS patterns are kept
+    only_vcs: bool,
+    /// Whether a deletion and an addition of the same content are decided together
+    pair_renames: bool,
+    /// File extensions that are kept (empty = all extensions)
+    only_extensions: Vec<String>,
+    /// File extensions that are dropped
+    excluded_extensions: Vec<String>,
+    /// Level of diagnostics written to stderr (0 = none)
+    verbosity: u8,
 }
 
 /// Configuration builder for functional composition
@@ -34,7 +221,41 @@ pub struct AppConfig {
 pub struct ConfigBuilder {
     vcs_enabled: Option<bool>,
     vcs_patterns: Option<Vec<String>>,
-    downstream_filter: Option<String>,
+    downstream_pipeline: Vec<String>,
+    sources: ConfigSources,
+    downstream_shell: bool,
+    downstream_timeout: Option<Duration>,
+    downstream_retries: u32,
+    downstream_max_lines: Option<usize>,
+    downstream_cwd: Option<PathBuf>,
+    downstream_env: Vec<(String, String)>,
+    diffignore: Option<PathBuf>,
+    force_include: Option<PathBuf>,
+    gitignore_file: Option<PathBuf>,
+    extra_ignore_patterns: Vec<String>,
+    strip_path_prefix: Option<String>,
+    path_prefix: Option<String>,
+    ignore_case: bool,
+    global_excludes_file: Option<PathBuf>,
+    diff_prefixes: Option<DiffPrefixes>,
+    unified: bool,
+    strip_ansi: bool,
+    dry_run: bool,
+    null_terminated: bool,
+    output_format: OutputFormat,
+    on_unparsable: UnparsableAction,
+    jobs: usize,
+    max_section_bytes: Option<usize>,
+    keep_empty_diff_headers: bool,
+    annotate: bool,
+    no_mode_only: bool,
+    no_deletions: bool,
+    apply_diff_gitignore: bool,
+    only_vcs: bool,
+    pair_renames: bool,
+    only_extensions: Vec<String>,
+    excluded_extensions: Vec<String>,
+    verbosity: u8,
 }
 
 impl ConfigBuilder {
@@ -44,7 +265,46 @@ impl ConfigBuilder {
         Self {
             vcs_enabled: None,
             vcs_patterns: None,
-            downstream_filter: None,
+            downstream_pipeline: Vec::new(),
+            sources: ConfigSources {
+                vcs_enabled: ConfigSource::Default,
+                vcs_patterns: ConfigSource::Default,
+                downstream_filter: ConfigSource::Default,
+                legacy_downstream_filter: false,
+            },
+            downstream_shell: false,
+            downstream_timeout: None,
+            downstream_retries: 0,
+            downstream_max_lines: None,
+            downstream_cwd: None,
+            diffignore: None,
+            force_include: None,
+            gitignore_file: None,
+            extra_ignore_patterns: Vec::new(),
+            downstream_env: Vec::new(),
+            strip_path_prefix: None,
+            path_prefix: None,
+            ignore_case: false,
+            global_excludes_file: None,
+            diff_prefixes: None,
+            unified: false,
+            strip_ansi: false,
+            dry_run: false,
+            null_terminated: false,
+            output_format: OutputFormat::Diff,
+            on_unparsable: UnparsableAction::Keep,
+            jobs: 1,
+            max_section_bytes: None,
+            keep_empty_diff_headers: false,
+            annotate: false,
+            no_mode_only: false,
+            no_deletions: false,
+            apply_diff_gitignore: false,
+            only_vcs: false,
+            pair_renames: false,
+            only_extensions: Vec::new(),
+            excluded_extensions: Vec::new(),
+            verbosity: 0,
         }
     }
 
@@ -62,9 +322,213 @@ impl ConfigBuilder {
         self
     }
 
-    /// Set downstream filter
-    pub fn with_downstream_filter(mut self, filter: Option<String>) -> Self {
-        self.downstream_filter = filter;
+    /// Set downstream pipeline stages
+    pub fn with_downstream_pipeline(mut self, pipeline: Vec<String>) -> Self {
+        self.downstream_pipeline = pipeline;
+        self
+    }
+
+    /// Set the layers the VCS and downstream settings were resolved from
+    pub fn with_sources(mut self, sources: ConfigSources) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Set whether downstream commands are run through a shell
+    pub fn with_downstream_shell(mut self, enabled: bool) -> Self {
+        self.downstream_shell = enabled;
+        self
+    }
+
+    /// Set downstream timeout
+    pub fn with_downstream_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.downstream_timeout = timeout;
+        self
+    }
+
+    /// Set how often a failed downstream pipeline is re-run
+    pub fn with_downstream_retries(mut self, retries: u32) -> Self {
+        self.downstream_retries = retries;
+        self
+    }
+
+    /// Set line count of the filtered diff above which the downstream pipeline is bypassed (0 = no limit)
+    pub fn with_downstream_max_lines(mut self, lines: Option<usize>) -> Self {
+        self.downstream_max_lines = lines.filter(|&lines| lines > 0);
+        self
+    }
+
+    /// Set working directory of the downstream commands
+    pub fn with_downstream_cwd(mut self, dir: Option<PathBuf>) -> Self {
+        self.downstream_cwd = dir;
+        self
+    }
+
+    /// Set the file with review-only ignore patterns
+    pub fn with_diffignore(mut self, file: Option<PathBuf>) -> Self {
+        self.diffignore = file;
+        self
+    }
+
+    /// Set the file with patterns of paths that are always kept
+    pub fn with_force_include(mut self, file: Option<PathBuf>) -> Self {
+        self.force_include = file;
+        self
+    }
+
+    /// Set the file read instead of the root .gitignore
+    pub fn with_gitignore_file(mut self, file: Option<PathBuf>) -> Self {
+        self.gitignore_file = file;
+        self
+    }
+
+    /// Set ignore patterns added after those of the root .gitignore
+    pub fn with_extra_ignore_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.extra_ignore_patterns = patterns;
+        self
+    }
+
+    /// Set environment variables for the downstream commands
+    pub fn with_downstream_env(mut self, vars: Vec<(String, String)>) -> Self {
+        self.downstream_env = vars;
+        self
+    }
+
+    /// Set path prefix to strip before matching
+    pub fn with_strip_path_prefix(mut self, prefix: Option<String>) -> Self {
+        self.strip_path_prefix = prefix;
+        self
+    }
+
+    /// Set directory prepended to diff paths before .gitignore matching
+    pub fn with_path_prefix(mut self, prefix: Option<String>) -> Self {
+        self.path_prefix = prefix;
+        self
+    }
+
+    /// Set case-insensitive .gitignore matching
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Set the global ignore file read beneath the repository's patterns
+    pub fn with_global_excludes_file(mut self, file: Option<PathBuf>) -> Self {
+        self.global_excludes_file = file;
+        self
+    }
+
+    /// Set diff header path prefixes
+    pub fn with_diff_prefixes(mut self, prefixes: DiffPrefixes) -> Self {
+        self.diff_prefixes = Some(prefixes);
+        self
+    }
+
+    /// Set whether plain unified diffs are split into file sections
+    pub fn with_unified(mut self, enabled: bool) -> Self {
+        self.unified = enabled;
+        self
+    }
+
+    /// Set whether ANSI color sequences are removed from the input before parsing
+    pub fn with_strip_ansi(mut self, enabled: bool) -> Self {
+        self.strip_ansi = enabled;
+        self
+    }
+
+    /// Set dry-run mode
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Set NUL-terminated report lines
+    pub fn with_null_terminated(mut self, enabled: bool) -> Self {
+        self.null_terminated = enabled;
+        self
+    }
+
+    /// Set output format
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Set what happens to sections whose path cannot be determined
+    pub fn with_on_unparsable(mut self, action: UnparsableAction) -> Self {
+        self.on_unparsable = action;
+        self
+    }
+
+    /// Set number of threads deciding about diff sections
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Set body size after which a section is streamed instead of buffered (0 = no limit)
+    pub fn with_max_section_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_section_bytes = limit.filter(|&limit| limit > 0);
+        self
+    }
+
+    /// Set whether dropped sections are replaced by their header and a comment line
+    pub fn with_keep_empty_diff_headers(mut self, enabled: bool) -> Self {
+        self.keep_empty_diff_headers = enabled;
+        self
+    }
+
+    /// Set whether kept sections are preceded by a comment line saying why they were kept
+    pub fn with_annotate(mut self, enabled: bool) -> Self {
+        self.annotate = enabled;
+        self
+    }
+
+    /// Set whether sections that only change the file mode are dropped
+    pub fn with_no_mode_only(mut self, enabled: bool) -> Self {
+        self.no_mode_only = enabled;
+        self
+    }
+
+    /// Set whether sections deleting a file are dropped
+    pub fn with_no_deletions(mut self, enabled: bool) -> Self {
+        self.no_deletions = enabled;
+        self
+    }
+
+    /// Set whether .gitignore files added or modified by the diff apply to later sections
+    pub fn with_apply_diff_gitignore(mut self, enabled: bool) -> Self {
+        self.apply_diff_gitignore = enabled;
+        self
+    }
+
+    /// Set whether only sections matching the VCS patterns are kept
+    pub fn with_only_vcs(mut self, enabled: bool) -> Self {
+        self.only_vcs = enabled;
+        self
+    }
+
+    /// Set whether a deletion and an addition of the same content are decided together
+    pub fn with_pair_renames(mut self, enabled: bool) -> Self {
+        self.pair_renames = enabled;
+        self
+    }
+
+    /// Set file extensions that are kept (empty = all extensions)
+    pub fn with_only_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.only_extensions = extensions;
+        self
+    }
+
+    /// Set file extensions that are dropped
+    pub fn with_excluded_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.excluded_extensions = extensions;
+        self
+    }
+
+    /// Set the level of diagnostics written to stderr (0 = none)
+    pub fn with_verbosity(mut self, level: u8) -> Self {
+        self.verbosity = level;
         self
     }
 
@@ -73,7 +537,41 @@ impl ConfigBuilder {
         AppConfig {
             vcs_enabled: self.vcs_enabled.unwrap_or(true),
             vcs_patterns: self.vcs_patterns.unwrap_or_else(Self::default_vcs_patterns),
-            downstream_filter: self.downstream_filter,
+            downstream_pipeline: self.downstream_pipeline,
+            sources: self.sources,
+            downstream_shell: self.downstream_shell,
+            downstream_timeout: self.downstream_timeout,
+            downstream_retries: self.downstream_retries,
+            downstream_max_lines: self.downstream_max_lines,
+            downstream_cwd: self.downstream_cwd,
+            diffignore: self.diffignore,
+            force_include: self.force_include,
+            gitignore_file: self.gitignore_file,
+            extra_ignore_patterns: self.extra_ignore_patterns,
+            downstream_env: self.downstream_env,
+            strip_path_prefix: self.strip_path_prefix,
+            path_prefix: self.path_prefix,
+            ignore_case: self.ignore_case,
+            global_excludes_file: self.global_excludes_file,
+            diff_prefixes: self.diff_prefixes.unwrap_or_default(),
+            unified: self.unified,
+            strip_ansi: self.strip_ansi,
+            dry_run: self.dry_run,
+            null_terminated: self.null_terminated,
+            output_format: self.output_format,
+            on_unparsable: self.on_unparsable,
+            jobs: self.jobs,
+            max_section_bytes: self.max_section_bytes,
+            keep_empty_diff_headers: self.keep_empty_diff_headers,
+            annotate: self.annotate,
+            no_mode_only: self.no_mode_only,
+            no_deletions: self.no_deletions,
+            apply_diff_gitignore: self.apply_diff_gitignore,
+            only_vcs: self.only_vcs,
+            pair_renames: self.pair_renames,
+            only_extensions: self.only_extensions,
+            excluded_extensions: self.excluded_extensions,
+            verbosity: self.verbosity,
         }
     }
 
@@ -87,6 +585,9 @@ impl ConfigBuilder {
             "CVS/".to_owned(),
             "CVSROOT/".to_owned(),
             ".bzr/".to_owned(),
+            ".jj/".to_owned(),
+            ".fslckout".to_owned(),
+            "_FOSSIL_".to_owned(),
         ]
     }
 }
@@ -102,19 +603,112 @@ impl AppConfig {
     ///
     /// Priority order:
     /// 1. CLI parameters (highest priority)
-    /// 2. Git configuration values
-    /// 3. Hardcoded defaults (only when Git config not set)
+    /// 2. Environment variables (see [`EnvConfig`])
+    /// 3. Git configuration values
+    /// 4. Hardcoded defaults (only when Git config not set)
     pub fn from_cli(cli_args: CliArgs) -> Result<Self, ConfigError> {
+        Self::from_cli_with_reader(cli_args, &SystemGitConfigReader)
+    }
+
+    /// Create AppConfig from CLI arguments, reading Git configuration through `reader`
+    ///
+    /// Resolves settings with the same priority as [`AppConfig::from_cli`], which lets
+    /// configuration resolution be tested without a real Git repository.
+    pub fn from_cli_with_reader<R: GitConfigReader>(
+        cli_args: CliArgs,
+        reader: &R,
+    ) -> Result<Self, ConfigError> {
+        let keys = Self::resolve_config_keys(&cli_args)?;
+        let (vcs_enabled, vcs_enabled_source) =
+            Self::resolve_vcs_enabled(&cli_args, reader, &keys)?;
+        let (vcs_patterns, vcs_patterns_source) =
+            Self::resolve_vcs_patterns(&cli_args, reader, &keys)?;
+        let (downstream_pipeline, downstream_source, legacy_downstream_filter) =
+            Self::resolve_downstream_pipeline(&cli_args, reader, &keys)?;
         let config_builder = ConfigBuilder::new()
-            .with_vcs_enabled(Self::resolve_vcs_enabled(&cli_args)?)
-            .with_vcs_patterns(Self::resolve_vcs_patterns(&cli_args)?)
-            .with_downstream_filter(Self::resolve_downstream_filter(&cli_args));
+            .with_vcs_enabled(vcs_enabled)
+            .with_vcs_patterns(vcs_patterns)
+            .with_downstream_pipeline(downstream_pipeline)
+            .with_sources(ConfigSources {
+                vcs_enabled: vcs_enabled_source,
+                vcs_patterns: vcs_patterns_source,
+                downstream_filter: downstream_source,
+                legacy_downstream_filter,
+            })
+            .with_downstream_shell(cli_args.downstream_shell)
+            .with_downstream_timeout(Self::resolve_downstream_timeout(&cli_args))
+            .with_downstream_retries(cli_args.downstream_retries)
+            .with_downstream_max_lines(cli_args.downstream_max_lines)
+            .with_downstream_cwd(cli_args.downstream_cwd.clone())
+            .with_diffignore(cli_args.diffignore.clone())
+            .with_force_include(cli_args.force_include.clone())
+            .with_gitignore_file(cli_args.gitignore_file.clone())
+            .with_extra_ignore_patterns(cli_args.ignore.clone())
+            .with_downstream_env(Self::resolve_downstream_env(&cli_args)?)
+            .with_strip_path_prefix(Self::resolve_strip_path_prefix(&cli_args)?)
+            .with_path_prefix(Self::resolve_path_prefix(&cli_args)?)
+            .with_ignore_case(Self::resolve_ignore_case(reader))
+            .with_global_excludes_file(Self::resolve_global_excludes_file(reader))
+            .with_diff_prefixes(Self::resolve_diff_prefixes(&cli_args, reader))
+            .with_unified(cli_args.unified)
+            .with_strip_ansi(cli_args.strip_ansi)
+            .with_dry_run(cli_args.dry_run)
+            .with_null_terminated(cli_args.null_terminated)
+            .with_jobs(cli_args.jobs)
+            .with_max_section_bytes(cli_args.max_section_bytes)
+            .with_keep_empty_diff_headers(cli_args.keep_empty_diff_headers)
+            .with_annotate(cli_args.annotate)
+            .with_no_mode_only(cli_args.no_mode_only)
+            .with_no_deletions(cli_args.no_deletions)
+            .with_apply_diff_gitignore(cli_args.apply_diff_gitignore)
+            .with_only_vcs(cli_args.only_vcs)
+            .with_pair_renames(cli_args.pair_renames)
+            .with_verbosity(cli_args.verbose)
+            .with_only_extensions(Self::parse_extensions(
+                "--only-ext",
+                cli_args.only_ext.as_deref(),
+            )?)
+            .with_excluded_extensions(Self::parse_extensions(
+                "--exclude-ext",
+                cli_args.exclude_ext.as_deref(),
+            )?)
+            .with_output_format(cli_args.output_format)
+            .with_on_unparsable(cli_args.on_unparsable);
 
         Ok(config_builder.build())
     }
 
-    /// Resolve VCS enabled state using functional combinators
-    fn resolve_vcs_enabled(cli_args: &CliArgs) -> Result<bool, ConfigError> {
+    /// Resolve the Git config keys from `--config-key-prefix` or the environment
+    fn resolve_config_keys(cli_args: &CliArgs) -> Result<ConfigKeys, ConfigError> {
+        const EXPECTED: &str = "Git config section name (letters, digits, '-' and '.')";
+
+        if let Some(prefix) = cli_args.config_key_prefix.as_deref() {
+            return ConfigKeys::with_prefix(prefix).ok_or_else(|| {
+                ConfigError::InvalidCliArgument {
+                    argument: "--config-key-prefix".to_owned(),
+                    value: prefix.to_owned(),
+                    expected: EXPECTED.to_owned(),
+                }
+            });
+        }
+
+        EnvConfig::get_config_key_prefix()
+            .map(|prefix| {
+                ConfigKeys::with_prefix(&prefix).ok_or_else(|| ConfigError::InvalidEnvValue {
+                    variable: ENV_CONFIG_KEY_PREFIX.to_owned(),
+                    value: prefix,
+                    expected: EXPECTED.to_owned(),
+                })
+            })
+            .unwrap_or_else(|| Ok(ConfigKeys::default()))
+    }
+
+    /// Resolve VCS enabled state and its source using functional combinators
+    fn resolve_vcs_enabled<R: GitConfigReader>(
+        cli_args: &CliArgs,
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<(bool, ConfigSource), ConfigError> {
         [
             cli_args.vcs.then_some(true),
             cli_args.no_vcs.then_some(false),
@@ -122,41 +716,276 @@ impl AppConfig {
         .into_iter()
         .flatten()
         .next()
-        .map(Ok)
+        .map(|enabled| Ok((enabled, ConfigSource::Cli)))
         .unwrap_or_else(|| {
-            // Fallback to Git config or default - exactly like original logic
-            match GitConfig::get_vcs_ignore_enabled() {
-                Ok(Some(enabled)) => Ok(enabled),
-                Ok(None) | Err(_) => Ok(true), // Default: VCS filtering enabled (also when not in Git repo)
+            // The environment overrides Git config
+            if let Some(enabled) = EnvConfig::get_vcs_enabled()? {
+                return Ok((enabled, ConfigSource::Env));
+            }
+
+            // Fallback to Git config or default; a value that is not a boolean is reported
+            match GitConfig::get_vcs_ignore_enabled_with_keys(reader, keys) {
+                Ok(Some(enabled)) => Ok((enabled, ConfigSource::GitConfig)),
+                Err(error @ ConfigError::InvalidGitConfig { .. }) => Err(error),
+                Ok(None) | Err(_) => Ok((true, ConfigSource::Default)), // Default: VCS filtering enabled (also when not in Git repo)
             }
         })
     }
 
-    /// Resolve VCS patterns using functional composition
-    fn resolve_vcs_patterns(cli_args: &CliArgs) -> Result<Vec<String>, ConfigError> {
-        cli_args
+    /// Resolve VCS patterns and the source of the pattern list using functional composition
+    fn resolve_vcs_patterns<R: GitConfigReader>(
+        cli_args: &CliArgs,
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<(Vec<String>, ConfigSource), ConfigError> {
+        let (mut patterns, source) = cli_args
             .vcs_pattern
             .as_ref()
-            .map(|patterns_str| Self::parse_cli_vcs_patterns(patterns_str))
+            .map(|patterns_str| {
+                Self::parse_cli_vcs_patterns(patterns_str, cli_args.allow_empty_vcs)
+                    .map(|patterns| (patterns, ConfigSource::Cli))
+            })
             .unwrap_or_else(|| {
+                // The environment overrides Git config
+                if let Some(env_patterns) = EnvConfig::get_vcs_patterns()? {
+                    return Ok((env_patterns, ConfigSource::Env));
+                }
+
                 // Fallback to existing Git-Config logic - exactly like original
-                match GitConfig::get_vcs_ignore_patterns() {
-                    Ok(Some(git_patterns)) => Ok(git_patterns),
-                    Ok(None) | Err(_) => Ok(ConfigBuilder::default_vcs_patterns()),
+                match GitConfig::get_vcs_ignore_patterns_with_keys(reader, keys) {
+                    Ok(Some(git_patterns)) => Ok((git_patterns, ConfigSource::GitConfig)),
+                    Ok(None) | Err(_) => {
+                        Ok((ConfigBuilder::default_vcs_patterns(), ConfigSource::Default))
+                    }
                 }
+            })?;
+
+        // Append additional patterns without replacing the effective ones
+        if let Some(added) = cli_args.vcs_pattern_add.as_deref() {
+            for pattern in Self::split_vcs_patterns("--vcs-pattern-add", added, false)? {
+                if !patterns.contains(&pattern) {
+                    patterns.push(pattern);
+                }
+            }
+        }
+
+        Ok((patterns, source))
+    }
+
+    /// Resolve downstream pipeline, its source and whether it was read from the
+    /// deprecated Git config key; the environment and git config filters are
+    /// single-stage pipelines
+    fn resolve_downstream_pipeline<R: GitConfigReader>(
+        cli_args: &CliArgs,
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<(Vec<String>, ConfigSource, bool), ConfigError> {
+        if !cli_args.downstream.is_empty() {
+            return Self::parse_downstream_commands(&cli_args.downstream)
+                .map(|pipeline| (pipeline, ConfigSource::Cli, false));
+        }
+
+        let resolved = EnvConfig::get_downstream_filter()
+            .map(|filter| (filter, ConfigSource::Env, false))
+            .or_else(|| {
+                GitConfig::get_downstream_filter_with_legacy_flag(reader, keys)
+                    .ok()
+                    .flatten()
+                    .map(|(filter, legacy)| (filter, ConfigSource::GitConfig, legacy))
+            });
+        Ok(match resolved {
+            Some((filter, source, legacy)) => (vec![filter], source, legacy),
+            None => (Vec::new(), ConfigSource::Default, false),
+        })
+    }
+
+    /// Trim the `--downstream` commands, dropping blank ones
+    ///
+    /// Blank commands mean "no downstream filter", so `--downstream ""` passes the
+    /// output through. A command that does not split into a non-empty program name,
+    /// e.g. one consisting only of quotes, is rejected.
+    fn parse_downstream_commands(commands: &[String]) -> Result<Vec<String>, ConfigError> {
+        commands
+            .iter()
+            .map(|command| command.trim())
+            .filter(|command| !command.is_empty())
+            .map(|command| {
+                let invalid = |expected: String| ConfigError::InvalidCliArgument {
+                    argument: "--downstream".to_owned(),
+                    value: command.to_owned(),
+                    expected,
+                };
+                let words = shell_words::split(command)
+                    .map_err(|e| invalid(format!("command with valid shell quoting ({e})")))?;
+                words
+                    .first()
+                    .filter(|program| !program.trim().is_empty())
+                    .map(|_| command.to_owned())
+                    .ok_or_else(|| invalid("command with a non-empty program name".to_owned()))
             })
+            .collect()
+    }
+
+    /// Resolve case-insensitive matching from Git's `core.ignorecase` (default: false)
+    fn resolve_ignore_case<R: GitConfigReader>(reader: &R) -> bool {
+        GitConfig::get_ignore_case_with_reader(reader)
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    /// Resolve the global ignore file like Git: `core.excludesFile`, or else
+    /// `$XDG_CONFIG_HOME/git/ignore`, or else `~/.config/git/ignore`
+    fn resolve_global_excludes_file<R: GitConfigReader>(reader: &R) -> Option<PathBuf> {
+        let non_empty = |name| env::var_os(name).filter(|value| !value.is_empty());
+
+        Self::global_excludes_path(
+            GitConfig::get_excludes_file_with_reader(reader)
+                .ok()
+                .flatten(),
+            non_empty("XDG_CONFIG_HOME").map(PathBuf::from),
+            non_empty("HOME").map(PathBuf::from),
+        )
+    }
+
+    /// Path of the global ignore file for the given `core.excludesFile`,
+    /// `$XDG_CONFIG_HOME` and `$HOME` values
+    ///
+    /// A leading `~/` in `core.excludesFile` is expanded to the home directory.
+    fn global_excludes_path(
+        excludes_file: Option<String>,
+        xdg_config_home: Option<PathBuf>,
+        home: Option<PathBuf>,
+    ) -> Option<PathBuf> {
+        match excludes_file {
+            Some(file) => match (file.strip_prefix("~/"), home) {
+                (Some(relative), Some(home)) => Some(home.join(relative)),
+                _ => Some(PathBuf::from(file)),
+            },
+            None => xdg_config_home
+                .or_else(|| Some(home?.join(".config")))
+                .map(|config| config.join("git").join("ignore")),
+        }
     }
 
-    /// Resolve downstream filter using functional combinators
-    fn resolve_downstream_filter(cli_args: &CliArgs) -> Option<String> {
+    /// Resolve diff header prefixes from Git config (default: `a/` and `b/`) and CLI overrides
+    fn resolve_diff_prefixes<R: GitConfigReader>(cli_args: &CliArgs, reader: &R) -> DiffPrefixes {
+        let git_prefixes = GitConfig::get_diff_prefixes_with_reader(reader)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        Self::override_diff_prefixes(cli_args, git_prefixes)
+    }
+
+    /// Replace each side of `prefixes` that is given on the command line
+    fn override_diff_prefixes(cli_args: &CliArgs, prefixes: DiffPrefixes) -> DiffPrefixes {
+        DiffPrefixes::new(
+            cli_args.src_prefix.clone().unwrap_or(prefixes.src),
+            cli_args.dst_prefix.clone().unwrap_or(prefixes.dst),
+        )
+    }
+
+    /// Resolve downstream timeout; a timeout of 0 seconds means no timeout
+    fn resolve_downstream_timeout(cli_args: &CliArgs) -> Option<Duration> {
+        cli_args
+            .downstream_timeout
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Split the `--downstream-env` arguments into variable names and values
+    fn resolve_downstream_env(cli_args: &CliArgs) -> Result<Vec<(String, String)>, ConfigError> {
         cli_args
-            .downstream
-            .clone()
-            .or_else(|| GitConfig::get_downstream_filter().ok().flatten())
+            .downstream_env
+            .iter()
+            .map(|var| {
+                var.split_once('=')
+                    .filter(|(name, _)| !name.is_empty())
+                    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                    .ok_or_else(|| ConfigError::InvalidCliArgument {
+                        argument: "--downstream-env".to_owned(),
+                        value: var.clone(),
+                        expected: "NAME=VALUE with a non-empty name".to_owned(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Resolve the path prefix to strip; surrounding slashes are removed
+    fn resolve_strip_path_prefix(cli_args: &CliArgs) -> Result<Option<String>, ConfigError> {
+        Self::parse_path_prefix("--strip-path-prefix", cli_args.strip_path_prefix.as_deref())
+    }
+
+    /// Resolve the directory the diff paths are relative to (`--path-prefix`)
+    fn resolve_path_prefix(cli_args: &CliArgs) -> Result<Option<String>, ConfigError> {
+        Self::parse_path_prefix("--path-prefix", cli_args.path_prefix.as_deref())
+    }
+
+    /// Trim surrounding slashes from a path prefix argument, rejecting empty prefixes
+    fn parse_path_prefix(
+        argument: &str,
+        prefix: Option<&str>,
+    ) -> Result<Option<String>, ConfigError> {
+        prefix
+            .map(|prefix| {
+                let trimmed = prefix.trim_matches('/');
+                (!trimmed.is_empty())
+                    .then(|| trimmed.to_owned())
+                    .ok_or_else(|| ConfigError::InvalidCliArgument {
+                        argument: argument.to_owned(),
+                        value: prefix.to_owned(),
+                        expected: "non-empty relative path prefix".to_owned(),
+                    })
+            })
+            .transpose()
+    }
+
+    /// Split a comma-separated extension list, dropping leading dots
+    ///
+    /// Lists that contain no extension or an extension with a path separator are rejected.
+    fn parse_extensions(argument: &str, list: Option<&str>) -> Result<Vec<String>, ConfigError> {
+        let Some(list) = list else {
+            return Ok(Vec::new());
+        };
+
+        let extensions = list
+            .split(',')
+            .map(|extension| extension.trim().trim_start_matches('.'))
+            .filter(|extension| !extension.is_empty())
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        if extensions.is_empty() || extensions.iter().any(|extension| extension.contains('/')) {
+            return Err(ConfigError::InvalidCliArgument {
+                argument: argument.to_owned(),
+                value: list.to_owned(),
+                expected: "comma-separated file extensions, e.g. 'rs,toml'".to_owned(),
+            });
+        }
+        Ok(extensions)
     }
 
     /// Parse and validate CLI VCS patterns using functional approach
-    fn parse_cli_vcs_patterns(patterns_str: &str) -> Result<Vec<String>, ConfigError> {
+    ///
+    /// A list that is empty after splitting and trimming is rejected unless `allow_empty`
+    /// is set, in which case no patterns are returned and VCS filtering matches nothing.
+    fn parse_cli_vcs_patterns(
+        patterns_str: &str,
+        allow_empty: bool,
+    ) -> Result<Vec<String>, ConfigError> {
+        Self::split_vcs_patterns("--vcs-pattern", patterns_str, allow_empty)
+    }
+
+    /// Split comma-separated VCS patterns given as value of the CLI option `argument`
+    ///
+    /// Patterns that would match every path (`*`, `**/`) or that contain a `..` component
+    /// are rejected, naming the offending pattern.
+    fn split_vcs_patterns(
+        argument: &str,
+        patterns_str: &str,
+        allow_empty: bool,
+    ) -> Result<Vec<String>, ConfigError> {
         let patterns: Vec<String> = patterns_str
             .split(',')
             .map(str::trim)
@@ -164,15 +993,135 @@ impl AppConfig {
             .map(ToOwned::to_owned)
             .collect();
 
-        (!patterns.is_empty())
+        if let Some(pattern) = patterns
+            .iter()
+            .find(|pattern| Self::is_dangerous_vcs_pattern(pattern))
+        {
+            return Err(ConfigError::InvalidCliArgument {
+                argument: argument.to_owned(),
+                value: pattern.clone(),
+                expected: "VCS directory pattern such as '.git/', not matching every path \
+                           or containing '..'"
+                    .to_owned(),
+            });
+        }
+
+        (allow_empty || !patterns.is_empty())
             .then_some(patterns)
             .ok_or_else(|| ConfigError::InvalidCliArgument {
-                argument: "--vcs-pattern".to_owned(),
+                argument: argument.to_owned(),
                 value: patterns_str.to_owned(),
                 expected: "comma-separated list of non-empty patterns".to_owned(),
             })
     }
 
+    /// Check if a VCS pattern matches every path or has a `..` path component
+    fn is_dangerous_vcs_pattern(pattern: &str) -> bool {
+        let glob = pattern.trim_start_matches('!').trim_matches('/');
+        glob.chars().all(|c| c == '*' || c == '/') || glob.split('/').any(|part| part == "..")
+    }
+
+    /// Validate the resolved configuration without processing any diff
+    ///
+    /// Checks that every VCS pattern is a valid gitignore-style glob and that the
+    /// executable of every downstream stage (its first word) can be found, either as
+    /// a path or in `PATH`. Shell builtins are not recognized as executables. Unless
+    /// the shell is enabled, each stage must also split into argv with valid quoting.
+    /// A configured downstream working directory must exist.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.vcs_patterns
+            .iter()
+            .try_for_each(|pattern| Self::validate_pattern("VCS pattern", pattern))?;
+        self.extra_ignore_patterns
+            .iter()
+            .try_for_each(|pattern| Self::validate_pattern("ignore pattern", pattern))?;
+
+        if let Some(dir) = self.downstream_cwd.as_deref().filter(|dir| !dir.is_dir()) {
+            return Err(ConfigError::ValidationFailed {
+                setting: "downstream working directory".to_owned(),
+                value: dir.display().to_string(),
+                reason: "not a directory".to_owned(),
+            });
+        }
+
+        if let Some(file) = self.diffignore.as_deref().filter(|file| !file.is_file()) {
+            return Err(ConfigError::ValidationFailed {
+                setting: "diffignore file".to_owned(),
+                value: file.display().to_string(),
+                reason: "not a file".to_owned(),
+            });
+        }
+
+        if let Some(file) = self.force_include.as_deref().filter(|file| !file.is_file()) {
+            return Err(ConfigError::ValidationFailed {
+                setting: "force-include file".to_owned(),
+                value: file.display().to_string(),
+                reason: "not a file".to_owned(),
+            });
+        }
+
+        if let Some(file) = self
+            .gitignore_file
+            .as_deref()
+            .filter(|file| !file.is_file())
+        {
+            return Err(ConfigError::ValidationFailed {
+                setting: "gitignore file".to_owned(),
+                value: file.display().to_string(),
+                reason: "not a file".to_owned(),
+            });
+        }
+
+        self.downstream_pipeline.iter().try_for_each(|command| {
+            Self::validate_downstream_filter(command, self.downstream_shell)
+        })
+    }
+
+    /// Validate a single pattern by compiling it as a gitignore glob
+    fn validate_pattern(setting: &str, pattern: &str) -> Result<(), ConfigError> {
+        GitignoreBuilder::new("")
+            .add_line(None, pattern)
+            .map(|_| ())
+            .map_err(|e| ConfigError::ValidationFailed {
+                setting: setting.to_owned(),
+                value: pattern.to_owned(),
+                reason: e.to_string(),
+            })
+    }
+
+    /// Validate that the downstream command's executable exists
+    fn validate_downstream_filter(command: &str, shell: bool) -> Result<(), ConfigError> {
+        let program = if shell {
+            command.split_whitespace().next().map(ToOwned::to_owned)
+        } else {
+            shell_words::split(command)
+                .map_err(|e| ConfigError::ValidationFailed {
+                    setting: "downstream command".to_owned(),
+                    value: command.to_owned(),
+                    reason: e.to_string(),
+                })?
+                .into_iter()
+                .next()
+        }
+        .unwrap_or_default();
+
+        let found = if program.contains('/') {
+            Path::new(&program).is_file()
+        } else {
+            env::var_os("PATH")
+                .map(|paths| env::split_paths(&paths).any(|dir| dir.join(&program).is_file()))
+                .unwrap_or(false)
+        };
+
+        found
+            .then_some(())
+            .ok_or_else(|| ConfigError::ValidationFailed {
+                setting: "downstream command".to_owned(),
+                value: command.to_owned(),
+                reason: format!("executable '{program}' not found"),
+            })
+    }
+
     /// Check if VCS filtering is enabled
     pub fn vcs_enabled(&self) -> bool {
         self.vcs_enabled
@@ -183,15 +1132,646 @@ impl AppConfig {
         &self.vcs_patterns
     }
 
-    /// Get downstream filter command
-    pub fn downstream_filter(&self) -> Option<&str> {
-        self.downstream_filter.as_deref()
+    /// Get downstream pipeline stages (empty if output is not piped)
+    pub fn downstream_pipeline(&self) -> &[String] {
+        &self.downstream_pipeline
+    }
+
+    /// Get the layers the VCS and downstream settings were resolved from
+    pub fn sources(&self) -> &ConfigSources {
+        &self.sources
+    }
+
+    /// Render the resolved VCS and downstream settings and their sources as TOML
+    ///
+    /// For `--print-config`, to show which layer won for each setting:
+    ///
+    /// ```toml
+    /// vcs_enabled = false
+    /// vcs_patterns = [".git/", ".svn/"]
+    /// downstream_filter = ["delta"]
+    ///
+    /// [sources]
+    /// vcs_enabled = "cli"
+    /// vcs_patterns = "default"
+    /// downstream_filter = "git-config"
+    /// ```
+    pub fn to_toml(&self) -> String {
+        // JSON string escapes are a subset of those of TOML basic strings
+        let string = |value: &str| serde_json::Value::from(value).to_string();
+        let array = |values: &[String]| {
+            let items: Vec<String> = values.iter().map(|value| string(value)).collect();
+            format!("[{}]", items.join(", "))
+        };
+
+        format!(
+            "vcs_enabled = {}\n\
+             vcs_patterns = {}\n\
+             downstream_filter = {}\n\
+             \n\
+             [sources]\n\
+             vcs_enabled = {}\n\
+             vcs_patterns = {}\n\
+             downstream_filter = {}\n",
+            self.vcs_enabled,
+            array(&self.vcs_patterns),
+            array(&self.downstream_pipeline),
+            string(self.sources.vcs_enabled.as_str()),
+            string(self.sources.vcs_patterns.as_str()),
+            string(self.sources.downstream_filter.as_str()),
+        )
+    }
+
+    /// Check if downstream commands are run through `sh -c`
+    pub fn downstream_shell(&self) -> bool {
+        self.downstream_shell
+    }
+
+    /// Get downstream timeout (None if the pipeline may run indefinitely)
+    pub fn downstream_timeout(&self) -> Option<Duration> {
+        self.downstream_timeout
+    }
+
+    /// Get how often a failed downstream pipeline is re-run (0 = never)
+    pub fn downstream_retries(&self) -> u32 {
+        self.downstream_retries
+    }
+
+    /// Get line count of the filtered diff above which the downstream pipeline is bypassed
+    pub fn downstream_max_lines(&self) -> Option<usize> {
+        self.downstream_max_lines
+    }
+
+    /// Get working directory of the downstream commands
+    pub fn downstream_cwd(&self) -> Option<&Path> {
+        self.downstream_cwd.as_deref()
+    }
+
+    /// Get the file with review-only ignore patterns, if given explicitly
+    pub fn diffignore(&self) -> Option<&Path> {
+        self.diffignore.as_deref()
+    }
+
+    /// Get the file with patterns of paths that are always kept, if given
+    pub fn force_include(&self) -> Option<&Path> {
+        self.force_include.as_deref()
+    }
+
+    /// Get the file read instead of the root .gitignore, if given
+    pub fn gitignore_file(&self) -> Option<&Path> {
+        self.gitignore_file.as_deref()
+    }
+
+    /// Get ignore patterns added after those of the root .gitignore
+    pub fn extra_ignore_patterns(&self) -> &[String] {
+        &self.extra_ignore_patterns
+    }
+
+    /// Get environment variables set for the downstream commands
+    pub fn downstream_env(&self) -> &[(String, String)] {
+        &self.downstream_env
+    }
+
+    /// Get path prefix stripped from diff paths before matching
+    pub fn strip_path_prefix(&self) -> Option<&str> {
+        self.strip_path_prefix.as_deref()
+    }
+
+    /// Get directory prepended to diff paths before .gitignore matching
+    pub fn path_prefix(&self) -> Option<&str> {
+        self.path_prefix.as_deref()
+    }
+
+    /// Check if .gitignore patterns match case-insensitively
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Get the global ignore file (`core.excludesFile` or Git's XDG default), if known
+    pub fn global_excludes_file(&self) -> Option<&Path> {
+        self.global_excludes_file.as_deref()
+    }
+
+    /// Get path prefixes of the `diff --git` headers
+    pub fn diff_prefixes(&self) -> &DiffPrefixes {
+        &self.diff_prefixes
+    }
+
+    /// Check if plain unified diffs are split into file sections
+    pub fn unified(&self) -> bool {
+        self.unified
+    }
+
+    /// Check if ANSI color sequences are removed from the input before parsing
+    pub fn strip_ansi(&self) -> bool {
+        self.strip_ansi
+    }
+
+    /// Check if decisions are reported instead of writing the filtered diff
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Check if report lines are terminated with NUL instead of newline
+    pub fn null_terminated(&self) -> bool {
+        self.null_terminated
+    }
+
+    /// Get number of threads deciding about diff sections
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Get body size after which a section is streamed instead of buffered
+    pub fn max_section_bytes(&self) -> Option<usize> {
+        self.max_section_bytes
+    }
+
+    /// Check if dropped sections are replaced by their header and a comment line
+    pub fn keep_empty_diff_headers(&self) -> bool {
+        self.keep_empty_diff_headers
+    }
+
+    /// Check if kept sections are preceded by a comment line saying why they were kept
+    pub fn annotate(&self) -> bool {
+        self.annotate
+    }
+
+    /// Check if sections that only change the file mode are dropped
+    pub fn no_mode_only(&self) -> bool {
+        self.no_mode_only
+    }
+
+    /// Check if sections deleting a file are dropped
+    pub fn no_deletions(&self) -> bool {
+        self.no_deletions
+    }
+
+    /// Check if .gitignore files added or modified by the diff apply to later sections
+    pub fn apply_diff_gitignore(&self) -> bool {
+        self.apply_diff_gitignore
+    }
+
+    /// Check if only sections matching the VCS patterns are kept
+    pub fn only_vcs(&self) -> bool {
+        self.only_vcs
+    }
+
+    /// Check if a deletion and an addition of the same content are decided together
+    pub fn pair_renames(&self) -> bool {
+        self.pair_renames
+    }
+
+    /// Get file extensions that are kept (empty = all extensions)
+    pub fn only_extensions(&self) -> &[String] {
+        &self.only_extensions
+    }
+
+    /// Get file extensions that are dropped
+    pub fn excluded_extensions(&self) -> &[String] {
+        &self.excluded_extensions
+    }
+
+    /// Get the level of diagnostics written to stderr (0 = none)
+    pub fn verbosity(&self) -> u8 {
+        self.verbosity
+    }
+
+    /// Get format of the output written for the processed diff
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// Get what happens to sections whose path cannot be determined
+    pub fn on_unparsable(&self) -> UnparsableAction {
+        self.on_unparsable
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MockGitConfigReader;
+
+    /// Mock git config setting every key `from_cli_with_reader` reads
+    fn configured_reader() -> MockGitConfigReader {
+        MockGitConfigReader::new()
+            .with_config("diff-gitignore-filter.vcs-ignore.enabled", "false")
+            .with_config("diff-gitignore-filter.vcs-ignore.patterns", ".svn/")
+            .with_config("gitignore-diff.downstream-filter", "less")
+            .with_config("core.ignorecase", "true")
+            .with_config("diff.noprefix", "true")
+    }
+
+    /// **What is tested:** Resolution of git config values through an injected reader
+    /// **Why it is tested:** Library users must be able to test configuration resolution without a real Git repository
+    /// **Test conditions:** Mock reader setting VCS filtering, patterns, downstream filter, `core.ignorecase` and `diff.noprefix`; an empty reader; a reader with a non-boolean VCS setting; no CLI arguments
+    /// **Expectations:** Every setting is taken from the mock reader, defaults apply without config, and the non-boolean value is reported with its key
+    #[test]
+    fn test_from_cli_with_reader_uses_git_config(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let config = AppConfig::from_cli_with_reader(CliArgs::default(), &configured_reader())?;
+
+        assert!(!config.vcs_enabled());
+        assert_eq!(config.vcs_patterns(), [".svn/".to_owned()]);
+        assert_eq!(config.downstream_pipeline(), ["less".to_owned()]);
+        assert!(config.ignore_case());
+        assert_eq!(config.diff_prefixes(), &DiffPrefixes::none());
+
+        let config =
+            AppConfig::from_cli_with_reader(CliArgs::default(), &MockGitConfigReader::new())?;
+        assert!(config.vcs_enabled());
+        assert_eq!(config.vcs_patterns(), ConfigBuilder::default_vcs_patterns());
+        assert!(config.downstream_pipeline().is_empty());
+        assert_eq!(config.diff_prefixes(), &DiffPrefixes::default());
+
+        let reader = MockGitConfigReader::new()
+            .with_config("diff-gitignore-filter.vcs-ignore.enabled", "maybe");
+        assert!(matches!(
+            AppConfig::from_cli_with_reader(CliArgs::default(), &reader),
+            Err(ConfigError::InvalidGitConfig { key, value, .. })
+                if key == "diff-gitignore-filter.vcs-ignore.enabled" && value == "maybe"
+        ));
+        Ok(())
+    }
+
+    /// **What is tested:** CLI arguments taking precedence over git config read through an injected reader
+    /// **Why it is tested:** The priority order must hold regardless of where git config values come from
+    /// **Test conditions:** Mock reader with every key set, CLI arguments overriding VCS state, patterns, downstream and prefixes
+    /// **Expectations:** CLI values win; settings without a CLI counterpart still come from the reader
+    #[test]
+    fn test_from_cli_with_reader_cli_precedence(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cli_args = CliArgs {
+            vcs: true,
+            vcs_pattern: Some(".hg/".to_owned()),
+            downstream: vec!["cat".to_owned()],
+            src_prefix: Some("a/".to_owned()),
+            ..Default::default()
+        };
+
+        let config = AppConfig::from_cli_with_reader(cli_args, &configured_reader())?;
+
+        assert!(config.vcs_enabled());
+        assert_eq!(config.vcs_patterns(), [".hg/".to_owned()]);
+        assert_eq!(config.downstream_pipeline(), ["cat".to_owned()]);
+        assert_eq!(config.diff_prefixes(), &DiffPrefixes::new("a/", ""));
+        assert!(config.ignore_case());
+        Ok(())
+    }
+
+    /// **What is tested:** Reading git config under the namespace given by `--config-key-prefix`
+    /// **Why it is tested:** The prefix must redirect every filter key while Git's own keys stay unprefixed
+    /// **Test conditions:** Mock reader with default keys, keys under `acme` and `core.ignorecase`; a valid and an invalid prefix
+    /// **Expectations:** Values come from the `acme` keys, ignore case is still read, and the invalid prefix fails with InvalidCliArgument
+    #[test]
+    fn test_from_cli_with_config_key_prefix() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let reader = configured_reader()
+            .with_config("acme.vcs-ignore.enabled", "true")
+            .with_config("acme.vcs-ignore.patterns", ".jj/")
+            .with_config("acme.downstream-filter", "bat");
+        let cli_args = |prefix: &str| CliArgs {
+            config_key_prefix: Some(prefix.to_owned()),
+            ..Default::default()
+        };
+
+        let config = AppConfig::from_cli_with_reader(cli_args("acme"), &reader)?;
+
+        assert!(config.vcs_enabled());
+        assert_eq!(config.vcs_patterns(), [".jj/".to_owned()]);
+        assert_eq!(config.downstream_pipeline(), ["bat".to_owned()]);
+        assert!(config.ignore_case());
+        assert!(matches!(
+            AppConfig::from_cli_with_reader(cli_args("my tool"), &reader),
+            Err(ConfigError::InvalidCliArgument { .. })
+        ));
+        Ok(())
+    }
+
+    /// **What is tested:** Resolution of the `--downstream-timeout` CLI argument
+    /// **Why it is tested:** A timeout of 0 must disable the deadline instead of killing the pipeline immediately
+    /// **Test conditions:** Unset timeout, a timeout of 0 seconds and a timeout of 5 seconds
+    /// **Expectations:** Unset and 0 should resolve to None, 5 should resolve to a 5 second duration
+    #[test]
+    fn test_resolve_downstream_timeout() {
+        let cli_args = |timeout| CliArgs {
+            downstream_timeout: timeout,
+            ..Default::default()
+        };
+
+        assert_eq!(AppConfig::resolve_downstream_timeout(&cli_args(None)), None);
+        assert_eq!(
+            AppConfig::resolve_downstream_timeout(&cli_args(Some(0))),
+            None
+        );
+        assert_eq!(
+            AppConfig::resolve_downstream_timeout(&cli_args(Some(5))),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    /// **What is tested:** Normalization of the `--downstream` CLI arguments
+    /// **Why it is tested:** Blank commands must mean "no downstream filter", while commands without a program must fail early
+    /// **Test conditions:** Empty, whitespace-only, padded, quote-only and unbalanced-quote commands
+    /// **Expectations:** Blank commands are dropped, others are trimmed, and quote-only or unbalanced commands fail with InvalidCliArgument
+    #[test]
+    fn test_resolve_downstream_commands() {
+        let resolve = |commands: &[&str]| {
+            AppConfig::from_cli_with_reader(
+                CliArgs {
+                    downstream: commands.iter().map(ToString::to_string).collect(),
+                    ..Default::default()
+                },
+                &MockGitConfigReader::new(),
+            )
+            .map(|config| config.downstream_pipeline().to_vec())
+        };
+
+        assert_eq!(resolve(&[""]), Ok(Vec::new()));
+        assert_eq!(resolve(&["   ", "\t"]), Ok(Vec::new()));
+        assert_eq!(
+            resolve(&["  less -R ", " "]),
+            Ok(vec!["less -R".to_owned()])
+        );
+        for invalid in ["\"\"", "'' ''", "\"   \" -R", "less 'unterminated"] {
+            assert!(
+                matches!(
+                    resolve(&[invalid]),
+                    Err(ConfigError::InvalidCliArgument { ref argument, .. }) if argument == "--downstream"
+                ),
+                "{invalid:?} should be rejected"
+            );
+        }
+    }
+
+    /// **What is tested:** Rendering of the resolved configuration and its sources with `to_toml`
+    /// **Why it is tested:** `--print-config` must show which layer won, and values must stay valid TOML
+    /// **Test conditions:** Mock reader setting VCS state, patterns and a downstream filter containing quotes; CLI arguments overriding the VCS state and the downstream filter; a reader setting only the deprecated downstream filter key
+    /// **Expectations:** Overridden settings are reported from `cli` and the others from `git-config` or `default`; quotes are escaped; a filter from the deprecated key is flagged
+    #[test]
+    fn test_to_toml_reports_sources() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let reader = MockGitConfigReader::new()
+            .with_config("diff-gitignore-filter.vcs-ignore.enabled", "false")
+            .with_config("diff-gitignore-filter.vcs-ignore.patterns", ".svn/,.hg/")
+            .with_config("diff-gitignore-filter.downstream-filter", "grep \"x\"");
+
+        let config = AppConfig::from_cli_with_reader(CliArgs::default(), &reader)?;
+        assert_eq!(
+            config.to_toml(),
+            "vcs_enabled = false\n\
+             vcs_patterns = [\".svn/\", \".hg/\"]\n\
+             downstream_filter = [\"grep \\\"x\\\"\"]\n\
+             \n\
+             [sources]\n\
+             vcs_enabled = \"git-config\"\n\
+             vcs_patterns = \"git-config\"\n\
+             downstream_filter = \"git-config\"\n"
+        );
+
+        let cli_args = CliArgs {
+            vcs: true,
+            downstream: vec!["less -R".to_owned()],
+            ..Default::default()
+        };
+        let config = AppConfig::from_cli_with_reader(cli_args, &reader)?;
+        assert_eq!(
+            config.sources(),
+            &ConfigSources {
+                vcs_enabled: ConfigSource::Cli,
+                vcs_patterns: ConfigSource::GitConfig,
+                downstream_filter: ConfigSource::Cli,
+                legacy_downstream_filter: false,
+            }
+        );
+        assert!(config.to_toml().starts_with("vcs_enabled = true\n"));
+
+        let legacy_reader =
+            MockGitConfigReader::new().with_config("gitignore-diff.downstream-filter", "less");
+        let config = AppConfig::from_cli_with_reader(CliArgs::default(), &legacy_reader)?;
+        assert_eq!(config.sources().downstream_filter, ConfigSource::GitConfig);
+        assert!(config.sources().legacy_downstream_filter);
+
+        let config =
+            AppConfig::from_cli_with_reader(CliArgs::default(), &MockGitConfigReader::new())?;
+        assert_eq!(config.sources(), &ConfigSources::default());
+        assert!(config.to_toml().contains("\ndownstream_filter = []\n"));
+        Ok(())
+    }
+
+    /// **What is tested:** Resolution of the `--downstream-env` CLI arguments
+    /// **Why it is tested:** Each argument must be split at the first `=`, and arguments without a name must be rejected
+    /// **Test conditions:** A plain variable, a value containing `=`, an empty value, and arguments without `=` or without a name
+    /// **Expectations:** Valid arguments resolve to name/value pairs in order, invalid ones fail with InvalidCliArgument
+    #[test]
+    fn test_resolve_downstream_env() {
+        let cli_args = |vars: &[&str]| CliArgs {
+            downstream_env: vars.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            AppConfig::resolve_downstream_env(&cli_args(&[
+                "PAGER=less",
+                "DELTA_FEATURES=+side=by",
+                "EMPTY="
+            ])),
+            Ok(vec![
+                ("PAGER".to_owned(), "less".to_owned()),
+                ("DELTA_FEATURES".to_owned(), "+side=by".to_owned()),
+                ("EMPTY".to_owned(), String::new()),
+            ])
+        );
+        for invalid in ["PAGER", "=less"] {
+            assert!(matches!(
+                AppConfig::resolve_downstream_env(&cli_args(&[invalid])),
+                Err(ConfigError::InvalidCliArgument { .. })
+            ));
+        }
+    }
+
+    /// **What is tested:** CLI overrides of the diff header prefixes
+    /// **Why it is tested:** `--src-prefix` and `--dst-prefix` must take precedence over Git config, one side at a time
+    /// **Test conditions:** No overrides, both sides overridden, and only the destination overridden with an empty prefix
+    /// **Expectations:** Unset sides should keep the configured prefix and set sides should replace it
+    #[test]
+    fn test_override_diff_prefixes() {
+        let cli_args = |src: Option<&str>, dst: Option<&str>| CliArgs {
+            src_prefix: src.map(ToOwned::to_owned),
+            dst_prefix: dst.map(ToOwned::to_owned),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            AppConfig::override_diff_prefixes(&cli_args(None, None), DiffPrefixes::default()),
+            DiffPrefixes::default()
+        );
+        assert_eq!(
+            AppConfig::override_diff_prefixes(
+                &cli_args(Some("i/"), Some("o/")),
+                DiffPrefixes::none()
+            ),
+            DiffPrefixes::new("i/", "o/")
+        );
+        assert_eq!(
+            AppConfig::override_diff_prefixes(
+                &cli_args(None, Some("")),
+                DiffPrefixes::new("src/", "dst/")
+            ),
+            DiffPrefixes::new("src/", "")
+        );
+    }
+
+    /// **What is tested:** Choice of the global ignore file from `core.excludesFile`, `$XDG_CONFIG_HOME` and `$HOME`
+    /// **Why it is tested:** The file must be found where Git looks for it
+    /// **Test conditions:** An absolute and a `~/` excludes file, no excludes file with and without `$XDG_CONFIG_HOME`, and nothing set
+    /// **Expectations:** The excludes file wins with `~/` expanded, then `$XDG_CONFIG_HOME/git/ignore`, then `~/.config/git/ignore`, else None
+    #[test]
+    fn test_global_excludes_path() {
+        let home = Some(PathBuf::from("/home/user"));
+        let xdg = Some(PathBuf::from("/xdg"));
+
+        assert_eq!(
+            AppConfig::global_excludes_path(Some("/etc/ignore".to_owned()), xdg.clone(), None),
+            Some(PathBuf::from("/etc/ignore"))
+        );
+        assert_eq!(
+            AppConfig::global_excludes_path(
+                Some("~/.gitignore_global".to_owned()),
+                xdg.clone(),
+                home.clone()
+            ),
+            Some(PathBuf::from("/home/user/.gitignore_global"))
+        );
+        assert_eq!(
+            AppConfig::global_excludes_path(None, xdg, home.clone()),
+            Some(PathBuf::from("/xdg/git/ignore"))
+        );
+        assert_eq!(
+            AppConfig::global_excludes_path(None, None, home),
+            Some(PathBuf::from("/home/user/.config/git/ignore"))
+        );
+        assert_eq!(AppConfig::global_excludes_path(None, None, None), None);
+    }
+
+    /// **What is tested:** Resolution of the `--strip-path-prefix` CLI argument
+    /// **Why it is tested:** The prefix is compared against path components, so surrounding slashes must not matter
+    /// **Test conditions:** Prefixes with and without slashes, an unset prefix, and a prefix consisting only of slashes
+    /// **Expectations:** Slashes should be trimmed, unset should stay None, and a slash-only prefix should be rejected
+    #[test]
+    fn test_resolve_strip_path_prefix() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cli_args = |prefix: Option<&str>| CliArgs {
+            strip_path_prefix: prefix.map(ToOwned::to_owned),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            AppConfig::resolve_strip_path_prefix(&cli_args(Some("/vendor/lib/")))?,
+            Some("vendor/lib".to_owned())
+        );
+        assert_eq!(
+            AppConfig::resolve_strip_path_prefix(&cli_args(Some("submodule")))?,
+            Some("submodule".to_owned())
+        );
+        assert_eq!(AppConfig::resolve_strip_path_prefix(&cli_args(None))?, None);
+        assert!(matches!(
+            AppConfig::resolve_strip_path_prefix(&cli_args(Some("//"))),
+            Err(ConfigError::InvalidCliArgument { .. })
+        ));
+        Ok(())
+    }
+
+    /// **What is tested:** `--vcs-pattern-add` appending to the effective VCS patterns
+    /// **Why it is tested:** Adding a pattern such as `.pijul/` must not drop the defaults or configured patterns, unlike `--vcs-pattern`
+    /// **Test conditions:** Additions on top of the defaults, of git config patterns, of `--vcs-pattern`, plus a duplicate and an empty list
+    /// **Expectations:** Additions are appended once after the effective patterns, and an empty addition is rejected for `--vcs-pattern-add`
+    #[test]
+    fn test_resolve_vcs_pattern_add() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cli_args = |pattern: Option<&str>, add: &str| CliArgs {
+            vcs_pattern: pattern.map(ToOwned::to_owned),
+            vcs_pattern_add: Some(add.to_owned()),
+            ..Default::default()
+        };
+        let no_git_config = MockGitConfigReader::new();
+        let keys = ConfigKeys::default();
+
+        let (patterns, source) = AppConfig::resolve_vcs_patterns(
+            &cli_args(None, ".pijul/, .git/"),
+            &no_git_config,
+            &keys,
+        )?;
+        let mut expected = ConfigBuilder::default_vcs_patterns();
+        expected.push(".pijul/".to_owned());
+        assert_eq!(patterns, expected);
+        assert_eq!(source, ConfigSource::Default);
+
+        let git_config = MockGitConfigReader::new()
+            .with_config("diff-gitignore-filter.vcs-ignore.patterns", ".svn/");
+        assert_eq!(
+            AppConfig::resolve_vcs_patterns(&cli_args(None, ".jj/"), &git_config, &keys)?,
+            (
+                vec![".svn/".to_owned(), ".jj/".to_owned()],
+                ConfigSource::GitConfig
+            )
+        );
+        assert_eq!(
+            AppConfig::resolve_vcs_patterns(&cli_args(Some(".hg/"), ".jj/"), &git_config, &keys)?,
+            (
+                vec![".hg/".to_owned(), ".jj/".to_owned()],
+                ConfigSource::Cli
+            )
+        );
+        assert!(matches!(
+            AppConfig::resolve_vcs_patterns(&cli_args(None, " , "), &no_git_config, &keys),
+            Err(ConfigError::InvalidCliArgument { argument, .. }) if argument == "--vcs-pattern-add"
+        ));
+        Ok(())
+    }
+
+    /// **What is tested:** Resolution of the `--path-prefix` CLI argument
+    /// **Why it is tested:** The prefix is joined to diff paths with a single slash, and errors must name the right option
+    /// **Test conditions:** A prefix with a trailing slash, an unset prefix, and an empty prefix
+    /// **Expectations:** Slashes are trimmed, unset stays None, and the empty prefix is rejected for `--path-prefix`
+    #[test]
+    fn test_resolve_path_prefix() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cli_args = |prefix: Option<&str>| CliArgs {
+            path_prefix: prefix.map(ToOwned::to_owned),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            AppConfig::resolve_path_prefix(&cli_args(Some("subdir/")))?,
+            Some("subdir".to_owned())
+        );
+        assert_eq!(AppConfig::resolve_path_prefix(&cli_args(None))?, None);
+        assert!(matches!(
+            AppConfig::resolve_path_prefix(&cli_args(Some(""))),
+            Err(ConfigError::InvalidCliArgument { argument, .. }) if argument == "--path-prefix"
+        ));
+        Ok(())
+    }
+
+    /// **What is tested:** Parsing of the `--only-ext` and `--exclude-ext` extension lists
+    /// **Why it is tested:** Extensions are compared without their leading dot, and unusable lists must name the option
+    /// **Test conditions:** An unset list, a list with dots, spaces and an empty entry, a list of only commas, and an entry with a slash
+    /// **Expectations:** Unset yields no extensions, dots and blanks are removed, and the last two lists are rejected for their option
+    #[test]
+    fn test_parse_extensions() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        assert!(AppConfig::parse_extensions("--only-ext", None)?.is_empty());
+        assert_eq!(
+            AppConfig::parse_extensions("--only-ext", Some(".rs, toml,,"))?,
+            vec!["rs", "toml"]
+        );
+        assert!(matches!(
+            AppConfig::parse_extensions("--exclude-ext", Some(",,")),
+            Err(ConfigError::InvalidCliArgument { argument, .. }) if argument == "--exclude-ext"
+        ));
+        assert!(matches!(
+            AppConfig::parse_extensions("--only-ext", Some("rs,src/main.rs")),
+            Err(ConfigError::InvalidCliArgument { argument, .. }) if argument == "--only-ext"
+        ));
+        Ok(())
+    }
 
     /// **What is tested:** Parsing of valid CLI VCS patterns from comma-separated string
     /// **Why it is tested:** Ensures that valid VCS pattern strings are correctly parsed into individual patterns
@@ -199,7 +1779,7 @@ mod tests {
     /// **Expectations:** Should return vector with correctly parsed individual patterns
     #[test]
     fn test_parse_cli_vcs_patterns_valid() -> std::result::Result<(), Box<dyn std::error::Error>> {
-        let patterns = AppConfig::parse_cli_vcs_patterns(".git/,.svn/,.hg/")?;
+        let patterns = AppConfig::parse_cli_vcs_patterns(".git/,.svn/,.hg/", false)?;
         assert_eq!(patterns, vec![".git/", ".svn/", ".hg/"]);
         Ok(())
     }
@@ -211,7 +1791,7 @@ mod tests {
     #[test]
     fn test_parse_cli_vcs_patterns_with_whitespace(
     ) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        let patterns = AppConfig::parse_cli_vcs_patterns(" .git/ , .svn/ , .hg/ ")?;
+        let patterns = AppConfig::parse_cli_vcs_patterns(" .git/ , .svn/ , .hg/ ", false)?;
         assert_eq!(patterns, vec![".git/", ".svn/", ".hg/"]);
         Ok(())
     }
@@ -222,7 +1802,7 @@ mod tests {
     /// **Expectations:** Should return InvalidCliArgument error for empty input
     #[test]
     fn test_parse_cli_vcs_patterns_empty_invalid() {
-        let result = AppConfig::parse_cli_vcs_patterns("");
+        let result = AppConfig::parse_cli_vcs_patterns("", false);
         assert!(matches!(
             result,
             Err(ConfigError::InvalidCliArgument { .. })
@@ -235,13 +1815,86 @@ mod tests {
     /// **Expectations:** Should return InvalidCliArgument error for comma-only input
     #[test]
     fn test_parse_cli_vcs_patterns_only_commas_invalid() {
-        let result = AppConfig::parse_cli_vcs_patterns(",,,");
+        let result = AppConfig::parse_cli_vcs_patterns(",,,", false);
         assert!(matches!(
             result,
             Err(ConfigError::InvalidCliArgument { .. })
         ));
     }
 
+    /// **What is tested:** Rejection of VCS patterns that match everything or contain `..`
+    /// **Why it is tested:** Such patterns would silently filter every file or can never match a VCS directory
+    /// **Test conditions:** Bare `*`, `**/`, `/*`, `!*`, patterns with a `..` component given to `--vcs-pattern` and `--vcs-pattern-add`; valid `.git/`, `.custom/` and `*.orig`
+    /// **Expectations:** Each dangerous pattern yields InvalidCliArgument naming the option and the pattern; valid patterns pass
+    #[test]
+    fn test_dangerous_vcs_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        for pattern in ["*", "**/", "/*", "!*", "../.git/", ".git/../", "a/../b"] {
+            let patterns_str = format!(".git/,{pattern}");
+            assert!(
+                matches!(
+                    AppConfig::parse_cli_vcs_patterns(&patterns_str, false),
+                    Err(ConfigError::InvalidCliArgument { argument, value, .. })
+                        if argument == "--vcs-pattern" && value == pattern
+                ),
+                "{pattern} should be rejected"
+            );
+        }
+
+        let cli_args = CliArgs {
+            vcs_pattern_add: Some(".custom/,**".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            AppConfig::from_cli(cli_args),
+            Err(ConfigError::InvalidCliArgument { argument, value, .. })
+                if argument == "--vcs-pattern-add" && value == "**"
+        ));
+
+        assert_eq!(
+            AppConfig::parse_cli_vcs_patterns(".git/, .custom/, *.orig, a..b/", false)?,
+            vec![".git/", ".custom/", "*.orig", "a..b/"]
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** VCS pattern lists that are empty after splitting and trimming, with and without `--allow-empty-vcs`
+    /// **Why it is tested:** Whitespace-only entries must be treated like empty ones, and the toggle decides between error and no-op
+    /// **Test conditions:** Whitespace and separator-only pattern strings parsed directly and resolved through `AppConfig::from_cli`
+    /// **Expectations:** Without the toggle an InvalidCliArgument error is returned; with it VCS stays enabled with no patterns
+    #[test]
+    fn test_empty_after_trim_vcs_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        for patterns_str in ["   ", " , \t, ", ",\n,"] {
+            assert!(matches!(
+                AppConfig::parse_cli_vcs_patterns(patterns_str, false),
+                Err(ConfigError::InvalidCliArgument { .. })
+            ));
+            assert!(AppConfig::parse_cli_vcs_patterns(patterns_str, true)?.is_empty());
+        }
+
+        let cli_args = |allow_empty_vcs| CliArgs {
+            vcs: true,
+            vcs_pattern: Some(" , , ".to_owned()),
+            allow_empty_vcs,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            AppConfig::from_cli(cli_args(false)),
+            Err(ConfigError::InvalidCliArgument { .. })
+        ));
+
+        let config = AppConfig::from_cli(cli_args(true))?;
+        assert!(config.vcs_enabled());
+        assert!(config.vcs_patterns().is_empty());
+
+        // Non-empty lists are unaffected by the toggle
+        assert_eq!(
+            AppConfig::parse_cli_vcs_patterns(" .git/ ,, ", true)?,
+            vec![".git/"]
+        );
+        Ok(())
+    }
+
     /// **What is tested:** Basic AppConfig creation from CLI arguments with VCS enabled
     /// **Why it is tested:** Validates the main configuration creation workflow with standard settings
     /// **Test conditions:** Creates CliArgs with VCS enabled and no custom patterns or downstream
@@ -251,8 +1904,9 @@ mod tests {
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // This test might fail if not in a git repo, but should not panic
@@ -278,8 +1932,9 @@ mod tests {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: Some(".custom/,.test/".to_owned()),
+            ..Default::default()
         };
 
         // This test might fail if not in a git repo, but should not panic
@@ -304,8 +1959,9 @@ mod tests {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // This test might fail if not in a git repo, but should not panic
@@ -330,8 +1986,9 @@ mod tests {
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: Some("less".to_owned()),
+            downstream: vec!["less".to_owned()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // This test might fail if not in a git repo, but should not panic
@@ -339,11 +1996,58 @@ mod tests {
 
         match result {
             Ok(config) => {
-                assert_eq!(config.downstream_filter(), Some("less"));
+                assert_eq!(config.downstream_pipeline(), ["less"]);
             }
             Err(_) => {
                 // Acceptable in test environment without proper git setup
             }
         }
     }
+
+    /// **What is tested:** Validation of resolved configuration values
+    /// **Why it is tested:** Ensures misconfiguration is detected up front by `--dry-run-config`
+    /// **Test conditions:** Configs built with valid patterns, an invalid glob pattern, and downstream commands that do or do not exist
+    /// **Expectations:** Valid configs pass; invalid patterns and missing executables yield ValidationFailed naming the value
+    #[test]
+    fn test_validate() {
+        let valid = ConfigBuilder::new()
+            .with_vcs_patterns(vec![".git/".to_owned(), ".custom/".to_owned()])
+            .with_downstream_pipeline(vec!["cat -n".to_owned(), "wc -l".to_owned()])
+            .build();
+        assert_eq!(valid.validate(), Ok(()));
+
+        let invalid_pattern = ConfigBuilder::new()
+            .with_vcs_patterns(vec![".git/".to_owned(), "[.svn/".to_owned()])
+            .build();
+        assert!(matches!(
+            invalid_pattern.validate(),
+            Err(ConfigError::ValidationFailed { ref value, .. }) if value == "[.svn/"
+        ));
+
+        let missing_downstream = ConfigBuilder::new()
+            .with_downstream_pipeline(vec![
+                "cat".to_owned(),
+                "definitely-not-a-real-command --flag".to_owned(),
+            ])
+            .build();
+        assert!(matches!(
+            missing_downstream.validate(),
+            Err(ConfigError::ValidationFailed { ref value, .. })
+                if value == "definitely-not-a-real-command --flag"
+        ));
+
+        // Unbalanced quoting only matters when the command is split into argv directly
+        let unbalanced_quote = |shell| {
+            ConfigBuilder::new()
+                .with_downstream_pipeline(vec!["cat 'unterminated".to_owned()])
+                .with_downstream_shell(shell)
+                .build()
+                .validate()
+        };
+        assert!(matches!(
+            unbalanced_quote(false),
+            Err(ConfigError::ValidationFailed { .. })
+        ));
+        assert_eq!(unbalanced_quote(true), Ok(()));
+    }
 }
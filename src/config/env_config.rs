@@ -0,0 +1,230 @@
+//! Environment configuration module
+//!
+//! This module reads configuration overrides from environment variables, for setups
+//! such as containerized CI where editing the Git configuration is awkward. Environment
+//! values take priority over Git configuration values but not over CLI parameters.
+//! Variables that are unset or empty are ignored.
+
+use super::ConfigError;
+use std::env;
+
+/// Environment variable enabling (`1`) or disabling (`0`) VCS filtering
+pub const ENV_VCS: &str = "DIFF_GITIGNORE_FILTER_VCS";
+/// Environment variable with comma-separated VCS patterns
+pub const ENV_VCS_PATTERNS: &str = "DIFF_GITIGNORE_FILTER_VCS_PATTERNS";
+/// Environment variable with the downstream filter command
+pub const ENV_DOWNSTREAM: &str = "DIFF_GITIGNORE_FILTER_DOWNSTREAM";
+/// Environment variable with the namespace of the filter's Git config keys
+pub const ENV_CONFIG_KEY_PREFIX: &str = "DIFF_GITIGNORE_FILTER_CONFIG_KEY_PREFIX";
+
+/// Environment configuration operations
+pub struct EnvConfig;
+
+impl EnvConfig {
+    /// Get VCS filtering enabled state from the environment
+    pub fn get_vcs_enabled() -> Result<Option<bool>, ConfigError> {
+        Self::get_vcs_enabled_with_lookup(Self::system_lookup)
+    }
+
+    /// Get VCS filtering enabled state with custom variable lookup (for testing)
+    pub fn get_vcs_enabled_with_lookup<F>(lookup: F) -> Result<Option<bool>, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::get_value(lookup, ENV_VCS)
+            .map(|value| Self::parse_boolean_value(&value, ENV_VCS))
+            .transpose()
+    }
+
+    /// Get VCS patterns from the environment
+    pub fn get_vcs_patterns() -> Result<Option<Vec<String>>, ConfigError> {
+        Self::get_vcs_patterns_with_lookup(Self::system_lookup)
+    }
+
+    /// Get VCS patterns with custom variable lookup (for testing)
+    pub fn get_vcs_patterns_with_lookup<F>(lookup: F) -> Result<Option<Vec<String>>, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::get_value(lookup, ENV_VCS_PATTERNS)
+            .map(|value| Self::parse_comma_separated_patterns(&value, ENV_VCS_PATTERNS))
+            .transpose()
+    }
+
+    /// Get downstream filter command from the environment
+    pub fn get_downstream_filter() -> Option<String> {
+        Self::get_downstream_filter_with_lookup(Self::system_lookup)
+    }
+
+    /// Get downstream filter command with custom variable lookup (for testing)
+    pub fn get_downstream_filter_with_lookup<F>(lookup: F) -> Option<String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::get_value(lookup, ENV_DOWNSTREAM)
+    }
+
+    /// Get the Git config key prefix from the environment
+    pub fn get_config_key_prefix() -> Option<String> {
+        Self::get_config_key_prefix_with_lookup(Self::system_lookup)
+    }
+
+    /// Get the Git config key prefix with custom variable lookup (for testing)
+    pub fn get_config_key_prefix_with_lookup<F>(lookup: F) -> Option<String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::get_value(lookup, ENV_CONFIG_KEY_PREFIX)
+    }
+
+    /// Read a variable from the process environment
+    fn system_lookup(variable: &str) -> Option<String> {
+        env::var(variable).ok()
+    }
+
+    /// Look up a variable, treating empty or whitespace-only values as unset
+    fn get_value<F>(lookup: F, variable: &str) -> Option<String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        lookup(variable)
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Parse boolean value from an environment variable
+    fn parse_boolean_value(value: &str, variable: &str) -> Result<bool, ConfigError> {
+        match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => Err(ConfigError::InvalidEnvValue {
+                variable: variable.to_owned(),
+                value: value.to_owned(),
+                expected: "1, 0, true, false, yes, no, on, or off".to_owned(),
+            }),
+        }
+    }
+
+    /// Parse comma-separated patterns from an environment variable
+    fn parse_comma_separated_patterns(
+        value: &str,
+        variable: &str,
+    ) -> Result<Vec<String>, ConfigError> {
+        let patterns: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+
+        (!patterns.is_empty())
+            .then_some(patterns)
+            .ok_or_else(|| ConfigError::InvalidEnvValue {
+                variable: variable.to_owned(),
+                value: value.to_owned(),
+                expected: "comma-separated list of non-empty patterns".to_owned(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+            .collect();
+        move |variable| vars.get(variable).cloned()
+    }
+
+    /// **What is tested:** Parsing of `DIFF_GITIGNORE_FILTER_VCS`
+    /// **Why it is tested:** CI setups pass `0`/`1`, and malformed values must be reported rather than guessed
+    /// **Test conditions:** Variable set to `1`, `0`, `Off`, an empty value, unset, and `maybe`
+    /// **Expectations:** Valid values parse to booleans, empty and unset give None, and `maybe` fails with InvalidEnvValue
+    #[test]
+    fn test_get_vcs_enabled() {
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[(ENV_VCS, "1")])),
+            Ok(Some(true))
+        );
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[(ENV_VCS, "0")])),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[(ENV_VCS, "Off")])),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[(ENV_VCS, " ")])),
+            Ok(None)
+        );
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[])),
+            Ok(None)
+        );
+        assert_eq!(
+            EnvConfig::get_vcs_enabled_with_lookup(lookup(&[(ENV_VCS, "maybe")])),
+            Err(ConfigError::InvalidEnvValue {
+                variable: ENV_VCS.to_owned(),
+                value: "maybe".to_owned(),
+                expected: "1, 0, true, false, yes, no, on, or off".to_owned(),
+            })
+        );
+    }
+
+    /// **What is tested:** Parsing of `DIFF_GITIGNORE_FILTER_VCS_PATTERNS` and `DIFF_GITIGNORE_FILTER_DOWNSTREAM`
+    /// **Why it is tested:** Patterns use the same comma-separated format as the Git configuration
+    /// **Test conditions:** Patterns with whitespace, a comma-only value, and a downstream command
+    /// **Expectations:** Patterns are trimmed, the comma-only value is rejected, and the command is returned as is
+    #[test]
+    fn test_get_vcs_patterns_and_downstream() {
+        let vars = lookup(&[
+            (ENV_VCS_PATTERNS, " .git/ , .hg/ "),
+            (ENV_DOWNSTREAM, "delta --side-by-side"),
+        ]);
+
+        assert_eq!(
+            EnvConfig::get_vcs_patterns_with_lookup(&vars),
+            Ok(Some(vec![".git/".to_owned(), ".hg/".to_owned()]))
+        );
+        assert_eq!(
+            EnvConfig::get_downstream_filter_with_lookup(&vars),
+            Some("delta --side-by-side".to_owned())
+        );
+        assert!(matches!(
+            EnvConfig::get_vcs_patterns_with_lookup(lookup(&[(ENV_VCS_PATTERNS, ",,")])),
+            Err(ConfigError::InvalidEnvValue { .. })
+        ));
+        assert_eq!(
+            EnvConfig::get_downstream_filter_with_lookup(lookup(&[])),
+            None
+        );
+    }
+
+    /// **What is tested:** Reading of `DIFF_GITIGNORE_FILTER_CONFIG_KEY_PREFIX`
+    /// **Why it is tested:** The prefix selects which Git config keys are read at all
+    /// **Test conditions:** Variable set with surrounding whitespace, set to an empty value, and unset
+    /// **Expectations:** The trimmed prefix is returned; empty and unset give None
+    #[test]
+    fn test_get_config_key_prefix() {
+        assert_eq!(
+            EnvConfig::get_config_key_prefix_with_lookup(lookup(&[(
+                ENV_CONFIG_KEY_PREFIX,
+                " acme-diff "
+            )])),
+            Some("acme-diff".to_owned())
+        );
+        assert_eq!(
+            EnvConfig::get_config_key_prefix_with_lookup(lookup(&[(ENV_CONFIG_KEY_PREFIX, "")])),
+            None
+        );
+        assert_eq!(
+            EnvConfig::get_config_key_prefix_with_lookup(lookup(&[])),
+            None
+        );
+    }
+}
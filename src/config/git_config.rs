@@ -4,6 +4,7 @@
 //! and error handling for diff-gitignore-filter settings.
 
 use super::git_reader::{GitConfigReader, GitError, SystemGitConfigReader};
+use crate::diff::DiffPrefixes;
 use std::fmt;
 use std::path::PathBuf;
 
@@ -32,6 +33,18 @@ pub enum ConfigError {
         value: String,
         expected: String,
     },
+    /// A resolved setting failed validation
+    ValidationFailed {
+        setting: String,
+        value: String,
+        reason: String,
+    },
+    /// Invalid environment variable value
+    InvalidEnvValue {
+        variable: String,
+        value: String,
+        expected: String,
+    },
 }
 
 impl fmt::Display for ConfigError {
@@ -67,6 +80,19 @@ impl fmt::Display for ConfigError {
                 f,
                 "Invalid CLI argument: {argument}='{value}' (expected: {expected})"
             ),
+            ConfigError::ValidationFailed {
+                setting,
+                value,
+                reason,
+            } => write!(f, "Invalid {setting} '{value}': {reason}"),
+            ConfigError::InvalidEnvValue {
+                variable,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid environment variable value: {variable}='{value}' (expected: {expected})"
+            ),
         }
     }
 }
@@ -93,6 +119,72 @@ impl From<GitError> for ConfigError {
     }
 }
 
+/// Git configuration keys of the filter's own settings
+///
+/// The defaults live under `diff-gitignore-filter.`; the deprecated
+/// `gitignore-diff.downstream-filter` is still read when `diff-gitignore-filter.downstream-filter`
+/// is not set. [`ConfigKeys::with_prefix`] moves all keys under one custom namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigKeys {
+    vcs_ignore_enabled: String,
+    vcs_ignore_patterns: String,
+    downstream_filter: String,
+    legacy_downstream_filter: Option<String>,
+}
+
+impl Default for ConfigKeys {
+    fn default() -> Self {
+        Self {
+            vcs_ignore_enabled: "diff-gitignore-filter.vcs-ignore.enabled".to_owned(),
+            vcs_ignore_patterns: "diff-gitignore-filter.vcs-ignore.patterns".to_owned(),
+            downstream_filter: "diff-gitignore-filter.downstream-filter".to_owned(),
+            legacy_downstream_filter: Some("gitignore-diff.downstream-filter".to_owned()),
+        }
+    }
+}
+
+impl ConfigKeys {
+    /// Keys under `<prefix>.`, e.g. `<prefix>.vcs-ignore.enabled` and `<prefix>.downstream-filter`
+    ///
+    /// Returns `None` when the prefix is empty or contains characters other than
+    /// ASCII alphanumerics, `-` and `.`. A trailing `.` is ignored.
+    pub fn with_prefix(prefix: &str) -> Option<Self> {
+        let prefix = prefix.trim_end_matches('.');
+        let valid = !prefix.is_empty()
+            && !prefix.starts_with('.')
+            && prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+
+        valid.then(|| Self {
+            vcs_ignore_enabled: format!("{prefix}.vcs-ignore.enabled"),
+            vcs_ignore_patterns: format!("{prefix}.vcs-ignore.patterns"),
+            downstream_filter: format!("{prefix}.downstream-filter"),
+            legacy_downstream_filter: None,
+        })
+    }
+
+    /// Key enabling or disabling VCS filtering
+    pub fn vcs_ignore_enabled(&self) -> &str {
+        &self.vcs_ignore_enabled
+    }
+
+    /// Key holding the VCS patterns
+    pub fn vcs_ignore_patterns(&self) -> &str {
+        &self.vcs_ignore_patterns
+    }
+
+    /// Key holding the downstream filter command
+    pub fn downstream_filter(&self) -> &str {
+        &self.downstream_filter
+    }
+
+    /// Deprecated key read when [`ConfigKeys::downstream_filter`] is not set
+    pub fn legacy_downstream_filter(&self) -> Option<&str> {
+        self.legacy_downstream_filter.as_deref()
+    }
+}
+
 /// Git configuration operations
 pub struct GitConfig;
 
@@ -107,7 +199,15 @@ impl GitConfig {
     pub fn get_vcs_ignore_enabled_with_reader<R: GitConfigReader>(
         reader: &R,
     ) -> Result<Option<bool>, ConfigError> {
-        let key = "diff-gitignore-filter.vcs-ignore.enabled";
+        Self::get_vcs_ignore_enabled_with_keys(reader, &ConfigKeys::default())
+    }
+
+    /// Get VCS ignore enabled setting from the key named by `keys`
+    pub fn get_vcs_ignore_enabled_with_keys<R: GitConfigReader>(
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<Option<bool>, ConfigError> {
+        let key = keys.vcs_ignore_enabled();
 
         reader
             .get_config(key)?
@@ -143,14 +243,25 @@ impl GitConfig {
     }
 
     /// Get VCS ignore patterns with custom reader (for testing)
+    ///
+    /// Each value of the key is a comma-separated list; values added with
+    /// `git config --add` are merged in configuration order.
     pub fn get_vcs_ignore_patterns_with_reader<R: GitConfigReader>(
         reader: &R,
     ) -> Result<Option<Vec<String>>, ConfigError> {
-        let key = "diff-gitignore-filter.vcs-ignore.patterns";
+        Self::get_vcs_ignore_patterns_with_keys(reader, &ConfigKeys::default())
+    }
 
-        reader
-            .get_config(key)?
-            .map(|value| Self::parse_comma_separated_patterns(&value, key))
+    /// Get VCS ignore patterns from the key named by `keys`
+    pub fn get_vcs_ignore_patterns_with_keys<R: GitConfigReader>(
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<Option<Vec<String>>, ConfigError> {
+        let key = keys.vcs_ignore_patterns();
+        let values = reader.get_all(key)?;
+
+        (!values.is_empty())
+            .then(|| Self::parse_comma_separated_patterns(&values.join(","), key))
             .transpose()
     }
 
@@ -179,17 +290,136 @@ impl GitConfig {
     }
 
     /// Get downstream filter command with custom reader (for testing)
+    ///
+    /// Reads `diff-gitignore-filter.downstream-filter` and falls back to the deprecated
+    /// `gitignore-diff.downstream-filter`; use
+    /// [`GitConfig::get_downstream_filter_with_legacy_flag`] to tell whether the fallback
+    /// was used.
     pub fn get_downstream_filter_with_reader<R: GitConfigReader>(
         reader: &R,
     ) -> Result<Option<String>, ConfigError> {
-        let key = "gitignore-diff.downstream-filter";
+        Self::get_downstream_filter_with_keys(reader, &ConfigKeys::default())
+    }
 
+    /// Get downstream filter command from the key named by `keys`
+    pub fn get_downstream_filter_with_keys<R: GitConfigReader>(
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<Option<String>, ConfigError> {
+        Self::get_downstream_filter_with_legacy_flag(reader, keys)
+            .map(|filter| filter.map(|(filter, _)| filter))
+    }
+
+    /// Get downstream filter command from the key named by `keys`, together with
+    /// whether it was read from [`ConfigKeys::legacy_downstream_filter`]
+    ///
+    /// Nothing is printed here, so callers decide how to tell users to migrate.
+    pub fn get_downstream_filter_with_legacy_flag<R: GitConfigReader>(
+        reader: &R,
+        keys: &ConfigKeys,
+    ) -> Result<Option<(String, bool)>, ConfigError> {
+        if let Some(filter) = Self::read_downstream_filter(reader, keys.downstream_filter())? {
+            return Ok(Some((filter, false)));
+        }
+
+        let Some(legacy_key) = keys.legacy_downstream_filter() else {
+            return Ok(None);
+        };
+        Self::read_downstream_filter(reader, legacy_key)
+            .map(|filter| filter.map(|filter| (filter, true)))
+    }
+
+    /// Read a downstream filter command from `key`, treating blank values as unset
+    fn read_downstream_filter<R: GitConfigReader>(
+        reader: &R,
+        key: &str,
+    ) -> Result<Option<String>, ConfigError> {
         reader
             .get_config(key)
             .map(|opt| opt.and_then(Self::parse_downstream_filter))
             .map_err(ConfigError::from)
     }
 
+    /// Get Git's `core.ignorecase` setting
+    pub fn get_ignore_case() -> Result<Option<bool>, ConfigError> {
+        let reader = SystemGitConfigReader;
+        Self::get_ignore_case_with_reader(&reader)
+    }
+
+    /// Get Git's `core.ignorecase` setting with custom reader (for testing)
+    pub fn get_ignore_case_with_reader<R: GitConfigReader>(
+        reader: &R,
+    ) -> Result<Option<bool>, ConfigError> {
+        let key = "core.ignorecase";
+
+        reader
+            .get_config(key)?
+            .map(|value| Self::parse_boolean_value(&value, key))
+            .transpose()
+    }
+
+    /// Get Git's `core.excludesFile` setting, the global ignore file
+    pub fn get_excludes_file() -> Result<Option<String>, ConfigError> {
+        let reader = SystemGitConfigReader;
+        Self::get_excludes_file_with_reader(&reader)
+    }
+
+    /// Get Git's `core.excludesFile` setting with custom reader (for testing)
+    ///
+    /// Blank values are treated as unset.
+    pub fn get_excludes_file_with_reader<R: GitConfigReader>(
+        reader: &R,
+    ) -> Result<Option<String>, ConfigError> {
+        Ok(reader
+            .get_config("core.excludesFile")?
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty()))
+    }
+
+    /// Get the diff path prefixes from Git's `diff.noprefix`, `diff.mnemonicPrefix`,
+    /// `diff.srcPrefix` and `diff.dstPrefix`
+    ///
+    /// Returns `None` when none of them is set. Like Git, `diff.noprefix` wins over
+    /// `diff.mnemonicPrefix`, which in turn makes Git ignore `diff.srcPrefix` and
+    /// `diff.dstPrefix`; its prefixes depend on what is compared, so `None` is returned
+    /// and the header parser recognizes them without configuration.
+    pub fn get_diff_prefixes() -> Result<Option<DiffPrefixes>, ConfigError> {
+        let reader = SystemGitConfigReader;
+        Self::get_diff_prefixes_with_reader(&reader)
+    }
+
+    /// Get the diff path prefixes with custom reader (for testing)
+    pub fn get_diff_prefixes_with_reader<R: GitConfigReader>(
+        reader: &R,
+    ) -> Result<Option<DiffPrefixes>, ConfigError> {
+        let key = "diff.noprefix";
+        let no_prefix = reader
+            .get_config(key)?
+            .map(|value| Self::parse_boolean_value(&value, key))
+            .transpose()?;
+
+        if no_prefix == Some(true) {
+            return Ok(Some(DiffPrefixes::none()));
+        }
+
+        let key = "diff.mnemonicPrefix";
+        let mnemonic_prefix = reader
+            .get_config(key)?
+            .map(|value| Self::parse_boolean_value(&value, key))
+            .transpose()?;
+
+        if mnemonic_prefix == Some(true) {
+            return Ok(None);
+        }
+
+        let src = reader.get_config("diff.srcPrefix")?;
+        let dst = reader.get_config("diff.dstPrefix")?;
+        let defaults = DiffPrefixes::default();
+
+        Ok((src.is_some() || dst.is_some())
+            .then(|| DiffPrefixes::new(src.unwrap_or(defaults.src), dst.unwrap_or(defaults.dst))))
+    }
+
     /// Parse downstream filter value using functional approach
     fn parse_downstream_filter(value: String) -> Option<String> {
         let trimmed = value.trim();
@@ -281,6 +511,116 @@ mod tests {
         assert_eq!(result, Ok(None));
     }
 
+    /// **What is tested:** Reading Git's `core.ignorecase` setting
+    /// **Why it is tested:** Case-insensitive .gitignore matching follows this setting
+    /// **Test conditions:** Mock git config with `core.ignorecase` set to true, false, an invalid value, and unset
+    /// **Expectations:** Should return the parsed boolean, InvalidGitConfig for invalid values and None when unset
+    #[test]
+    fn test_get_ignore_case() {
+        let reader = |value| MockGitConfigReader::new().with_config("core.ignorecase", value);
+
+        assert_eq!(
+            GitConfig::get_ignore_case_with_reader(&reader("true")),
+            Ok(Some(true))
+        );
+        assert_eq!(
+            GitConfig::get_ignore_case_with_reader(&reader("false")),
+            Ok(Some(false))
+        );
+        assert!(matches!(
+            GitConfig::get_ignore_case_with_reader(&reader("sometimes")),
+            Err(ConfigError::InvalidGitConfig { .. })
+        ));
+        assert_eq!(
+            GitConfig::get_ignore_case_with_reader(&MockGitConfigReader::new()),
+            Ok(None)
+        );
+    }
+
+    /// **What is tested:** Reading Git's `core.excludesFile` setting
+    /// **Why it is tested:** The global ignore file named there replaces Git's default location
+    /// **Test conditions:** Mock git config with a path, a blank value, and unset
+    /// **Expectations:** Should return the trimmed path, and None for the blank and unset values
+    #[test]
+    fn test_get_excludes_file() {
+        let reader = |value| MockGitConfigReader::new().with_config("core.excludesFile", value);
+
+        assert_eq!(
+            GitConfig::get_excludes_file_with_reader(&reader(" ~/.gitignore_global ")),
+            Ok(Some("~/.gitignore_global".to_owned()))
+        );
+        assert_eq!(
+            GitConfig::get_excludes_file_with_reader(&reader("  ")),
+            Ok(None)
+        );
+        assert_eq!(
+            GitConfig::get_excludes_file_with_reader(&MockGitConfigReader::new()),
+            Ok(None)
+        );
+    }
+
+    /// **What is tested:** Reading the diff path prefixes from `diff.noprefix`, `diff.mnemonicPrefix`, `diff.srcPrefix` and `diff.dstPrefix`
+    /// **Why it is tested:** Headers of diffs generated with custom prefixes can only be split reliably with the right prefixes
+    /// **Test conditions:** Mock git config with no prefix, custom prefixes, one custom side, mnemonic prefixes combined with other keys, and nothing set
+    /// **Expectations:** Should return empty prefixes, the configured prefixes with defaults for unset sides, and None for mnemonic prefixes or when unset
+    #[test]
+    fn test_get_diff_prefixes() {
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.noprefix", "true")
+            .with_config("diff.srcPrefix", "i/");
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&reader),
+            Ok(Some(DiffPrefixes::none()))
+        );
+
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.noprefix", "false")
+            .with_config("diff.srcPrefix", "i/")
+            .with_config("diff.dstPrefix", "o/");
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&reader),
+            Ok(Some(DiffPrefixes::new("i/", "o/")))
+        );
+
+        let reader = MockGitConfigReader::new().with_config("diff.dstPrefix", "new/");
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&reader),
+            Ok(Some(DiffPrefixes::new("a/", "new/")))
+        );
+
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.mnemonicPrefix", "true")
+            .with_config("diff.srcPrefix", "old/");
+        assert_eq!(GitConfig::get_diff_prefixes_with_reader(&reader), Ok(None));
+
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.mnemonicPrefix", "true")
+            .with_config("diff.noprefix", "true");
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&reader),
+            Ok(Some(DiffPrefixes::none()))
+        );
+
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.mnemonicPrefix", "false")
+            .with_config("diff.srcPrefix", "old/");
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&reader),
+            Ok(Some(DiffPrefixes::new("old/", "b/")))
+        );
+
+        assert_eq!(
+            GitConfig::get_diff_prefixes_with_reader(&MockGitConfigReader::new()),
+            Ok(None)
+        );
+        assert!(matches!(
+            GitConfig::get_diff_prefixes_with_reader(
+                &MockGitConfigReader::new().with_config("diff.noprefix", "maybe")
+            ),
+            Err(ConfigError::InvalidGitConfig { .. })
+        ));
+    }
+
     /// **What is tested:** VCS ignore patterns parsing for valid comma-separated values
     /// **Why it is tested:** Validates that comma-separated VCS patterns are correctly parsed into individual patterns
     /// **Test conditions:** Mock git config with valid comma-separated VCS patterns
@@ -325,6 +665,29 @@ mod tests {
         );
     }
 
+    /// **What is tested:** VCS ignore patterns set as several values with `git config --add`
+    /// **Why it is tested:** Multi-valued keys must not collapse to their last value, and may mix with comma-separated values
+    /// **Test conditions:** Mock git config with one comma-separated value and two single-pattern values
+    /// **Expectations:** Should return all patterns merged in configuration order
+    #[test]
+    fn test_get_vcs_ignore_patterns_multi_valued() {
+        let mock_reader = MockGitConfigReader::new().with_multi_config(
+            "diff-gitignore-filter.vcs-ignore.patterns",
+            &[".git/, .svn/", ".hg/", " CVS/ "],
+        );
+
+        let result = GitConfig::get_vcs_ignore_patterns_with_reader(&mock_reader);
+        assert_eq!(
+            result,
+            Ok(Some(vec![
+                ".git/".to_owned(),
+                ".svn/".to_owned(),
+                ".hg/".to_owned(),
+                "CVS/".to_owned()
+            ]))
+        );
+    }
+
     /// **What is tested:** Error handling for empty VCS ignore patterns configuration
     /// **Why it is tested:** Validates that empty pattern strings result in appropriate configuration errors
     /// **Test conditions:** Mock git config with empty string for VCS patterns
@@ -413,4 +776,117 @@ mod tests {
         let result = GitConfig::get_downstream_filter_with_reader(&mock_reader);
         assert_eq!(result, Ok(None));
     }
+
+    /// **What is tested:** Resolution of the downstream filter from the new and the deprecated key
+    /// **Why it is tested:** Existing setups use `gitignore-diff.downstream-filter` and must keep working after the move to `diff-gitignore-filter.downstream-filter`
+    /// **Test conditions:** Mock git config with only the new key, only the legacy key, both keys, and a blank new key next to the legacy key
+    /// **Expectations:** Both keys resolve on their own, the new key wins when both are set, a blank new key falls back to the legacy key, and the legacy flag is set only when the legacy key was used
+    #[test]
+    fn test_get_downstream_filter_new_and_legacy_key() {
+        let new_key = "diff-gitignore-filter.downstream-filter";
+        let legacy_key = "gitignore-diff.downstream-filter";
+
+        let new_only = MockGitConfigReader::new().with_config(new_key, "delta");
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_reader(&new_only),
+            Ok(Some("delta".to_owned()))
+        );
+
+        let legacy_only = MockGitConfigReader::new().with_config(legacy_key, "less");
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_reader(&legacy_only),
+            Ok(Some("less".to_owned()))
+        );
+
+        let both = MockGitConfigReader::new()
+            .with_config(new_key, "delta")
+            .with_config(legacy_key, "less");
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_reader(&both),
+            Ok(Some("delta".to_owned()))
+        );
+
+        let blank_new = MockGitConfigReader::new()
+            .with_config(new_key, " ")
+            .with_config(legacy_key, "less");
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_reader(&blank_new),
+            Ok(Some("less".to_owned()))
+        );
+
+        let keys = ConfigKeys::default();
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_legacy_flag(&new_only, &keys),
+            Ok(Some(("delta".to_owned(), false)))
+        );
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_legacy_flag(&legacy_only, &keys),
+            Ok(Some(("less".to_owned(), true)))
+        );
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_legacy_flag(&both, &keys),
+            Ok(Some(("delta".to_owned(), false)))
+        );
+
+        let keys = ConfigKeys::with_prefix("acme").unwrap();
+        assert_eq!(keys.legacy_downstream_filter(), None);
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_keys(&legacy_only, &keys),
+            Ok(None)
+        );
+    }
+
+    /// **What is tested:** Reading the filter settings under a custom key prefix
+    /// **Why it is tested:** `--config-key-prefix` must move every filter key to the new namespace and ignore the default keys
+    /// **Test conditions:** Mock git config with settings under `acme.diff` and conflicting settings under the default keys
+    /// **Expectations:** The prefixed values are returned and the default keys are not consulted
+    #[test]
+    fn test_get_settings_with_custom_key_prefix() {
+        let mock_reader = MockGitConfigReader::new()
+            .with_config("acme.diff.vcs-ignore.enabled", "false")
+            .with_multi_config("acme.diff.vcs-ignore.patterns", &[".git/", ".hg/"])
+            .with_config("acme.diff.downstream-filter", "less")
+            .with_config("diff-gitignore-filter.vcs-ignore.enabled", "true")
+            .with_config("gitignore-diff.downstream-filter", "delta");
+        let keys = ConfigKeys::with_prefix("acme.diff.").unwrap();
+
+        assert_eq!(keys.vcs_ignore_enabled(), "acme.diff.vcs-ignore.enabled");
+        assert_eq!(
+            GitConfig::get_vcs_ignore_enabled_with_keys(&mock_reader, &keys),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            GitConfig::get_vcs_ignore_patterns_with_keys(&mock_reader, &keys),
+            Ok(Some(vec![".git/".to_owned(), ".hg/".to_owned()]))
+        );
+        assert_eq!(
+            GitConfig::get_downstream_filter_with_keys(&mock_reader, &keys),
+            Ok(Some("less".to_owned()))
+        );
+        assert_eq!(
+            GitConfig::get_vcs_ignore_enabled_with_keys(&mock_reader, &ConfigKeys::default()),
+            Ok(Some(true))
+        );
+
+        let invalid = MockGitConfigReader::new().with_config("acme.diff.vcs-ignore.enabled", "x");
+        assert!(matches!(
+            GitConfig::get_vcs_ignore_enabled_with_keys(&invalid, &keys),
+            Err(ConfigError::InvalidGitConfig { key, .. }) if key == "acme.diff.vcs-ignore.enabled"
+        ));
+    }
+
+    /// **What is tested:** Validation of custom key prefixes
+    /// **Why it is tested:** Git rejects section names with other characters, so such prefixes could never match
+    /// **Test conditions:** Empty, dot-only, leading-dot and whitespace prefixes and a valid prefix
+    /// **Expectations:** Invalid prefixes give None, the valid prefix gives keys under it
+    #[test]
+    fn test_config_keys_with_prefix_validation() {
+        for prefix in ["", ".", ".acme", "my tool", "acme_diff"] {
+            assert_eq!(ConfigKeys::with_prefix(prefix), None, "prefix {prefix:?}");
+        }
+
+        let keys = ConfigKeys::with_prefix("my-tool").unwrap();
+        assert_eq!(keys.vcs_ignore_patterns(), "my-tool.vcs-ignore.patterns");
+        assert_eq!(keys.downstream_filter(), "my-tool.downstream-filter");
+    }
 }
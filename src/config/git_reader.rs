@@ -50,6 +50,15 @@ impl std::error::Error for GitError {}
 pub trait GitConfigReader {
     /// Get a Git configuration value by key
     fn get_config(&self, key: &str) -> Result<Option<String>, GitError>;
+
+    /// Get all values of a multi-valued Git configuration key, in configuration order
+    ///
+    /// Keys set repeatedly with `git config --add` have one value per entry. Returns an
+    /// empty list if the key is not set. The default implementation returns the single
+    /// value of [`GitConfigReader::get_config`].
+    fn get_all(&self, key: &str) -> Result<Vec<String>, GitError> {
+        Ok(self.get_config(key)?.into_iter().collect())
+    }
 }
 
 /// System Git configuration reader that executes actual Git commands
@@ -65,6 +74,16 @@ impl GitConfigReader for SystemGitConfigReader {
 
         Self::parse_git_config_output(output, key)
     }
+
+    fn get_all(&self, key: &str) -> Result<Vec<String>, GitError> {
+        let current_dir = Self::get_current_directory()?;
+
+        Self::validate_git_repository(&current_dir)?;
+
+        let output = Self::execute_git_config_get_all_command(key, &current_dir)?;
+
+        Self::parse_git_config_get_all_output(output, key)
+    }
 }
 
 impl SystemGitConfigReader {
@@ -98,6 +117,43 @@ impl SystemGitConfigReader {
             })
     }
 
+    /// Execute git config command listing all values of a key, NUL-terminated
+    fn execute_git_config_get_all_command(
+        key: &str,
+        current_dir: &PathBuf,
+    ) -> Result<std::process::Output, GitError> {
+        Command::new("git")
+            .args(["config", "--null", "--get-all", key])
+            .current_dir(current_dir)
+            .output()
+            .map_err(|e| GitError::IoError {
+                command: format!("git config --get-all {key}"),
+                error: e.to_string(),
+            })
+    }
+
+    /// Parse the NUL-terminated values of `git config --null --get-all`
+    fn parse_git_config_get_all_output(
+        output: std::process::Output,
+        key: &str,
+    ) -> Result<Vec<String>, GitError> {
+        match output.status.code() {
+            Some(0) => Ok(String::from_utf8_lossy(&output.stdout)
+                .split_terminator('\0')
+                .map(|value| value.trim().to_owned())
+                .collect()),
+            Some(1) => Ok(Vec::new()), // Key is not set
+            exit_code => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(GitError::CommandFailed {
+                    command: format!("git config --get-all {key}"),
+                    exit_code: exit_code.unwrap_or(-1),
+                    stderr: stderr.to_string(),
+                })
+            }
+        }
+    }
+
     /// Parse git config command output using functional approach
     fn parse_git_config_output(
         output: std::process::Output,
@@ -139,7 +195,7 @@ impl SystemGitConfigReader {
 /// Mock Git configuration reader for testing
 #[cfg(test)]
 pub struct MockGitConfigReader {
-    config: std::collections::HashMap<String, String>,
+    config: std::collections::HashMap<String, Vec<String>>,
 }
 
 #[cfg(test)]
@@ -160,7 +216,16 @@ impl MockGitConfigReader {
 
     /// Add a configuration value to the mock reader
     pub fn with_config(mut self, key: &str, value: &str) -> Self {
-        self.config.insert(key.to_owned(), value.to_owned());
+        self.config.insert(key.to_owned(), vec![value.to_owned()]);
+        self
+    }
+
+    /// Add a multi-valued configuration key, as set with repeated `git config --add`
+    pub fn with_multi_config(mut self, key: &str, values: &[&str]) -> Self {
+        self.config.insert(
+            key.to_owned(),
+            values.iter().map(|value| (*value).to_owned()).collect(),
+        );
         self
     }
 }
@@ -168,7 +233,16 @@ impl MockGitConfigReader {
 #[cfg(test)]
 impl GitConfigReader for MockGitConfigReader {
     fn get_config(&self, key: &str) -> Result<Option<String>, GitError> {
-        Ok(self.config.get(key).cloned())
+        // Like `git config --get`, the last value of a multi-valued key wins
+        Ok(self
+            .config
+            .get(key)
+            .and_then(|values| values.last())
+            .cloned())
+    }
+
+    fn get_all(&self, key: &str) -> Result<Vec<String>, GitError> {
+        Ok(self.config.get(key).cloned().unwrap_or_default())
     }
 }
 
@@ -205,6 +279,31 @@ mod tests {
     fn test_mock_git_config_reader_empty() {
         let mock_reader = MockGitConfigReader::new();
         assert_eq!(mock_reader.get_config("any.key"), Ok(None));
+        assert_eq!(mock_reader.get_all("any.key"), Ok(Vec::new()));
+    }
+
+    /// **What is tested:** Multi-valued keys in MockGitConfigReader
+    /// **Why it is tested:** Keys set with repeated `git config --add` must be readable as a whole, while single reads see the last value like Git
+    /// **Test conditions:** Mock reader with a multi-valued key and a single-valued key
+    /// **Expectations:** `get_all` returns all values in order, `get_config` the last one, and single values are returned as one-element lists
+    #[test]
+    fn test_mock_git_config_reader_multi_values() {
+        let mock_reader = MockGitConfigReader::new()
+            .with_multi_config("test.multi", &[".git/", ".svn/"])
+            .with_config("test.single", "value");
+
+        assert_eq!(
+            mock_reader.get_all("test.multi"),
+            Ok(vec![".git/".to_owned(), ".svn/".to_owned()])
+        );
+        assert_eq!(
+            mock_reader.get_config("test.multi"),
+            Ok(Some(".svn/".to_owned()))
+        );
+        assert_eq!(
+            mock_reader.get_all("test.single"),
+            Ok(vec!["value".to_owned()])
+        );
     }
 
     /// **What is tested:** Display formatting for GitError::CommandFailed variant
@@ -9,6 +9,7 @@
 //!
 //! - [`git_reader`] - Low-level Git command abstraction with error handling
 //! - [`git_config`] - Git-specific configuration operations with validation
+//! - [`env_config`] - Environment variable overrides with validation
 //! - [`app_config`] - High-level application configuration with CLI integration
 //!
 //! # Error Handling
@@ -24,8 +25,13 @@
 //! Configuration values are resolved with the following priority:
 //!
 //! 1. CLI parameters (highest priority)
-//! 2. Git configuration values
-//! 3. Hardcoded defaults (only when Git config not set)
+//! 2. Environment variables (`DIFF_GITIGNORE_FILTER_VCS`, `DIFF_GITIGNORE_FILTER_VCS_PATTERNS`,
+//!    `DIFF_GITIGNORE_FILTER_DOWNSTREAM`)
+//! 3. Git configuration values
+//! 4. Hardcoded defaults (only when Git config not set)
+//!
+//! The filter's own Git config keys can be moved under a custom namespace with
+//! [`ConfigKeys::with_prefix`] (`--config-key-prefix`, `DIFF_GITIGNORE_FILTER_CONFIG_KEY_PREFIX`).
 //!
 //! # Usage
 //!
@@ -38,16 +44,17 @@
 //! let cli_args = CliArgs {
 //!     vcs: false,
 //!     no_vcs: false,
-//!     downstream: None,
+//!     downstream: Vec::new(),
 //!     vcs_pattern: None,
+//!     ..Default::default()
 //! };
 //!
 //! match AppConfig::from_cli(cli_args) {
 //!     Ok(config) => {
 //!         println!("VCS enabled: {}", config.vcs_enabled());
 //!         println!("Patterns: {:?}", config.vcs_patterns());
-//!         if let Some(filter) = config.downstream_filter() {
-//!             println!("Downstream filter: {}", filter);
+//!         for stage in config.downstream_pipeline() {
+//!             println!("Downstream stage: {}", stage);
 //!         }
 //!     }
 //!     Err(ConfigError::GitCommandFailed { command, exit_code, stderr }) => {
@@ -65,6 +72,12 @@
 //!     Err(ConfigError::InvalidCliArgument { argument, value, expected }) => {
 //!         // Handle invalid CLI argument
 //!     }
+//!     Err(ConfigError::ValidationFailed { setting, value, reason }) => {
+//!         // Handle a setting rejected by AppConfig::validate()
+//!     }
+//!     Err(ConfigError::InvalidEnvValue { variable, value, expected }) => {
+//!         // Handle invalid environment variable
+//!     }
 //! }
 //! ```
 //!
@@ -73,6 +86,8 @@
 //! The configuration system provides mock implementations for testing:
 //!
 //! - `MockGitConfigReader` for testing Git configuration scenarios
+//! - [`AppConfig::from_cli_with_reader()`] resolves the configuration against any
+//!   [`GitConfigReader`], so resolution can be tested without a real Git repository
 //! - All modules include comprehensive unit tests with error scenarios
 //!
 //! # Error Types
@@ -82,12 +97,14 @@
 
 // Public modules
 pub mod app_config;
+pub mod env_config;
 pub mod git_config;
 pub mod git_reader;
 
 // Re-export public types for convenient access
-pub use app_config::{AppConfig, CliArgs};
-pub use git_config::{ConfigError, GitConfig};
+pub use app_config::{AppConfig, CliArgs, ConfigSource, ConfigSources};
+pub use env_config::EnvConfig;
+pub use git_config::{ConfigError, ConfigKeys, GitConfig};
 pub use git_reader::{GitConfigReader, GitError, SystemGitConfigReader};
 
 // Re-export mock types for testing
@@ -110,8 +127,9 @@ mod integration_tests {
         let _cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // ConfigError should be available for error handling
@@ -199,8 +217,9 @@ mod integration_tests {
         let config = AppConfig::from_cli(CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         });
 
         // Should handle Result properly
@@ -221,8 +240,9 @@ mod integration_tests {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: Some("filter".to_owned()),
+            downstream: vec!["filter".to_owned()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // Should implement Debug
@@ -235,8 +255,9 @@ mod integration_tests {
         let cli_args2 = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: Some("filter".to_owned()),
+            downstream: vec!["filter".to_owned()],
             vcs_pattern: None,
+            ..Default::default()
         };
         assert_eq!(cli_args, cli_args2);
     }
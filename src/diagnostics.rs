@@ -0,0 +1,42 @@
+//! Leveled diagnostics module
+//!
+//! This module writes diagnostic lines to stderr, so they never mix with the filtered
+//! diff on stdout. Nothing is written at the default level 0; the CLI raises the level
+//! with `-v` (1: repository context, selected root, downstream commands) and `-vv`
+//! (2: additionally the decision made for each file section). Library users can enable
+//! the same lines with [`set_verbosity`].
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Level up to which diagnostics are written, shared by the whole process
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+/// Set the level up to which diagnostics are written to stderr (0 = none)
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Level up to which diagnostics are written to stderr
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Check if diagnostics of the given level are written
+pub fn enabled(level: u8) -> bool {
+    verbosity() >= level
+}
+
+/// Write a diagnostic line to stderr if its level is enabled
+///
+/// The message is only formatted when it is written. Exported so the command-line
+/// binary writes its diagnostics with the same prefix.
+#[macro_export]
+macro_rules! diag {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::diagnostics::enabled($level) {
+            eprintln!("diff-gitignore-filter: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub use diag;
@@ -0,0 +1,139 @@
+//! ANSI color stripping module
+//!
+//! This module removes SGR escape sequences (`ESC [ <params> m`), as written by
+//! `git diff --color`, from a diff stream, so that its headers are recognized again.
+//! Other escape sequences and all remaining bytes, including line endings, are kept
+//! unchanged; a diff without escape sequences passes through byte for byte.
+
+use std::io::{self, BufRead, Read};
+
+/// Escape character starting an ANSI control sequence
+const ESC: u8 = 0x1b;
+
+/// Reader adapter that removes SGR escape sequences from the lines of a stream
+///
+/// ```rust
+/// use diff_gitignore_filter::diff::StripAnsi;
+/// use std::io::Read;
+///
+/// let colored: &[u8] = b"\x1b[1mdiff --git a/x b/x\x1b[m\n";
+/// let mut plain = String::new();
+/// StripAnsi::new(colored).read_to_string(&mut plain)?;
+/// assert_eq!(plain, "diff --git a/x b/x\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct StripAnsi<R> {
+    /// Stream the lines are read from
+    inner: R,
+    /// Raw line last read from `inner`
+    raw: Vec<u8>,
+    /// Last line with its escape sequences removed
+    line: Vec<u8>,
+    /// Number of bytes of `line` already consumed
+    pos: usize,
+}
+
+impl<R: BufRead> StripAnsi<R> {
+    /// Create a reader that strips SGR escape sequences from `inner`
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            raw: Vec::new(),
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Read for StripAnsi<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for StripAnsi<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A last line made of escape sequences only is empty after stripping
+        while self.pos == self.line.len() {
+            self.raw.clear();
+            if self.inner.read_until(b'\n', &mut self.raw)? == 0 {
+                return Ok(&[]);
+            }
+            self.line.clear();
+            self.pos = 0;
+            strip_sgr(&self.raw, &mut self.line);
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.line.len());
+    }
+}
+
+/// Append `input` to `output` without its SGR escape sequences
+///
+/// Incomplete sequences and control sequences other than SGR are copied unchanged.
+pub fn strip_sgr(input: &[u8], output: &mut Vec<u8>) {
+    let mut rest = input;
+    while let Some(start) = rest.iter().position(|&byte| byte == ESC) {
+        output.extend_from_slice(&rest[..start]);
+        match sgr_len(&rest[start..]) {
+            Some(len) => rest = &rest[start + len..],
+            None => {
+                output.push(ESC);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    output.extend_from_slice(rest);
+}
+
+/// Length of the SGR sequence at the start of `bytes`, which starts with `ESC`
+fn sgr_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.get(1) != Some(&b'[') {
+        return None;
+    }
+    let params = bytes[2..]
+        .iter()
+        .take_while(|&&byte| byte.is_ascii_digit() || byte == b';' || byte == b':')
+        .count();
+    (bytes.get(2 + params) == Some(&b'm')).then_some(3 + params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// **What is tested:** Removal of SGR sequences by `strip_sgr` and the `StripAnsi` reader
+    /// **Why it is tested:** Colored headers must become plain again, while other bytes are kept exactly
+    /// **Test conditions:** Colored header and hunk lines, a CRLF line, an incomplete and a non-SGR sequence, and a stream ending in an escape-only line
+    /// **Expectations:** Only complete SGR sequences are removed; line endings and other bytes are unchanged
+    #[test]
+    fn test_strip_ansi() -> io::Result<()> {
+        let strip = |input: &[u8]| {
+            let mut output = Vec::new();
+            strip_sgr(input, &mut output);
+            output
+        };
+
+        assert_eq!(
+            strip(b"\x1b[1mdiff --git a/x b/x\x1b[m\n"),
+            b"diff --git a/x b/x\n"
+        );
+        assert_eq!(strip(b"\x1b[32m+added\x1b[m\r\n"), b"+added\r\n");
+        assert_eq!(strip(b"\x1b[1;38;5;208mx\x1b[0m"), b"x");
+        assert_eq!(strip(b"+\x1b[2K kept\x1b["), b"+\x1b[2K kept\x1b[");
+        assert_eq!(strip(b"plain line\n"), b"plain line\n");
+
+        let mut output = Vec::new();
+        StripAnsi::new(&b"\x1b[36m@@ -1 +1 @@\x1b[m\n-old\n\x1b[m"[..]).read_to_end(&mut output)?;
+        assert_eq!(output, b"@@ -1 +1 @@\n-old\n");
+        Ok(())
+    }
+}
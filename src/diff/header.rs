@@ -0,0 +1,642 @@
+//! Diff header parsing module
+//!
+//! This module parses `diff --git a/<old> b/<new>` header lines. Git never emits a raw
+//! tab inside an unquoted path, so anything after the first tab is treated as trailing
+//! metadata appended by other tools and is kept separately from the two paths. If the
+//! paths cannot be split without the tab, the whole line is parsed as paths instead.
+//!
+//! Combined diffs of merge commits use `diff --cc <path>` or `diff --combined <path>`
+//! headers with a single path instead; [`parse_diff_header`] accepts both forms.
+//!
+//! The `a/` and `b/` path prefixes can be changed in Git (`diff.srcPrefix`/`diff.dstPrefix`),
+//! replaced by mnemonic ones (`diff.mnemonicPrefix`, e.g. `i/` and `w/`) or dropped entirely
+//! (`diff.noprefix`). Headers are parsed with the configured [`DiffPrefixes`] first; if that
+//! fails, the default, the mnemonic and finally the empty prefixes are tried in turn.
+//!
+//! Some tools on Windows emit headers with backslash separators, e.g.
+//! `diff --git a\src\main.rs b\src\main.rs`. When both paths start with the configured
+//! or default prefixes written with a backslash, the backslashes in the paths are turned
+//! into forward slashes. Headers using forward-slash prefixes are never changed, so
+//! backslashes in Unix file names are kept.
+//!
+//! Paths containing special or non-ASCII characters are emitted by Git in C-style
+//! quoted form (see `core.quotePath`), e.g. `"a/caf\303\251.txt"`. Such paths are
+//! decoded back into their original bytes before they are returned.
+//!
+//! Where a header cannot be split into two paths, the `--- <old>`/`+++ <new>` lines
+//! before the first hunk name them as well; [`parse_patch_paths`] parses them, using the real path for
+//! the `/dev/null` side of added and deleted files.
+
+/// Path on the `---`/`+++` lines standing for the missing side of an added or deleted file
+const DEV_NULL: &str = "/dev/null";
+
+/// Prefixes of the combined diff headers Git emits for merge commits
+const COMBINED_HEADER_PREFIXES: &[&str] = &["diff --cc ", "diff --combined "];
+
+/// Path prefix pairs tried when a header does not match the configured prefixes: the
+/// defaults and the pairs used by `diff.mnemonicPrefix`
+const FALLBACK_PREFIXES: &[(&str, &str)] = &[
+    ("a/", "b/"),
+    ("i/", "w/"),
+    ("c/", "w/"),
+    ("c/", "i/"),
+    ("o/", "w/"),
+    ("i/", "o/"),
+    ("1/", "2/"),
+];
+
+/// Prefixes Git puts in front of the source and destination paths of a `diff --git` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffPrefixes {
+    /// Prefix of the source path (`a/` by default)
+    pub src: String,
+    /// Prefix of the destination path (`b/` by default)
+    pub dst: String,
+}
+
+impl DiffPrefixes {
+    /// Create prefixes for the given source and destination sides
+    pub fn new(src: impl Into<String>, dst: impl Into<String>) -> Self {
+        Self {
+            src: src.into(),
+            dst: dst.into(),
+        }
+    }
+
+    /// Prefixes of diffs generated with `--no-prefix` or `diff.noprefix=true`
+    pub fn none() -> Self {
+        Self::new("", "")
+    }
+}
+
+impl Default for DiffPrefixes {
+    fn default() -> Self {
+        Self::new("a/", "b/")
+    }
+}
+
+/// Paths and trailing metadata parsed from a `diff --git` header line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitHeader {
+    /// Path on the source side with the `a/` prefix removed
+    pub old_path: String,
+    /// Path on the destination side with the `b/` prefix removed
+    pub new_path: String,
+    /// Tab-separated metadata following the two paths, if any
+    pub metadata: Option<String>,
+}
+
+/// Parse a `diff --git` header line with the default `a/` and `b/` prefixes
+///
+/// Exactly two path fields are consumed. Quoted paths are decoded. For unquoted paths,
+/// when both sides name the same file the line is split in the middle, which keeps paths
+/// containing spaces (or even ` b/`) intact; otherwise the first ` b/` separates the two
+/// paths.
+///
+/// Returns `None` if the line is not a `diff --git` header or the paths cannot be split.
+pub fn parse_git_header(line: &str) -> Option<GitHeader> {
+    parse_git_header_with_prefixes(line, &DiffPrefixes::default())
+}
+
+/// Parse a `diff --git` header line whose paths carry the given prefixes
+///
+/// Works like [`parse_git_header`] with ` <dst prefix>` as separator. If the paths do not
+/// carry the given prefixes, the fallback prefixes are tried, then the given and default
+/// prefixes with a backslash separator, ending with no prefix.
+pub fn parse_git_header_with_prefixes(line: &str, prefixes: &DiffPrefixes) -> Option<GitHeader> {
+    let remaining = line.strip_prefix("diff --git ")?;
+
+    std::iter::once((prefixes.src.as_str(), prefixes.dst.as_str()))
+        .chain(FALLBACK_PREFIXES.iter().copied())
+        .find_map(|(src, dst)| parse_header_paths(remaining, src, dst))
+        .or_else(|| parse_backslash_header_paths(remaining, prefixes))
+        .or_else(|| parse_header_paths(remaining, "", ""))
+}
+
+/// Parse the part of a `diff --git` header after the command with one pair of prefixes
+fn parse_header_paths(remaining: &str, src: &str, dst: &str) -> Option<GitHeader> {
+    // Prefer splitting off trailing metadata, but fall back to the whole remainder so
+    // hand-written headers with a literal tab inside an unquoted path still parse.
+    let ((old_token, new_token), metadata) = remaining
+        .split_once('\t')
+        .and_then(|(paths, metadata)| {
+            split_header_paths(paths, src, dst).map(|tokens| (tokens, Some(metadata.to_string())))
+        })
+        .or_else(|| split_header_paths(remaining, src, dst).map(|tokens| (tokens, None)))?;
+
+    let old_path = old_token.strip_prefix(src)?;
+    let new_path = new_token.strip_prefix(dst)?;
+
+    (!old_path.is_empty() && !new_path.is_empty()).then(|| GitHeader {
+        old_path: old_path.to_string(),
+        new_path: new_path.to_string(),
+        metadata,
+    })
+}
+
+/// Parse a header whose paths use backslash separators, e.g. `a\src\main.rs b\src\main.rs`
+///
+/// Tries the given and the default prefixes with their trailing `/` written as `\` and
+/// returns the paths with forward slashes.
+fn parse_backslash_header_paths(remaining: &str, prefixes: &DiffPrefixes) -> Option<GitHeader> {
+    let defaults = DiffPrefixes::default();
+
+    let header = [prefixes, &defaults]
+        .into_iter()
+        .filter_map(|prefixes| {
+            let src = prefixes.src.strip_suffix('/')?;
+            let dst = prefixes.dst.strip_suffix('/')?;
+            Some((format!("{src}\\"), format!("{dst}\\")))
+        })
+        .find_map(|(src, dst)| parse_header_paths(remaining, &src, &dst))?;
+
+    Some(GitHeader {
+        old_path: header.old_path.replace('\\', "/"),
+        new_path: header.new_path.replace('\\', "/"),
+        metadata: header.metadata,
+    })
+}
+
+/// Parse a combined diff header line (`diff --cc <path>` or `diff --combined <path>`)
+///
+/// Combined diffs of merge commits name a single, unprefixed path, which is reported as
+/// both the old and the new path. Returns `None` for any other line.
+pub fn parse_combined_header(line: &str) -> Option<GitHeader> {
+    let remaining = COMBINED_HEADER_PREFIXES
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))?;
+
+    let (path, metadata) = match remaining.split_once('\t') {
+        Some((path, metadata)) => (path, Some(metadata.to_string())),
+        None => (remaining, None),
+    };
+
+    let path = decode_path(path);
+    (!path.is_empty()).then(|| GitHeader {
+        old_path: path.clone(),
+        new_path: path,
+        metadata,
+    })
+}
+
+/// Parse any header line that starts a diff section
+///
+/// Accepts both the regular `diff --git` form and the combined form of merge diffs.
+pub fn parse_diff_header(line: &str) -> Option<GitHeader> {
+    parse_diff_header_with_prefixes(line, &DiffPrefixes::default())
+}
+
+/// Parse any header line that starts a diff section, with the given path prefixes
+///
+/// Combined headers never carry prefixes, so the prefixes only apply to `diff --git`.
+pub fn parse_diff_header_with_prefixes(line: &str, prefixes: &DiffPrefixes) -> Option<GitHeader> {
+    parse_git_header_with_prefixes(line, prefixes).or_else(|| parse_combined_header(line))
+}
+
+/// Check if a line starts a new diff section
+pub fn is_diff_header(line: &str) -> bool {
+    line.starts_with("diff --git")
+        || COMBINED_HEADER_PREFIXES
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+}
+
+/// Decode a single path token that may be C-style quoted
+///
+/// Used for the paths on `rename from`/`rename to`/`copy from`/`copy to` lines, which Git
+/// quotes the same way as the header paths. Unquoted tokens are returned unchanged.
+pub fn decode_path(token: &str) -> String {
+    unquote(token)
+        .filter(|(_, rest)| rest.is_empty())
+        .map(|(decoded, _)| decoded)
+        .unwrap_or_else(|| token.to_string())
+}
+
+/// Parse the paths of a `--- <old>` line followed by a `+++ <new>` line
+///
+/// Paths may be quoted and followed by tab-separated metadata; the given prefixes are
+/// removed. The `/dev/null` side of an added (`--- /dev/null`) or deleted
+/// (`+++ /dev/null`) file is replaced by the other side, so both paths name the real
+/// file. Returns `None` for other lines, or if both sides are `/dev/null`.
+pub fn parse_patch_paths(
+    old_line: &str,
+    new_line: &str,
+    prefixes: &DiffPrefixes,
+) -> Option<(String, String)> {
+    let old_path = parse_patch_path(old_line.strip_prefix("--- ")?, &prefixes.src);
+    let new_path = parse_patch_path(new_line.strip_prefix("+++ ")?, &prefixes.dst);
+
+    match (old_path, new_path) {
+        (Some(old_path), Some(new_path)) => Some((old_path, new_path)),
+        (Some(path), None) | (None, Some(path)) => Some((path.clone(), path)),
+        (None, None) => None,
+    }
+}
+
+/// Parse the path of a `---`/`+++` line after the marker; `None` for `/dev/null`
+fn parse_patch_path(token: &str, prefix: &str) -> Option<String> {
+    let token = token.split_once('\t').map_or(token, |(path, _)| path);
+    let path = decode_path(token);
+    if path == DEV_NULL {
+        return None;
+    }
+
+    let path = path.strip_prefix(prefix).unwrap_or(&path);
+    (!path.is_empty()).then(|| path.to_string())
+}
+
+/// Split the path part of a header into its two (decoded) tokens, prefixes included
+fn split_header_paths(paths: &str, src: &str, dst: &str) -> Option<(String, String)> {
+    if paths.starts_with('"') {
+        // Quoted source path, destination may be quoted or not
+        let (old_token, rest) = unquote(paths)?;
+        let new_token = rest.strip_prefix(' ')?;
+        return Some((old_token, decode_path(new_token)));
+    }
+
+    // Unquoted source path followed by a quoted destination path. A quoted token can
+    // only contain `"` escaped, and Git quotes any path containing `"`, so the first ` "`
+    // is the separator. Hand-written headers may still carry a raw `"` in an unquoted
+    // path; they fall through to the unquoted splitting below.
+    let quoted_destination = paths.find(" \"").and_then(|position| {
+        let (new_token, rest) = unquote(&paths[position + 1..])?;
+        rest.is_empty()
+            .then(|| (paths[..position].to_string(), new_token))
+    });
+
+    quoted_destination.or_else(|| {
+        split_identical_paths(paths, src, dst)
+            .or_else(|| {
+                paths
+                    .find(&format!(" {dst}"))
+                    .map(|position| (&paths[..position], &paths[position + 1..]))
+            })
+            .map(|(old_token, new_token)| (old_token.to_string(), new_token.to_string()))
+    })
+}
+
+/// Decode a C-style quoted token at the start of `input`
+///
+/// Handles the escapes Git produces: `\a`, `\b`, `\t`, `\n`, `\v`, `\f`, `\r`, `\"`, `\\`
+/// and three-digit octal escapes (`\303\251`) for raw bytes. The decoded bytes are
+/// converted to a string lossily. Returns the decoded token and the remaining input after
+/// the closing quote, or `None` if `input` does not start with a well-formed quoted token.
+fn unquote(input: &str) -> Option<(String, &str)> {
+    let bytes = input.as_bytes();
+    if bytes.first() != Some(&b'"') {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 1;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'"' => {
+                return Some((
+                    String::from_utf8_lossy(&decoded).into_owned(),
+                    &input[index + 1..],
+                ));
+            }
+            b'\\' => {
+                let escape = *bytes.get(index + 1)?;
+                let (byte, consumed) = match escape {
+                    b'a' => (0x07, 1),
+                    b'b' => (0x08, 1),
+                    b't' => (b'\t', 1),
+                    b'n' => (b'\n', 1),
+                    b'v' => (0x0b, 1),
+                    b'f' => (0x0c, 1),
+                    b'r' => (b'\r', 1),
+                    b'"' => (b'"', 1),
+                    b'\\' => (b'\\', 1),
+                    b'0'..=b'7' => {
+                        let digits = bytes.get(index + 1..index + 4)?;
+                        let value = digits.iter().try_fold(0u16, |value, digit| {
+                            matches!(digit, b'0'..=b'7')
+                                .then(|| value * 8 + u16::from(digit - b'0'))
+                        })?;
+                        (u8::try_from(value).ok()?, 3)
+                    }
+                    _ => return None,
+                };
+                decoded.push(byte);
+                index += 1 + consumed;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    None
+}
+
+/// Split `a/<path> b/<path>` in the middle when both sides name the same path
+fn split_identical_paths<'a>(paths: &'a str, src: &str, dst: &str) -> Option<(&'a str, &'a str)> {
+    let middle = paths.len() / 2;
+
+    (paths.len() % 2 == 1 && paths.is_char_boundary(middle))
+        .then(|| (&paths[..middle], &paths[middle..]))
+        .and_then(|(old_token, rest)| Some((old_token, rest.strip_prefix(' ')?)))
+        .filter(|(old_token, new_token)| {
+            old_token.strip_prefix(src).is_some()
+                && old_token.strip_prefix(src) == new_token.strip_prefix(dst)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// **What is tested:** Parsing of standard `diff --git` header lines
+    /// **Why it is tested:** The header parser is shared by the filter and the root finder, so both rely on its basic correctness
+    /// **Test conditions:** Headers for an unchanged path, a path with spaces, a rename, and malformed lines
+    /// **Expectations:** Paths should be extracted without prefixes; malformed lines should return None
+    #[test]
+    fn test_parse_git_header_basic() {
+        let header = parse_git_header("diff --git a/src/main.rs b/src/main.rs");
+        assert_eq!(
+            header,
+            Some(GitHeader {
+                old_path: "src/main.rs".to_string(),
+                new_path: "src/main.rs".to_string(),
+                metadata: None,
+            })
+        );
+
+        let header = parse_git_header("diff --git a/my dir/a b/c.txt b/my dir/a b/c.txt");
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some((
+                "my dir/a b/c.txt".to_string(),
+                "my dir/a b/c.txt".to_string()
+            ))
+        );
+
+        let header = parse_git_header("diff --git a/old/file.txt b/new/file.txt");
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some(("old/file.txt".to_string(), "new/file.txt".to_string()))
+        );
+
+        assert_eq!(parse_git_header("not a diff line"), None);
+        assert_eq!(parse_git_header("diff --git a/file.txt"), None);
+    }
+
+    /// **What is tested:** Parsing of headers carrying trailing tab-separated metadata
+    /// **Why it is tested:** Extra fields appended by tools must not leak into the paths, especially for paths with spaces
+    /// **Test conditions:** Headers with metadata after a tab, for an identical path with spaces and for a rename
+    /// **Expectations:** Exactly the two paths should be extracted and the metadata should be preserved separately
+    #[test]
+    fn test_parse_git_header_trailing_metadata() {
+        let header = parse_git_header(
+            "diff --git a/docs/user guide.md b/docs/user guide.md\tmode=100644 tool=review",
+        );
+        assert_eq!(
+            header,
+            Some(GitHeader {
+                old_path: "docs/user guide.md".to_string(),
+                new_path: "docs/user guide.md".to_string(),
+                metadata: Some("mode=100644 tool=review".to_string()),
+            })
+        );
+
+        let header = parse_git_header("diff --git a/old name.rs b/new name.rs\tsimilarity=90");
+        assert_eq!(
+            header,
+            Some(GitHeader {
+                old_path: "old name.rs".to_string(),
+                new_path: "new name.rs".to_string(),
+                metadata: Some("similarity=90".to_string()),
+            })
+        );
+
+        let header = parse_git_header("diff --git a/tab\tname.rs b/tab\tname.rs");
+        assert_eq!(
+            header,
+            Some(GitHeader {
+                old_path: "tab\tname.rs".to_string(),
+                new_path: "tab\tname.rs".to_string(),
+                metadata: None,
+            })
+        );
+    }
+
+    /// **What is tested:** Parsing of C-style quoted header paths
+    /// **Why it is tested:** Git quotes paths with special or non-ASCII characters (core.quotepath), which must still be matched
+    /// **Test conditions:** Quoted headers with spaces and escapes, UTF-8 octal escapes, and a mixed quoted/unquoted rename
+    /// **Expectations:** Quotes should be removed and escapes decoded into the original characters
+    #[test]
+    fn test_parse_git_header_quoted_paths() {
+        let header =
+            parse_git_header(r#"diff --git "a/my dir/tab\there.log" "b/my dir/tab\there.log""#);
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some((
+                "my dir/tab\there.log".to_string(),
+                "my dir/tab\there.log".to_string()
+            ))
+        );
+
+        let header = parse_git_header(r#"diff --git "a/caf\303\251.txt" "b/caf\303\251.txt""#);
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some(("café.txt".to_string(), "café.txt".to_string()))
+        );
+
+        let header = parse_git_header(r#"diff --git a/plain.txt "b/quo\"ted.txt""#);
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some(("plain.txt".to_string(), "quo\"ted.txt".to_string()))
+        );
+
+        assert_eq!(
+            parse_git_header(r#"diff --git "a/unterminated.txt b/x"#),
+            None
+        );
+
+        // A raw `"` inside an unquoted path is not the start of a quoted destination
+        let header = parse_git_header("diff --git a/x \"y b/x \"y");
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some(("x \"y".to_string(), "x \"y".to_string()))
+        );
+    }
+
+    /// **What is tested:** Decoding of single path tokens from rename/copy lines
+    /// **Why it is tested:** Rename destinations are quoted the same way as header paths and drive the filter decision
+    /// **Test conditions:** Quoted token with octal escapes, unquoted token, and a token with trailing garbage
+    /// **Expectations:** Quoted tokens should be decoded; anything else should be returned unchanged
+    #[test]
+    fn test_decode_path() {
+        assert_eq!(decode_path(r#""na\303\257ve.log""#), "naïve.log");
+        assert_eq!(decode_path("plain/path.rs"), "plain/path.rs");
+        assert_eq!(decode_path(r#""a" trailing"#), r#""a" trailing"#);
+    }
+
+    /// **What is tested:** Parsing of the `---`/`+++` line pair with `parse_patch_paths`
+    /// **Why it is tested:** Ambiguous headers fall back to these lines, which name `/dev/null` for added and deleted files
+    /// **Test conditions:** A modified file, an added file, a deleted file, a quoted path with tab metadata, both sides `/dev/null`, and lines in the wrong order
+    /// **Expectations:** Prefixes are stripped, the `/dev/null` side is replaced by the real path, and invalid pairs give None
+    #[test]
+    fn test_parse_patch_paths() {
+        let prefixes = DiffPrefixes::default();
+        let paths = |old: &str, new: &str| parse_patch_paths(old, new, &prefixes);
+
+        assert_eq!(
+            paths("--- a/src/old.rs", "+++ b/src/new.rs"),
+            Some(("src/old.rs".to_string(), "src/new.rs".to_string()))
+        );
+        assert_eq!(
+            paths("--- /dev/null", "+++ b/new.rs"),
+            Some(("new.rs".to_string(), "new.rs".to_string()))
+        );
+        assert_eq!(
+            paths("--- a/gone.rs", "+++ /dev/null"),
+            Some(("gone.rs".to_string(), "gone.rs".to_string()))
+        );
+        assert_eq!(
+            paths(
+                "--- \"a/caf\\303\\251 b/x.log\"\t2024-01-01",
+                "+++ /dev/null"
+            ),
+            Some(("café b/x.log".to_string(), "café b/x.log".to_string()))
+        );
+        assert_eq!(paths("--- /dev/null", "+++ /dev/null"), None);
+        assert_eq!(paths("+++ b/new.rs", "--- a/new.rs"), None);
+    }
+
+    /// **What is tested:** Parsing of combined diff headers from merge commits
+    /// **Why it is tested:** `diff --cc` and `diff --combined` sections must be recognized as section boundaries and filtered by their path
+    /// **Test conditions:** Both combined header forms, a quoted combined path, and the dispatching parser with a regular header
+    /// **Expectations:** The single path should be reported as old and new path; non-header lines should be rejected
+    #[test]
+    fn test_parse_combined_header() {
+        let header = parse_combined_header("diff --cc src/main.rs");
+        assert_eq!(
+            header.map(|h| (h.old_path, h.new_path)),
+            Some(("src/main.rs".to_string(), "src/main.rs".to_string()))
+        );
+
+        let header = parse_combined_header(r#"diff --combined "build/caf\303\251.log""#);
+        assert_eq!(
+            header.map(|h| h.new_path),
+            Some("build/café.log".to_string())
+        );
+
+        assert_eq!(parse_combined_header("diff --cc "), None);
+        assert_eq!(parse_combined_header("diff --git a/x b/x"), None);
+
+        assert_eq!(
+            parse_diff_header("diff --git a/x.rs b/y.rs").map(|h| h.new_path),
+            Some("y.rs".to_string())
+        );
+        assert_eq!(
+            parse_diff_header("diff --cc x.rs").map(|h| h.new_path),
+            Some("x.rs".to_string())
+        );
+
+        assert!(is_diff_header("diff --git a/x b/x"));
+        assert!(is_diff_header("diff --cc x"));
+        assert!(is_diff_header("diff --combined x"));
+        assert!(!is_diff_header("index 1234567,89abcde..0123456"));
+    }
+
+    /// **What is tested:** Parsing of headers generated with `--no-prefix` or custom `--src-prefix`/`--dst-prefix`
+    /// **Why it is tested:** Users with `diff.noprefix` or `diff.mnemonicPrefix` set produce headers without `a/` and `b/`
+    /// **Test conditions:** No-prefix headers, mnemonic `i/ w/` headers, and `i/ o/` headers with and without configured prefixes
+    /// **Expectations:** The prefixes should be stripped and the plain paths returned in every case
+    #[test]
+    fn test_parse_git_header_with_prefixes() {
+        let paths = |header: Option<GitHeader>| header.map(|h| (h.old_path, h.new_path));
+
+        assert_eq!(
+            paths(parse_git_header("diff --git src/main.rs src/main.rs")),
+            Some(("src/main.rs".to_string(), "src/main.rs".to_string()))
+        );
+        assert_eq!(
+            paths(parse_git_header_with_prefixes(
+                "diff --git old.rs new.rs",
+                &DiffPrefixes::none()
+            )),
+            Some(("old.rs".to_string(), "new.rs".to_string()))
+        );
+
+        let custom = DiffPrefixes::new("i/", "o/");
+        assert_eq!(
+            paths(parse_git_header_with_prefixes(
+                "diff --git i/src/old.rs o/src/new.rs",
+                &custom
+            )),
+            Some(("src/old.rs".to_string(), "src/new.rs".to_string()))
+        );
+        assert_eq!(
+            paths(parse_git_header("diff --git i/src/x.rs o/src/x.rs")),
+            Some(("src/x.rs".to_string(), "src/x.rs".to_string()))
+        );
+        assert_eq!(
+            paths(parse_git_header("diff --git i/debug.log w/debug.log")),
+            Some(("debug.log".to_string(), "debug.log".to_string()))
+        );
+        assert_eq!(
+            paths(parse_diff_header_with_prefixes(
+                "diff --git \"i/my file.log\" \"o/my file.log\"",
+                &custom
+            )),
+            Some(("my file.log".to_string(), "my file.log".to_string()))
+        );
+
+        // Configured prefixes take precedence over the defaults
+        assert_eq!(
+            paths(parse_git_header_with_prefixes(
+                "diff --git a/b/x.rs a/b/x.rs",
+                &DiffPrefixes::none()
+            )),
+            Some(("a/b/x.rs".to_string(), "a/b/x.rs".to_string()))
+        );
+    }
+
+    /// **What is tested:** Parsing of headers with backslash-separated paths
+    /// **Why it is tested:** Windows tools emit `a\src\main.rs`, which must match forward-slash gitignore patterns
+    /// **Test conditions:** Backslash headers with default, configured and missing prefixes, with trailing metadata, and forward-slash headers whose file names contain backslashes
+    /// **Expectations:** Backslash headers yield forward-slash paths; file names in forward-slash headers keep their backslashes
+    #[test]
+    fn test_parse_git_header_backslash_paths() {
+        let paths = |header: Option<GitHeader>| header.map(|h| (h.old_path, h.new_path));
+
+        assert_eq!(
+            paths(parse_git_header(r"diff --git a\src\main.rs b\src\main.rs")),
+            Some(("src/main.rs".to_string(), "src/main.rs".to_string()))
+        );
+        assert_eq!(
+            parse_git_header("diff --git a\\src\\old.rs b\\src\\new.rs\tmeta"),
+            Some(GitHeader {
+                old_path: "src/old.rs".to_string(),
+                new_path: "src/new.rs".to_string(),
+                metadata: Some("meta".to_string()),
+            })
+        );
+        assert_eq!(
+            paths(parse_git_header_with_prefixes(
+                r"diff --git old\target\x.o new\target\x.o",
+                &DiffPrefixes::new("old/", "new/")
+            )),
+            Some(("target/x.o".to_string(), "target/x.o".to_string()))
+        );
+
+        // Backslashes in file names of regular headers are kept
+        assert_eq!(
+            paths(parse_git_header(r"diff --git a/dir\name.rs b/dir\name.rs")),
+            Some((r"dir\name.rs".to_string(), r"dir\name.rs".to_string()))
+        );
+        assert_eq!(
+            paths(parse_git_header_with_prefixes(
+                r"diff --git x\y.rs x\y.rs",
+                &DiffPrefixes::none()
+            )),
+            Some((r"x\y.rs".to_string(), r"x\y.rs".to_string()))
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Diff parsing module
+//!
+//! This module provides the parsing primitives shared by the filter and the root finder,
+//! so that both interpret diff headers in exactly the same way.
+//!
+//! # Architecture
+//!
+//! - [`ansi`] - Removal of ANSI color sequences from diffs generated with `--color`
+//! - [`header`] - Parsing of `diff --git` and combined (`diff --cc`) header lines into their (decoded) paths
+//! - [`section`] - Iteration over the sections of a diff stream
+
+pub mod ansi;
+pub mod header;
+pub mod section;
+
+pub use ansi::StripAnsi;
+pub use header::{
+    decode_path, is_diff_header, parse_combined_header, parse_diff_header,
+    parse_diff_header_with_prefixes, parse_git_header, parse_git_header_with_prefixes,
+    parse_patch_paths, DiffPrefixes, GitHeader,
+};
+pub use section::{DiffSection, DiffSections};
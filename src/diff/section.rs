@@ -0,0 +1,1052 @@
+//! Diff section iteration module
+//!
+//! This module splits a diff stream into its sections: one per `diff --git` (or combined
+//! `diff --cc`/`diff --combined`) header, holding the header line and the raw bytes that
+//! follow it up to the next header. Lines are kept with their original endings, so
+//! writing all sections back reproduces the input byte for byte.
+//!
+//! Paths are taken from the header, unless the extended header lines contain a
+//! `rename from`/`copy from` or `rename to`/`copy to` line, which is authoritative.
+//! If the header cannot be split into two paths, the `---`/`+++` lines before the first
+//! hunk are used instead.
+//! The base85 data of `GIT binary patch` blocks is opaque and never starts a new section.
+//! Submodule sections (gitlink mode 160000, `Subproject commit <sha>` content) name a
+//! directory; see [`DiffSection::is_submodule`].
+//!
+//! In `git log -p` output, each `commit <sha>` line starts a section holding the commit
+//! metadata (author, date, message) up to the first header of the commit. Like the text
+//! before the first header, such sections have no paths and are never dropped.
+//!
+//! With a section size limit, a section is cut off after the line that exceeds the
+//! limit; the rest of it is read line by line instead of being accumulated.
+//!
+//! In unified mode, a `---` line directly followed by a `+++` line also starts a section,
+//! for plain unified diffs such as `diff -u` output, as does a `diff` command line like
+//! `diff -ruN old/x new/x` preceding such a pair. These lines are only recognized outside
+//! `diff --git` sections and outside hunks, whose line counts are tracked.
+//!
+//! Diffs that no filter can shorten are copied line by line instead, reporting only the
+//! path of each section; see `copy_sections`.
+
+use super::header::{
+    decode_path, is_diff_header, parse_diff_header_with_prefixes, parse_patch_paths, DiffPrefixes,
+};
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::report::FilterDecision;
+use std::io::{self, BufRead, Write};
+
+/// Prefixes of the extended header lines Git emits between `diff --git` and the content
+const EXTENDED_HEADER_PREFIXES: &[&str] = &[
+    "old mode ",
+    "new mode ",
+    "deleted file mode ",
+    "new file mode ",
+    "copy from ",
+    "copy to ",
+    "rename from ",
+    "rename to ",
+    "similarity index ",
+    "dissimilarity index ",
+    "index ",
+];
+
+/// File mode Git records for a submodule entry (a gitlink)
+const GITLINK_MODE: &str = "160000";
+
+/// Position within the opaque body of a `GIT binary patch` block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryPatch {
+    /// Not inside a binary patch
+    Outside,
+    /// Inside a `literal`/`delta` hunk of base85 data
+    InHunk,
+    /// After the blank line terminating a hunk; another hunk may follow
+    BetweenHunks,
+}
+
+impl BinaryPatch {
+    /// Advance past `line` and report whether it belongs to a binary patch
+    ///
+    /// Binary patch bodies (the marker, `literal`/`delta` hunks of base85 data and
+    /// their terminating blank lines) are opaque and must never be interpreted as
+    /// section boundaries.
+    fn consume(&mut self, line: &str) -> bool {
+        let next = match *self {
+            Self::Outside if line == "GIT binary patch" => Self::BetweenHunks,
+            Self::InHunk if line.is_empty() => Self::BetweenHunks,
+            Self::InHunk => Self::InHunk,
+            Self::BetweenHunks if line.starts_with("literal ") || line.starts_with("delta ") => {
+                Self::InHunk
+            }
+            Self::Outside | Self::BetweenHunks => Self::Outside,
+        };
+
+        *self = next;
+        next != Self::Outside
+    }
+}
+
+/// One section of a diff
+///
+/// Text before the first header is returned as a preamble section with an empty header
+/// and no paths. The metadata of each commit in `git log -p` output is a preamble
+/// section too, whose header is the `commit <sha>` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSection {
+    /// Header line starting the section, without its line ending (empty for the preamble
+    /// before the first header)
+    pub header: String,
+    /// Source path (`rename from`/`copy from` or the header's old path)
+    pub old_path: Option<String>,
+    /// Destination path (`rename to`/`copy to` or the header's new path)
+    pub new_path: Option<String>,
+    /// Raw bytes following the header line, up to the next header
+    pub body: Vec<u8>,
+    /// Header line as read, including its line ending
+    raw_header: Vec<u8>,
+    /// Whether `body` was cut off at the section size limit
+    truncated: bool,
+    /// Whether the section holds text before the first header or commit metadata
+    preamble: bool,
+}
+
+impl DiffSection {
+    /// Start a section from its raw header line
+    ///
+    /// A `commit <sha>` line starts a preamble section of commit metadata.
+    fn from_header(raw_header: Vec<u8>, prefixes: &DiffPrefixes) -> Self {
+        let header = strip_line_ending(&String::from_utf8_lossy(&raw_header)).to_string();
+        let (old_path, new_path) = parse_diff_header_with_prefixes(&header, prefixes)
+            .map_or((None, None), |parsed| {
+                (Some(parsed.old_path), Some(parsed.new_path))
+            });
+        let preamble = is_commit_line(&header);
+
+        Self {
+            header,
+            old_path,
+            new_path,
+            body: Vec::new(),
+            raw_header,
+            truncated: false,
+            preamble,
+        }
+    }
+
+    /// Start the section holding the text before the first header
+    fn preamble() -> Self {
+        Self {
+            header: String::new(),
+            old_path: None,
+            new_path: None,
+            body: Vec::new(),
+            raw_header: Vec::new(),
+            truncated: false,
+            preamble: true,
+        }
+    }
+
+    /// Check if this is the text before the first header or the metadata of a commit
+    /// rather than a file section
+    pub fn is_preamble(&self) -> bool {
+        self.preamble
+    }
+
+    /// Number of input bytes the section was read from, header line included
+    pub(crate) fn raw_len(&self) -> usize {
+        self.raw_header.len() + self.body.len()
+    }
+
+    /// Check if the body was cut off at the section size limit
+    ///
+    /// The rest of the section is then read with [`DiffSections::read_rest_line`].
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Path the keep/drop decision is based on (the destination path)
+    pub fn path(&self) -> Option<&str> {
+        self.new_path.as_deref()
+    }
+
+    /// Decide whether `filter` drops this section
+    ///
+    /// Sections whose path cannot be parsed are dropped unless the filter keeps them
+    /// (see [`Filter::with_on_unparsable`]); the preamble is never dropped.
+    pub fn is_ignored(&self, filter: &Filter) -> bool {
+        !self.is_preamble() && !self.decision(filter).is_kept()
+    }
+
+    /// Decision `filter` makes for this section, based on [`DiffSection::path`] and,
+    /// for sections kept by the path, the kind of change
+    ///
+    /// The path of a submodule section is matched as a directory, so patterns such as
+    /// `vendor/` drop it.
+    pub(crate) fn decision(&self, filter: &Filter) -> FilterDecision {
+        let decide = |path| {
+            if self.is_submodule() {
+                filter.decide_submodule(path)
+            } else {
+                filter.decide_file(path)
+            }
+        };
+
+        match self.path().map(decide) {
+            None => filter.unparsable_decision(),
+            Some(FilterDecision::Keep) => filter.decide_change(self),
+            Some(decision) => decision,
+        }
+    }
+
+    /// Check if the section deletes its file (`deleted file mode` extended header line)
+    pub fn is_deletion(&self) -> bool {
+        String::from_utf8_lossy(&self.body)
+            .lines()
+            .take_while(|line| is_extended_header_line(line))
+            .any(|line| line.starts_with("deleted file mode "))
+    }
+
+    /// Check if the section changes a submodule rather than a file
+    ///
+    /// Git marks submodule entries with mode 160000 on the `index`, `new file mode` or
+    /// `deleted file mode` line. Without such a line, a section whose content consists
+    /// of `Subproject commit <sha>` lines only is a submodule change too.
+    pub fn is_submodule(&self) -> bool {
+        let body = String::from_utf8_lossy(&self.body);
+        let has_gitlink_mode = body
+            .lines()
+            .take_while(|line| is_extended_header_line(line))
+            .any(|line| line.split_whitespace().last() == Some(GITLINK_MODE));
+
+        let mut content = body
+            .lines()
+            .skip_while(|line| is_extended_header_line(line))
+            .filter(|line| {
+                !line.starts_with("--- ")
+                    && !line.starts_with("+++ ")
+                    && !line.starts_with("@@")
+                    && !line.starts_with('\\')
+            })
+            .peekable();
+
+        !self.is_preamble()
+            && (has_gitlink_mode
+                || content.peek().is_some()
+                    && content.all(|line| {
+                        line.strip_prefix(['+', '-', ' '])
+                            .unwrap_or(line)
+                            .starts_with("Subproject commit ")
+                    }))
+    }
+
+    /// Check if the section adds its file (`new file mode` extended header line)
+    pub fn is_addition(&self) -> bool {
+        String::from_utf8_lossy(&self.body)
+            .lines()
+            .take_while(|line| is_extended_header_line(line))
+            .any(|line| line.starts_with("new file mode "))
+    }
+
+    /// Object names of the old and new file content from the `index <old>..<new>` line
+    ///
+    /// The names are abbreviated unless the diff was made with `--full-index`.
+    pub fn index_blobs(&self) -> Option<(String, String)> {
+        String::from_utf8_lossy(&self.body)
+            .lines()
+            .take_while(|line| is_extended_header_line(line))
+            .find_map(|line| {
+                let blobs = line.strip_prefix("index ")?.split(' ').next()?;
+                let (old, new) = blobs.split_once("..")?;
+                Some((old.to_owned(), new.to_owned()))
+            })
+    }
+
+    /// Check if the section only changes the file mode
+    ///
+    /// True if the body consists of `old mode`/`new mode` lines only, i.e. the change
+    /// has no content, rename or copy.
+    pub fn is_mode_only(&self) -> bool {
+        let body = String::from_utf8_lossy(&self.body);
+        let mut lines = body.lines().peekable();
+
+        !self.is_preamble()
+            && lines.peek().is_some()
+            && lines.all(|line| line.starts_with("old mode ") || line.starts_with("new mode "))
+    }
+
+    /// Write the section exactly as it appeared in the input
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.raw_header)?;
+        writer.write_all(&self.body)
+    }
+
+    /// Write only the header line, followed by a `# filtered by <reason>` comment line
+    ///
+    /// Stands in for a dropped section for tools that expect every file to be present.
+    /// The comment line uses the header's line ending.
+    pub(crate) fn write_filtered_marker_to<W: Write>(
+        &self,
+        mut writer: W,
+        reason: &str,
+    ) -> io::Result<()> {
+        let line_ending = self.line_ending();
+
+        writer.write_all(&self.raw_header)?;
+        if !self.raw_header.ends_with(b"\n") {
+            writer.write_all(line_ending.as_bytes())?;
+        }
+        write!(writer, "# filtered by {reason}{line_ending}")
+    }
+
+    /// Write the section preceded by a `# kept: <reason>` comment line
+    ///
+    /// The comment makes the output unsuitable for `git apply`.
+    pub(crate) fn write_annotated_to<W: Write>(
+        &self,
+        mut writer: W,
+        reason: &str,
+    ) -> io::Result<()> {
+        write!(writer, "# kept: {reason}{}", self.line_ending())?;
+        self.write_to(writer)
+    }
+
+    /// Line ending of the header line, used for lines added to the section
+    fn line_ending(&self) -> &'static str {
+        if self.raw_header.ends_with(b"\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Take the paths from the `---`/`+++` lines before the first hunk if the header
+    /// cannot be split into two paths and no rename or copy names them
+    ///
+    /// The header itself is the `---` line of a plain unified diff section.
+    fn resolve_ambiguous_header(&mut self, prefixes: &DiffPrefixes) {
+        if self.old_path.is_some() && self.new_path.is_some() {
+            return;
+        }
+
+        let body = String::from_utf8_lossy(&self.body);
+        let preface: Vec<&str> = std::iter::once(self.header.as_str())
+            .chain(body.lines())
+            .take_while(|line| !line.starts_with("@@"))
+            .collect();
+
+        if let Some((old_path, new_path)) = preface
+            .windows(2)
+            .find_map(|lines| parse_patch_paths(lines[0], lines[1], prefixes))
+        {
+            self.old_path = self.old_path.take().or(Some(old_path));
+            self.new_path = self.new_path.take().or(Some(new_path));
+        }
+    }
+
+    /// Record rename/copy information from an extended header line
+    fn record_extended_header(&mut self, line: &str) {
+        if let Some(path) = line
+            .strip_prefix("rename from ")
+            .or_else(|| line.strip_prefix("copy from "))
+        {
+            self.old_path = Some(decode_path(path));
+        } else if let Some(path) = line
+            .strip_prefix("rename to ")
+            .or_else(|| line.strip_prefix("copy to "))
+        {
+            self.new_path = Some(decode_path(path));
+        }
+    }
+}
+
+/// Iterator over the sections of a diff stream, see [`Filter::sections`]
+pub struct DiffSections<R> {
+    /// Diff stream being read
+    reader: R,
+    /// Path prefixes of the `diff --git` headers
+    prefixes: DiffPrefixes,
+    /// Header line of the next section, read while finishing the previous one
+    pending_header: Option<Vec<u8>>,
+    /// Whether the stream is exhausted (or failed)
+    finished: bool,
+    /// Body size after which a section is cut off (None = no limit)
+    max_section_bytes: Option<usize>,
+    /// Position within a binary patch of the section being read
+    binary_patch: BinaryPatch,
+    /// Whether the section being read is the preamble
+    in_preamble: bool,
+    /// Whether the last section returned was cut off and has unread lines
+    unread_rest: bool,
+    /// Whether `---`/`+++` pairs start sections, for plain unified diffs
+    unified: bool,
+    /// Line read ahead to recognize a `---`/`+++` pair, returned by the next read
+    lookahead: Option<Vec<u8>>,
+    /// Whether the section being read started with a `diff --git` style header
+    in_git_section: bool,
+    /// Whether the section being read started with a `diff` command line and its
+    /// `---`/`+++` pair is still to come
+    awaiting_patch_lines: bool,
+    /// Old and new lines left in the current hunk of a plain unified diff section
+    hunk_lines: (usize, usize),
+}
+
+impl<R: BufRead> DiffSections<R> {
+    /// Iterate over the sections of `reader` with the given header path prefixes
+    pub fn new(reader: R, prefixes: DiffPrefixes) -> Self {
+        Self {
+            reader,
+            prefixes,
+            pending_header: None,
+            finished: false,
+            max_section_bytes: None,
+            binary_patch: BinaryPatch::Outside,
+            in_preamble: false,
+            unread_rest: false,
+            unified: false,
+            lookahead: None,
+            in_git_section: false,
+            awaiting_patch_lines: false,
+            hunk_lines: (0, 0),
+        }
+    }
+
+    /// Also start a section at each `---` line directly followed by a `+++` line, and at
+    /// `diff` command lines such as `diff -ruN old/x new/x`
+    ///
+    /// For unified diffs without `diff --git` headers, e.g. from `diff -u`. Within
+    /// `diff --git` sections such lines never start a section.
+    pub(crate) fn with_unified(mut self, enabled: bool) -> Self {
+        self.unified = enabled;
+        self
+    }
+
+    /// Cut sections off once their body exceeds `limit` bytes
+    ///
+    /// The body of such a section holds the lines read up to and including the one
+    /// exceeding the limit; the rest is returned by [`DiffSections::read_rest_line`],
+    /// or skipped when the next section is read.
+    pub(crate) fn with_max_section_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_section_bytes = limit;
+        self
+    }
+
+    /// Read the next unread line of the last section returned if it was cut off
+    ///
+    /// Returns `None` once the section is complete.
+    pub(crate) fn read_rest_line(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.unread_rest {
+            return Ok(None);
+        }
+
+        let raw_line = self.read_section_line()?;
+        self.unread_rest = raw_line.is_some();
+        Ok(raw_line)
+    }
+
+    /// Read the next line of the current section; `None` at the next header or the end
+    /// of the stream
+    fn read_section_line(&mut self) -> Result<Option<Vec<u8>>> {
+        let raw_line = match self.lookahead.take() {
+            Some(raw_line) => raw_line,
+            None => match self.read_raw_line()? {
+                Some(raw_line) => raw_line,
+                None => return Ok(None),
+            },
+        };
+
+        let text = String::from_utf8_lossy(&raw_line);
+        let line = strip_line_ending(&text);
+
+        // Lines of a binary patch body belong to the current section
+        let in_binary_patch = !self.in_preamble && self.binary_patch.consume(line);
+
+        if !in_binary_patch
+            && (is_diff_header(line)
+                || is_commit_line(line)
+                || self.starts_unified_section(line)?)
+        {
+            self.pending_header = Some(raw_line);
+            return Ok(None);
+        }
+
+        if self.unified && !self.in_git_section && !self.in_preamble {
+            self.track_hunk(line);
+        }
+
+        Ok(Some(raw_line))
+    }
+
+    /// Read a line from the stream; `None` once it is exhausted
+    fn read_raw_line(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let mut raw_line = Vec::new();
+        let read = self
+            .reader
+            .read_until(b'\n', &mut raw_line)
+            .map_err(|e| Error::processing_error(format!("Failed to read input data: {e}")))?;
+
+        if read == 0 {
+            self.finished = true;
+            return Ok(None);
+        }
+
+        Ok(Some(raw_line))
+    }
+
+    /// Check if `line` starts a plain unified diff section: a `diff -…` command line, or the
+    /// `---` line of a `---`/`+++` pair not preceded by one
+    ///
+    /// The line following a `---` line is read ahead and returned by the next read either way.
+    fn starts_unified_section(&mut self, line: &str) -> Result<bool> {
+        if !self.unified || self.in_git_section || self.hunk_lines != (0, 0) {
+            return Ok(false);
+        }
+
+        if line.starts_with("diff -") {
+            return Ok(true);
+        }
+
+        if !line.starts_with("--- ") {
+            return Ok(false);
+        }
+
+        // The pair following a `diff` command line belongs to its section
+        if self.awaiting_patch_lines {
+            self.awaiting_patch_lines = false;
+            return Ok(false);
+        }
+
+        self.lookahead = self.read_raw_line()?;
+        Ok(self.lookahead.as_ref().is_some_and(|next| {
+            strip_line_ending(&String::from_utf8_lossy(next)).starts_with("+++ ")
+        }))
+    }
+
+    /// Count the lines of the current hunk, or start a new one at a `@@` line
+    fn track_hunk(&mut self, line: &str) {
+        let (old, new) = &mut self.hunk_lines;
+        if (*old, *new) == (0, 0) {
+            if let Some(counts) = parse_hunk_counts(line) {
+                self.hunk_lines = counts;
+            }
+            return;
+        }
+
+        match line.as_bytes().first() {
+            // Context lines; tools may strip the space of empty ones
+            Some(b' ') | None => {
+                *old = old.saturating_sub(1);
+                *new = new.saturating_sub(1);
+            }
+            Some(b'-') => *old = old.saturating_sub(1),
+            Some(b'+') => *new = new.saturating_sub(1),
+            // `\ No newline at end of file`
+            _ => {}
+        }
+    }
+
+    /// Read the next section; `None` if the stream holds no further section
+    fn read_section(&mut self) -> Result<Option<DiffSection>> {
+        // Skip what the caller did not read of a section that was cut off
+        while self.read_rest_line()?.is_some() {}
+
+        let mut section = match self.pending_header.take() {
+            Some(raw_header) => DiffSection::from_header(raw_header, &self.prefixes),
+            None => DiffSection::preamble(),
+        };
+        self.binary_patch = BinaryPatch::Outside;
+        self.in_preamble = section.is_preamble();
+        self.in_git_section = is_diff_header(&section.header);
+        self.awaiting_patch_lines = !self.in_git_section && section.header.starts_with("diff -");
+        self.hunk_lines = (0, 0);
+        let mut in_extended_header = !section.is_preamble();
+
+        while let Some(raw_line) = self.read_section_line()? {
+            if in_extended_header {
+                let text = String::from_utf8_lossy(&raw_line);
+                let line = strip_line_ending(&text);
+                if is_extended_header_line(line) {
+                    section.record_extended_header(line);
+                } else {
+                    in_extended_header = false;
+                }
+            }
+
+            section.body.extend_from_slice(&raw_line);
+
+            if self
+                .max_section_bytes
+                .is_some_and(|limit| section.body.len() > limit)
+            {
+                section.truncated = true;
+                self.unread_rest = true;
+                break;
+            }
+        }
+
+        // An empty preamble is not a section of its own
+        if section.raw_header.is_empty() && section.body.is_empty() {
+            return match self.pending_header {
+                Some(_) => self.read_section(),
+                None => Ok(None),
+            };
+        }
+
+        if !section.is_preamble() {
+            section.resolve_ambiguous_header(&self.prefixes);
+        }
+
+        Ok(Some(section))
+    }
+}
+
+impl<R: BufRead> Iterator for DiffSections<R> {
+    type Item = Result<DiffSection>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished && self.pending_header.is_none() && self.lookahead.is_none() {
+            return None;
+        }
+
+        match self.read_section() {
+            Ok(section) => section.map(Ok),
+            Err(e) => {
+                self.finished = true;
+                self.pending_header = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Copy the sections of a diff unchanged, line by line, calling `on_section` with the
+/// path of each one
+///
+/// For diffs no filter can shorten: only the current line is held in memory, and only
+/// header lines and the extended header lines after them are decoded. Paths
+/// follow the header, rename and copy rules of [`DiffSections`], and `on_section` is
+/// called once a section is complete. Copying stops at the first header whose paths
+/// cannot be parsed from the header line; that line is returned unwritten, so the rest
+/// can be split into sections as usual. Write errors other than a broken pipe fail.
+pub(crate) fn copy_sections<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prefixes: &DiffPrefixes,
+    mut on_section: impl FnMut(&str),
+) -> Result<Option<Vec<u8>>> {
+    // Path of the section being copied, and whether its extended header is still read
+    let mut section: Option<(String, bool)> = None;
+    let mut binary_patch = BinaryPatch::Outside;
+    let mut raw_line = Vec::new();
+
+    loop {
+        raw_line.clear();
+        let read = reader
+            .read_until(b'\n', &mut raw_line)
+            .map_err(|e| Error::processing_error(format!("Failed to read input data: {e}")))?;
+        if read == 0 {
+            break;
+        }
+
+        // Only lines that may start a section, name its path or belong to a binary patch
+        // are decoded; everything else is copied as bytes
+        let in_binary_patch = section.is_some()
+            && (binary_patch != BinaryPatch::Outside || raw_line.starts_with(b"GIT binary patch"))
+            && binary_patch.consume(strip_line_ending(&String::from_utf8_lossy(&raw_line)));
+        let header = (!in_binary_patch && raw_line.starts_with(b"diff "))
+            .then(|| String::from_utf8_lossy(&raw_line))
+            .filter(|text| is_diff_header(strip_line_ending(text)));
+
+        if let Some(text) = header {
+            if let Some((path, _)) = section.take() {
+                on_section(&path);
+            }
+            let Some(header) = parse_diff_header_with_prefixes(strip_line_ending(&text), prefixes)
+            else {
+                return Ok(Some(raw_line));
+            };
+            section = Some((header.new_path, true));
+            binary_patch = BinaryPatch::Outside;
+        } else if let Some((path, in_extended_header @ true)) = &mut section {
+            let text = String::from_utf8_lossy(&raw_line);
+            let line = strip_line_ending(&text);
+            if is_extended_header_line(line) {
+                if let Some(to) = line
+                    .strip_prefix("rename to ")
+                    .or_else(|| line.strip_prefix("copy to "))
+                {
+                    *path = decode_path(to);
+                }
+            } else {
+                *in_extended_header = false;
+            }
+        }
+
+        match writer.write_all(&raw_line) {
+            // A closed reader is not an error; the remaining sections are still reported
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                return Err(Error::processing_error(format!(
+                    "Failed to write section: {e}"
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((path, _)) = section {
+        on_section(&path);
+    }
+    Ok(None)
+}
+
+/// Remove the line ending (`\n` or `\r\n`) from a raw line
+fn strip_line_ending(raw_line: &str) -> &str {
+    raw_line
+        .strip_suffix('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .unwrap_or(raw_line)
+}
+
+/// Old and new line counts of a `@@ -a[,b] +c[,d] @@` hunk header (counts default to 1)
+fn parse_hunk_counts(line: &str) -> Option<(usize, usize)> {
+    let ranges = line.strip_prefix("@@ -")?;
+    let (old, rest) = ranges.split_once(" +")?;
+    let (new, _) = rest.split_once(" @@")?;
+    let count = |range: &str| match range.split_once(',') {
+        Some((_, count)) => count.parse().ok(),
+        None => range.parse::<usize>().ok().map(|_| 1),
+    };
+
+    Some((count(old)?, count(new)?))
+}
+
+/// Check if a line starts the metadata of a commit in `git log -p` output
+///
+/// That is `commit` followed by a full or abbreviated object name, optionally followed
+/// by decorations such as `(HEAD -> main)`. Hunk lines start with a space, `+` or `-`,
+/// so such a line never occurs within a file section.
+fn is_commit_line(line: &str) -> bool {
+    line.strip_prefix("commit ")
+        .and_then(|rest| rest.split(' ').next())
+        .is_some_and(|sha| {
+            (4..=64).contains(&sha.len()) && sha.bytes().all(|byte| byte.is_ascii_hexdigit())
+        })
+}
+
+/// Check if a line belongs to the extended header block following `diff --git`
+fn is_extended_header_line(line: &str) -> bool {
+    EXTENDED_HEADER_PREFIXES
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// **What is tested:** Splitting a diff stream with a preamble, CRLF lines and a binary patch into sections
+    /// **Why it is tested:** Section boundaries decide which bytes are kept or dropped together
+    /// **Test conditions:** Preamble text, a CRLF section, and a binary patch whose base85 data contains a line looking like a header
+    /// **Expectations:** The preamble and two file sections are returned, and writing them all back reproduces the input byte for byte
+    #[test]
+    fn test_diff_sections_round_trip() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let diff_content = "From 1234567 Mon Sep 17 00:00:00 2001\n\
+                            \n\
+                            diff --git a/src/main.rs b/src/main.rs\r\n\
+                            index 1234567..abcdefg 100644\r\n\
+                            diff --git a/image.png b/image.png\n\
+                            GIT binary patch\n\
+                            literal 3\n\
+                            diff --git a/x b/x\n\
+                            \n\
+                            literal 0\n\
+                            \n";
+
+        let sections = DiffSections::new(diff_content.as_bytes(), DiffPrefixes::default())
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(sections.len(), 3);
+        assert!(sections[0].is_preamble());
+        assert_eq!(sections[0].header, "");
+        assert_eq!(sections[0].path(), None);
+        assert_eq!(sections[1].header, "diff --git a/src/main.rs b/src/main.rs");
+        assert_eq!(sections[1].body, b"index 1234567..abcdefg 100644\r\n");
+        assert_eq!(sections[2].path(), Some("image.png"));
+        assert!(sections[2].body.ends_with(b"literal 0\n\n"));
+
+        let mut output = Vec::new();
+        for section in &sections {
+            section.write_to(&mut output)?;
+        }
+        assert_eq!(output, diff_content.as_bytes());
+
+        assert_eq!(
+            DiffSections::new(&b""[..], DiffPrefixes::default()).count(),
+            0
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Boundaries of sections without `---`/`+++` lines or hunks
+    /// **Why it is tested:** A mode-only section must end at the next header, not be merged with the following file
+    /// **Test conditions:** A mode-only section between two content sections, followed by a `diff --cc` section and a mode-only section at the end of the stream without final newline; split with and without unified mode
+    /// **Expectations:** Five independent sections with their own paths; only the mode-only ones are detected as such, and writing them back reproduces the input
+    #[test]
+    fn test_mode_only_section_boundaries() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let before = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                      index 1234567..abcdefg 100644\n\
+                      --- a/src/lib.rs\n\
+                      +++ b/src/lib.rs\n\
+                      @@ -1 +1 @@\n\
+                      -old\n\
+                      +new\n";
+        let mode_only = "diff --git a/run.sh b/run.sh\n\
+                         old mode 100644\n\
+                         new mode 100755\n";
+        let after = "diff --git a/src/main.rs b/src/main.rs\n\
+                     index 1234567..abcdefg 100644\n\
+                     --- a/src/main.rs\n\
+                     +++ b/src/main.rs\n\
+                     @@ -1 +1 @@\n\
+                     -old\n\
+                     +new\n";
+        let combined = "diff --cc src/merged.rs\n\
+                        index 1234567,89abcde..fedcba9\n";
+        let last = "diff --git a/build.sh b/build.sh\n\
+                    old mode 100644\n\
+                    new mode 100755";
+        let input = format!("{before}{mode_only}{after}{combined}{last}");
+
+        for unified in [false, true] {
+            let sections = DiffSections::new(input.as_bytes(), DiffPrefixes::default())
+                .with_unified(unified)
+                .collect::<Result<Vec<_>>>()?;
+
+            let paths = sections.iter().map(DiffSection::path).collect::<Vec<_>>();
+            assert_eq!(
+                paths,
+                [
+                    Some("src/lib.rs"),
+                    Some("run.sh"),
+                    Some("src/main.rs"),
+                    Some("src/merged.rs"),
+                    Some("build.sh")
+                ]
+            );
+            let mode_only_flags = sections
+                .iter()
+                .map(DiffSection::is_mode_only)
+                .collect::<Vec<_>>();
+            assert_eq!(mode_only_flags, [false, true, false, false, true]);
+            assert_eq!(sections[1].body, b"old mode 100644\nnew mode 100755\n");
+
+            let mut output = Vec::new();
+            for section in &sections {
+                section.write_to(&mut output)?;
+            }
+            assert_eq!(output, input.as_bytes());
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Cutting sections off at the limit set with `with_max_section_bytes`
+    /// **Why it is tested:** The body of a huge section must not be accumulated, and its rest must stay readable
+    /// **Test conditions:** Limit of 30 bytes; a large section whose rest is read, a large section whose rest is skipped, and a small section
+    /// **Expectations:** Large sections are truncated after the line exceeding the limit, the rest comes from `read_rest_line`, and skipping it does not disturb the next section
+    #[test]
+    fn test_max_section_bytes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let large = "diff --git a/a.rs b/a.rs\n\
+                     rename from old.rs\n\
+                     rename to a.rs\n\
+                     @@ -1 +1 @@\n\
+                     +line 1\n\
+                     +line 2\n";
+        let skipped = "diff --git a/b.rs b/b.rs\n\
+                       index 1234567..abcdefg 100644\n\
+                       +line 1\n";
+        let small = "diff --git a/c.rs b/c.rs\n\
+                     +c\n";
+        let diff_content = format!("{large}{skipped}{small}");
+        let mut sections = DiffSections::new(diff_content.as_bytes(), DiffPrefixes::default())
+            .with_max_section_bytes(Some(30));
+
+        let first = sections.next().ok_or("missing section")??;
+        assert!(first.is_truncated());
+        assert_eq!(first.body, b"rename from old.rs\nrename to a.rs\n");
+        assert_eq!(first.old_path.as_deref(), Some("old.rs"));
+        let mut rest = Vec::new();
+        while let Some(raw_line) = sections.read_rest_line()? {
+            rest.extend_from_slice(&raw_line);
+        }
+        assert_eq!(rest, b"@@ -1 +1 @@\n+line 1\n+line 2\n");
+
+        let second = sections.next().ok_or("missing section")??;
+        assert!(second.is_truncated());
+        assert_eq!(second.path(), Some("b.rs"));
+
+        let third = sections.next().ok_or("missing section")??;
+        assert!(!third.is_truncated());
+        assert_eq!(third.body, b"+c\n");
+        assert!(sections.next().is_none());
+        Ok(())
+    }
+
+    /// **What is tested:** Paths of sections whose header is ambiguous, for added and deleted files
+    /// **Why it is tested:** The `---`/`+++` lines name `/dev/null` for the missing side, which must never become the section path
+    /// **Test conditions:** An added and a deleted file with headers naming only one path, a removed content line looking like a `---` line, and a regular section
+    /// **Expectations:** The added file takes the `+++` path, the deleted file the `---` path, and the regular section keeps its header paths
+    #[test]
+    fn test_ambiguous_header_uses_patch_lines(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let diff_content = "diff --git a/src/new.rs\n\
+                            new file mode 100644\n\
+                            --- /dev/null\n\
+                            +++ b/src/new.rs\n\
+                            @@ -0,0 +1 @@\n\
+                            +fn main() {}\n\
+                            diff --git a/gone.log\n\
+                            deleted file mode 100644\n\
+                            --- a/gone.log\n\
+                            +++ /dev/null\n\
+                            @@ -1,2 +0,0 @@\n\
+                            --- a/other\n\
+                            -+++ b/other\n\
+                            diff --git a/src/lib.rs b/src/lib.rs\n\
+                            --- a/src/lib.rs\n\
+                            +++ b/src/lib.rs\n";
+
+        let sections = DiffSections::new(diff_content.as_bytes(), DiffPrefixes::default())
+            .collect::<Result<Vec<_>>>()?;
+        let paths: Vec<_> = sections
+            .iter()
+            .map(|section| (section.old_path.as_deref(), section.path()))
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                (Some("src/new.rs"), Some("src/new.rs")),
+                (Some("gone.log"), Some("gone.log")),
+                (Some("src/lib.rs"), Some("src/lib.rs")),
+            ]
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Splitting plain unified diffs with `with_unified`
+    /// **Why it is tested:** `diff -u` output has no `diff --git` headers, and lines inside hunks or git sections must not be mistaken for boundaries
+    /// **Test conditions:** A preamble, a `diff -ruN` command line with its pair, a bare pair whose hunk removes `-- x` and adds `++ y`, and a git section with a `---`/`+++` pair
+    /// **Expectations:** Sections start at the command line, the bare pair and the git header only; paths come from the pairs; without unified mode only the git header splits
+    #[test]
+    fn test_unified_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let diff_content = "Only in old: notes\n\
+                            diff -ruN old/debug.log new/debug.log\n\
+                            --- old/debug.log\t2024-01-01 00:00:00.000000000 +0000\n\
+                            +++ new/debug.log\t2024-01-01 00:00:01.000000000 +0000\n\
+                            @@ -1 +1 @@\n\
+                            -x\n\
+                            +y\n\
+                            --- a/src/main.rs\n\
+                            +++ b/src/main.rs\n\
+                            @@ -1,3 +1,3 @@\n \
+                            a\n\
+                            --- x\n\
+                            +++ y\n \
+                            c\n\
+                            diff --git a/lib.rs b/lib.rs\n\
+                            --- a/lib.rs\n\
+                            +++ b/lib.rs\n";
+        let prefixes = DiffPrefixes::new("old/", "new/");
+
+        let sections = DiffSections::new(diff_content.as_bytes(), prefixes.clone())
+            .with_unified(true)
+            .collect::<Result<Vec<_>>>()?;
+        let headers: Vec<&str> = sections
+            .iter()
+            .map(|section| section.header.as_str())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "",
+                "diff -ruN old/debug.log new/debug.log",
+                "--- a/src/main.rs",
+                "diff --git a/lib.rs b/lib.rs"
+            ]
+        );
+        assert_eq!(sections[1].path(), Some("debug.log"));
+        assert_eq!(sections[2].path(), Some("b/src/main.rs"));
+        assert!(sections[2].body.ends_with(b"+++ y\n c\n"));
+
+        let mut output = Vec::new();
+        for section in &sections {
+            section.write_to(&mut output)?;
+        }
+        assert_eq!(output, diff_content.as_bytes());
+
+        let sections =
+            DiffSections::new(diff_content.as_bytes(), prefixes).collect::<Result<Vec<_>>>()?;
+        assert_eq!(sections.len(), 2);
+        Ok(())
+    }
+
+    /// **What is tested:** Splitting `git log -p` output at `commit <sha>` lines
+    /// **Why it is tested:** Commit metadata must form sections of its own instead of trailing the last file section of the previous commit
+    /// **Test conditions:** Two commits, the first with a decorated and the second with an abbreviated object name; a commit message line looking like a header, and lines that only resemble commit lines
+    /// **Expectations:** Each commit line starts a preamble section without paths holding the metadata, file sections end before the next commit, and writing all sections back reproduces the input
+    #[test]
+    fn test_git_log_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let diff_content = "commit 3f9c2a7e5b1d4c8a9e0f6b2d7c1a5e3f8b4d9c6a (HEAD -> main)\n\
+                            Author: Jane Doe <jane@example.com>\n\
+                            \n    \
+                            commit message\n    \
+                            diff --git a/x b/x\n\
+                            \n\
+                            diff --git a/debug.log b/debug.log\n\
+                            +commit 1234567\n\
+                            diff --git a/src/main.rs b/src/main.rs\n\
+                            +x\n\
+                            commit 8e1b4d2\n\
+                            Merge: 1234567 89abcde\n\
+                            commit message follows\n";
+
+        let sections = DiffSections::new(diff_content.as_bytes(), DiffPrefixes::default())
+            .collect::<Result<Vec<_>>>()?;
+        let summary: Vec<_> = sections
+            .iter()
+            .map(|section| (section.is_preamble(), section.path()))
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                (true, None),
+                (false, Some("debug.log")),
+                (false, Some("src/main.rs")),
+                (true, None),
+            ]
+        );
+        assert_eq!(
+            sections[0].header,
+            "commit 3f9c2a7e5b1d4c8a9e0f6b2d7c1a5e3f8b4d9c6a (HEAD -> main)"
+        );
+        assert_eq!(sections[1].body, b"+commit 1234567\n");
+        assert_eq!(sections[2].body, b"+x\n");
+        assert_eq!(sections[3].header, "commit 8e1b4d2");
+        assert_eq!(
+            sections[3].body,
+            b"Merge: 1234567 89abcde\ncommit message follows\n"
+        );
+
+        let mut output = Vec::new();
+        for section in &sections {
+            section.write_to(&mut output)?;
+        }
+        assert_eq!(output, diff_content.as_bytes());
+        Ok(())
+    }
+}
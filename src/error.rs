@@ -16,10 +16,29 @@ pub enum Error {
     Processing(String),
     /// Configuration errors
     Config(crate::config::ConfigError),
-    /// Downstream command spawn failed
-    DownstreamSpawnFailed(String),
-    /// Downstream process failed
-    DownstreamProcessFailed(String),
+    /// Downstream command could not be started (not found, not executable or unparsable)
+    DownstreamSpawnFailed {
+        /// Downstream command as configured
+        command: String,
+        /// Error reported when starting the command
+        source: std::io::Error,
+    },
+    /// Downstream process exited with a non-zero status
+    DownstreamExitNonZero {
+        /// Downstream command as configured
+        command: String,
+        /// Exit code, or None if the process was terminated by a signal
+        code: Option<i32>,
+        /// Position of the command in a pipeline as (stage, stages), if there are several
+        stage: Option<(usize, usize)>,
+    },
+    /// Writing the filtered diff to the downstream process failed
+    DownstreamWriteFailed {
+        /// Error reported by the write
+        source: std::io::Error,
+    },
+    /// Downstream process did not finish before the deadline and was killed
+    DownstreamTimeout(String),
 }
 
 impl Error {
@@ -28,6 +47,14 @@ impl Error {
         Error::Processing(message)
     }
 
+    /// Check if this is a write to a reader that exited early (`BrokenPipe`)
+    ///
+    /// The CLI treats such an error on stdout as normal early termination, e.g. when
+    /// its output is piped to `head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Error::Io(err) if err.kind() == std::io::ErrorKind::BrokenPipe)
+    }
+
     /// Chain this error with a function for functional composition
     ///
     /// This allows for functional error handling patterns where errors can be
@@ -60,8 +87,7 @@ impl Error {
     {
         match self {
             Error::Processing(msg) => Error::Processing(f(msg)),
-            Error::DownstreamSpawnFailed(msg) => Error::DownstreamSpawnFailed(f(msg)),
-            Error::DownstreamProcessFailed(msg) => Error::DownstreamProcessFailed(f(msg)),
+            Error::DownstreamTimeout(msg) => Error::DownstreamTimeout(f(msg)),
             other => other,
         }
     }
@@ -118,8 +144,45 @@ impl fmt::Display for Error {
             Error::Io(err) => write!(f, "IO error: {err}"),
             Error::Processing(msg) => write!(f, "Processing error: {msg}"),
             Error::Config(err) => write!(f, "Configuration error: {err}"),
-            Error::DownstreamSpawnFailed(msg) => write!(f, "DownstreamSpawnFailed: {msg}"),
-            Error::DownstreamProcessFailed(msg) => write!(f, "DownstreamProcessFailed: {msg}"),
+            // Reported like `sh -c` does for an unknown command (exit code 127)
+            Error::DownstreamSpawnFailed { command, source }
+                if source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                write!(
+                    f,
+                    "DownstreamProcessFailed: Downstream command '{command}' not found: {source}"
+                )
+            }
+            Error::DownstreamSpawnFailed { command, source }
+                if source.kind() == std::io::ErrorKind::InvalidInput =>
+            {
+                write!(
+                    f,
+                    "DownstreamSpawnFailed: Failed to parse downstream command '{command}': {source}"
+                )
+            }
+            Error::DownstreamSpawnFailed { command, source } => write!(
+                f,
+                "DownstreamSpawnFailed: Failed to spawn downstream command '{command}': {source}"
+            ),
+            Error::DownstreamExitNonZero {
+                command,
+                code,
+                stage,
+            } => {
+                let stage = stage
+                    .map(|(stage, stages)| format!(" (stage {stage} of {stages})"))
+                    .unwrap_or_default();
+                write!(
+                    f,
+                    "DownstreamProcessFailed: Downstream command '{command}'{stage} failed with exit code: {code:?}"
+                )
+            }
+            Error::DownstreamWriteFailed { source } => write!(
+                f,
+                "DownstreamWriteFailed: Failed to write to downstream command: {source}"
+            ),
+            Error::DownstreamTimeout(msg) => write!(f, "DownstreamTimeout: {msg}"),
         }
     }
 }
@@ -130,8 +193,10 @@ impl std::error::Error for Error {
             Error::Io(err) => Some(err),
             Error::Processing(_) => None,
             Error::Config(err) => Some(err),
-            Error::DownstreamSpawnFailed(_) => None,
-            Error::DownstreamProcessFailed(_) => None,
+            Error::DownstreamSpawnFailed { source, .. } => Some(source),
+            Error::DownstreamExitNonZero { .. } => None,
+            Error::DownstreamWriteFailed { source } => Some(source),
+            Error::DownstreamTimeout(_) => None,
         }
     }
 }
@@ -247,6 +312,44 @@ mod tests {
         assert!(format!("{config_error}").contains("Configuration error"));
     }
 
+    /// **What is tested:** Display and source of the structured downstream error variants
+    /// **Why it is tested:** The CLI prints these messages, which must stay as they were before the variants were split
+    /// **Test conditions:** A command not found, a failing pipeline stage and a failed write to the downstream command
+    /// **Expectations:** Messages keep the `DownstreamProcessFailed` wording where it was used before, and I/O causes are exposed as source
+    #[test]
+    fn test_downstream_error_display() {
+        use std::error::Error as StdError;
+
+        let not_found = Error::DownstreamSpawnFailed {
+            command: "delta".to_string(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"),
+        };
+        assert_eq!(
+            not_found.to_string(),
+            "DownstreamProcessFailed: Downstream command 'delta' not found: no such file"
+        );
+        assert!(StdError::source(&not_found).is_some());
+
+        let exit_non_zero = Error::DownstreamExitNonZero {
+            command: "false".to_string(),
+            code: Some(1),
+            stage: Some((2, 3)),
+        };
+        assert_eq!(
+            exit_non_zero.to_string(),
+            "DownstreamProcessFailed: Downstream command 'false' (stage 2 of 3) failed with exit code: Some(1)"
+        );
+        assert!(StdError::source(&exit_non_zero).is_none());
+
+        let write_failed = Error::DownstreamWriteFailed {
+            source: std::io::Error::other("device full"),
+        };
+        assert!(write_failed
+            .to_string()
+            .starts_with("DownstreamWriteFailed"));
+        assert!(StdError::source(&write_failed).is_some());
+    }
+
     /// **What is tested:** Conversion from std::io::Error to application Error type
     /// **Why it is tested:** Verifies that the From trait implementation correctly wraps IO errors in the application's error type
     /// **Test conditions:** Creates a std::io::Error with PermissionDenied kind and converts it using From trait
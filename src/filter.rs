@@ -3,22 +3,237 @@
 //! This module provides the main filtering functionality that respects .gitignore patterns
 //! and supports VCS pattern filtering with optional downstream processing.
 
+use crate::diagnostics::diag;
+use crate::diff::section::copy_sections;
+use crate::diff::{DiffPrefixes, DiffSection, DiffSections, StripAnsi};
 use crate::error::{Error, Result};
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use std::io::{BufRead, Write};
-use std::path::Path;
-use std::process::{Command, Stdio};
+use crate::matcher::{self, Gitignore, GitignoreBuilder, Glob, Match};
+use crate::report::{FilterDecision, FilterReport, SectionDecision, SectionInfo};
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// Interval for polling downstream processes while a timeout is active
+const DOWNSTREAM_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Number of writes buffered between the filtering thread and the downstream writer
+const DOWNSTREAM_CHANNEL_CAPACITY: usize = 64;
+
+/// Writer adapter counting the bytes written through it
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer to the first downstream stage, keeping the first write error for reporting
+///
+/// A broken pipe is not kept: a stage that stops reading early (like `head`) is not a
+/// failure of its own.
+struct DownstreamStdin<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W> DownstreamStdin<W> {
+    /// Keep a copy of the first write error that is not a broken pipe or an interruption
+    fn record(&mut self, error: io::Error) -> io::Error {
+        if self.error.is_none()
+            && !matches!(
+                error.kind(),
+                io::ErrorKind::BrokenPipe | io::ErrorKind::Interrupted
+            )
+        {
+            self.error = Some(io::Error::new(error.kind(), error.to_string()));
+        }
+        error
+    }
+
+    /// Close the writer and report a write error as [`Error::DownstreamWriteFailed`]
+    /// instead of the processing result
+    fn finish<T>(self, result: Result<T>) -> Result<T> {
+        match (result, self.error) {
+            (_, Some(source)) => Err(Error::DownstreamWriteFailed { source }),
+            (result, None) => result,
+        }
+    }
+}
+
+impl<W: Write> Write for DownstreamStdin<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).map_err(|e| self.record(e))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().map_err(|e| self.record(e))
+    }
+}
+
+/// Writer handing each write to another thread through a bounded channel
+///
+/// Writes block while the channel is full and fail with a broken pipe once the
+/// receiving thread has stopped, e.g. because the downstream command exited.
+struct ChannelWriter {
+    sender: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.sender.send(buf.to_vec()).map_err(|_| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "downstream writer stopped")
+            })?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Callback invoked with each decided section, see [`Filter::on_section`]
+type SectionCallback = Box<dyn FnMut(&SectionInfo<'_>) + Send>;
+
+/// Output written by [`Filter::process_diff`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The filtered diff, byte for byte
+    #[default]
+    Diff,
+    /// A JSON array describing every section and the decision made for it
+    Json,
+}
+
+/// What happens to a file section whose path cannot be determined from its header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnparsableAction {
+    /// Keep the section, as if no pattern matched it
+    #[default]
+    Keep,
+    /// Drop the section, recorded as [`FilterDecision::Unparsable`]
+    Drop,
+    /// Fail processing with an error naming the header
+    Error,
+}
 
 /// Main filter for processing Git diffs
 pub struct Filter {
-    /// Gitignore patterns for filtering
-    gitignore: Option<Gitignore>,
+    /// Root directory the diff paths are relative to
+    root: PathBuf,
+    /// Directory the .gitignore is read from and its patterns are anchored at
+    gitignore_root: PathBuf,
+    /// Directory of `git diff --relative` output below `root`, set with `with_path_prefix`
+    path_prefix: Option<String>,
+    /// Path of `root` below `gitignore_root` joined with `path_prefix`, prepended to diff
+    /// paths for gitignore matching
+    gitignore_path_prefix: Option<String>,
+    /// Whether .gitignore patterns match case-insensitively
+    case_insensitive: bool,
+    /// Gitignore patterns for filtering, shareable between filters
+    gitignore: Option<Arc<Gitignore>>,
+    /// Whether .gitignore files in subdirectories are read as well
+    nested_gitignore: bool,
+    /// Matchers of the .gitignore files in subdirectories, keyed by their directory
+    /// relative to `gitignore_root`, parents before children
+    nested_gitignores: Vec<(PathBuf, Gitignore)>,
+    /// Whether .gitignore files added or modified by the diff apply to the sections after them
+    apply_diff_gitignore: bool,
+    /// Whether a deletion and an addition of the same content are decided together
+    pair_renames: bool,
+    /// Problems found while reading the .gitignore, e.g. lines with invalid patterns
+    gitignore_warnings: Vec<String>,
+    /// In-memory content used instead of the root .gitignore file
+    gitignore_content: Option<String>,
+    /// File read instead of the root .gitignore file (None = `.gitignore` in `gitignore_root`)
+    gitignore_file: Option<PathBuf>,
+    /// Global ignore file whose patterns precede the root .gitignore's (`core.excludesFile`)
+    global_excludes_file: Option<PathBuf>,
+    /// Ignore patterns added after the root .gitignore's own patterns
+    extra_ignore_patterns: Vec<String>,
+    /// File with review-only ignore patterns (None = `.diffignore` in `gitignore_root`)
+    diffignore_file: Option<PathBuf>,
+    /// Matcher of the .diffignore patterns, anchored at `gitignore_root`
+    diffignore: Option<Gitignore>,
+    /// File with patterns of paths that are always kept
+    force_include_file: Option<PathBuf>,
+    /// Matcher of the force-include patterns, anchored at `gitignore_root`
+    force_include: Option<Gitignore>,
     /// VCS patterns for filtering VCS-related files
     vcs_patterns: Vec<String>,
+    /// VCS patterns compiled into a single matcher, built once in `with_vcs_patterns`
+    vcs_matcher: Option<Gitignore>,
     /// Whether VCS filtering is enabled (true = filter out VCS files, false = include VCS files)
     vcs_filtering_enabled: bool,
-    /// Optional downstream command for piping output
-    downstream_command: Option<String>,
+    /// Downstream commands for piping output, each stage feeding the next
+    downstream_pipeline: Vec<String>,
+    /// Whether downstream commands are run through `sh -c` instead of being spawned directly
+    downstream_shell: bool,
+    /// Deadline for writing to and waiting on the downstream pipeline (None = no limit)
+    downstream_timeout: Option<Duration>,
+    /// Number of times a downstream pipeline that failed to spawn or exited non-zero is re-run
+    downstream_retries: u32,
+    /// Filtered diffs with more lines than this bypass the downstream pipeline (None = no limit)
+    downstream_max_lines: Option<usize>,
+    /// Working directory of the downstream commands (None = inherited)
+    downstream_cwd: Option<PathBuf>,
+    /// Environment variables set for the downstream commands, in addition to inherited ones
+    downstream_env: Vec<(String, String)>,
+    /// Optional leading path prefix removed from diff paths before matching
+    strip_path_prefix: Option<String>,
+    /// Path prefixes of the `diff --git` headers
+    diff_prefixes: DiffPrefixes,
+    /// Whether `---`/`+++` pairs without a `diff --git` header start sections
+    unified: bool,
+    /// Whether ANSI color sequences are removed from the input before parsing
+    strip_ansi: bool,
+    /// Whether decision lines are written instead of the filtered diff
+    dry_run: bool,
+    /// Whether decision lines are terminated with NUL instead of newline
+    null_terminated: bool,
+    /// Whether dropped sections are replaced by their header and a comment line
+    keep_empty_diff_headers: bool,
+    /// Whether kept sections are preceded by a comment line saying why they were kept
+    annotate: bool,
+    /// Whether sections that only change the file mode are dropped
+    drop_mode_only: bool,
+    /// Whether only sections matching the VCS patterns are kept
+    only_vcs: bool,
+    /// Whether sections deleting a file are dropped
+    drop_deletions: bool,
+    /// File extensions that are kept, without leading dot (empty = all extensions)
+    only_extensions: Vec<String>,
+    /// File extensions that are dropped, without leading dot
+    excluded_extensions: Vec<String>,
+    /// Format of the output written for the processed diff
+    output_format: OutputFormat,
+    /// What happens to sections whose path cannot be determined
+    on_unparsable: UnparsableAction,
+    /// Number of threads deciding about sections (1 = decide while reading)
+    parallelism: usize,
+    /// Body size after which a section is streamed instead of buffered (None = no limit)
+    max_section_bytes: Option<usize>,
+    /// Callback invoked with each decided section, in section order
+    section_callback: Option<Mutex<SectionCallback>>,
 }
 
 impl Filter {
@@ -27,434 +242,4880 @@ impl Filter {
         // Log the root directory received from RootFinder
 
         // Build gitignore patterns
-        let gitignore = Self::build_gitignore(root.as_ref())?;
+        let (gitignore, gitignore_warnings) =
+            Self::build_gitignore(root.as_ref(), None, None, false, &[])?;
+
+        let mut filter =
+            Self::with_gitignore(root.as_ref(), gitignore.map(Arc::new), gitignore_warnings);
+        filter.load_diffignore()?;
+        Ok(filter)
+    }
 
-        Ok(Filter {
+    /// Create a filter for the given root directory from an already built .gitignore matcher
+    ///
+    /// The .gitignore is not read from disk, so callers processing many diffs of the same
+    /// repository can parse it once and share the matcher between filters. The matcher's
+    /// patterns are taken relative to `root`. A `.diffignore` in `root` is still read, as
+    /// by [`Filter::new`]. Options that change how the .gitignore is
+    /// read ([`Filter::with_case_insensitive`], [`Filter::with_nested_gitignore`],
+    /// [`Filter::with_gitignore_root`], [`Filter::with_diffignore`],
+    /// [`Filter::with_extra_ignore_patterns`]) read it from disk again and replace the matcher,
+    /// as does [`Filter::with_gitignore_str`].
+    ///
+    /// ```rust,no_run
+    /// use diff_gitignore_filter::Filter;
+    /// use diff_gitignore_filter::matcher::Gitignore;
+    /// use std::sync::Arc;
+    ///
+    /// let (matcher, _) = Gitignore::new("repo/.gitignore");
+    /// let matcher = Arc::new(matcher);
+    /// for diff in ["diff --git a/debug.log b/debug.log\n"] {
+    ///     let filter = Filter::from_matcher("repo", Arc::clone(&matcher));
+    ///     let output = filter.filter_bytes(diff.as_bytes())?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_matcher<P: AsRef<Path>>(root: P, matcher: Arc<Gitignore>) -> Self {
+        let mut filter = Self::with_gitignore(root.as_ref(), Some(matcher), Vec::new());
+        // Without a configured file, reading `.diffignore` can only fail to set up
+        // case-sensitive matching; the filter then goes without it
+        if let Err(e) = filter.load_diffignore() {
+            filter
+                .gitignore_warnings
+                .push(format!("Ignoring .diffignore patterns: {e}"));
+        }
+        filter
+    }
+
+    /// Create a filter with default options around the given .gitignore matcher
+    fn with_gitignore(
+        root: &Path,
+        gitignore: Option<Arc<Gitignore>>,
+        gitignore_warnings: Vec<String>,
+    ) -> Self {
+        Filter {
+            root: root.to_path_buf(),
+            gitignore_root: root.to_path_buf(),
+            path_prefix: None,
+            gitignore_path_prefix: None,
+            case_insensitive: false,
             gitignore,
+            nested_gitignore: false,
+            nested_gitignores: Vec::new(),
+            apply_diff_gitignore: false,
+            pair_renames: false,
+            gitignore_warnings,
+            gitignore_content: None,
+            gitignore_file: None,
+            global_excludes_file: None,
+            extra_ignore_patterns: Vec::new(),
+            diffignore_file: None,
+            diffignore: None,
+            force_include_file: None,
+            force_include: None,
             vcs_patterns: Vec::new(),
+            vcs_matcher: None,
             vcs_filtering_enabled: false, // Default: VCS filtering disabled (include VCS files)
-            downstream_command: None,
-        })
+            downstream_pipeline: Vec::new(),
+            downstream_shell: false,
+            downstream_timeout: None,
+            downstream_retries: 0,
+            downstream_max_lines: None,
+            downstream_cwd: None,
+            downstream_env: Vec::new(),
+            strip_path_prefix: None,
+            diff_prefixes: DiffPrefixes::default(),
+            unified: false,
+            strip_ansi: false,
+            dry_run: false,
+            null_terminated: false,
+            keep_empty_diff_headers: false,
+            annotate: false,
+            drop_mode_only: false,
+            only_vcs: false,
+            drop_deletions: false,
+            only_extensions: Vec::new(),
+            excluded_extensions: Vec::new(),
+            output_format: OutputFormat::Diff,
+            on_unparsable: UnparsableAction::Keep,
+            parallelism: 1,
+            max_section_bytes: None,
+            section_callback: None,
+        }
     }
 
-    /// Add VCS patterns for filtering and enable VCS filtering
+    /// Set VCS patterns for filtering
+    ///
+    /// The patterns only take effect once VCS filtering is enabled with
+    /// [`Filter::with_vcs_enabled`].
     pub fn with_vcs_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.vcs_matcher = Self::build_vcs_matcher(&self.root, &patterns);
         self.vcs_patterns = patterns;
-        self.vcs_filtering_enabled = true; // Enable VCS filtering when patterns are provided
+        self
+    }
+
+    /// Enable or disable VCS filtering (true = filter out VCS files, false = include VCS files)
+    pub fn with_vcs_enabled(mut self, enabled: bool) -> Self {
+        self.vcs_filtering_enabled = enabled;
         self
     }
 
     /// Add downstream command for piping output
-    pub fn with_downstream(mut self, command: String) -> Self {
-        self.downstream_command = Some(command);
+    ///
+    /// Shorthand for a single-stage [`Filter::with_downstream_pipeline`].
+    pub fn with_downstream(self, command: String) -> Self {
+        self.with_downstream_pipeline(vec![command])
+    }
+
+    /// Add downstream command given as already split program and arguments
+    ///
+    /// The arguments are quoted, so they reach the program unchanged.
+    pub fn with_downstream_argv(self, argv: Vec<String>) -> Self {
+        self.with_downstream(shell_words::join(argv))
+    }
+
+    /// Add a pipeline of downstream commands for piping output
+    ///
+    /// The filtered diff is written to the first stage; the stdout of each stage is
+    /// connected to the stdin of the next, and the last stage writes to our stdout.
+    /// Kept sections reach the first stage as soon as they are decided, while the
+    /// rest of the input is still being read.
+    /// Each command is split into program and arguments with shell quoting rules and
+    /// spawned directly, unless [`Filter::with_downstream_shell`] is enabled.
+    pub fn with_downstream_pipeline(mut self, pipeline: Vec<String>) -> Self {
+        self.downstream_pipeline = pipeline;
         self
     }
 
-    /// Build gitignore patterns from the repository
-    fn build_gitignore(root: &Path) -> Result<Option<Gitignore>> {
-        // Log the path where we search for .gitignore
+    /// Run downstream commands through `sh -c`, allowing pipes, redirections and expansions
+    pub fn with_downstream_shell(mut self, enabled: bool) -> Self {
+        self.downstream_shell = enabled;
+        self
+    }
 
-        let mut builder = GitignoreBuilder::new(root);
+    /// Kill the downstream pipeline if it has not finished after the given time
+    ///
+    /// The deadline covers writing the filtered diff as well as waiting for the stages
+    /// to exit. A timeout of zero disables the deadline.
+    pub fn with_downstream_timeout(mut self, timeout: Duration) -> Self {
+        self.downstream_timeout = (!timeout.is_zero()).then_some(timeout);
+        self
+    }
 
-        // Add .gitignore file if it exists
-        let gitignore_path = root.join(".gitignore");
-        if gitignore_path.exists() {
-            builder.add(&gitignore_path);
-        }
+    /// Re-run the downstream pipeline up to `retries` times when a stage cannot be
+    /// spawned or exits with a non-zero status
+    ///
+    /// With retries the diff is filtered once into memory and the filtered diff is
+    /// replayed to every attempt, so each section is decided and reported once. Output a
+    /// failed attempt already wrote is not taken back, and a
+    /// pipeline killed by [`Filter::with_downstream_timeout`] is not re-run.
+    pub fn with_downstream_retries(mut self, retries: u32) -> Self {
+        self.downstream_retries = retries;
+        self
+    }
 
-        match builder.build() {
-            Ok(gitignore) => Ok(Some(gitignore)),
-            Err(_e) => {
-                // If gitignore building fails, continue without it
-                Ok(None)
-            }
-        }
+    /// Write the filtered diff directly instead of piping it to the downstream pipeline
+    /// when it has more than `lines` lines
+    ///
+    /// Lets a pager or highlighter handle ordinary diffs while huge ones are written
+    /// plainly. Counting the lines needs the whole filtered diff, so it is held in memory
+    /// before the pipeline is started. A limit of zero disables the check.
+    pub fn with_downstream_max_lines(mut self, lines: usize) -> Self {
+        self.downstream_max_lines = (lines > 0).then_some(lines);
+        self
     }
 
-    /// Process a diff stream and filter it according to patterns
-    pub fn process_diff<R: BufRead, W: Write>(&self, reader: R, writer: W) -> Result<()> {
-        // Process as UTF-8 text data directly without binary detection
-        // The binary detection was causing issues with BufReader state
-        if let Some(ref command) = self.downstream_command {
-            self.process_with_downstream(reader, command)
-        } else {
-            self.process_direct(reader, writer)
-        }
+    /// Run the downstream commands in `dir` instead of the current directory
+    ///
+    /// Lets tools such as `delta` find their configuration when the filter itself runs
+    /// in a different directory.
+    pub fn with_downstream_cwd(mut self, dir: PathBuf) -> Self {
+        self.downstream_cwd = Some(dir);
+        self
     }
 
-    /// Process diff directly to the writer with streaming optimization
-    fn process_direct<R: BufRead, W: Write>(&self, mut reader: R, mut writer: W) -> Result<()> {
-        // Read all data as bytes first, then process as UTF-8
-        let mut all_data = Vec::new();
-        reader
-            .read_to_end(&mut all_data)
-            .map_err(|e| Error::processing_error(format!("Failed to read input data: {e}")))?;
-
-        // Convert to UTF-8 string with lossy conversion for robustness
-        let content = String::from_utf8_lossy(&all_data);
-
-        // First pass: Check if any sections should be filtered out by VCS patterns
-        let mut has_vcs_filtered_content = false;
-        if self.vcs_filtering_enabled {
-            for line in content.lines() {
-                if line.starts_with("diff --git") {
-                    if let Some(file_path) = self.extract_file_path(line) {
-                        if self.is_vcs_file(&file_path) {
-                            has_vcs_filtered_content = true;
-                            break;
-                        }
-                    }
-                }
-            }
-        }
+    /// Set environment variables for the downstream commands
+    ///
+    /// The variables are added to the inherited environment, overriding variables of
+    /// the same name.
+    pub fn with_downstream_env(mut self, vars: Vec<(String, String)>) -> Self {
+        self.downstream_env = vars;
+        self
+    }
 
-        // Only check for binary content if we don't have VCS content to filter
-        // This ensures VCS filtering takes precedence over binary content preservation
-        if !has_vcs_filtered_content {
-            let is_binary = self.is_git_diff_with_binary_content(&all_data);
+    /// Strip a leading path prefix (e.g. a submodule directory) from diff paths before matching
+    ///
+    /// The prefix is matched on whole path components; paths outside the prefix are
+    /// matched unchanged.
+    pub fn with_strip_path_prefix(mut self, prefix: String) -> Self {
+        self.strip_path_prefix = Some(prefix.trim_matches('/').to_string());
+        self
+    }
 
-            if is_binary {
-                // For Git diffs with binary content, pass through unchanged
-                match writer.write_all(&all_data) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        // Check if this is a broken pipe error - this should be handled gracefully
-                        if e.kind() == std::io::ErrorKind::BrokenPipe {
-                            // For broken pipe, we should exit gracefully rather than treating it as an error
-                            return Ok(());
-                        }
-                        return Err(Error::processing_error(format!(
-                            "Failed to write binary data: {e}"
-                        )));
-                    }
-                }
-                return Ok(());
-            }
-        }
+    /// Set the path prefixes of the `diff --git` headers (`a/` and `b/` by default)
+    pub fn with_diff_prefixes(mut self, prefixes: DiffPrefixes) -> Self {
+        self.diff_prefixes = prefixes;
+        self
+    }
 
-        // Functional state management using Option and closures
-        let mut current_section_state: Option<(Vec<String>, bool)> = None;
+    /// Also split plain unified diffs (e.g. from `diff -u`) into file sections
+    ///
+    /// A `---` line directly followed by a `+++` line then starts a section unless it is
+    /// part of a `diff --git` section or a hunk. Paths are taken from these lines with the
+    /// prefixes of [`Filter::with_diff_prefixes`] stripped. Without this option such
+    /// input has no sections and is passed through unchanged.
+    pub fn with_unified(mut self, enabled: bool) -> Self {
+        self.unified = enabled;
+        self
+    }
 
-        // Use a smaller buffer size for better memory efficiency
-        const MAX_BUFFER_SIZE: usize = 1024; // Limit buffer to 1KB of lines
+    /// Remove ANSI color sequences (SGR, e.g. from `git diff --color`) from the input
+    ///
+    /// The sequences would otherwise hide the `diff --git` headers. The stripped input is
+    /// what gets filtered and written, so kept sections are written without colors. A
+    /// diff without color sequences is processed unchanged.
+    pub fn with_strip_ansi(mut self, enabled: bool) -> Self {
+        self.strip_ansi = enabled;
+        self
+    }
 
-        // Helper closure for flushing buffer functionally
-        let flush_buffer = |writer: &mut W, buffer: &[String]| -> Result<()> {
-            for line in buffer {
-                match writeln!(writer, "{line}") {
-                    Ok(()) => {}
-                    Err(e) => {
-                        // Check for broken pipe in line writing as well
-                        if e.kind() == std::io::ErrorKind::BrokenPipe {
-                            return Ok(()); // Convert broken pipe to success
-                        }
-                        return Err(Error::processing_error(format!(
-                            "Failed to write line: {e}"
-                        )));
-                    }
-                }
-            }
-            Ok(())
-        };
+    /// Write one decision line per section (e.g. `DROP debug.log (gitignore)`) instead of
+    /// the filtered diff; downstream commands are not run
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
 
-        // Process lines using functional iterator with try_for_each
-        content.lines().try_for_each(|line| -> Result<()> {
-            if line.starts_with("diff --git") {
-                // Flush previous section if it should be included
-                if let Some((ref buffer, should_include)) = current_section_state {
-                    if should_include {
-                        flush_buffer(&mut writer, buffer)?;
-                    }
-                }
+    /// Replace dropped sections by their `diff --git` line and a `# filtered by <reason>` line
+    ///
+    /// For downstream tools that expect a header for every file of the diff; by default
+    /// dropped sections are removed entirely.
+    pub fn with_keep_empty_diff_headers(mut self, enabled: bool) -> Self {
+        self.keep_empty_diff_headers = enabled;
+        self
+    }
 
-                // Start new section using functional approach
-                let file_path = self.extract_file_path(line);
-                let should_include = file_path
-                    .as_ref()
-                    .map(|path| self.should_include_file(path))
-                    .unwrap_or(false);
+    /// Precede each kept section with a comment line saying why it was kept
+    ///
+    /// For example `# kept: no matching ignore rule` or `# kept: re-included by !keep.log`.
+    /// Meant for debugging pattern interactions; the annotated output cannot be applied
+    /// with `git apply`.
+    pub fn with_annotate(mut self, enabled: bool) -> Self {
+        self.annotate = enabled;
+        self
+    }
+
+    /// Drop sections that only change the file mode (`old mode`/`new mode` without content)
+    ///
+    /// Applies to sections kept by the gitignore and VCS patterns.
+    pub fn with_drop_mode_only(mut self, enabled: bool) -> Self {
+        self.drop_mode_only = enabled;
+        self
+    }
 
-                current_section_state = Some((vec![line.to_string()], should_include));
-            } else if let Some((ref mut buffer, should_include)) = current_section_state {
-                buffer.push(line.to_string());
+    /// Keep only the sections matching the VCS patterns and drop all others
+    ///
+    /// Inverts the VCS filter, e.g. to extract the changes to `.git/` hooks from a diff.
+    /// The VCS patterns apply whether or not VCS filtering is enabled; .gitignore,
+    /// .diffignore and extension filters are not applied. Other sections are dropped
+    /// with [`FilterDecision::FilteredByOnlyVcs`].
+    pub fn with_only_vcs(mut self, enabled: bool) -> Self {
+        self.only_vcs = enabled;
+        self
+    }
 
-                // Flush buffer periodically using functional approach
-                if should_include && buffer.len() >= MAX_BUFFER_SIZE {
-                    flush_buffer(&mut writer, buffer)?;
-                    buffer.clear();
-                }
-            } else {
-                // Header lines before any diff - write immediately
-                match writeln!(writer, "{line}") {
-                    Ok(()) => {}
-                    Err(e) => {
-                        // Check for broken pipe in header writing
-                        if e.kind() == std::io::ErrorKind::BrokenPipe {
-                            return Ok(()); // Convert broken pipe to success
-                        }
-                        return Err(Error::processing_error(format!(
-                            "Failed to write header line: {e}"
-                        )));
-                    }
-                }
-            }
-            Ok(())
-        })?;
+    /// Drop sections that delete a file (`deleted file mode`)
+    ///
+    /// Applies to sections kept by the gitignore and VCS patterns.
+    pub fn with_drop_deletions(mut self, enabled: bool) -> Self {
+        self.drop_deletions = enabled;
+        self
+    }
 
-        // Process remaining buffer for last section using functional approach
-        current_section_state
-            .filter(|(_, should_include)| *should_include)
-            .map(|(buffer, _)| flush_buffer(&mut writer, &buffer))
-            .transpose()?;
+    /// Keep only sections whose file extension is one of `extensions`, e.g. `rs` or `toml`
+    ///
+    /// Applies to sections kept by the gitignore and VCS patterns. The extension is the part
+    /// of the file name after its last dot, compared case-sensitively; a leading dot in
+    /// `extensions` is ignored. Files without an extension, such as `Makefile`, and dotfiles
+    /// such as `.env` are dropped. An empty list keeps all extensions.
+    pub fn with_only_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.only_extensions = Self::normalize_extensions(extensions);
+        self
+    }
 
-        Ok(())
+    /// Drop sections whose file extension is one of `extensions`, e.g. `lock` or `snap`
+    ///
+    /// Applies to sections kept by the gitignore and VCS patterns, with the extension taken
+    /// as for [`Filter::with_only_extensions`]. Files without an extension and dotfiles
+    /// such as `.env` are never dropped by this list.
+    pub fn with_excluded_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.excluded_extensions = Self::normalize_extensions(extensions);
+        self
     }
 
-    /// Process diff with downstream command
-    fn process_with_downstream<R: BufRead>(&self, reader: R, command: &str) -> Result<()> {
-        let mut child = Command::new("sh")
-            .arg("-c")
-            .arg(command)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .spawn()
-            .map_err(|e| {
-                Error::DownstreamSpawnFailed(format!(
-                    "Failed to spawn downstream command '{command}': {e}"
-                ))
-            })?;
+    /// Remove the leading dot of extensions given as `.rs`
+    fn normalize_extensions(extensions: Vec<String>) -> Vec<String> {
+        extensions
+            .into_iter()
+            .map(|extension| extension.trim_start_matches('.').to_owned())
+            .collect()
+    }
 
-        let stdin = child.stdin.take().ok_or_else(|| {
-            Error::processing_error("Failed to get stdin of downstream command".to_string())
-        })?;
+    /// Decide about the sections of a diff on `threads` threads
+    ///
+    /// The diff is split into sections first, the keep/drop decisions are made in
+    /// parallel, and the kept sections are written in their original order, so the
+    /// output is identical to serial mode. Pays off for very large diffs; 0 and 1
+    /// decide serially while reading. Output piped to a downstream pipeline is always
+    /// decided serially, so it can be streamed.
+    pub fn with_parallelism(mut self, threads: usize) -> Self {
+        self.parallelism = threads.max(1);
+        self
+    }
+
+    /// Stop buffering a section once its body exceeds `limit` bytes
+    ///
+    /// The decision for such a section is made from its header path, and the rest of
+    /// it is streamed to the output if it is kept or skipped if it is dropped. Bounds
+    /// the memory a single huge section (e.g. a generated file) takes. Sections are then
+    /// decided while reading, regardless of [`Filter::with_parallelism`]. A limit of
+    /// zero disables it.
+    pub fn with_max_section_bytes(mut self, limit: usize) -> Self {
+        self.max_section_bytes = (limit > 0).then_some(limit);
+        self
+    }
 
-        // Process diff to downstream command's stdin
-        let process_result = self.process_direct(reader, stdin);
+    /// Call `callback` with the path and decision of each section as it is processed
+    ///
+    /// The callback runs in section order once a section is decided, e.g. to drive a
+    /// progress bar without parsing the output. Decisions are also made while the output
+    /// is piped to a downstream pipeline and in dry-run and JSON mode, so the callback is
+    /// called there too; text before the first header is not a section.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    ///
+    /// let filter = Filter::new(".")?.on_section(|section| {
+    ///     eprintln!("{:?}: {}", section.path, section.decision.reason());
+    /// });
+    /// let output = filter.filter_bytes(b"diff --git a/src/lib.rs b/src/lib.rs\n")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn on_section<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&SectionInfo<'_>) + Send + 'static,
+    {
+        self.section_callback = Some(Mutex::new(Box::new(callback)));
+        self
+    }
 
-        let exit_status = child.wait().map_err(|e| {
-            Error::processing_error(format!("Failed to wait for downstream command: {e}"))
-        })?;
+    /// Terminate dry-run decision lines with NUL instead of newline, like `git diff -z`
+    ///
+    /// Keeps the output unambiguous for paths containing newlines. The filtered diff
+    /// itself is not affected.
+    pub fn with_null_terminated(mut self, enabled: bool) -> Self {
+        self.null_terminated = enabled;
+        self
+    }
 
-        if !exit_status.success() {
-            return Err(Error::DownstreamProcessFailed(format!(
-                "Downstream command '{}' failed with exit code: {:?}",
-                command,
-                exit_status.code()
-            )));
+    /// Set the output format; with [`OutputFormat::Json`] downstream commands are not run
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Set what happens to sections whose path cannot be determined from their header,
+    /// e.g. because it is malformed or uses unknown prefixes (default: keep them)
+    ///
+    /// With [`UnparsableAction::Error`], processing fails at the first such section;
+    /// sections before it may already have been written.
+    pub fn with_on_unparsable(mut self, action: UnparsableAction) -> Self {
+        self.on_unparsable = action;
+        self
+    }
+
+    /// Decision for a section whose path cannot be determined
+    pub(crate) fn unparsable_decision(&self) -> FilterDecision {
+        match self.on_unparsable {
+            UnparsableAction::Keep => FilterDecision::Keep,
+            UnparsableAction::Drop | UnparsableAction::Error => FilterDecision::Unparsable,
         }
+    }
 
-        // Return the result from process_direct - if it was a broken pipe, we should handle it gracefully
-        process_result
+    /// Match .gitignore patterns case-insensitively, like Git with `core.ignorecase=true`
+    ///
+    /// Reads the ignore files again; fails if one of them cannot be read.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Result<Self> {
+        self.case_insensitive = case_insensitive;
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Also read the .gitignore files in subdirectories of the gitignore root
+    ///
+    /// As in Git, patterns are relative to the directory of their .gitignore, and a
+    /// deeper .gitignore takes precedence over the ones above it. Directories that are
+    /// already ignored, and `.git` directories, are not searched. Fails if one of the
+    /// ignore files cannot be read.
+    pub fn with_nested_gitignore(mut self, enabled: bool) -> Result<Self> {
+        self.nested_gitignore = enabled;
+        self.load_gitignores()?;
+        Ok(self)
     }
 
-    /// Extract file path from diff header line
-    fn extract_file_path(&self, line: &str) -> Option<String> {
-        // Parse "diff --git a/path b/path" format using functional combinators
-        let result = line.strip_prefix("diff --git ").and_then(|remaining| {
-            // Find positions of "a/" and " b/" using functional approach
-            let a_pos = remaining.find("a/")?;
-            let b_pos = remaining.find(" b/")?;
+    /// Apply the patterns of .gitignore files added or modified by the diff to later sections
+    ///
+    /// The added (`+`) lines of a `.gitignore` section are read as patterns relative to
+    /// the directory of that .gitignore and can drop the sections that follow it in the
+    /// diff; sections before it are already decided, so the result depends on the order
+    /// of the sections. The patterns only add to the matchers read from disk: a `!pattern`
+    /// cannot re-include a path those ignore, and removed lines stay in effect if the
+    /// .gitignore on disk has them. Sections are then decided serially.
+    pub fn with_apply_diff_gitignore(mut self, enabled: bool) -> Self {
+        self.apply_diff_gitignore = enabled;
+        self
+    }
 
-            // Ensure "a/" comes before " b/"
-            if a_pos + 2 < b_pos {
-                Some(remaining[a_pos + 2..b_pos].to_string())
-            } else {
-                None
-            }
+    /// Decide a rename shown as a deletion and an addition as one unit
+    ///
+    /// Without rename detection (e.g. `git diff --no-renames`), a renamed file appears
+    /// as a section deleting the old path and one adding the new path. With pairing, a
+    /// deletion kept by its own path is dropped as [`FilterDecision::FilteredByRename`] if
+    /// the addition is dropped, so both are dropped together like a single rename section
+    /// that is decided by its destination. Force-included deletions and decisions about
+    /// the kind of change (e.g. [`Filter::with_drop_deletions`]) are not overridden.
+    ///
+    /// Pairing is a heuristic with limits: a deletion and an addition are paired only if
+    /// the object names on their `index` lines are equal, i.e. the content was not
+    /// changed, and only within one diff (one commit of `git log -p` output). Empty files
+    /// are never paired. The whole diff is buffered, also for the downstream pipeline,
+    /// and [`Filter::with_max_section_bytes`] does not apply.
+    pub fn with_pair_renames(mut self, enabled: bool) -> Self {
+        self.pair_renames = enabled;
+        self
+    }
+
+    /// Read the .gitignore from `gitignore_root` instead of the filter root
+    ///
+    /// Diff paths stay relative to the filter root, as in `git diff --relative` output
+    /// taken in a subdirectory. If the filter root lies below `gitignore_root`, its
+    /// relative path is prepended to diff paths before they are matched, so patterns
+    /// anchored at the repository root (e.g. `/sub/build/`) apply as Git applies them.
+    /// A prefix set with [`Filter::with_path_prefix`] is appended to that path.
+    pub fn with_gitignore_root(mut self, gitignore_root: PathBuf) -> Result<Self> {
+        self.gitignore_root = gitignore_root;
+        self.update_gitignore_path_prefix();
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Use `content` instead of the .gitignore file in the gitignore root
+    ///
+    /// Decouples filtering from the filesystem, e.g. for a patch reviewed in a bare
+    /// checkout whose files and .gitignore are not on disk. The patterns are anchored at
+    /// the gitignore root like the file's would be; patterns added with
+    /// [`Filter::with_extra_ignore_patterns`] still follow them, and nested .gitignore
+    /// files are still read from disk if enabled. Invalid lines are skipped and reported
+    /// by [`Filter::gitignore_warnings`]. Fails if one of the other ignore files cannot be
+    /// read.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    ///
+    /// let filter = Filter::new("/nonexistent/checkout")?.with_gitignore_str("*.log\n")?;
+    /// let output = filter.filter_bytes(b"diff --git a/debug.log b/debug.log\n")?;
+    /// assert!(output.is_empty());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_gitignore_str(mut self, content: &str) -> Result<Self> {
+        self.gitignore_content = Some(content.to_owned());
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Read the root .gitignore patterns from `file` instead of `.gitignore` in the gitignore root
+    ///
+    /// The patterns are anchored at the gitignore root as if `file` were its .gitignore,
+    /// so paths can be filtered without a repository, e.g. when linting patches in CI.
+    /// Replaces content set with [`Filter::with_gitignore_str`]. Fails if `file` does not
+    /// exist.
+    pub fn with_gitignore_file(mut self, file: PathBuf) -> Result<Self> {
+        self.gitignore_file = Some(file);
+        self.gitignore_content = None;
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Read global ignore patterns from `file`, e.g. Git's `core.excludesFile` or
+    /// `~/.config/git/ignore`
+    ///
+    /// The patterns are anchored at the gitignore root and come before those of the root
+    /// .gitignore, so a `!pattern` in the repository re-includes paths they ignore. As in
+    /// Git, a missing file adds no patterns.
+    pub fn with_global_excludes_file(mut self, file: PathBuf) -> Result<Self> {
+        self.global_excludes_file = Some(file);
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Add ignore patterns to those of the root .gitignore, e.g. `*.snap` or `generated/`
+    ///
+    /// The patterns use .gitignore syntax and are anchored at the gitignore root. They
+    /// follow the file's patterns, so a `!pattern` among them re-includes paths the
+    /// .gitignore ignores. Invalid patterns are skipped and reported by
+    /// [`Filter::gitignore_warnings`]. Fails if the ignore files cannot be read again.
+    pub fn with_extra_ignore_patterns(mut self, patterns: Vec<String>) -> Result<Self> {
+        self.extra_ignore_patterns = patterns;
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Read review-only ignore patterns from `file` instead of `.diffignore` in the gitignore root
+    ///
+    /// The file uses .gitignore syntax and its patterns are anchored at the gitignore root.
+    /// Sections matching them are dropped even for tracked files, e.g. committed vendored
+    /// code that is not worth reviewing. Fails if `file` does not exist.
+    pub fn with_diffignore(mut self, file: PathBuf) -> Result<Self> {
+        self.diffignore_file = Some(file);
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Always keep sections whose path matches a pattern in `file`
+    ///
+    /// The file uses .gitignore syntax and its patterns are anchored at the gitignore root,
+    /// e.g. a committed list of paths to show even though they are ignored. A match has
+    /// the highest priority: the section is kept even if .gitignore, .diffignore, VCS or
+    /// extension filters would drop it, while sections dropped for the kind of change
+    /// (e.g. [`Filter::with_drop_deletions`]) stay dropped. Fails if `file` does not exist.
+    pub fn with_force_include(mut self, file: PathBuf) -> Result<Self> {
+        self.force_include_file = Some(file);
+        self.load_gitignores()?;
+        Ok(self)
+    }
+
+    /// Prepend a directory to diff paths before matching them against .gitignore patterns
+    ///
+    /// For `git diff --relative` output taken in `prefix`, whose paths are relative to that
+    /// directory while the .gitignore patterns are relative to the repository root. The
+    /// prefix is prepended after [`Filter::with_strip_path_prefix`] has been applied; VCS
+    /// patterns match at any depth and are not affected. The prefix is relative to the
+    /// filter root: with [`Filter::with_gitignore_root`], the filter root's path below the
+    /// gitignore root is prepended in front of it.
+    pub fn with_path_prefix(mut self, prefix: String) -> Self {
+        let prefix = prefix.trim_matches('/');
+        self.path_prefix = (!prefix.is_empty()).then(|| prefix.to_string());
+        self.update_gitignore_path_prefix();
+        self
+    }
+
+    /// Join the path of the filter root below the gitignore root and the path prefix
+    fn update_gitignore_path_prefix(&mut self) {
+        let base = Self::relative_base(&self.gitignore_root, &self.root);
+        self.gitignore_path_prefix = match (base, &self.path_prefix) {
+            (Some(base), Some(prefix)) => Some(format!("{base}/{prefix}")),
+            (base, prefix) => base.or_else(|| prefix.clone()),
+        };
+    }
+
+    /// Path of `root` relative to `base` with `/` separators, if `root` lies below `base`
+    fn relative_base(base: &Path, root: &Path) -> Option<String> {
+        let relative = root.strip_prefix(base).map(Path::to_path_buf).or_else(|_| {
+            // Compare canonical paths when one of them is relative or contains symlinks
+            let base = base.canonicalize()?;
+            let root = root.canonicalize()?;
+            root.strip_prefix(base)
+                .map(Path::to_path_buf)
+                .map_err(io::Error::other)
         });
 
-        result
+        relative
+            .ok()
+            .map(|relative| {
+                relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .filter(|relative| !relative.is_empty())
     }
 
-    /// Check if a file should be included based on gitignore and VCS patterns
-    fn should_include_file(&self, file_path: &str) -> bool {
-        // Check VCS patterns first - only if VCS filtering is enabled
-        if self.vcs_filtering_enabled && self.is_vcs_file(file_path) {
-            return false; // Exclude VCS files when VCS filtering is enabled
-        }
-
-        // Check gitignore patterns using functional combinators
-        self.gitignore.as_ref().is_none_or(|gitignore| {
-            let path = Path::new(file_path);
-
-            // First try as a file
-            match gitignore.matched(path, false) {
-                ignore::Match::Ignore(_) => false,
-                ignore::Match::Whitelist(_) => true,
-                ignore::Match::None => {
-                    // Check parent directories using find_map for cleaner iteration
-                    path.ancestors()
-                        .skip(1) // Skip the file itself
-                        .take_while(|parent| *parent != Path::new(""))
-                        .find_map(|parent| match gitignore.matched(parent, true) {
-                            ignore::Match::Ignore(_) => Some(false),
-                            ignore::Match::Whitelist(_) => Some(true),
-                            ignore::Match::None => None,
-                        })
-                        .unwrap_or(true)
+    /// Problems found while reading the .gitignore
+    ///
+    /// Lines with invalid patterns (e.g. an unclosed `[` character class) are skipped
+    /// individually while the remaining patterns stay in effect; each skipped line is
+    /// reported here.
+    pub fn gitignore_warnings(&self) -> &[String] {
+        &self.gitignore_warnings
+    }
+
+    /// VCS patterns set with [`Filter::with_vcs_patterns`], also while VCS filtering is disabled
+    pub fn vcs_patterns(&self) -> &[String] {
+        &self.vcs_patterns
+    }
+
+    /// Check if sections matching the VCS patterns are filtered out
+    pub fn is_vcs_enabled(&self) -> bool {
+        self.vcs_filtering_enabled
+    }
+
+    /// Check if the filtered diff is piped to a downstream pipeline by [`Filter::process_diff`]
+    pub fn has_downstream(&self) -> bool {
+        !self.downstream_pipeline.is_empty()
+    }
+
+    /// Build gitignore patterns from the repository, along with warnings for skipped lines
+    ///
+    /// The patterns are read from `global`, if given, followed by `file`, or by
+    /// `.gitignore` in `root` if it is None.
+    fn build_gitignore(
+        root: &Path,
+        file: Option<&Path>,
+        global: Option<&Path>,
+        case_insensitive: bool,
+        extra_patterns: &[String],
+    ) -> Result<(Option<Gitignore>, Vec<String>)> {
+        let file = file.map_or_else(|| root.join(".gitignore"), Path::to_path_buf);
+        let mut builder = Self::ignore_builder(root, case_insensitive)?;
+        let mut warnings = Vec::new();
+        if let Some(global) = global {
+            Self::add_ignore_file(&mut builder, global, &mut warnings);
+        }
+        Self::add_ignore_file(&mut builder, &file, &mut warnings);
+        Self::add_ignore_lines(&mut builder, extra_patterns, &mut warnings);
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        Ok(Self::finish_ignore_matcher(&builder, &name, warnings))
+    }
+
+    /// Build a matcher from an ignore file with patterns anchored at `root`, followed by
+    /// `extra_patterns`
+    fn build_ignore_file(
+        root: &Path,
+        file: &Path,
+        case_insensitive: bool,
+        extra_patterns: &[String],
+    ) -> Result<(Option<Gitignore>, Vec<String>)> {
+        let mut builder = Self::ignore_builder(root, case_insensitive)?;
+        let mut warnings = Vec::new();
+        Self::add_ignore_file(&mut builder, file, &mut warnings);
+        Self::add_ignore_lines(&mut builder, extra_patterns, &mut warnings);
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        Ok(Self::finish_ignore_matcher(&builder, &name, warnings))
+    }
+
+    /// Add the patterns of `file` to `builder` if it exists; invalid lines are skipped
+    /// with a warning
+    fn add_ignore_file(builder: &mut GitignoreBuilder, file: &Path, warnings: &mut Vec<String>) {
+        if file.exists() {
+            match builder.add(file) {
+                Some(matcher::Error::Partial(errors)) => {
+                    warnings.extend(errors.iter().map(ToString::to_string));
                 }
+                Some(e) => warnings.push(e.to_string()),
+                None => {}
             }
-        })
+        }
     }
 
-    /// Check if a file matches VCS patterns
-    fn is_vcs_file(&self, file_path: &str) -> bool {
-        self.vcs_patterns
-            .iter()
-            .any(|pattern| self.matches_vcs_pattern(file_path, pattern))
-    }
-
-    /// Helper method to check if file path matches a specific VCS pattern
-    /// Optimized to reduce string allocations by using strip_suffix and direct comparisons
-    fn matches_vcs_pattern(&self, file_path: &str, pattern: &str) -> bool {
-        match pattern.strip_suffix("/*") {
-            Some(dir_pattern) => {
-                // For directory patterns (ending with /*), check directory boundaries
-                file_path.starts_with(&format!("{dir_pattern}/"))
-                    || file_path.contains(&format!("/{dir_pattern}/"))
-            }
-            None => {
-                // For exact patterns, improved logic to handle nested paths correctly
-                if file_path == pattern {
-                    return true;
-                }
+    /// Build a matcher from in-memory .gitignore content with patterns anchored at `root`,
+    /// preceded by the patterns of `global` and followed by `extra_patterns`
+    fn build_gitignore_str(
+        root: &Path,
+        content: &str,
+        global: Option<&Path>,
+        case_insensitive: bool,
+        extra_patterns: &[String],
+    ) -> Result<(Option<Gitignore>, Vec<String>)> {
+        let mut builder = Self::ignore_builder(root, case_insensitive)?;
+        let mut warnings = Vec::new();
+        if let Some(global) = global {
+            Self::add_ignore_file(&mut builder, global, &mut warnings);
+        }
+        Self::add_ignore_lines(&mut builder, content.lines(), &mut warnings);
+        Self::add_ignore_lines(&mut builder, extra_patterns, &mut warnings);
+        Ok(Self::finish_ignore_matcher(
+            &builder,
+            ".gitignore",
+            warnings,
+        ))
+    }
 
-                // Check if pattern ends with "/" (directory pattern without "/*")
-                if pattern.ends_with('/') {
-                    // For directory patterns like ".git/", check if file path contains this directory
-                    // This handles cases like "jira-timesheet-cli/.git/COMMIT_EDITMSG"
-                    file_path.contains(pattern) || file_path.starts_with(pattern)
-                } else {
-                    // For non-directory patterns, check prefix match or nested match
-                    file_path.starts_with(&format!("{pattern}/"))
-                        || file_path.contains(&format!("/{pattern}/"))
-                        || file_path.contains(&format!("/{pattern}"))
-                        || (file_path.starts_with(pattern)
-                            && (file_path.len() == pattern.len()
-                                || file_path.chars().nth(pattern.len()) == Some('/')))
-                }
+    /// Create a builder for patterns anchored at `root`
+    fn ignore_builder(root: &Path, case_insensitive: bool) -> Result<GitignoreBuilder> {
+        let mut builder = GitignoreBuilder::new(root);
+        builder.case_insensitive(case_insensitive).map_err(|e| {
+            Error::processing_error(format!("Failed to configure gitignore matching: {e}"))
+        })?;
+        Ok(builder)
+    }
+
+    /// Add pattern lines to `builder`, skipping invalid ones with a warning
+    fn add_ignore_lines<I, S>(builder: &mut GitignoreBuilder, lines: I, warnings: &mut Vec<String>)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for pattern in lines {
+            let pattern = pattern.as_ref();
+            if let Err(e) = builder.add_line(None, pattern) {
+                warnings.push(format!("Ignoring pattern '{pattern}': {e}"));
             }
         }
     }
 
-    /// Check if the data contains binary content that should be preserved unchanged
-    ///
-    /// This method handles:
-    /// 1. Pure binary data (no diff headers) - pass through unchanged
-    /// 2. Git diffs with binary content - pass through unchanged
-    /// 3. Regular text content - process normally
-    fn is_git_diff_with_binary_content(&self, data: &[u8]) -> bool {
-        // If data is empty, treat as text
-        if data.is_empty() {
-            return false;
-        }
+    /// Build the matcher, continuing without it if that fails
+    fn finish_ignore_matcher(
+        builder: &GitignoreBuilder,
+        name: &str,
+        mut warnings: Vec<String>,
+    ) -> (Option<Gitignore>, Vec<String>) {
+        match builder.build() {
+            Ok(gitignore) => (Some(gitignore), warnings),
+            Err(e) => {
+                warnings.push(format!("Ignoring {name} patterns: {e}"));
+                (None, warnings)
+            }
+        }
+    }
+
+    /// Read the configured diffignore file, or `.diffignore` in `gitignore_root` if it exists
+    fn load_diffignore(&mut self) -> Result<()> {
+        let file = match &self.diffignore_file {
+            Some(file) if !file.is_file() => {
+                return Err(Error::processing_error(format!(
+                    "Diffignore file not found: {}",
+                    file.display()
+                )));
+            }
+            Some(file) => file.clone(),
+            None => self.gitignore_root.join(".diffignore"),
+        };
+
+        self.diffignore = None;
+        if file.is_file() {
+            let (diffignore, warnings) =
+                Self::build_ignore_file(&self.gitignore_root, &file, self.case_insensitive, &[])?;
+            self.diffignore = diffignore;
+            self.gitignore_warnings.extend(warnings);
+        }
+        Ok(())
+    }
+
+    /// Read the configured force-include file, if any
+    fn load_force_include(&mut self) -> Result<()> {
+        self.force_include = None;
+        let Some(file) = &self.force_include_file else {
+            return Ok(());
+        };
+        if !file.is_file() {
+            return Err(Error::processing_error(format!(
+                "Force-include file not found: {}",
+                file.display()
+            )));
+        }
+
+        let (force_include, warnings) =
+            Self::build_ignore_file(&self.gitignore_root, file, self.case_insensitive, &[])?;
+        self.force_include = force_include;
+        self.gitignore_warnings.extend(warnings);
+        Ok(())
+    }
+
+    /// Read the .gitignore from `gitignore_root` (or the in-memory content or configured
+    /// file), and the ones below it if enabled
+    ///
+    /// Subdirectories are visited breadth-first, so parents come before their children
+    /// and ignored directories can be skipped using the matchers read so far.
+    fn load_gitignores(&mut self) -> Result<()> {
+        if let (None, Some(file)) = (&self.gitignore_content, &self.gitignore_file) {
+            if !file.is_file() {
+                return Err(Error::processing_error(format!(
+                    "Gitignore file not found: {}",
+                    file.display()
+                )));
+            }
+        }
+
+        let (gitignore, warnings) = match &self.gitignore_content {
+            Some(content) => Self::build_gitignore_str(
+                &self.gitignore_root,
+                content,
+                self.global_excludes_file.as_deref(),
+                self.case_insensitive,
+                &self.extra_ignore_patterns,
+            ),
+            None => Self::build_gitignore(
+                &self.gitignore_root,
+                self.gitignore_file.as_deref(),
+                self.global_excludes_file.as_deref(),
+                self.case_insensitive,
+                &self.extra_ignore_patterns,
+            ),
+        }?;
+        self.gitignore = gitignore.map(Arc::new);
+        self.gitignore_warnings = warnings;
+        self.nested_gitignores.clear();
+        self.load_diffignore()?;
+        self.load_force_include()?;
+
+        if !self.nested_gitignore {
+            return Ok(());
+        }
+
+        let mut directories = VecDeque::from([PathBuf::new()]);
+        while let Some(relative) = directories.pop_front() {
+            let Ok(entries) = std::fs::read_dir(self.gitignore_root.join(&relative)) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                // Symlinked directories are not followed, as in Git
+                if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    continue;
+                }
+
+                let name = entry.file_name();
+                if name == ".git" {
+                    continue;
+                }
+
+                let child = relative.join(&name);
+                if self.is_gitignored(&child.to_string_lossy(), true) {
+                    continue;
+                }
+
+                if entry.path().join(".gitignore").is_file() {
+                    let (gitignore, warnings) = Self::build_gitignore(
+                        &entry.path(),
+                        None,
+                        None,
+                        self.case_insensitive,
+                        &[],
+                    )?;
+                    self.nested_gitignores
+                        .extend(gitignore.map(|gitignore| (child.clone(), gitignore)));
+                    self.gitignore_warnings.extend(warnings);
+                }
+                directories.push_back(child);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile VCS patterns into one matcher
+    ///
+    /// VCS patterns match at any depth, so `.git/` also matches `project/.git/config`
+    /// and `CVS/*` matches `lib/CVS/Entries`; a leading `/` anchors a pattern at the root
+    /// instead. Patterns are otherwise read with .gitignore syntax, so `**` spans any
+    /// number of directories, e.g. `vendor/**/.git/`; invalid patterns (rejected by config
+    /// validation) are skipped.
+    fn build_vcs_matcher(root: &Path, patterns: &[String]) -> Option<Gitignore> {
+        let mut builder = GitignoreBuilder::new(root);
+
+        patterns
+            .iter()
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty())
+            .for_each(|pattern| {
+                let (negation, glob) = match pattern.strip_prefix('!') {
+                    Some(glob) => ("!", glob),
+                    None => ("", pattern),
+                };
+                let unanchored = if glob.starts_with('/') || glob.starts_with("**/") {
+                    format!("{negation}{glob}")
+                } else {
+                    format!("{negation}**/{glob}")
+                };
+                // Ignore invalid patterns; they are reported by config validation
+                let _ = builder.add_line(None, &unanchored);
+            });
+
+        builder.build().ok()
+    }
+
+    /// Process a diff stream and filter it according to patterns
+    ///
+    /// Kept sections are written byte-identical to their input, including line endings,
+    /// whitespace and `\ No newline at end of file` markers, so the output can be fed
+    /// to `git apply`. Only [`Filter::with_annotate`] and
+    /// [`Filter::with_keep_empty_diff_headers`] add lines of their own.
+    pub fn process_diff<R: BufRead, W: Write>(&self, reader: R, writer: W) -> Result<()> {
+        let plain_output = !self.dry_run
+            && self.output_format == OutputFormat::Diff
+            && self.downstream_pipeline.is_empty();
+        if !plain_output {
+            return self.process_diff_with_report(reader, writer).map(|_| ());
+        }
+        if self.strip_ansi {
+            return self.process_direct_unreported(StripAnsi::new(reader), writer);
+        }
+        self.process_direct_unreported(reader, writer)
+    }
+
+    /// Process a diff stream like [`Filter::process_diff`] and report the decisions made
+    ///
+    /// The report counts every section, also when the output is piped to a downstream command.
+    /// [`FilterReport::output_bytes`] tells whether any output was produced, e.g. to detect
+    /// a diff that filtering emptied, and [`FilterReport::kept`] and
+    /// [`FilterReport::filtered`] how many sections were kept and removed.
+    pub fn process_diff_with_report<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<FilterReport> {
+        if self.strip_ansi {
+            return self.process_plain_diff(StripAnsi::new(reader), writer);
+        }
+        self.process_plain_diff(reader, writer)
+    }
+
+    /// Process a diff without color sequences in the configured mode
+    fn process_plain_diff<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<FilterReport> {
+        // Process as UTF-8 text data directly without binary detection
+        // The binary detection was causing issues with BufReader state
+        if self.dry_run {
+            self.process_dry_run(reader, writer)
+        } else if self.output_format == OutputFormat::Json {
+            self.process_json(reader, writer)
+        } else if !self.downstream_pipeline.is_empty() {
+            match self.downstream_max_lines {
+                Some(max_lines) => self.process_with_downstream_limit(reader, writer, max_lines),
+                None => self.process_with_downstream_retries(reader),
+            }
+        } else {
+            self.process_direct(reader, writer)
+        }
+    }
+
+    /// Filter a diff stream, writing kept sections to `kept_writer` and dropped ones to
+    /// `filtered_writer`
+    ///
+    /// Each file section goes to exactly one of the writers, byte-identical to its input,
+    /// so the two outputs together hold every section. Text before the first header
+    /// (and commit metadata) goes to `kept_writer`, as do the markers of
+    /// [`Filter::with_keep_empty_diff_headers`]. Like [`Filter::filter_bytes`], the
+    /// downstream pipeline is never spawned, and dry-run mode and the output format are
+    /// not applied.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    ///
+    /// let filter = Filter::new(".")?.with_gitignore_str("*.log\n")?;
+    /// let input = "diff --git a/debug.log b/debug.log\n+x\ndiff --git a/lib.rs b/lib.rs\n+y\n";
+    ///
+    /// let (mut kept, mut filtered) = (Vec::new(), Vec::new());
+    /// filter.process_diff_split(input.as_bytes(), &mut kept, &mut filtered)?;
+    /// assert_eq!(kept, b"diff --git a/lib.rs b/lib.rs\n+y\n");
+    /// assert_eq!(filtered, b"diff --git a/debug.log b/debug.log\n+x\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn process_diff_split<R: BufRead, K: Write, F: Write>(
+        &self,
+        reader: R,
+        kept_writer: K,
+        mut filtered_writer: F,
+    ) -> Result<FilterReport> {
+        if self.strip_ansi {
+            self.process_direct_split(StripAnsi::new(reader), kept_writer, &mut filtered_writer)
+        } else {
+            self.process_direct_split(reader, kept_writer, &mut filtered_writer)
+        }
+    }
+
+    /// Filter a diff held in memory and return the kept sections
+    ///
+    /// Uses the same section splitting and decisions as [`Filter::process_diff`], but
+    /// always produces the filtered diff: the downstream pipeline is never spawned, and
+    /// dry-run mode and the output format are not applied.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    ///
+    /// let filter = Filter::new(".")?;
+    /// let output = filter.filter_bytes(b"diff --git a/src/lib.rs b/src/lib.rs\n")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn filter_bytes(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len());
+        if self.strip_ansi {
+            self.process_direct_unreported(StripAnsi::new(input), &mut output)?;
+        } else {
+            self.process_direct_unreported(input, &mut output)?;
+        }
+        Ok(output)
+    }
+
+    /// Filter a diff stream and return the kept sections as a string
+    ///
+    /// Shares the processing of [`Filter::filter_bytes`]: the downstream pipeline is
+    /// never spawned, and dry-run mode and the output format are not applied. The
+    /// output is converted lossily, so invalid UTF-8 (e.g. in binary content) is
+    /// replaced by U+FFFD; use [`Filter::filter_bytes`] to keep the output byte-exact.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    /// use std::io::Cursor;
+    ///
+    /// let filter = Filter::new(".")?;
+    /// let input = "diff --git a/ignored.log b/ignored.log\n";
+    ///
+    /// let output = filter.process_to_string(Cursor::new(input))?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn process_to_string<R: BufRead>(&self, reader: R) -> Result<String> {
+        let mut output = Vec::new();
+        if self.strip_ansi {
+            self.process_direct_unreported(StripAnsi::new(reader), &mut output)?;
+        } else {
+            self.process_direct_unreported(reader, &mut output)?;
+        }
+        Ok(String::from_utf8(output)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+    }
+
+    /// Wrap `writer` in a [`FilterWriter`] that filters the diff written to it
+    ///
+    /// Lets the filter sit in the middle of a pipeline of writers. Like
+    /// [`Filter::filter_bytes`], the downstream pipeline is never spawned, and dry-run
+    /// mode and the output format are not applied.
+    ///
+    /// ```rust
+    /// use diff_gitignore_filter::Filter;
+    /// use std::io;
+    ///
+    /// let filter = Filter::new(".")?.with_gitignore_str("*.log\n")?;
+    /// let input = "diff --git a/debug.log b/debug.log\n+x\ndiff --git a/lib.rs b/lib.rs\n+y\n";
+    ///
+    /// let mut output = Vec::new();
+    /// io::copy(&mut input.as_bytes(), &mut filter.writer(&mut output))?;
+    /// assert_eq!(output, b"diff --git a/lib.rs b/lib.rs\n+y\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn writer<W: Write>(&self, writer: W) -> FilterWriter<'_, W> {
+        FilterWriter {
+            filter: self,
+            inner: writer,
+            pending: Vec::new(),
+            scanned: 0,
+            continues_kept: None,
+            report: FilterReport::default(),
+            finished: false,
+        }
+    }
+
+    /// Iterate over the sections of a diff stream
+    ///
+    /// Headers are parsed with the prefixes set by [`Filter::with_diff_prefixes`]. Each
+    /// section can be checked with [`DiffSection::is_ignored`] and written back with
+    /// [`DiffSection::write_to`], which lets callers make their own keep/drop decisions.
+    pub fn sections<R: BufRead>(&self, reader: R) -> impl Iterator<Item = Result<DiffSection>> {
+        DiffSections::new(reader, self.diff_prefixes.clone()).with_unified(self.unified)
+    }
+
+    /// Iterate over the sections of a diff stream, cut off at the section size limit
+    fn limited_sections<R: BufRead>(&self, reader: R) -> DiffSections<R> {
+        DiffSections::new(reader, self.diff_prefixes.clone())
+            .with_unified(self.unified)
+            .with_max_section_bytes(self.max_section_bytes)
+    }
+
+    /// Collect the distinct top-level directories touched by the kept sections of a diff
+    ///
+    /// Returns the first path component of each kept section's path. Files at the
+    /// repository root have no top-level directory and do not contribute. The filtered
+    /// diff itself is discarded and a configured downstream command is not run.
+    pub fn kept_top_dirs(&self, diff: &str) -> Result<BTreeSet<String>> {
+        let report = self.process_direct(diff.as_bytes(), io::sink())?;
+
+        Ok(report
+            .kept_paths()
+            .filter_map(|path| path.split_once('/'))
+            .map(|(top_dir, _)| top_dir.to_string())
+            .collect())
+    }
+
+    /// Split changed file paths, e.g. from `git diff --name-only`, into kept and filtered ones
+    ///
+    /// Each path is decided as a file, like the path of a diff section, with the same
+    /// gitignore, .diffignore, VCS and extension filters as [`Filter::process_diff`]. Both
+    /// lists keep the order of `paths`.
+    ///
+    /// ```rust,no_run
+    /// use diff_gitignore_filter::Filter;
+    ///
+    /// let filter = Filter::new(".")?;
+    /// let paths = vec!["src/lib.rs".to_string(), "debug.log".to_string()];
+    /// let (kept, filtered) = filter.partition_paths(&paths);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn partition_paths(&self, paths: &[String]) -> (Vec<String>, Vec<String>) {
+        paths
+            .iter()
+            .cloned()
+            .partition(|path| self.decide_file(path).is_kept())
+    }
+
+    /// Process diff directly to the writer with streaming optimization
+    fn process_direct<R: BufRead, W: Write>(&self, reader: R, writer: W) -> Result<FilterReport> {
+        self.process_direct_split(reader, writer, &mut io::sink())
+    }
+
+    /// Process diff directly when the decisions are not reported
+    ///
+    /// If no filter can drop a section, unparsable sections are kept, and no callback or
+    /// diagnostic observes the decisions, the input is copied with [`io::copy`] without
+    /// looking for sections.
+    fn process_direct_unreported<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<()> {
+        let observed = self.section_callback.is_some() || crate::diagnostics::enabled(2);
+        if !self.is_unfiltered() || self.on_unparsable != UnparsableAction::Keep || observed {
+            return self.process_direct(reader, writer).map(|_| ());
+        }
+        match io::copy(&mut reader, &mut writer) {
+            // A closed reader is not an error
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(Error::processing_error(
+                format!("Failed to copy input data: {e}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Process diff directly, writing kept sections to `writer` and dropped ones to `filtered`
+    fn process_direct_split<R: BufRead, W: Write, F: Write>(
+        &self,
+        reader: R,
+        writer: W,
+        filtered: &mut F,
+    ) -> Result<FilterReport> {
+        let mut writer = CountingWriter {
+            inner: writer,
+            written: 0,
+        };
+
+        let mut report = if self.is_unfiltered() {
+            self.copy_unfiltered(reader, &mut writer, filtered)?
+        } else {
+            self.filter_sections(reader, &mut writer, filtered)?
+        };
+        report.record_output(writer.written);
+        Ok(report)
+    }
+
+    /// Check if no configured filter can drop a section whose path can be parsed
+    ///
+    /// True without ignore patterns (VCS patterns count only when VCS filtering is
+    /// enabled), extension and change filters, annotations and unified mode, e.g. for
+    /// `--no-vcs` with an empty .gitignore.
+    fn is_unfiltered(&self) -> bool {
+        let has_ignores = |gitignore: &Gitignore| gitignore.num_ignores() > 0;
+
+        !self.unified
+            && !self.apply_diff_gitignore
+            && !self.annotate
+            && !self.drop_mode_only
+            && !self.only_vcs
+            && !self.drop_deletions
+            && self.only_extensions.is_empty()
+            && self.excluded_extensions.is_empty()
+            && !(self.vcs_filtering_enabled && self.vcs_matcher.as_ref().is_some_and(has_ignores))
+            && !self.gitignore.as_deref().is_some_and(has_ignores)
+            && !self.diffignore.as_ref().is_some_and(has_ignores)
+            && !self
+                .nested_gitignores
+                .iter()
+                .any(|(_, gitignore)| has_ignores(gitignore))
+    }
+
+    /// Copy a diff that no filter can shorten without buffering it or its sections
+    ///
+    /// Every section is recorded as kept. From the first header whose paths cannot be
+    /// parsed on, the rest of the diff is split into sections and decided as usual, as
+    /// such a section may be dropped.
+    fn copy_unfiltered<R: BufRead, W: Write, F: Write>(
+        &self,
+        mut reader: R,
+        writer: &mut W,
+        filtered: &mut F,
+    ) -> Result<FilterReport> {
+        let mut report = FilterReport::default();
+        let unparsable_header = copy_sections(&mut reader, writer, &self.diff_prefixes, |path| {
+            self.record_decision(&mut report, Some(path), FilterDecision::Keep);
+        })?;
+
+        if let Some(header) = unparsable_header {
+            let rest = io::Read::chain(io::Cursor::new(header), reader);
+            self.write_sections(rest, &mut report, writer, filtered)?;
+        }
+        Ok(report)
+    }
+
+    /// Write the kept sections of the diff and record the decisions made
+    ///
+    /// The whole diff is read first only to decide sections in parallel or to pair
+    /// renames; otherwise each section is written as soon as it is decided.
+    fn filter_sections<R: BufRead, W: Write, F: Write>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+        filtered: &mut F,
+    ) -> Result<FilterReport> {
+        let mut report = FilterReport::default();
+
+        let parallel =
+            self.parallelism > 1 && self.max_section_bytes.is_none() && !self.apply_diff_gitignore;
+        if parallel || self.pair_renames {
+            let mut all_data = Vec::new();
+            reader
+                .read_to_end(&mut all_data)
+                .map_err(|e| Error::processing_error(format!("Failed to read input data: {e}")))?;
+
+            let sections = self
+                .sections(all_data.as_slice())
+                .collect::<Result<Vec<_>>>()?;
+            let decisions = if parallel {
+                self.decide_parallel(&sections)?
+            } else {
+                self.decide_serial(&sections)
+            };
+            let decisions = if self.pair_renames {
+                self.pair_rename_decisions(&sections, decisions)
+            } else {
+                decisions
+            };
+
+            sections
+                .iter()
+                .zip(decisions)
+                .try_for_each(|(section, decision)| {
+                    self.write_decided_section(
+                        section,
+                        decision,
+                        &mut report,
+                        &mut writer,
+                        filtered,
+                    )
+                })?;
+
+            return Ok(report);
+        }
+
+        self.write_sections(reader, &mut report, &mut writer, filtered)?;
+        Ok(report)
+    }
+
+    /// Decide about each section while reading it and write the kept ones to `writer`
+    /// and the dropped ones to `filtered`
+    fn write_sections<R: BufRead, W: Write, F: Write>(
+        &self,
+        reader: R,
+        report: &mut FilterReport,
+        writer: &mut W,
+        filtered: &mut F,
+    ) -> Result<()> {
+        self.decide_sections(reader, |sections, section, decision| {
+            self.write_decided_section(section, decision, report, writer, filtered)?;
+            if section.is_truncated() {
+                let keep = decision.is_none_or(FilterDecision::is_kept);
+                Self::write_rest_of_section(sections, keep, writer, filtered)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Decide about each section while reading it and pass it with its decision to `visit`
+    ///
+    /// The preamble gets no decision. The rest of a section cut off at the size limit is
+    /// left in `sections` for `visit` to read.
+    fn decide_sections<R: BufRead, V>(&self, reader: R, mut visit: V) -> Result<()>
+    where
+        V: FnMut(&mut DiffSections<R>, &DiffSection, Option<FilterDecision>) -> Result<()>,
+    {
+        let mut sections = self.limited_sections(reader);
+        let mut diff_gitignores = Vec::new();
+        while let Some(section) = sections.next() {
+            let section = section?;
+            let decision = (!section.is_preamble())
+                .then(|| self.decide_with_diff_gitignores(&section, &diff_gitignores));
+            visit(&mut sections, &section, decision)?;
+            if self.apply_diff_gitignore {
+                diff_gitignores.extend(self.diff_gitignore(&section));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the kept sections as soon as they are decided, without reading the whole diff first
+    ///
+    /// Used for the downstream pipeline, so its first stage receives each kept section
+    /// while the rest of the input is still being read and only one section is held in
+    /// memory. Sections are always decided serially. To pair renames, the whole diff is read first instead.
+    fn process_streaming<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<FilterReport> {
+        // Pairing renames needs the whole diff
+        if self.pair_renames {
+            return self.process_direct(reader, writer);
+        }
+
+        let mut writer = CountingWriter {
+            inner: writer,
+            written: 0,
+        };
+
+        let mut report = FilterReport::default();
+        self.write_sections(reader, &mut report, &mut writer, &mut io::sink())?;
+        report.record_output(writer.written);
+        Ok(report)
+    }
+
+    /// Decide about a section, also dropping it if a .gitignore earlier in the diff ignores it
+    fn decide_with_diff_gitignores(
+        &self,
+        section: &DiffSection,
+        diff_gitignores: &[(PathBuf, Gitignore)],
+    ) -> FilterDecision {
+        match (section.decision(self), section.path()) {
+            (FilterDecision::Keep, Some(path))
+                if !diff_gitignores.is_empty()
+                    && !self.only_vcs
+                    && !self.is_force_included(path, section.is_submodule()) =>
+            {
+                let path = self.gitignore_path(self.strip_prefix(path));
+                // As for nested .gitignore files, the deepest one with a match decides;
+                // of several for the same directory, the last one in the diff
+                let ignored = diff_gitignores
+                    .iter()
+                    .filter_map(|(directory, gitignore)| {
+                        let relative = Path::new(path.as_ref()).strip_prefix(directory).ok()?;
+                        let matched = Self::matched_path_or_parents(
+                            gitignore,
+                            &relative.to_string_lossy(),
+                            section.is_submodule(),
+                        );
+                        (!matched.is_none()).then(|| (directory.components().count(), matched))
+                    })
+                    .max_by_key(|(depth, _)| *depth)
+                    .is_some_and(|(_, matched)| matched.is_ignore());
+
+                if ignored {
+                    FilterDecision::FilteredByGitignore
+                } else {
+                    FilterDecision::Keep
+                }
+            }
+            (decision, _) => decision,
+        }
+    }
+
+    /// Matcher of the lines a `.gitignore` section adds, keyed by the directory of the
+    /// .gitignore relative to `gitignore_root`
+    ///
+    /// None for other sections and for sections deleting their .gitignore.
+    fn diff_gitignore(&self, section: &DiffSection) -> Option<(PathBuf, Gitignore)> {
+        let path = section.path()?;
+        let path = self.gitignore_path(self.strip_prefix(path));
+        let path = Path::new(path.as_ref());
+        if path.file_name()? != ".gitignore" || section.is_deletion() {
+            return None;
+        }
+
+        let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let body = String::from_utf8_lossy(&section.body);
+        let added = body
+            .lines()
+            .filter(|line| !line.starts_with("+++ "))
+            .filter_map(|line| line.strip_prefix('+'))
+            .map(|line| line.trim_end_matches('\r'));
+
+        let mut builder =
+            Self::ignore_builder(&self.gitignore_root.join(&directory), self.case_insensitive)
+                .ok()?;
+        Self::add_ignore_lines(&mut builder, added, &mut Vec::new());
+        let gitignore = builder.build().ok()?;
+        (gitignore.num_ignores() > 0 || gitignore.num_whitelists() > 0)
+            .then_some((directory, gitignore))
+    }
+
+    /// Stream the rest of a section cut off at the size limit to `writer` if it is kept,
+    /// or to `filtered` if it is dropped
+    fn write_rest_of_section<R: BufRead, W: Write, F: Write>(
+        sections: &mut DiffSections<R>,
+        keep: bool,
+        writer: &mut W,
+        filtered: &mut F,
+    ) -> Result<()> {
+        while let Some(raw_line) = sections.read_rest_line()? {
+            let written = if keep {
+                writer.write_all(&raw_line)
+            } else {
+                filtered.write_all(&raw_line)
+            };
+            Self::check_written(written)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decide about each section on a pool of `parallelism` threads, in section order
+    ///
+    /// The preamble gets no decision.
+    fn decide_parallel(&self, sections: &[DiffSection]) -> Result<Vec<Option<FilterDecision>>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.parallelism)
+            .build()
+            .map_err(|e| Error::processing_error(format!("Failed to start threads: {e}")))?;
+
+        Ok(pool.install(|| {
+            sections
+                .par_iter()
+                .map(|section| (!section.is_preamble()).then(|| section.decision(self)))
+                .collect()
+        }))
+    }
+
+    /// Decide about each section in order, applying .gitignore files added by the diff
+    ///
+    /// The preamble gets no decision.
+    fn decide_serial(&self, sections: &[DiffSection]) -> Vec<Option<FilterDecision>> {
+        let mut diff_gitignores = Vec::new();
+        sections
+            .iter()
+            .map(|section| {
+                let decision = (!section.is_preamble())
+                    .then(|| self.decide_with_diff_gitignores(section, &diff_gitignores));
+                if self.apply_diff_gitignore {
+                    diff_gitignores.extend(self.diff_gitignore(section));
+                }
+                decision
+            })
+            .collect()
+    }
+
+    /// Drop each deleted file kept by its path whose content was added under a dropped path
+    ///
+    /// Deletions and additions are matched by the object names of their `index` lines,
+    /// in diff order and only between the same preambles (commits); see
+    /// [`Filter::with_pair_renames`].
+    fn pair_rename_decisions(
+        &self,
+        sections: &[DiffSection],
+        mut decisions: Vec<Option<FilterDecision>>,
+    ) -> Vec<Option<FilterDecision>> {
+        /// Object name of the empty file, shared by all empty files
+        const EMPTY_BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+        let mut commit = 0;
+        let mut additions: HashMap<(usize, String), VecDeque<usize>> = HashMap::new();
+        let mut deletions = Vec::new();
+        for (index, section) in sections.iter().enumerate() {
+            if section.is_preamble() {
+                commit += 1;
+                continue;
+            }
+
+            let Some((old, new)) = section.index_blobs() else {
+                continue;
+            };
+            if section.is_addition() && !EMPTY_BLOB.starts_with(&new) {
+                additions.entry((commit, new)).or_default().push_back(index);
+            } else if section.is_deletion() && !EMPTY_BLOB.starts_with(&old) {
+                deletions.push((index, (commit, old)));
+            }
+        }
+
+        for (deletion, key) in deletions {
+            if let Some(addition) = additions.get_mut(&key).and_then(VecDeque::pop_front) {
+                let section = &sections[deletion];
+                let added = decisions[addition].unwrap_or(FilterDecision::Keep);
+                diag!(
+                    2,
+                    "pairing deletion of {} with addition of {} ({})",
+                    section.path().unwrap_or("<unknown>"),
+                    sections[addition].path().unwrap_or("<unknown>"),
+                    added.reason()
+                );
+
+                let forced = section
+                    .path()
+                    .is_some_and(|path| self.is_force_included(path, section.is_submodule()));
+                if !added.is_kept() && decisions[deletion] == Some(FilterDecision::Keep) && !forced
+                {
+                    decisions[deletion] = Some(FilterDecision::FilteredByRename);
+                }
+            }
+        }
+
+        decisions
+    }
+
+    /// Record the decision for a section and pass it to the section callback
+    fn record_decision(
+        &self,
+        report: &mut FilterReport,
+        path: Option<&str>,
+        decision: FilterDecision,
+    ) {
+        report.record(path, decision);
+        diag!(
+            2,
+            "{}",
+            SectionDecision {
+                path: path.map(str::to_owned),
+                decision
+            }
+        );
+        if let Some(callback) = &self.section_callback {
+            let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+            callback(&SectionInfo { path, decision });
+        }
+    }
+
+    /// Record the decision for a section and write it to `writer` if it is kept, or to
+    /// `filtered` if it is dropped
+    ///
+    /// Text before the first header has no decision and is always written. Sections
+    /// keep their original line endings (LF or CRLF, possibly mixed), so they are
+    /// written byte-exact.
+    fn write_decided_section<W: Write, F: Write>(
+        &self,
+        section: &DiffSection,
+        decision: Option<FilterDecision>,
+        report: &mut FilterReport,
+        writer: &mut W,
+        filtered: &mut F,
+    ) -> Result<()> {
+        self.check_parsable(section, decision)?;
+        let written = match decision {
+            Some(decision) => {
+                self.record_decision(report, section.path(), decision);
+
+                match (decision.is_kept(), self.keep_empty_diff_headers) {
+                    (true, _) if self.annotate => section.write_annotated_to(
+                        writer,
+                        &self.keep_reason(section.path().unwrap_or_default()),
+                    ),
+                    (true, _) => section.write_to(writer),
+                    (false, keep_header) => {
+                        Self::check_written(section.write_to(&mut *filtered))?;
+                        if !keep_header {
+                            return Ok(());
+                        }
+                        section.write_filtered_marker_to(writer, decision.reason())
+                    }
+                }
+            }
+            None => section.write_to(writer),
+        };
+
+        Self::check_written(written)
+    }
+
+    /// Fail on a section whose path cannot be determined if such sections are errors
+    fn check_parsable(
+        &self,
+        section: &DiffSection,
+        decision: Option<FilterDecision>,
+    ) -> Result<()> {
+        match decision {
+            Some(FilterDecision::Unparsable) if self.on_unparsable == UnparsableAction::Error => {
+                Err(Error::processing_error(format!(
+                    "Cannot determine the path of diff section '{}'",
+                    section.header
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Turn the result of writing (part of) a section into a processing result
+    fn check_written(written: io::Result<()>) -> Result<()> {
+        match written {
+            // A closed reader is not an error; remaining sections are still
+            // recorded in the report
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(Error::processing_error(
+                format!("Failed to write section: {e}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Process the diff and write the decision made for each section instead of the diff
+    fn process_dry_run<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        mut writer: W,
+    ) -> Result<FilterReport> {
+        let report = self.process_direct(reader, io::sink())?;
+        let terminator = if self.null_terminated { '\0' } else { '\n' };
+
+        report
+            .sections()
+            .iter()
+            .try_for_each(|section| write!(writer, "{section}{terminator}"))
+            .or_else(|e| match e.kind() {
+                io::ErrorKind::BrokenPipe => Ok(()),
+                _ => Err(Error::processing_error(format!(
+                    "Failed to write decisions: {e}"
+                ))),
+            })?;
+
+        Ok(report)
+    }
+
+    /// Process the diff and write a JSON array describing each section instead of the diff
+    ///
+    /// Each section becomes one object with its `path`, `status` (`kept` or `filtered`),
+    /// `reason` (see [`FilterDecision::reason`]), `header` and `body_bytes`. The objects
+    /// are written one per line as the sections are decided, so large diffs are not
+    /// held in memory. To pair renames, the whole diff is read first instead.
+    fn process_json<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<FilterReport> {
+        let mut report = FilterReport::default();
+        let mut objects = 0;
+        Self::check_json_written(writer.write_all(b"["))?;
+
+        let mut write_object = |report: &mut FilterReport,
+                                section: &DiffSection,
+                                decision: Option<FilterDecision>,
+                                body_bytes: usize| {
+            // Preambles, including commit metadata, have no decision
+            let Some(decision) = decision else {
+                return Ok(());
+            };
+            self.check_parsable(section, Some(decision))?;
+            self.record_decision(report, section.path(), decision);
+
+            let object = serde_json::json!({
+                "path": section.path(),
+                "status": if decision.is_kept() { "kept" } else { "filtered" },
+                "reason": decision.reason(),
+                "header": section.header,
+                "body_bytes": body_bytes,
+            });
+            let separator = if objects == 0 { "\n" } else { ",\n" };
+            objects += 1;
+            Self::check_json_written(write!(writer, "{separator}{object}"))
+        };
+
+        if self.pair_renames {
+            // Pairing renames needs the whole diff
+            let mut all_data = Vec::new();
+            reader
+                .read_to_end(&mut all_data)
+                .map_err(|e| Error::processing_error(format!("Failed to read input data: {e}")))?;
+
+            let sections = self
+                .sections(all_data.as_slice())
+                .collect::<Result<Vec<_>>>()?;
+            let decisions = self.pair_rename_decisions(&sections, self.decide_serial(&sections));
+            for (section, decision) in sections.iter().zip(decisions) {
+                write_object(&mut report, section, decision, section.body.len())?;
+            }
+        } else {
+            self.decide_sections(reader, |sections, section, decision| {
+                // Sections cut off at the size limit are counted without buffering the rest
+                let mut body_bytes = section.body.len();
+                if section.is_truncated() {
+                    while let Some(raw_line) = sections.read_rest_line()? {
+                        body_bytes += raw_line.len();
+                    }
+                }
+                write_object(&mut report, section, decision, body_bytes)
+            })?;
+        }
+
+        Self::check_json_written(writer.write_all(b"\n]\n"))?;
+        Ok(report)
+    }
+
+    /// Turn the result of writing JSON output into a processing result
+    fn check_json_written(written: io::Result<()>) -> Result<()> {
+        match written {
+            // A closed reader is not an error
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(Error::processing_error(
+                format!("Failed to write JSON output: {e}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Process diff with the downstream pipeline, re-running it after transient failures
+    ///
+    /// With retries, the diff is filtered once and the filtered diff is replayed to each
+    /// attempt, so sections are decided (and reported to the callback) only once.
+    fn process_with_downstream_retries<R: BufRead>(&self, reader: R) -> Result<FilterReport> {
+        if self.downstream_retries == 0 {
+            return self.process_with_downstream(reader, &self.downstream_pipeline);
+        }
+
+        let mut filtered = Vec::new();
+        let report = self.process_streaming(reader, &mut filtered)?;
+        self.replay_downstream(&filtered, &report)
+    }
+
+    /// Filter the whole diff first, then pipe it to the downstream pipeline if it has at
+    /// most `max_lines` lines and write it to `writer` otherwise
+    fn process_with_downstream_limit<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        mut writer: W,
+        max_lines: usize,
+    ) -> Result<FilterReport> {
+        let mut filtered = Vec::new();
+        let report = self.process_streaming(reader, &mut filtered)?;
+
+        let lines = filtered.split_inclusive(|&byte| byte == b'\n').count();
+        if lines > max_lines {
+            diag!(
+                1,
+                "filtered diff has {lines} lines, more than {max_lines}; not piping it downstream"
+            );
+            Self::check_written(writer.write_all(&filtered))?;
+            return Ok(report);
+        }
+
+        self.replay_downstream(&filtered, &report)
+    }
+
+    /// Pipe an already filtered diff to the downstream pipeline, retrying on transient
+    /// failures
+    fn replay_downstream(&self, filtered: &[u8], report: &FilterReport) -> Result<FilterReport> {
+        self.retry_downstream(|| {
+            self.run_downstream(&self.downstream_pipeline, |mut stdin| {
+                Self::check_written(stdin.write_all(filtered))?;
+                Ok(report.clone())
+            })
+        })
+    }
+
+    /// Run an attempt at the downstream pipeline, re-running it up to the configured
+    /// number of retries while a stage fails to spawn or exits non-zero
+    fn retry_downstream<F>(&self, mut attempt: F) -> Result<FilterReport>
+    where
+        F: FnMut() -> Result<FilterReport>,
+    {
+        let mut retries_left = self.downstream_retries;
+        loop {
+            match attempt() {
+                Err(Error::DownstreamSpawnFailed { .. } | Error::DownstreamExitNonZero { .. })
+                    if retries_left > 0 =>
+                {
+                    retries_left -= 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Process diff with a pipeline of downstream commands
+    fn process_with_downstream<R: BufRead>(
+        &self,
+        reader: R,
+        pipeline: &[String],
+    ) -> Result<FilterReport> {
+        self.run_downstream(pipeline, |stdin| self.process_streaming(reader, stdin))
+    }
+
+    /// Run a pipeline of downstream commands, with `feed` writing the input of the first stage
+    fn run_downstream<F>(&self, pipeline: &[String], feed: F) -> Result<FilterReport>
+    where
+        F: FnOnce(ChannelWriter) -> Result<FilterReport>,
+    {
+        let mut stages = self.spawn_stages(pipeline)?;
+
+        let Some(stdin) = stages.first_mut().and_then(|(_, child)| child.stdin.take()) else {
+            Self::stop_stages(stages);
+            return Err(Error::processing_error(
+                "Failed to get stdin of downstream command".to_string(),
+            ));
+        };
+
+        let (commands, children): (Vec<&str>, Vec<Child>) = stages.into_iter().unzip();
+        let children = Mutex::new(children);
+        let timed_out = AtomicBool::new(false);
+
+        let (process_result, exit_statuses) = thread::scope(|scope| {
+            // Dropping the sender tells the watchdog that the pipeline finished in time
+            let (done, finished) = mpsc::channel::<()>();
+            if let Some(timeout) = self.downstream_timeout {
+                let (children, timed_out) = (&children, &timed_out);
+                scope.spawn(move || {
+                    if finished.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                        Self::kill_running_stages(children, timed_out);
+                    }
+                });
+            }
+
+            // A writer thread feeds the kept sections to the first stage's stdin, so
+            // reading and filtering go on while the stage is busy, up to the channel's
+            // capacity. Stdin is closed once processing ends and the channel is drained.
+            // Killing the first stage on timeout unblocks a write to a full pipe.
+            let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(DOWNSTREAM_CHANNEL_CAPACITY);
+            let writer = scope.spawn(move || {
+                let mut stdin = DownstreamStdin {
+                    inner: stdin,
+                    error: None,
+                };
+                // Dropping the receiver on a failed write stops the filtering thread
+                for chunk in receiver {
+                    if stdin.write_all(&chunk).is_err() {
+                        break;
+                    }
+                }
+                stdin
+            });
+
+            let process_result = feed(ChannelWriter { sender });
+            let stdin = writer
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            let process_result = stdin.finish(process_result);
+            let exit_statuses = Self::wait_for_stages(&children, self.downstream_timeout.is_some());
+            drop(done);
+            (process_result, exit_statuses)
+        });
+
+        if timed_out.load(Ordering::SeqCst) {
+            return Err(Error::DownstreamTimeout(format!(
+                "Downstream command '{}' did not finish within {:?} and was killed",
+                commands.join(" | "),
+                self.downstream_timeout.unwrap_or_default()
+            )));
+        }
+
+        let exit_statuses = exit_statuses.map_err(|e| {
+            Error::processing_error(format!("Failed to wait for downstream command: {e}"))
+        })?;
+
+        let stage_count = commands.len();
+        for (index, (command, exit_status)) in commands.into_iter().zip(exit_statuses).enumerate() {
+            // A stage killed because a later stage stopped reading is not a failure,
+            // just like a broken pipe when writing to the first stage
+            if !exit_status.success() && !Self::terminated_by_broken_pipe(&exit_status) {
+                return Err(Error::DownstreamExitNonZero {
+                    command: command.to_string(),
+                    code: exit_status.code(),
+                    stage: (stage_count > 1).then_some((index + 1, stage_count)),
+                });
+            }
+        }
+
+        // Return the result from process_streaming - if it was a broken pipe, we should handle it gracefully
+        process_result
+    }
+
+    /// Spawn the downstream stages, each reading the previous stage's stdout
+    ///
+    /// If a stage cannot be spawned, the stages already running are stopped, so that a
+    /// failed attempt leaves no processes behind.
+    fn spawn_stages<'p>(&self, pipeline: &'p [String]) -> Result<Vec<(&'p str, Child)>> {
+        let mut stages: Vec<(&str, Child)> = Vec::with_capacity(pipeline.len());
+
+        for (index, command) in pipeline.iter().enumerate() {
+            let is_last = index + 1 == pipeline.len();
+
+            // The first stage reads the filtered diff, later stages the previous stage's stdout
+            let stdin = match stages.last_mut() {
+                Some((_, previous)) => previous
+                    .stdout
+                    .take()
+                    .map_or_else(Stdio::piped, Stdio::from),
+                None => Stdio::piped(),
+            };
+
+            let spawned = self.downstream_command(command).and_then(|mut process| {
+                process
+                    .stdin(stdin)
+                    .stdout(if is_last {
+                        Stdio::inherit()
+                    } else {
+                        Stdio::piped()
+                    })
+                    .stderr(Stdio::inherit())
+                    .spawn()
+                    .map_err(|source| Error::DownstreamSpawnFailed {
+                        command: command.clone(),
+                        source,
+                    })
+            });
+            let child = match spawned {
+                Ok(child) => child,
+                Err(error) => {
+                    Self::stop_stages(stages);
+                    return Err(error);
+                }
+            };
+            diag!(
+                1,
+                "spawned downstream command '{command}' (pid {})",
+                child.id()
+            );
+
+            stages.push((command, child));
+        }
+
+        Ok(stages)
+    }
+
+    /// Kill the downstream stages and wait for them to exit
+    fn stop_stages(stages: Vec<(&str, Child)>) {
+        for (_, mut child) in stages {
+            // A stage that already exited cannot be killed; waiting reaps it either way
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    /// Wait for all downstream stages to exit
+    ///
+    /// With a watchdog running the stages are polled, so the lock is released between
+    /// checks and the watchdog can kill them.
+    fn wait_for_stages(children: &Mutex<Vec<Child>>, poll: bool) -> io::Result<Vec<ExitStatus>> {
+        if !poll {
+            let mut children = children.lock().unwrap_or_else(PoisonError::into_inner);
+            return children.iter_mut().map(Child::wait).collect();
+        }
+
+        loop {
+            let exit_statuses = children
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter_mut()
+                .map(Child::try_wait)
+                .collect::<io::Result<Option<Vec<_>>>>()?;
+
+            if let Some(exit_statuses) = exit_statuses {
+                return Ok(exit_statuses);
+            }
+            thread::sleep(DOWNSTREAM_POLL_INTERVAL);
+        }
+    }
+
+    /// Kill the downstream stages that are still running and flag the timeout
+    fn kill_running_stages(children: &Mutex<Vec<Child>>, timed_out: &AtomicBool) {
+        let mut children = children.lock().unwrap_or_else(PoisonError::into_inner);
+        for child in children.iter_mut() {
+            if matches!(child.try_wait(), Ok(None)) {
+                timed_out.store(true, Ordering::SeqCst);
+                // The stage may exit on its own right now; then there is nothing to kill
+                let _ = child.kill();
+            }
+        }
+    }
+
+    /// Build the process for one downstream stage
+    ///
+    /// Without the shell opt-in the command is split into argv with shell quoting rules
+    /// and the program is spawned directly, so no shell syntax is interpreted.
+    fn downstream_command(&self, command: &str) -> Result<Command> {
+        let mut process = if self.downstream_shell {
+            let mut process = Command::new("sh");
+            process.arg("-c").arg(command);
+            process
+        } else {
+            let argv = Self::split_downstream_command(command)?;
+            let (program, args) =
+                argv.split_first()
+                    .ok_or_else(|| Error::DownstreamSpawnFailed {
+                        command: command.to_string(),
+                        source: io::Error::new(io::ErrorKind::InvalidInput, "command is empty"),
+                    })?;
+
+            let mut process = Command::new(program);
+            process.args(args);
+            process
+        };
+
+        if let Some(dir) = &self.downstream_cwd {
+            // Checked here, as spawning reports a missing directory like a missing command
+            if !dir.is_dir() {
+                return Err(Error::DownstreamSpawnFailed {
+                    command: command.to_string(),
+                    source: io::Error::other(format!(
+                        "working directory '{}' is not a directory",
+                        dir.display()
+                    )),
+                });
+            }
+            process.current_dir(dir);
+        }
+        process.envs(
+            self.downstream_env
+                .iter()
+                .map(|(name, value)| (name, value)),
+        );
+
+        Ok(process)
+    }
+
+    /// Split a downstream command into program and arguments
+    fn split_downstream_command(command: &str) -> Result<Vec<String>> {
+        shell_words::split(command).map_err(|e| Error::DownstreamSpawnFailed {
+            command: command.to_string(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, e),
+        })
+    }
+
+    /// Check if a downstream process was terminated by `SIGPIPE`
+    ///
+    /// Stages run through `sh -c`, so the shell may report the signal as exit code 128 + `SIGPIPE`.
+    #[cfg(unix)]
+    fn terminated_by_broken_pipe(exit_status: &ExitStatus) -> bool {
+        use std::os::unix::process::ExitStatusExt;
+
+        const SIGPIPE: i32 = 13;
+        exit_status.signal() == Some(SIGPIPE) || exit_status.code() == Some(128 + SIGPIPE)
+    }
+
+    /// Check if a downstream process was terminated by `SIGPIPE`
+    #[cfg(not(unix))]
+    fn terminated_by_broken_pipe(_exit_status: &ExitStatus) -> bool {
+        false
+    }
+
+    /// Decide whether a file is kept based on gitignore and VCS patterns, and which
+    /// kind of pattern filtered it otherwise
+    pub(crate) fn decide_file(&self, file_path: &str) -> FilterDecision {
+        self.decide_path(file_path, false)
+    }
+
+    /// Decide whether a submodule is kept, matching its path as a directory
+    pub(crate) fn decide_submodule(&self, path: &str) -> FilterDecision {
+        self.decide_path(path, true)
+    }
+
+    /// Decide about a file or directory path based on gitignore, VCS and extension filters
+    ///
+    /// Force-included paths are always kept; with [`Filter::with_only_vcs`], only the VCS
+    /// patterns decide.
+    fn decide_path(&self, file_path: &str, is_dir: bool) -> FilterDecision {
+        if self.is_force_included(file_path, is_dir) {
+            return FilterDecision::Keep;
+        }
+
+        if self.only_vcs {
+            return if self.is_vcs_path(self.strip_prefix(file_path), is_dir) {
+                FilterDecision::Keep
+            } else {
+                FilterDecision::FilteredByOnlyVcs
+            };
+        }
+
+        match self.decide_by_ignore_rules(file_path, is_dir) {
+            FilterDecision::Keep if !self.is_extension_kept(file_path) => {
+                FilterDecision::FilteredByExtension
+            }
+            decision => decision,
+        }
+    }
+
+    /// Check a file's extension against the allowed and excluded extensions
+    ///
+    /// Dotfiles such as `.env` count as files without an extension.
+    fn is_extension_kept(&self, file_path: &str) -> bool {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|extension| extension.to_str());
+        let allowed = self.only_extensions.is_empty()
+            || extension
+                .is_some_and(|extension| self.only_extensions.iter().any(|e| e == extension));
+        let excluded = extension
+            .is_some_and(|extension| self.excluded_extensions.iter().any(|e| e == extension));
+        allowed && !excluded
+    }
+
+    /// Decide whether a section kept by its path is dropped for the kind of change it makes
+    pub(crate) fn decide_change(&self, section: &DiffSection) -> FilterDecision {
+        if self.drop_deletions && section.is_deletion() {
+            FilterDecision::FilteredByDeletion
+        } else if self.drop_mode_only && section.is_mode_only() {
+            FilterDecision::FilteredByModeOnly
+        } else {
+            FilterDecision::Keep
+        }
+    }
+
+    /// Decide whether a single path would be filtered, without processing a diff
+    ///
+    /// Makes the same decision as [`Filter::process_diff`] for a section of the path,
+    /// including the extension filters; with [`Filter::with_only_vcs`], paths matching a
+    /// VCS pattern are kept and all others are [`FilterDecision::FilteredByOnlyVcs`].
+    /// Paths matching [`Filter::with_force_include`] are always kept.
+    /// Set `is_dir` when `path` names a directory, so that directory-only patterns such as
+    /// `build/` match it. The result is never [`FilterDecision::Unparsable`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use diff_gitignore_filter::{Filter, FilterDecision};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let filter = Filter::new(".")?;
+    /// if filter.is_path_filtered("target", true) != FilterDecision::Keep {
+    ///     println!("target/ is filtered");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_path_filtered(&self, path: &str, is_dir: bool) -> FilterDecision {
+        self.decide_path(path, is_dir)
+    }
+
+    /// Decide about a path by the VCS, gitignore and .diffignore patterns alone
+    fn decide_by_ignore_rules(&self, path: &str, is_dir: bool) -> FilterDecision {
+        let path = self.strip_prefix(path);
+
+        // Check VCS patterns first - only if VCS filtering is enabled
+        if self.vcs_filtering_enabled && self.is_vcs_path(path, is_dir) {
+            return FilterDecision::FilteredByVcs; // Exclude VCS files when VCS filtering is enabled
+        }
+
+        // Check gitignore patterns, relative to the directory they are anchored at
+        let gitignore_path = self.gitignore_path(path);
+        if self.is_gitignored(&gitignore_path, is_dir) {
+            FilterDecision::FilteredByGitignore
+        } else if self.is_diffignored(&gitignore_path, is_dir) {
+            FilterDecision::FilteredByDiffignore
+        } else {
+            FilterDecision::Keep
+        }
+    }
+
+    /// Why a path was not filtered: the `!pattern` of a .gitignore or .diffignore that
+    /// re-included it or one of its parents, or `no matching ignore rule`
+    ///
+    /// A matching pattern of [`Filter::with_force_include`] comes first; with
+    /// [`Filter::with_only_vcs`], kept paths are the ones matching a VCS pattern.
+    pub(crate) fn keep_reason(&self, file_path: &str) -> String {
+        let gitignore_path = self.gitignore_path(self.strip_prefix(file_path));
+        if let Some(Match::Ignore(glob)) = self.force_include.as_ref().map(|force_include| {
+            Self::matched_path_or_parents(force_include, &gitignore_path, false)
+        }) {
+            return format!("force-included by {}", glob.original());
+        }
+
+        if self.only_vcs {
+            return "matches a VCS pattern".to_owned();
+        }
+
+        // The deepest re-inclusion is the one that applies to the path
+        Path::new(gitignore_path.as_ref())
+            .ancestors()
+            .take_while(|path| *path != Path::new("") && *path != Path::new("/"))
+            .enumerate()
+            .find_map(|(depth, path)| {
+                let is_dir = depth > 0;
+                let diffignore = self
+                    .diffignore
+                    .as_ref()
+                    .map(|diffignore| diffignore.matched(path, is_dir));
+
+                [Some(self.gitignore_matched(path, is_dir)), diffignore]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|matched| match matched {
+                        Match::Whitelist(glob) => Some(glob.original().to_owned()),
+                        _ => None,
+                    })
+            })
+            .map_or_else(
+                || "no matching ignore rule".to_owned(),
+                |pattern| format!("re-included by {pattern}"),
+            )
+    }
+
+    /// Diff path as matched against .gitignore patterns, relative to `gitignore_root`
+    fn gitignore_path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        match &self.gitignore_path_prefix {
+            Some(prefix) if !Path::new(path).is_absolute() => {
+                Cow::Owned(format!("{prefix}/{path}"))
+            }
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    /// Check if a diff path matches the force-include patterns
+    fn is_force_included(&self, file_path: &str, is_dir: bool) -> bool {
+        self.force_include.as_ref().is_some_and(|force_include| {
+            let path = self.gitignore_path(self.strip_prefix(file_path));
+            Self::matched_path_or_parents(force_include, &path, is_dir).is_ignore()
+        })
+    }
+
+    /// Check if a path relative to `gitignore_root` matches the .diffignore patterns
+    fn is_diffignored(&self, path: &str, is_dir: bool) -> bool {
+        self.diffignore.as_ref().is_some_and(|diffignore| {
+            Self::matched_path_or_parents(diffignore, path, is_dir).is_ignore()
+        })
+    }
+
+    /// Check if a path relative to `gitignore_root` is ignored by the .gitignore files
+    fn is_gitignored(&self, path: &str, is_dir: bool) -> bool {
+        if self.nested_gitignores.is_empty() {
+            return self.gitignore.as_ref().is_some_and(|gitignore| {
+                Self::matched_path_or_parents(gitignore, path, is_dir).is_ignore()
+            });
+        }
+
+        // Same rule as `matched_path_or_parents`, across all .gitignore files
+        let path = Path::new(path);
+        path.ancestors()
+            .skip(1) // Skip the file itself
+            .take_while(|parent| *parent != Path::new("") && *parent != Path::new("/"))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .any(|parent| self.gitignore_match(parent, true))
+            || self.gitignore_match(path, is_dir)
+    }
+
+    /// Match a single path against the .gitignore files that apply to it
+    ///
+    /// The deepest .gitignore with a matching pattern decides, with the path taken
+    /// relative to its directory; the root .gitignore is consulted last.
+    fn gitignore_match(&self, path: &Path, is_dir: bool) -> bool {
+        self.gitignore_matched(path, is_dir).is_ignore()
+    }
+
+    /// Match of the deciding .gitignore for a single path, see [`Filter::gitignore_match`]
+    fn gitignore_matched(&self, path: &Path, is_dir: bool) -> Match<&Glob> {
+        self.nested_gitignores
+            .iter()
+            .rev()
+            .filter_map(|(directory, gitignore)| {
+                path.strip_prefix(directory)
+                    .ok()
+                    .filter(|relative| *relative != Path::new(""))
+                    .map(|relative| gitignore.matched(relative, is_dir))
+            })
+            .chain(
+                self.gitignore
+                    .iter()
+                    .map(|gitignore| gitignore.matched(path, is_dir)),
+            )
+            .find(|matched| !matched.is_none())
+            .unwrap_or(Match::None)
+    }
+
+    /// Remove the configured path prefix from a diff path, if it starts with it
+    fn strip_prefix<'a>(&self, file_path: &'a str) -> &'a str {
+        self.strip_path_prefix
+            .as_deref()
+            .filter(|prefix| !prefix.is_empty())
+            .and_then(|prefix| file_path.strip_prefix(prefix))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|rest| !rest.is_empty())
+            .unwrap_or(file_path)
+    }
+
+    /// Match a file path against a matcher the way Git decides whether it is ignored
+    ///
+    /// Parent directories are checked first, from the top down: once a directory is
+    /// ignored, nothing below it can be re-included by a `!pattern`, so the first ignored
+    /// parent decides. Otherwise the file's own match (the last matching pattern, which
+    /// accounts for negation order) decides.
+    ///
+    /// Unlike `Gitignore::matched_path_or_any_parents`, paths outside the matcher's root
+    /// (e.g. absolute diff paths) do not panic.
+    fn matched_path_or_parents<'a>(
+        gitignore: &'a Gitignore,
+        file_path: &str,
+        is_dir: bool,
+    ) -> Match<&'a Glob> {
+        let path = Path::new(file_path);
+        let parents: Vec<&Path> = path
+            .ancestors()
+            .skip(1) // Skip the file itself
+            .take_while(|parent| *parent != Path::new("") && *parent != Path::new("/"))
+            .collect();
+
+        parents
+            .into_iter()
+            .rev()
+            .map(|parent| gitignore.matched(parent, true))
+            .find(Match::is_ignore)
+            .unwrap_or_else(|| gitignore.matched(path, is_dir))
+    }
+
+    /// Check if a file or directory matches VCS patterns
+    fn is_vcs_path(&self, path: &str, is_dir: bool) -> bool {
+        self.vcs_matcher
+            .as_ref()
+            .is_some_and(|matcher| Self::matched_path_or_parents(matcher, path, is_dir).is_ignore())
+    }
+}
+
+/// Writer adapter filtering the diff written to it, created by [`Filter::writer`]
+///
+/// Written bytes are buffered until the header of the next section shows that a section
+/// is complete; the complete sections are then filtered to the inner writer, so only
+/// about one section is held in memory. A header split across writes is recognized once
+/// its line is complete. [`Write::flush`] also filters the bytes of the last section up
+/// to its last complete line, and bytes written after the flush that continue that
+/// section follow the decision made for it. [`FilterWriter::finish`], or dropping the
+/// writer, filters the rest.
+///
+/// Sections are decided serially, as for a downstream pipeline. When a section's
+/// decision depends on later sections ([`Filter::with_pair_renames`]), on .gitignore
+/// files earlier in the diff ([`Filter::with_apply_diff_gitignore`]), or when color
+/// sequences are stripped, the whole diff is buffered and filtered only when the writer
+/// is finished.
+pub struct FilterWriter<'a, W: Write> {
+    filter: &'a Filter,
+    inner: W,
+    /// Bytes written but not yet filtered
+    pending: Vec<u8>,
+    /// Length of the start of `pending` already searched for section headers
+    scanned: usize,
+    /// Whether the section last filtered by a flush was kept, for the bytes continuing it
+    continues_kept: Option<bool>,
+    report: FilterReport,
+    finished: bool,
+}
+
+impl<W: Write> FilterWriter<'_, W> {
+    /// Writer the kept sections are written to
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Decisions made for the sections filtered so far
+    pub fn report(&self) -> &FilterReport {
+        &self.report
+    }
+
+    /// Filter the rest of the diff, flush the inner writer and return the report
+    ///
+    /// Unlike dropping the writer, this reports errors.
+    pub fn finish(mut self) -> io::Result<FilterReport> {
+        self.finish_pending()?;
+        Ok(std::mem::take(&mut self.report))
+    }
+
+    /// Filter all pending bytes and flush the inner writer
+    fn finish_pending(&mut self) -> io::Result<()> {
+        self.finished = true;
+        self.filter_pending(self.pending.len(), false)?;
+        self.inner.flush()
+    }
+
+    /// Check if sections can only be decided once the whole diff has been written
+    fn buffers_whole_diff(&self) -> bool {
+        self.filter.pair_renames || self.filter.apply_diff_gitignore || self.filter.strip_ansi
+    }
+
+    /// Start of the last section in `pending` if the lines completed since the last
+    /// call hold a section header, so the sections before it are complete
+    fn last_section_start(&mut self) -> Option<usize> {
+        let complete = self.scanned
+            + self.pending[self.scanned..]
+                .iter()
+                .rposition(|&byte| byte == b'\n')?
+            + 1;
+        let new_lines = &self.pending[self.scanned..complete];
+        self.scanned = complete;
+
+        let has_header = new_lines
+            .split_inclusive(|&byte| byte == b'\n')
+            .any(|line| {
+                line.starts_with(b"diff ")
+                    || line.starts_with(b"commit ")
+                    || line.starts_with(b"--- ")
+            });
+        if !has_header {
+            return None;
+        }
+
+        // A parse error is reported when the section is filtered
+        let last = self
+            .filter
+            .sections(&self.pending[..complete])
+            .last()?
+            .ok()?;
+        let start = complete - last.raw_len();
+        (start > 0).then_some(start)
+    }
+
+    /// Filter the first `len` bytes of `pending` to the inner writer
+    ///
+    /// With `at_boundary` the bytes end right before a section header; otherwise the bytes
+    /// written next may continue their last section.
+    fn filter_pending(&mut self, len: usize, at_boundary: bool) -> io::Result<()> {
+        let batch: Vec<u8> = self.pending.drain(..len).collect();
+        self.scanned = self.scanned.saturating_sub(len);
+        let mut batch = batch.as_slice();
+
+        // Bytes before the first header continue the section of the previous batch
+        if let Some(kept) = self.continues_kept {
+            let continued = match self.filter.sections(batch).next() {
+                Some(Ok(section)) if section.header.is_empty() => section.raw_len(),
+                _ => 0,
+            };
+            if kept {
+                self.inner.write_all(&batch[..continued])?;
+            }
+            batch = &batch[continued..];
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let report = if self.filter.strip_ansi {
+            self.filter
+                .process_streaming(StripAnsi::new(batch), &mut self.inner)
+        } else {
+            self.filter.process_streaming(batch, &mut self.inner)
+        }
+        .map_err(io::Error::other)?;
+
+        self.continues_kept = if at_boundary {
+            None
+        } else {
+            match self.filter.sections(batch).last() {
+                Some(Ok(section)) if !section.is_preamble() => report
+                    .sections()
+                    .last()
+                    .map(|decision| decision.decision.is_kept()),
+                _ => Some(true),
+            }
+        };
+        self.report.merge(report);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for FilterWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if !self.buffers_whole_diff() {
+            if let Some(start) = self.last_section_start() {
+                self.filter_pending(start, true)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffers_whole_diff() {
+            if let Some(end) = self.pending.iter().rposition(|&byte| byte == b'\n') {
+                self.filter_pending(end + 1, false)?;
+            }
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for FilterWriter<'_, W> {
+    fn drop(&mut self) {
+        // Errors cannot be reported from here; call `finish` to see them
+        if !self.finished {
+            let _ = self.finish_pending();
+        }
+    }
+}
+
+/// Builder collecting the filter options before the fallible construction of a [`Filter`]
+///
+/// Convenient when several options come from configuration; all of them are validated
+/// together in [`FilterBuilder::build`], which reads the ignore files only once. Options
+/// not covered here are set on the built filter with its `with_*` methods.
+///
+/// ```rust
+/// use diff_gitignore_filter::FilterBuilder;
+///
+/// let filter = FilterBuilder::new(".")
+///     .with_vcs_enabled(true)
+///     .with_vcs_patterns(vec![".git/".to_string()])
+///     .with_case_insensitive(true)
+///     .with_extra_ignore_patterns(vec!["*.snap".to_string()])
+///     .build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FilterBuilder {
+    /// Root directory the diff paths are relative to
+    root: PathBuf,
+    /// VCS patterns used when VCS filtering is enabled
+    vcs_patterns: Vec<String>,
+    /// Whether VCS filtering is enabled
+    vcs_enabled: bool,
+    /// Downstream commands for piping output, each stage feeding the next
+    downstream: Vec<String>,
+    /// Whether .gitignore patterns match case-insensitively
+    case_insensitive: bool,
+    /// Whether .gitignore files in subdirectories are read as well
+    nested_gitignore: bool,
+    /// File read instead of the root .gitignore
+    gitignore_file: Option<PathBuf>,
+    /// Global ignore file read before the root .gitignore
+    global_excludes_file: Option<PathBuf>,
+    /// Ignore patterns following those of the root .gitignore
+    extra_ignore_patterns: Vec<String>,
+    /// File read instead of `.diffignore` in the root
+    diffignore: Option<PathBuf>,
+    /// File with patterns of paths that are always kept
+    force_include: Option<PathBuf>,
+}
+
+impl FilterBuilder {
+    /// Create a builder for a filter on the given root directory
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Set VCS patterns, used when VCS filtering is enabled
+    pub fn with_vcs_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.vcs_patterns = patterns;
+        self
+    }
+
+    /// Set VCS filtering enabled state
+    pub fn with_vcs_enabled(mut self, enabled: bool) -> Self {
+        self.vcs_enabled = enabled;
+        self
+    }
+
+    /// Set a single downstream command
+    pub fn with_downstream(self, command: String) -> Self {
+        self.with_downstream_pipeline(vec![command])
+    }
+
+    /// Set downstream pipeline stages, see [`Filter::with_downstream_pipeline`]
+    pub fn with_downstream_pipeline(mut self, pipeline: Vec<String>) -> Self {
+        self.downstream = pipeline;
+        self
+    }
+
+    /// Set case-insensitive .gitignore matching
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Set whether .gitignore files in subdirectories are read, see [`Filter::with_nested_gitignore`]
+    pub fn with_nested_gitignore(mut self, enabled: bool) -> Self {
+        self.nested_gitignore = enabled;
+        self
+    }
+
+    /// Set the file read instead of the root .gitignore, see [`Filter::with_gitignore_file`]
+    pub fn with_gitignore_file(mut self, file: PathBuf) -> Self {
+        self.gitignore_file = Some(file);
+        self
+    }
+
+    /// Set the global ignore file, see [`Filter::with_global_excludes_file`]
+    pub fn with_global_excludes_file(mut self, file: PathBuf) -> Self {
+        self.global_excludes_file = Some(file);
+        self
+    }
+
+    /// Set additional ignore patterns, see [`Filter::with_extra_ignore_patterns`]
+    pub fn with_extra_ignore_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.extra_ignore_patterns = patterns;
+        self
+    }
+
+    /// Set the file read instead of `.diffignore`, see [`Filter::with_diffignore`]
+    pub fn with_diffignore(mut self, file: PathBuf) -> Self {
+        self.diffignore = Some(file);
+        self
+    }
+
+    /// Set the file of always kept paths, see [`Filter::with_force_include`]
+    pub fn with_force_include(mut self, file: PathBuf) -> Self {
+        self.force_include = Some(file);
+        self
+    }
+
+    /// Validate the options and build the filter
+    ///
+    /// Fails if a downstream command is empty, a configured gitignore, diffignore or
+    /// force-include file does not exist, or the .gitignore matching cannot be set up.
+    pub fn build(self) -> Result<Filter> {
+        if self
+            .downstream
+            .iter()
+            .any(|command| command.trim().is_empty())
+        {
+            return Err(Error::processing_error(
+                "Downstream command must not be empty".to_string(),
+            ));
+        }
+
+        let mut filter = Filter::with_gitignore(&self.root, None, Vec::new());
+        filter.case_insensitive = self.case_insensitive;
+        filter.nested_gitignore = self.nested_gitignore;
+        filter.gitignore_file = self.gitignore_file;
+        filter.global_excludes_file = self.global_excludes_file;
+        filter.extra_ignore_patterns = self.extra_ignore_patterns;
+        filter.diffignore_file = self.diffignore;
+        filter.force_include_file = self.force_include;
+        filter.load_gitignores()?;
+
+        Ok(filter
+            .with_vcs_patterns(self.vcs_patterns)
+            .with_vcs_enabled(self.vcs_enabled)
+            .with_downstream_pipeline(self.downstream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicUsize;
+    use tempfile::TempDir;
+
+    fn create_test_repo() -> Result<TempDir> {
+        let temp_dir = TempDir::new().map_err(|e| Error::processing_error(e.to_string()))?;
+
+        // Create .git directory
+        fs::create_dir(temp_dir.path().join(".git"))
+            .map_err(|e| Error::processing_error(e.to_string()))?;
+
+        // Create .gitignore
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n")
+            .map_err(|e| Error::processing_error(e.to_string()))?;
+
+        Ok(temp_dir)
+    }
+
+    /// Reader of a header followed by a repeated line, failing if it is read more than
+    /// 1 MiB ahead of the output counted in `written`
+    struct Generated {
+        data: Vec<u8>,
+        line: &'static [u8],
+        remaining: usize,
+        read: usize,
+        written: Arc<AtomicUsize>,
+    }
+
+    impl Generated {
+        fn new(
+            header: &[u8],
+            line: &'static [u8],
+            count: usize,
+            written: &Arc<AtomicUsize>,
+        ) -> Self {
+            Self {
+                data: header.to_vec(),
+                line,
+                remaining: count,
+                read: 0,
+                written: Arc::clone(written),
+            }
+        }
+    }
+
+    impl io::Read for Generated {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.read > self.written.load(Ordering::SeqCst) + (1 << 20) {
+                return Err(io::Error::other("input was buffered"));
+            }
+            if self.data.is_empty() {
+                if self.remaining == 0 {
+                    return Ok(0);
+                }
+                self.data = self.line.to_vec();
+                self.remaining -= 1;
+            }
+            let len = buf.len().min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data.drain(..len);
+            self.read += len;
+            Ok(len)
+        }
+    }
+
+    /// Writer counting the bytes it receives
+    struct Counted(Arc<AtomicUsize>, Vec<u8>);
+
+    impl Write for Counted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.fetch_add(buf.len(), Ordering::SeqCst);
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// **What is tested:** Basic filter creation with default settings
+    /// **Why it is tested:** Ensures that a filter can be created successfully with proper default values and gitignore loading
+    /// **Test conditions:** Creates a temporary repository with .git directory and .gitignore file
+    /// **Expectations:** Filter should have gitignore loaded, empty VCS patterns, VCS filtering disabled, and no downstream command
+    #[test]
+    fn test_filter_creation() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        assert!(filter.gitignore.is_some());
+        assert!(filter.vcs_patterns.is_empty());
+        assert!(!filter.vcs_filtering_enabled); // Default: VCS filtering disabled
+        assert!(filter.downstream_pipeline.is_empty());
+        Ok(())
+    }
+
+    /// **What is tested:** Filter configuration with VCS patterns
+    /// **Why it is tested:** Verifies that VCS patterns are stored independently of the VCS filtering toggle
+    /// **Test conditions:** Creates filter with VCS patterns, then enables VCS filtering with with_vcs_enabled
+    /// **Expectations:** Patterns are stored but inactive until VCS filtering is enabled
+    #[test]
+    fn test_filter_with_vcs_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let patterns = vec![".git/".to_string(), ".svn/".to_string()];
+        let filter = Filter::new(temp_dir.path())?.with_vcs_patterns(patterns.clone());
+
+        assert_eq!(filter.vcs_patterns, patterns);
+        assert!(!filter.vcs_filtering_enabled); // Patterns alone do not enable VCS filtering
+        assert!(filter.decide_file(".git/config").is_kept());
+
+        let filter = filter.with_vcs_enabled(true);
+        assert!(filter.vcs_filtering_enabled);
+        assert_eq!(
+            filter.decide_file(".git/config"),
+            FilterDecision::FilteredByVcs
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Filter configuration with downstream command
+    /// **Why it is tested:** Ensures that downstream commands can be properly configured for piping filtered output
+    /// **Test conditions:** Creates filter and adds downstream command using with_downstream method
+    /// **Expectations:** Downstream command should be stored correctly in the filter
+    #[test]
+    fn test_filter_with_downstream() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let command = "cat".to_string();
+        let filter = Filter::new(temp_dir.path())?.with_downstream(command.clone());
+
+        assert_eq!(filter.downstream_pipeline, vec![command]);
+        Ok(())
+    }
+
+    /// **What is tested:** Splitting downstream commands into argv and the `with_downstream_argv` API
+    /// **Why it is tested:** Commands are spawned without a shell, so quoting must decide the argument boundaries
+    /// **Test conditions:** `grep -v debug` compared with a command using a quoted argument, malformed quoting, and pre-split argv
+    /// **Expectations:** Unquoted words become separate arguments, a quoted argument stays whole, bad quoting is an error and argv round-trips unchanged
+    #[test]
+    fn test_split_downstream_command() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            Filter::split_downstream_command("grep -v debug")?,
+            vec!["grep", "-v", "debug"]
+        );
+        assert_eq!(
+            Filter::split_downstream_command("grep -v 'debug log' --label=\"a b\"")?,
+            vec!["grep", "-v", "debug log", "--label=a b"]
+        );
+        assert!(matches!(
+            Filter::split_downstream_command("grep -v 'debug"),
+            Err(Error::DownstreamSpawnFailed { .. })
+        ));
+
+        let temp_dir = create_test_repo()?;
+        let argv = vec![
+            "grep".to_string(),
+            "-v".to_string(),
+            "debug; rm -rf x".to_string(),
+        ];
+        let filter = Filter::new(temp_dir.path())?.with_downstream_argv(argv.clone());
+        assert_eq!(
+            Filter::split_downstream_command(&filter.downstream_pipeline[0])?,
+            argv
+        );
+        assert!(!filter.downstream_shell);
+        Ok(())
+    }
+
+    /// **What is tested:** Copying a diff unchanged when no filter is active
+    /// **Why it is tested:** `--no-vcs` with an empty .gitignore must not buffer the diff, yet produce the same output and report as full filtering
+    /// **Test conditions:** Empty .gitignore, VCS filtering disabled and unparsable sections dropped; a diff with a preamble, CRLF lines, invalid UTF-8, a rename, a binary patch and an unparsable header, compared with a filter whose pattern matches nothing, also through `filter_bytes`; the unparsable diff with unparsable sections kept (the default); a large generated diff whose reader fails if it is read more than 1 MiB ahead of the output
+    /// **Expectations:** Output and report equal those of full filtering, only the unparsable section is dropped unless unparsable sections are kept, and the large diff streams through byte-identical
+    #[test]
+    fn test_unfiltered_passthrough() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "")?;
+        let filter = Filter::new(temp_dir.path())?.with_on_unparsable(UnparsableAction::Drop);
+        assert!(filter.is_unfiltered());
+
+        let mut diff = b"From 1234 Mon Sep 17 00:00:00 2001\n\n".to_vec();
+        diff.extend_from_slice(b"diff --git a/src/main.rs b/src/main.rs\r\n+fn main() {}\r\n");
+        diff.extend_from_slice(b"diff --git a/debug.log b/debug.log\n+\xff\xfe\n");
+        diff.extend_from_slice(
+            b"diff --git a/old.rs b/new.rs\nsimilarity index 100%\nrename from old.rs\nrename to lib/new.rs\n",
+        );
+        diff.extend_from_slice(
+            b"diff --git a/img.png b/img.png\nGIT binary patch\nliteral 5\ndiff --git a/x b/x\n\n",
+        );
+        diff.extend_from_slice(b"diff --git a/tail.rs b/tail.rs\n+tail\n");
+        let unparsable: &[u8] =
+            b"diff --git a/x.rs b/x.rs\n+kept\ndiff --git unparsable\n+dropped\ndiff --git a/y.rs b/y.rs\n";
+
+        // A pattern matching nothing disables the fast path but keeps every section
+        let filtering = Filter::new(temp_dir.path())?
+            .with_on_unparsable(UnparsableAction::Drop)
+            .with_gitignore_str("*.nomatch\n")?;
+        assert!(!filtering.is_unfiltered());
+        for (input, kept) in [
+            (
+                diff.as_slice(),
+                vec![
+                    "src/main.rs",
+                    "debug.log",
+                    "lib/new.rs",
+                    "img.png",
+                    "tail.rs",
+                ],
+            ),
+            (unparsable, vec!["x.rs", "y.rs"]),
+        ] {
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(input, &mut output)?;
+            let mut expected = Vec::new();
+            let expected_report = filtering.process_diff_with_report(input, &mut expected)?;
+            assert_eq!(output, expected);
+            assert_eq!(report, expected_report);
+            assert_eq!(report.kept_paths().collect::<Vec<_>>(), kept);
+            assert_eq!(filter.filter_bytes(input)?, expected);
+            if input == unparsable {
+                assert_eq!(
+                    output,
+                    b"diff --git a/x.rs b/x.rs\n+kept\ndiff --git a/y.rs b/y.rs\n"
+                );
+                assert_eq!(report.unparsable(), 1);
+            } else {
+                assert_eq!(output, input);
+            }
+        }
+
+        // With unparsable sections kept nothing can be dropped, so the input is copied
+        let keeping = Filter::new(temp_dir.path())?.with_on_unparsable(UnparsableAction::Keep);
+        assert_eq!(keeping.filter_bytes(unparsable)?, unparsable);
+        let mut output = Vec::new();
+        keeping.process_diff(unparsable, &mut output)?;
+        assert_eq!(output, unparsable);
+
+        let section: &[u8] = b"diff --git a/src/lib.rs b/src/lib.rs\n+line\r\n";
+        let count = 50_000;
+        let written = Arc::new(AtomicUsize::new(0));
+        let reader = io::BufReader::new(Generated::new(b"", section, count, &written));
+        let mut output = Counted(Arc::clone(&written), Vec::new());
+        let report = filter.process_diff_with_report(reader, &mut output)?;
+        assert_eq!(output.1, section.repeat(count));
+        assert_eq!(report.kept(), count);
+        Ok(())
+    }
+
+    /// **What is tested:** Applying .gitignore files added or modified by the diff
+    /// **Why it is tested:** Their added lines must drop only later sections, relative to their directory, also with several threads
+    /// **Test conditions:** A root .gitignore adding `*.tmp`, a `sub/.gitignore` adding `/gen/` with a `!keep.tmp` line and a CRLF line, followed by sections of matching and non-matching paths; processed serially and with 4 threads
+    /// **Expectations:** `foo.tmp` before the .gitignore is kept, later `.tmp` files and `sub/gen/` are dropped with reason gitignore, `gen/` outside `sub` and the re-included `sub/keep.tmp` are kept
+    #[test]
+    fn test_apply_diff_gitignore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let diff = "diff --git a/foo.tmp b/foo.tmp\n+before\n\
+                    diff --git a/.gitignore b/.gitignore\n\
+                    --- a/.gitignore\n\
+                    +++ b/.gitignore\n\
+                    @@ -1 +1,2 @@\n\
+                    \x20*.log\n\
+                    +*.tmp\n\
+                    diff --git a/sub/.gitignore b/sub/.gitignore\n\
+                    new file mode 100644\n\
+                    --- /dev/null\n\
+                    +++ b/sub/.gitignore\n\
+                    @@ -0,0 +1,2 @@\n\
+                    +/gen/\r\n\
+                    +!keep.tmp\n\
+                    diff --git a/bar.tmp b/bar.tmp\n+after\n\
+                    diff --git a/sub/gen/out.rs b/sub/gen/out.rs\n+gen\n\
+                    diff --git a/gen/out.rs b/gen/out.rs\n+gen\n\
+                    diff --git a/sub/keep.tmp b/sub/keep.tmp\n+keep\n";
+
+        for threads in [1, 4] {
+            let filter = Filter::new(temp_dir.path())?
+                .with_parallelism(threads)
+                .with_apply_diff_gitignore(true);
+            let report = filter.process_diff_with_report(diff.as_bytes(), io::sink())?;
+            let decisions = report
+                .sections()
+                .iter()
+                .map(|section| {
+                    (
+                        section.path.as_deref().unwrap_or_default(),
+                        section.decision,
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                decisions,
+                vec![
+                    ("foo.tmp", FilterDecision::Keep),
+                    (".gitignore", FilterDecision::Keep),
+                    ("sub/.gitignore", FilterDecision::Keep),
+                    ("bar.tmp", FilterDecision::FilteredByGitignore),
+                    ("sub/gen/out.rs", FilterDecision::FilteredByGitignore),
+                    ("gen/out.rs", FilterDecision::Keep),
+                    ("sub/keep.tmp", FilterDecision::Keep),
+                ]
+            );
+        }
+
+        let filter = Filter::new(temp_dir.path())?;
+        let report = filter.process_diff_with_report(diff.as_bytes(), io::sink())?;
+        assert_eq!(report.filtered(), 0);
+        Ok(())
+    }
+
+    /// **What is tested:** Downstream timeout while waiting for a stage that does not exit
+    /// **Why it is tested:** The deadline must also cover the wait after the whole diff was written
+    /// **Test conditions:** Small diff piped to `sleep 5` with a 200ms timeout, and a zero timeout configured
+    /// **Expectations:** Processing fails with DownstreamTimeout well before `sleep` exits; zero disables the deadline
+    #[test]
+    fn test_downstream_timeout() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let diff_content =
+            "diff --git a/src/main.rs b/src/main.rs\nindex 1234567..abcdefg 100644\n";
+
+        let filter = Filter::new(temp_dir.path())?
+            .with_downstream("sleep 5".to_string())
+            .with_downstream_timeout(Duration::from_millis(200));
+        let started = std::time::Instant::now();
+        let result = filter.process_diff(Cursor::new(diff_content), Vec::new());
+
+        assert!(matches!(result, Err(Error::DownstreamTimeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let filter = filter.with_downstream_timeout(Duration::ZERO);
+        assert_eq!(filter.downstream_timeout, None);
+        Ok(())
+    }
+
+    /// **What is tested:** Re-running the downstream pipeline with `with_downstream_retries`
+    /// **Why it is tested:** Flaky network-backed tools must not abort the run on their first failure
+    /// **Test conditions:** A shell command that counts its runs in a file and fails on the first one, with one retry and a section callback, and without retries; a nonexistent command with two retries
+    /// **Expectations:** The retried run succeeds on the second attempt with the full filtered diff and calls the callback once per section; without retries it fails; the spawn failure is returned after the retries are used up
+    #[test]
+    fn test_downstream_retries() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let counter = temp_dir.path().join("runs");
+        let output = temp_dir.path().join("downstream.out");
+        let diff_content = "diff --git a/src/main.rs b/src/main.rs\n+kept\n\
+                            diff --git a/debug.log b/debug.log\n+ignored\n";
+        let flaky = format!(
+            "runs=$(cat '{counter}' 2>/dev/null || echo 0); echo $((runs + 1)) > '{counter}'; \
+             [ \"$runs\" -ge 1 ] && cat > '{output}'",
+            counter = counter.display(),
+            output = output.display()
+        );
+        let filter = Filter::new(temp_dir.path())?
+            .with_downstream_shell(true)
+            .with_downstream(flaky);
+
+        let result = filter.process_diff(Cursor::new(diff_content), Vec::new());
+        assert!(matches!(result, Err(Error::DownstreamExitNonZero { .. })));
+        fs::remove_file(&counter)?;
+
+        let callbacks = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&callbacks);
+        let report = filter
+            .with_downstream_retries(1)
+            .on_section(move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .process_diff_with_report(Cursor::new(diff_content), Vec::new())?;
+        assert_eq!(fs::read_to_string(&counter)?, "2\n");
+        assert_eq!(callbacks.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            fs::read_to_string(&output)?,
+            "diff --git a/src/main.rs b/src/main.rs\n+kept\n"
+        );
+        assert_eq!(report.sections().len(), 2);
+
+        let result = Filter::new(temp_dir.path())?
+            .with_downstream("this-command-does-not-exist-12345".to_string())
+            .with_downstream_retries(2)
+            .process_diff(Cursor::new(diff_content), Vec::new());
+        assert!(matches!(result, Err(Error::DownstreamSpawnFailed { .. })));
+        Ok(())
+    }
+
+    /// **What is tested:** Stopping the stages already spawned when a later downstream stage cannot be spawned
+    /// **Why it is tested:** Running stages must not be orphaned, least of all once per retry
+    /// **Test conditions:** A pipeline of a long `sleep` with a unique duration followed by a nonexistent command, with two retries
+    /// **Expectations:** The spawn failure is returned, and no process running the `sleep` is left afterwards
+    #[cfg(unix)]
+    #[test]
+    fn test_downstream_spawn_failure_stops_earlier_stages(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let sleep = format!("sleep 61.{}", std::process::id());
+
+        let result = Filter::new(temp_dir.path())?
+            .with_downstream_pipeline(vec![
+                sleep.clone(),
+                "this-command-does-not-exist-12345".to_string(),
+            ])
+            .with_downstream_retries(2)
+            .process_diff(
+                Cursor::new("diff --git a/src/main.rs b/src/main.rs\n"),
+                Vec::new(),
+            );
+        assert!(matches!(result, Err(Error::DownstreamSpawnFailed { .. })));
+
+        let running = Command::new("pgrep").args(["-f", &sleep]).output()?;
+        assert!(
+            running.stdout.is_empty(),
+            "'{sleep}' is still running: {}",
+            String::from_utf8_lossy(&running.stdout)
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Streaming of kept sections to the downstream pipeline while the input is read
+    /// **Why it is tested:** Interactive use needs output before the whole diff is read, and large diffs must not be buffered
+    /// **Test conditions:** 100,000 generated sections, half of them ignored, piped to `cat` writing a file; the generator stalls after two sections until the file is non-empty
+    /// **Expectations:** The first section arrives while the input is still open, processing completes, and the file holds exactly the kept sections
+    #[test]
+    fn test_downstream_streams_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        /// Reader generating a diff section by section, stalling after the second one
+        /// until the downstream output appears
+        struct GeneratedDiff<'a> {
+            next: usize,
+            count: usize,
+            pending: Cursor<Vec<u8>>,
+            output: &'a Path,
+        }
+
+        impl io::Read for GeneratedDiff<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                while self.pending.position() == self.pending.get_ref().len() as u64 {
+                    if self.next == self.count {
+                        return Ok(0);
+                    }
+                    if self.next == 2 {
+                        let started = std::time::Instant::now();
+                        while fs::metadata(self.output).map_or(0, |m| m.len()) == 0 {
+                            if started.elapsed() > Duration::from_secs(10) {
+                                return Err(io::Error::other("first section was not streamed"));
+                            }
+                            thread::sleep(Duration::from_millis(10));
+                        }
+                    }
+                    self.pending = Cursor::new(generated_section(self.next).into_bytes());
+                    self.next += 1;
+                }
+                io::Read::read(&mut self.pending, buf)
+            }
+        }
+
+        fn generated_section(index: usize) -> String {
+            let path = if index % 2 == 0 {
+                format!("src/file{index}.rs")
+            } else {
+                format!("logs/run{index}.log")
+            };
+            format!(
+                "diff --git a/{path} b/{path}\n\
+                 index 1234567..abcdefg 100644\n\
+                 --- a/{path}\n\
+                 +++ b/{path}\n\
+                 @@ -1 +1 @@\n\
+                 -old line {index}\n\
+                 +new line {index}\n"
+            )
+        }
+
+        let temp_dir = create_test_repo()?;
+        let output = temp_dir.path().join("downstream.out");
+        let count = 100_000;
+        let filter = Filter::new(temp_dir.path())?
+            .with_downstream_shell(true)
+            .with_downstream(format!("cat > '{}'", output.display()));
+
+        let reader = io::BufReader::new(GeneratedDiff {
+            next: 0,
+            count,
+            pending: Cursor::new(Vec::new()),
+            output: &output,
+        });
+        let report = filter.process_diff_with_report(reader, io::sink())?;
+
+        let expected: String = (0..count).step_by(2).map(generated_section).collect();
+        assert_eq!(fs::read_to_string(&output)?, expected);
+        assert_eq!(report.sections().len(), count);
+        Ok(())
+    }
+
+    /// **What is tested:** The structured error variants of the downstream path
+    /// **Why it is tested:** Library callers match on them to tell start failures, failing commands and write errors apart
+    /// **Test conditions:** A nonexistent command, `sh -c 'exit 42'`, a large diff piped to `true` (broken pipe), and a first stage whose stdin fails
+    /// **Expectations:** DownstreamSpawnFailed with a NotFound source, DownstreamExitNonZero with code 42, success for the broken pipe, and DownstreamWriteFailed
+    #[test]
+    fn test_downstream_error_variants() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("device full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let temp_dir = create_test_repo()?;
+        let diff_content =
+            "diff --git a/src/main.rs b/src/main.rs\nindex 1234567..abcdefg 100644\n";
+        let filter = Filter::new(temp_dir.path())?;
+
+        let result = Filter::new(temp_dir.path())?
+            .with_downstream("this-command-does-not-exist-12345".to_string())
+            .process_diff(Cursor::new(diff_content), Vec::new());
+        match result {
+            Err(Error::DownstreamSpawnFailed { command, source }) => {
+                assert_eq!(command, "this-command-does-not-exist-12345");
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            other => panic!("Expected DownstreamSpawnFailed, got {other:?}"),
+        }
+
+        let result = Filter::new(temp_dir.path())?
+            .with_downstream("sh -c 'exit 42'".to_string())
+            .process_diff(Cursor::new(diff_content), Vec::new());
+        assert!(matches!(
+            result,
+            Err(Error::DownstreamExitNonZero {
+                code: Some(42),
+                stage: None,
+                ..
+            })
+        ));
+
+        // A stage that stops reading early is not a write failure
+        let large_diff = diff_content.repeat(20_000);
+        Filter::new(temp_dir.path())?
+            .with_downstream("true".to_string())
+            .process_diff(Cursor::new(large_diff), Vec::new())?;
+
+        let mut stdin = DownstreamStdin {
+            inner: FailingWriter,
+            error: None,
+        };
+        let result = filter.process_direct(Cursor::new(diff_content), &mut stdin);
+        match stdin.finish(result) {
+            Err(Error::DownstreamWriteFailed { source }) => {
+                assert_eq!(source.to_string(), "device full");
+            }
+            other => panic!("Expected DownstreamWriteFailed, got {other:?}"),
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Case-insensitive .gitignore matching via `with_case_insensitive`
+    /// **Why it is tested:** With `core.ignorecase=true` Git lets `*.LOG` match `debug.log`, and the filter must agree
+    /// **Test conditions:** Repository ignoring `*.LOG`, diff touching `debug.log`, matching toggled on and off again
+    /// **Expectations:** The lowercase path is filtered only while case-insensitive matching is enabled
+    #[test]
+    fn test_filter_with_case_insensitive() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.LOG\n")?;
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert!(filter.decide_file("debug.log").is_kept());
+
+        let filter = filter.with_case_insensitive(true)?;
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert!(filter.decide_file("src/main.rs").is_kept());
+
+        let filter = filter.with_case_insensitive(false)?;
+        assert!(filter.decide_file("debug.log").is_kept());
+        Ok(())
+    }
+
+    /// **What is tested:** `.gitignore` negation (`!pattern`) in the keep/drop decision
+    /// **Why it is tested:** Git lets the last matching pattern win, but never re-includes files below an ignored directory
+    /// **Test conditions:** `*.log` + `!keep.log`, then `build/` + `!build/important`, then `dist/*` + `!dist/keep`
+    /// **Expectations:** `keep.log` and `dist/keep` are kept; `debug.log`, `build/important` and `dist/other` are dropped
+    #[test]
+    fn test_decide_file_negated_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n!keep.log\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+        assert!(filter.decide_file("keep.log").is_kept());
+        assert!(filter.decide_file("logs/keep.log").is_kept());
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+
+        fs::write(
+            temp_dir.path().join(".gitignore"),
+            "build/\n!build/important\n",
+        )?;
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.decide_file("build/important"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("build/other"),
+            FilterDecision::FilteredByGitignore
+        );
+
+        fs::write(temp_dir.path().join(".gitignore"), "dist/*\n!dist/keep\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+        assert!(filter.decide_file("dist/keep").is_kept());
+        assert_eq!(
+            filter.decide_file("dist/other"),
+            FilterDecision::FilteredByGitignore
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** A .gitignore with one invalid pattern among valid ones
+    /// **Why it is tested:** A single bad line must not disable all other patterns
+    /// **Test conditions:** `.gitignore` with `*.log`, the unclosed character class `foo[`, and `*.tmp`
+    /// **Expectations:** `debug.log` and `cache.tmp` are dropped, `src/main.rs` is kept, and one warning mentions the bad pattern
+    #[test]
+    fn test_invalid_gitignore_line_is_skipped(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\nfoo[\n*.tmp\n")?;
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("cache.tmp"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert!(filter.decide_file("src/main.rs").is_kept());
+
+        let warnings = filter.gitignore_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("foo["));
+
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n")?;
+        assert!(Filter::new(temp_dir.path())?
+            .gitignore_warnings()
+            .is_empty());
+        Ok(())
+    }
+
+    /// **What is tested:** Directory patterns with a trailing slash (`build/`) applied to diff paths
+    /// **Why it is tested:** Diff paths are always files, so the pattern must match through their parent directories
+    /// **Test conditions:** `.gitignore` with `build/`; diff touching `build/sub/file.o`, `src/build/out.o`, a file named `build` and `src/main.rs`
+    /// **Expectations:** Files below any `build/` directory are dropped; the plain file `build` and `src/main.rs` are kept
+    #[test]
+    fn test_directory_pattern_matches_files_below(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "build/\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff = "diff --git a/build/sub/file.o b/build/sub/file.o\n\
+                    Binary files differ\n\
+                    diff --git a/src/build/out.o b/src/build/out.o\n\
+                    Binary files differ\n\
+                    diff --git a/build b/build\n\
+                    +plain file\n\
+                    diff --git a/src/main.rs b/src/main.rs\n\
+                    +fn main() {}\n";
+
+        let output = String::from_utf8(filter.filter_bytes(diff.as_bytes())?)?;
+        assert_eq!(
+            output,
+            "diff --git a/build b/build\n+plain file\n\
+             diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n"
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Parallel section decisions with `with_parallelism`
+    /// **Why it is tested:** Parallel mode must produce output identical to serial mode, in the original order
+    /// **Test conditions:** Synthetic diff with a preamble and 3000 sections of kept, .gitignore-matched, VCS and CRLF files, filtered with 1 and 4 threads
+    /// **Expectations:** Outputs are byte-for-byte identical, the reports match, and ignored and VCS sections are dropped
+    #[test]
+    fn test_parallel_matches_serial() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let mut diff = String::from("From 1234567 Mon Sep 17 00:00:00 2001\n\n");
+        for i in 0..3000 {
+            let (path, eol) = match i % 4 {
+                0 => (format!("src/file{i}.rs"), "\n"),
+                1 => (format!("logs/run{i}.log"), "\n"),
+                2 => (format!(".git/objects/{i}"), "\n"),
+                _ => (format!("docs/page{i}.md"), "\r\n"),
+            };
+            diff.push_str(&format!(
+                "diff --git a/{path} b/{path}{eol}index 1111111..2222222 100644{eol}\
+                 --- a/{path}{eol}+++ b/{path}{eol}@@ -1 +1 @@{eol}-old {i}{eol}+new {i}{eol}"
+            ));
+        }
+
+        let serial = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true);
+        let mut serial_output = Vec::new();
+        let serial_report = serial.process_diff_with_report(diff.as_bytes(), &mut serial_output)?;
+
+        let parallel = serial.with_parallelism(4);
+        let mut parallel_output = Vec::new();
+        let parallel_report =
+            parallel.process_diff_with_report(diff.as_bytes(), &mut parallel_output)?;
+
+        assert_eq!(parallel_output, serial_output);
+        assert_eq!(parallel_report, serial_report);
+        assert_eq!(serial_report.kept(), 1500);
+        let output = String::from_utf8(parallel_output)?;
+        assert!(output.starts_with("From 1234567"));
+        assert!(!output.contains(".log"));
+        assert!(!output.contains(".git/objects"));
+        Ok(())
+    }
+
+    /// **What is tested:** Sections larger than the limit set with `with_max_section_bytes`
+    /// **Why it is tested:** Huge sections must be streamed or skipped without changing the output
+    /// **Test conditions:** Limit of 64 bytes; a diff with a small section and large kept and ignored sections, filtered as diff, with `--keep-empty-diff-headers`, with 4 threads and as JSON; a generated section of about 5 MB whose reader fails if it is read more than 1 MiB ahead of the output
+    /// **Expectations:** Output and report match the unlimited filter, JSON still reports the full body size, and the generated section streams through byte-identical
+    #[test]
+    fn test_max_section_bytes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let large_section = |path: &str| {
+            let lines: String = (0..100).map(|i| format!("+line {i}\n")).collect();
+            format!(
+                "diff --git a/{path} b/{path}\nindex 1111111..2222222 100644\n\
+                 --- a/{path}\n+++ b/{path}\n@@ -0,0 +1,100 @@\n{lines}"
+            )
+        };
+        let small = "diff --git a/README.md b/README.md\nindex 1111111..2222222 100644\n";
+        let diff = format!(
+            "{}{small}{}{}",
+            large_section("debug.log"),
+            large_section("src/generated.rs"),
+            large_section("trace.log")
+        );
+
+        for filter in [
+            Filter::new(temp_dir.path())?,
+            Filter::new(temp_dir.path())?.with_keep_empty_diff_headers(true),
+            Filter::new(temp_dir.path())?.with_parallelism(4),
+            Filter::new(temp_dir.path())?.with_output_format(OutputFormat::Json),
+        ] {
+            let mut expected = Vec::new();
+            let expected_report =
+                filter.process_diff_with_report(diff.as_bytes(), &mut expected)?;
+
+            let filter = filter.with_max_section_bytes(64);
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(diff.as_bytes(), &mut output)?;
+
+            assert_eq!(String::from_utf8(output)?, String::from_utf8(expected)?);
+            assert_eq!(report, expected_report);
+            assert_eq!(report.kept(), 2);
+            assert_eq!(report.filtered_by_gitignore(), 2);
+        }
+
+        let header: &[u8] = b"diff --git a/src/generated.rs b/src/generated.rs\n";
+        let line: &[u8] = b"+generated line\n";
+        let count = 300_000;
+        let written = Arc::new(AtomicUsize::new(0));
+        let reader = io::BufReader::new(Generated::new(header, line, count, &written));
+        let filter = Filter::new(temp_dir.path())?.with_max_section_bytes(64);
+        assert!(!filter.is_unfiltered());
+        let mut output = Counted(Arc::clone(&written), Vec::new());
+        filter.process_diff(reader, &mut output)?;
+        assert_eq!(output.1, [header, &line.repeat(count)].concat());
+        Ok(())
+    }
+
+    /// **What is tested:** Writing the JSON description of each section as it is decided
+    /// **Why it is tested:** `--format json` must be usable on large diffs, so objects must not wait for the end of the input
+    /// **Test conditions:** About 5 MB of generated kept sections whose reader fails if it is read more than 1 MiB ahead of the output; a rename without rename detection with `with_pair_renames`
+    /// **Expectations:** One object per section in input order, the report counts every section, and the paired deletion gets the decision of its addition
+    #[test]
+    fn test_json_streams_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?.with_output_format(OutputFormat::Json);
+
+        let section: &[u8] = b"diff --git a/src/lib.rs b/src/lib.rs\n+line\n";
+        let count = 100_000;
+        let written = Arc::new(AtomicUsize::new(0));
+        let reader = io::BufReader::new(Generated::new(b"", section, count, &written));
+        let mut output = Counted(Arc::clone(&written), Vec::new());
+        let report = filter.process_diff_with_report(reader, &mut output)?;
+        assert_eq!(report.kept(), count);
+
+        let json: serde_json::Value = serde_json::from_slice(&output.1)?;
+        let objects = json.as_array().ok_or("not an array")?;
+        assert_eq!(objects.len(), count);
+        assert_eq!(
+            objects[0],
+            serde_json::json!({
+                "path": "src/lib.rs",
+                "status": "kept",
+                "reason": "none",
+                "header": "diff --git a/src/lib.rs b/src/lib.rs",
+                "body_bytes": 6,
+            })
+        );
+
+        let diff = "diff --git a/old.rs b/old.rs\ndeleted file mode 100644\n\
+                    index 1234567..0000000\n\
+                    diff --git a/debug.log b/debug.log\nnew file mode 100644\n\
+                    index 0000000..1234567\n";
+        let mut output = Vec::new();
+        filter
+            .with_pair_renames(true)
+            .process_diff_with_report(diff.as_bytes(), &mut output)?;
+        let json: serde_json::Value = serde_json::from_slice(&output)?;
+        let statuses: Vec<_> = json
+            .as_array()
+            .ok_or("not an array")?
+            .iter()
+            .map(|object| object["status"].clone())
+            .collect();
+        assert_eq!(statuses, ["filtered", "filtered"]);
+        Ok(())
+    }
+
+    /// **What is tested:** Reusing one pre-built .gitignore matcher across filters with `from_matcher`
+    /// **Why it is tested:** Callers processing many diffs must be able to parse the .gitignore once
+    /// **Test conditions:** Matcher built from `*.log`, the .gitignore removed from disk, a `.diffignore` with `*.lock`, three filters sharing the matcher each processing a diff
+    /// **Expectations:** Every run drops `debug.log` and `Cargo.lock` and keeps `src/main.rs`, and all filters share the same matcher
+    #[test]
+    fn test_from_matcher_reuses_gitignore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let (matcher, error) = Gitignore::new(temp_dir.path().join(".gitignore"));
+        assert!(error.is_none());
+        let matcher = Arc::new(matcher);
+        fs::remove_file(temp_dir.path().join(".gitignore"))?;
+        fs::write(temp_dir.path().join(".diffignore"), "*.lock\n")?;
+
+        let diff = "diff --git a/debug.log b/debug.log\n\
+                    +log line\n\
+                    diff --git a/Cargo.lock b/Cargo.lock\n\
+                    +lock\n\
+                    diff --git a/src/main.rs b/src/main.rs\n\
+                    +fn main() {}\n";
+
+        let filters = (0..3)
+            .map(|_| Filter::from_matcher(temp_dir.path(), Arc::clone(&matcher)))
+            .collect::<Vec<_>>();
+        assert_eq!(Arc::strong_count(&matcher), 4);
+
+        for filter in &filters {
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(diff.as_bytes(), &mut output)?;
+            assert_eq!(
+                output,
+                b"diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n"
+            );
+            assert_eq!(report.filtered_by_diffignore(), 1);
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering a diff held in a byte slice with `filter_bytes`
+    /// **Why it is tested:** In-memory callers need the same result as the stream API without downstream processing
+    /// **Test conditions:** Diff with an ignored `debug.log` and a kept `src/main.rs`, on a filter with a downstream command configured
+    /// **Expectations:** Only the `src/main.rs` section is returned, byte for byte, and no downstream command is run
+    #[test]
+    fn test_filter_bytes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?.with_downstream("false".to_string());
+
+        let kept = "diff --git a/src/main.rs b/src/main.rs\n\
+                    index 1234567..abcdefg 100644\n\
+                    --- a/src/main.rs\n\
+                    +++ b/src/main.rs\n\
+                    @@ -1 +1 @@\n\
+                    -old\n\
+                    +new\n";
+        let ignored = "diff --git a/debug.log b/debug.log\n\
+                       index 1234567..abcdefg 100644\n";
+        let input = format!("{ignored}{kept}");
+
+        assert_eq!(filter.filter_bytes(input.as_bytes())?, kept.as_bytes());
+        assert!(filter.filter_bytes(b"")?.is_empty());
+        Ok(())
+    }
+
+    /// **What is tested:** Removing ANSI color sequences from the input with `with_strip_ansi`
+    /// **Why it is tested:** Colored headers are not recognized, so a colored diff would pass through unfiltered
+    /// **Test conditions:** A colored diff of an ignored `debug.log` and a kept `src/main.rs`, with and without the option, and a plain diff with the option
+    /// **Expectations:** Without the option the input is passed through; with it the ignored section is dropped and the kept one written without colors; the plain diff is filtered as usual
+    #[test]
+    fn test_strip_ansi() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let colored = "\x1b[1mdiff --git a/debug.log b/debug.log\x1b[m\n\
+                       \x1b[32m+log line\x1b[m\n\
+                       \x1b[1mdiff --git a/src/main.rs b/src/main.rs\x1b[m\n\
+                       \x1b[1m--- a/src/main.rs\x1b[m\n\
+                       \x1b[1m+++ b/src/main.rs\x1b[m\n\
+                       \x1b[36m@@ -1 +1 @@\x1b[m\n\
+                       \x1b[31m-old\x1b[m\n\
+                       \x1b[32m+new\x1b[m\n";
+        let kept = "diff --git a/src/main.rs b/src/main.rs\n\
+                    --- a/src/main.rs\n\
+                    +++ b/src/main.rs\n\
+                    @@ -1 +1 @@\n\
+                    -old\n\
+                    +new\n";
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(filter.filter_bytes(colored.as_bytes())?, colored.as_bytes());
+
+        let filter = filter.with_strip_ansi(true);
+        assert_eq!(filter.filter_bytes(colored.as_bytes())?, kept.as_bytes());
+        assert_eq!(filter.process_to_string(Cursor::new(colored))?, kept);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(colored.as_bytes(), &mut output)?;
+        assert_eq!(output, kept.as_bytes());
+        assert_eq!(report.filtered_by_gitignore(), 1);
+
+        let plain = format!("{kept}diff --git a/b.log b/b.log\n");
+        assert_eq!(filter.filter_bytes(plain.as_bytes())?, kept.as_bytes());
+        Ok(())
+    }
+
+    /// **What is tested:** Dropping mode-only changes and deletions with `with_drop_mode_only` and `with_drop_deletions`
+    /// **Why it is tested:** These sections are dropped for the kind of change, independent of the gitignore rules
+    /// **Test conditions:** A diff with a mode-only change, a deletion, a mode change with content, a normal edit, and an ignored deletion
+    /// **Expectations:** Nothing is dropped by default; each option drops only its kind of section, and the ignored file keeps its gitignore reason
+    #[test]
+    fn test_drop_mode_only_and_deletions() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let mode_only = "diff --git a/run.sh b/run.sh\n\
+                         old mode 100644\n\
+                         new mode 100755\n";
+        let deletion = "diff --git a/old.rs b/old.rs\n\
+                        deleted file mode 100644\n\
+                        index 1234567..0000000\n\
+                        --- a/old.rs\n\
+                        +++ /dev/null\n\
+                        @@ -1 +0,0 @@\n\
+                        -old\n";
+        let mode_and_content = "diff --git a/build.sh b/build.sh\n\
+                                old mode 100644\n\
+                                new mode 100755\n\
+                                index 1234567..abcdefg\n\
+                                --- a/build.sh\n\
+                                +++ b/build.sh\n\
+                                @@ -1 +1 @@\n\
+                                -old\n\
+                                +new\n";
+        let edit = "diff --git a/src/main.rs b/src/main.rs\n\
+                    index 1234567..abcdefg 100644\n\
+                    --- a/src/main.rs\n\
+                    +++ b/src/main.rs\n\
+                    @@ -1 +1 @@\n\
+                    -old\n\
+                    +new\n";
+        let ignored_deletion = "diff --git a/debug.log b/debug.log\n\
+                                deleted file mode 100644\n";
+        let input = format!("{mode_only}{deletion}{mode_and_content}{edit}{ignored_deletion}");
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{mode_only}{deletion}{mode_and_content}{edit}").as_bytes()
+        );
+
+        let filter = Filter::new(temp_dir.path())?.with_drop_mode_only(true);
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{deletion}{mode_and_content}{edit}").as_bytes()
+        );
+
+        let filter = Filter::new(temp_dir.path())?.with_drop_deletions(true);
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{mode_only}{mode_and_content}{edit}").as_bytes()
+        );
+
+        let filter = Filter::new(temp_dir.path())?
+            .with_drop_mode_only(true)
+            .with_drop_deletions(true);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(output, format!("{mode_and_content}{edit}").as_bytes());
+        assert_eq!(report.filtered_by_mode_only(), 1);
+        assert_eq!(report.filtered_by_deletion(), 1);
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        Ok(())
+    }
+
+    /// **What is tested:** Handling of sections with a malformed header set with `with_on_unparsable`
+    /// **Why it is tested:** Whether such sections fail open, fail closed or abort must follow the configured action
+    /// **Test conditions:** A kept file, a section whose `diff --git` line names no paths and an ignored file, filtered with each action on 1 and 4 threads
+    /// **Expectations:** `Keep` (the default) writes the malformed section unchanged, `Drop` removes it as unparsable, `Error` fails with a message naming the header; the ignored file is dropped in every mode
+    #[test]
+    fn test_on_unparsable() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let kept = "diff --git a/src/main.rs b/src/main.rs\n\
+                    +main\n";
+        let malformed = "diff --git malformed-header\n\
+                         +hidden\n";
+        let ignored = "diff --git a/debug.log b/debug.log\n\
+                       +log\n";
+        let input = format!("{kept}{malformed}{ignored}");
+
+        for threads in [1, 4] {
+            let filter = Filter::new(temp_dir.path())?.with_parallelism(threads);
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+            assert_eq!(output, format!("{kept}{malformed}").as_bytes());
+            assert_eq!((report.kept(), report.unparsable()), (2, 0));
+
+            let filter = Filter::new(temp_dir.path())?
+                .with_parallelism(threads)
+                .with_on_unparsable(UnparsableAction::Drop);
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+            assert_eq!(output, kept.as_bytes());
+            assert_eq!(report.unparsable(), 1);
+
+            let filter = Filter::new(temp_dir.path())?
+                .with_parallelism(threads)
+                .with_on_unparsable(UnparsableAction::Error);
+            let error = filter
+                .filter_bytes(input.as_bytes())
+                .expect_err("malformed header must fail");
+            assert!(error.to_string().contains("'diff --git malformed-header'"));
+            assert!(filter.filter_bytes(kept.as_bytes()).is_ok());
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Pairing a deletion and an addition of the same content with `with_pair_renames`
+    /// **Why it is tested:** A rename shown as delete + add must be dropped as a unit when its destination is ignored
+    /// **Test conditions:** `notes.txt` deleted and added as `notes.log` with the same blob; an unpaired deletion and addition; empty files; a deletion whose matching addition is in the next commit; on 1 and 4 threads, and without pairing
+    /// **Expectations:** With pairing, `notes.txt` is dropped with reason `rename`; all other deletions are kept; without pairing `notes.txt` is kept
+    #[test]
+    fn test_pair_renames() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let deletion = |path: &str, blob: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\ndeleted file mode 100644\nindex {blob}..0000000\n\
+                 --- a/{path}\n+++ /dev/null\n@@ -1 +0,0 @@\n-content\n"
+            )
+        };
+        let addition = |path: &str, blob: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\nnew file mode 100644\nindex 0000000..{blob}\n\
+                 --- /dev/null\n+++ b/{path}\n@@ -0,0 +1 @@\n+content\n"
+            )
+        };
+
+        let renamed_from = deletion("notes.txt", "1234567");
+        let renamed_to = addition("notes.log", "1234567");
+        let deleted = deletion("src/old.rs", "abcdef0");
+        let added = addition("debug.log", "0fedcba");
+        let empty_deleted = deletion("empty.txt", "e69de29");
+        let empty_added = addition("empty.log", "e69de29");
+        let next_commit_deleted = deletion("moved.txt", "7654321");
+        let next_commit = "commit 89abcdef\n\n    Move file\n\n";
+        let next_commit_added = addition("moved.log", "7654321");
+        let input = format!(
+            "{renamed_from}{deleted}{empty_deleted}{next_commit_deleted}{added}{renamed_to}\
+             {empty_added}{next_commit}{next_commit_added}"
+        );
+        let kept = format!("{deleted}{empty_deleted}{next_commit_deleted}{next_commit}");
+
+        for parallelism in [1, 4] {
+            let filter = Filter::new(temp_dir.path())?
+                .with_pair_renames(true)
+                .with_parallelism(parallelism);
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+
+            assert_eq!(String::from_utf8(output)?, kept);
+            assert_eq!(
+                report.sections()[0],
+                SectionDecision {
+                    path: Some("notes.txt".to_string()),
+                    decision: FilterDecision::FilteredByRename
+                }
+            );
+            assert_eq!(report.filtered_by_gitignore(), 4);
+            assert_eq!(report.filtered_by_rename(), 1);
+        }
+
+        let filter = Filter::new(temp_dir.path())?;
+        let output = filter.filter_bytes(input.as_bytes())?;
+        assert_eq!(String::from_utf8(output)?, format!("{renamed_from}{kept}"));
+        Ok(())
+    }
+
+    /// **What is tested:** Root-anchored patterns (`/build`) versus unanchored ones (`build/`) on diff paths
+    /// **Why it is tested:** Diff paths are relative to the repository root and must reach the matcher that way, so anchored patterns only match at the root
+    /// **Test conditions:** Diffs changing `build/x`, `src/build/x` and `src/main.rs`, filtered with `/build`, `/build/` and `build/`, and the same paths decided one by one
+    /// **Expectations:** The anchored patterns drop `build/x` but keep `src/build/x`; `build/` drops both; `src/main.rs` is always kept
+    #[test]
+    fn test_anchored_and_unanchored_patterns() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = create_test_repo()?;
+        let section = |path: &str| format!("diff --git a/{path} b/{path}\n+change\n");
+        let (root_build, nested_build, main) = (
+            section("build/x"),
+            section("src/build/x"),
+            section("src/main.rs"),
+        );
+        let input = format!("{root_build}{nested_build}{main}");
+
+        for (patterns, kept) in [
+            ("/build\n", format!("{nested_build}{main}")),
+            ("/build/\n", format!("{nested_build}{main}")),
+            ("build/\n", main.clone()),
+        ] {
+            let filter = Filter::new(temp_dir.path())?.with_gitignore_str(patterns)?;
+            let output = filter.filter_bytes(input.as_bytes())?;
+            assert_eq!(String::from_utf8(output)?, kept, "{patterns:?}");
+        }
+
+        let filter = Filter::new(temp_dir.path())?.with_gitignore_str("/build\n")?;
+        assert_eq!(
+            filter.decide_file("build/x"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert!(filter.decide_file("src/build/x").is_kept());
+        assert!(filter.decide_file("src/build").is_kept());
+        Ok(())
+    }
+
+    /// **What is tested:** Decisions for submodule sections, whose path names a directory
+    /// **Why it is tested:** Directory-only patterns such as `vendor/` must drop a submodule, while a file of the same name is kept
+    /// **Test conditions:** `vendor/` and `build/` ignored; submodule updates of `vendor` (with hunk) and `libs/core` (bare `Subproject commit` lines), a new `build` submodule and a new regular file `build`, on 1 and 4 threads
+    /// **Expectations:** The `vendor` and `build` submodules are dropped by gitignore; `libs/core` is kept with its `Subproject commit` body and the regular file `build` is kept
+    #[test]
+    fn test_submodule_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let ignored = "diff --git a/vendor b/vendor\n\
+                       index 1111111..2222222 160000\n\
+                       --- a/vendor\n\
+                       +++ b/vendor\n\
+                       @@ -1 +1 @@\n\
+                       -Subproject commit 1111111111111111111111111111111111111111\n\
+                       +Subproject commit 2222222222222222222222222222222222222222\n";
+        let kept = "diff --git a/libs/core b/libs/core\n\
+                    -Subproject commit 3333333333333333333333333333333333333333\n\
+                    +Subproject commit 4444444444444444444444444444444444444444-dirty\n";
+        let new_ignored = "diff --git a/build b/build\n\
+                           new file mode 160000\n\
+                           index 0000000..5555555\n\
+                           --- /dev/null\n\
+                           +++ b/build\n\
+                           @@ -0,0 +1 @@\n\
+                           +Subproject commit 5555555555555555555555555555555555555555\n";
+        let file = "diff --git a/build b/build\n\
+                    new file mode 100644\n\
+                    --- /dev/null\n\
+                    +++ b/build\n\
+                    @@ -0,0 +1 @@\n\
+                    +#!/bin/sh\n";
+        let input = format!("{ignored}{kept}{new_ignored}{file}");
+
+        for parallelism in [1, 4] {
+            let filter = Filter::new(temp_dir.path())?
+                .with_gitignore_str("vendor/\nbuild/\n")?
+                .with_parallelism(parallelism);
+            let sections = filter
+                .sections(input.as_bytes())
+                .collect::<Result<Vec<_>>>()?;
+            let submodules: Vec<bool> = sections.iter().map(DiffSection::is_submodule).collect();
+            assert_eq!(submodules, [true, true, true, false]);
+            assert_eq!(
+                sections[0].decision(&filter),
+                FilterDecision::FilteredByGitignore
+            );
+
+            let output = filter.filter_bytes(input.as_bytes())?;
+            assert_eq!(String::from_utf8(output)?, format!("{kept}{file}"));
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Routing of kept and dropped sections to two writers with `process_diff_split`
+    /// **Why it is tested:** Audit tooling needs the dropped sections byte-exact on a separate sink, without changing the kept output
+    /// **Test conditions:** A preamble, a kept file, an ignored file, a VCS file and a large ignored section cut off at the size limit, split serially, on 4 threads, with the size limit and with header markers; an unfiltered filter dropping an unparsable header
+    /// **Expectations:** The kept writer receives what `filter_bytes` returns, the filtered writer exactly the dropped sections in order, and the report counts both
+    #[test]
+    fn test_process_diff_split() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let preamble = "From 1234567 Mon Sep 17 00:00:00 2001\n\n";
+        let kept = "diff --git a/src/main.rs b/src/main.rs\n+main\n";
+        let ignored = "diff --git a/debug.log b/debug.log\r\n+log\r\n";
+        let vcs = "diff --git a/.git/config b/.git/config\n+[core]\n";
+        let large = format!("diff --git a/huge.log b/huge.log\n{}", "+line\n".repeat(20));
+        let input = format!("{preamble}{kept}{ignored}{vcs}{large}");
+
+        let vcs_filter = || -> Result<Filter> {
+            Ok(Filter::new(temp_dir.path())?
+                .with_vcs_patterns(vec![".git/".to_string()])
+                .with_vcs_enabled(true))
+        };
+        let filters = [
+            vcs_filter()?,
+            vcs_filter()?.with_parallelism(4),
+            vcs_filter()?.with_max_section_bytes(16),
+            vcs_filter()?.with_keep_empty_diff_headers(true),
+        ];
+        for filter in filters {
+            let (mut kept_output, mut filtered_output) = (Vec::new(), Vec::new());
+            let report = filter.process_diff_split(
+                input.as_bytes(),
+                &mut kept_output,
+                &mut filtered_output,
+            )?;
+
+            assert_eq!(kept_output, filter.filter_bytes(input.as_bytes())?);
+            assert!(kept_output.starts_with(format!("{preamble}{kept}").as_bytes()));
+            assert_eq!(filtered_output, format!("{ignored}{vcs}{large}").as_bytes());
+            assert_eq!((report.kept(), report.filtered()), (1, 3));
+        }
+
+        let filter = Filter::new(temp_dir.path())?
+            .with_on_unparsable(UnparsableAction::Drop)
+            .with_gitignore_str("")?;
+        let unparsable = "diff --git unparsable\n+hidden\n";
+        let input = format!("{kept}{unparsable}");
+        let (mut kept_output, mut filtered_output) = (Vec::new(), Vec::new());
+        filter.process_diff_split(input.as_bytes(), &mut kept_output, &mut filtered_output)?;
+        assert_eq!(kept_output, kept.as_bytes());
+        assert_eq!(filtered_output, unparsable.as_bytes());
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering a diff written in chunks through `Filter::writer`
+    /// **Why it is tested:** Writers hand over arbitrary pieces of the diff, so headers and lines split across writes and flushes must not change the result
+    /// **Test conditions:** A preamble, kept and ignored sections with LF and CRLF lines and commit metadata, written in chunks of 1, 7 and 64 bytes, also with rename pairing; flushes inside a dropped and a kept section
+    /// **Expectations:** The output equals `filter_bytes`; after a flush the inner writer holds the sections decided so far, and bytes continuing a section follow its decision
+    #[test]
+    fn test_filter_writer_chunks() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let preamble = "From 1234567 Mon Sep 17 00:00:00 2001\n\n";
+        let kept =
+            "diff --git a/src/main.rs b/src/main.rs\n--- a/src/main.rs\n+++ b/src/main.rs\n+main\n";
+        let ignored = "diff --git a/debug.log b/debug.log\r\n+log\r\n";
+        let commit = "commit 89abcdef\nAuthor: A <a@example.com>\n\n";
+        let input = format!("{preamble}{kept}{ignored}{commit}{ignored}{kept}");
+
+        let filters = [
+            Filter::new(temp_dir.path())?,
+            Filter::new(temp_dir.path())?.with_pair_renames(true),
+        ];
+        for filter in filters {
+            let expected = filter.filter_bytes(input.as_bytes())?;
+            for chunk_size in [1, 7, 64] {
+                let mut writer = filter.writer(Vec::new());
+                for chunk in input.as_bytes().chunks(chunk_size) {
+                    writer.write_all(chunk)?;
+                }
+                writer.flush()?;
+                // Pairing renames holds the whole diff back until the writer is finished
+                let flushed = if filter.pair_renames {
+                    &[][..]
+                } else {
+                    &expected[..]
+                };
+                assert_eq!(writer.get_ref().as_slice(), flushed);
+                let report = writer.finish()?;
+                assert_eq!((report.kept(), report.filtered()), (2, 2));
+            }
+
+            let mut output = Vec::new();
+            io::copy(&mut input.as_bytes(), &mut filter.writer(&mut output))?;
+            assert_eq!(output, expected);
+        }
+
+        let filter = Filter::new(temp_dir.path())?;
+        let lib = "diff --git a/src/lib.rs b/src/lib.rs\n+a\n";
+        let mut output = Vec::new();
+        let mut writer = filter.writer(&mut output);
+        writer.write_all(
+            format!("{preamble}{kept}diff --git a/debug.log b/debug.log\r\n+lo").as_bytes(),
+        )?;
+        writer.flush()?;
+        assert_eq!(
+            writer.get_ref().as_slice(),
+            format!("{preamble}{kept}").as_bytes()
+        );
+        writer.write_all(format!("g\r\n+more\r\n{lib}").as_bytes())?;
+        writer.flush()?;
+        assert_eq!(
+            writer.get_ref().as_slice(),
+            format!("{preamble}{kept}{lib}").as_bytes()
+        );
+        writer.write_all(b"+b\n")?;
+        let report = writer.finish()?;
+        assert_eq!(output, format!("{preamble}{kept}{lib}+b\n").as_bytes());
+        assert_eq!((report.kept(), report.filtered()), (2, 1));
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering by file extension with `with_only_extensions` and `with_excluded_extensions`
+    /// **Why it is tested:** Extension lists narrow a review beyond the gitignore rules, and files without an extension need a defined outcome
+    /// **Test conditions:** A diff of `src/main.rs`, `Cargo.toml`, `Cargo.lock`, the dotfile `.env`, `Makefile` and an ignored `debug.log`, filtered with an allowlist and with a denylist
+    /// **Expectations:** The allowlist keeps only `.rs` and `.toml` files and drops the dotfile and `Makefile`; the denylist drops only `Cargo.lock`; the ignored file keeps its gitignore reason; `is_path_filtered` agrees with both
+    #[test]
+    fn test_extension_filters() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let section = |path: &str| format!("diff --git a/{path} b/{path}\n+change\n");
+        let (main, manifest, lock, env, makefile, log) = (
+            section("src/main.rs"),
+            section("Cargo.toml"),
+            section("Cargo.lock"),
+            section(".env"),
+            section("Makefile"),
+            section("debug.log"),
+        );
+        let input = format!("{main}{manifest}{lock}{env}{makefile}{log}");
+
+        let filter = Filter::new(temp_dir.path())?
+            .with_only_extensions(vec!["rs".to_string(), ".toml".to_string()]);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(output, format!("{main}{manifest}").as_bytes());
+        assert_eq!(report.filtered_by_extension(), 3);
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(
+            filter.is_path_filtered("Makefile", false),
+            FilterDecision::FilteredByExtension
+        );
+        assert_eq!(
+            filter.is_path_filtered("Cargo.toml", false),
+            FilterDecision::Keep
+        );
+
+        let filter =
+            Filter::new(temp_dir.path())?.with_excluded_extensions(vec!["lock".to_string()]);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(
+            output,
+            format!("{main}{manifest}{env}{makefile}").as_bytes()
+        );
+        assert_eq!(
+            report.sections()[2].decision,
+            FilterDecision::FilteredByExtension
+        );
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(
+            filter.is_path_filtered("Cargo.lock", false),
+            FilterDecision::FilteredByExtension
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Dropping sections matched by `.diffignore` and by a file given to `with_diffignore`
+    /// **Why it is tested:** Committed code such as `vendor/` is never matched by .gitignore, yet must be hideable at review time
+    /// **Test conditions:** Repository with `.diffignore` listing `vendor/`, a diff of `vendor/lib.rs`, `src/main.rs` and `debug.log`, then a custom file listing `*.lock`
+    /// **Expectations:** `vendor/lib.rs` is dropped with reason diffignore only while `.diffignore` applies, the custom file replaces it, and a missing file fails
+    #[test]
+    fn test_diffignore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let section = |path: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\n\
+                 index 1234567..abcdefg 100644\n\
+                 --- a/{path}\n\
+                 +++ b/{path}\n\
+                 @@ -1 +1 @@\n\
+                 -old\n\
+                 +new\n"
+            )
+        };
+        let (vendored, main, log, lock) = (
+            section("vendor/lib.rs"),
+            section("src/main.rs"),
+            section("debug.log"),
+            section("Cargo.lock"),
+        );
+        let input = format!("{vendored}{main}{log}{lock}");
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{vendored}{main}{lock}").as_bytes()
+        );
+
+        fs::write(temp_dir.path().join(".diffignore"), "vendor/\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(output, format!("{main}{lock}").as_bytes());
+        assert_eq!(report.filtered_by_diffignore(), 1);
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(
+            filter.is_path_filtered("vendor/lib.rs", false),
+            FilterDecision::FilteredByDiffignore
+        );
+
+        let custom = temp_dir.path().join("review.ignore");
+        fs::write(&custom, "*.lock\n")?;
+        let filter = Filter::new(temp_dir.path())?.with_diffignore(custom)?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{vendored}{main}").as_bytes()
+        );
+
+        assert!(Filter::new(temp_dir.path())?
+            .with_diffignore(temp_dir.path().join("missing.ignore"))
+            .is_err());
+        Ok(())
+    }
+
+    /// **What is tested:** Keeping sections matched by the file given to `with_force_include`
+    /// **Why it is tested:** A committed include list must override .gitignore, .diffignore and VCS rules for the paths it names
+    /// **Test conditions:** .gitignore with `*.log`, `.diffignore` with `vendor/`, VCS filtering of `.git/`; an include file listing `logs/important.log`, `vendor/keep.rs` and `.git/hooks/`; a diff of these paths, `logs/debug.log` and `src/main.rs`; annotation and a missing include file
+    /// **Expectations:** Included paths are kept, `logs/debug.log` and `vendor/lib.rs` are still dropped, annotations name the include pattern, `is_path_filtered` gives the same decisions, and a missing file fails
+    #[test]
+    fn test_force_include() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".diffignore"), "vendor/\n")?;
+        let include = temp_dir.path().join("review.include");
+        fs::write(
+            &include,
+            "logs/important.log\nvendor/keep.rs\n.git/hooks/\n",
+        )?;
+
+        let section = |path: &str| format!("diff --git a/{path} b/{path}\n+change\n");
+        let (important, debug, kept_vendor, vendor, hook, main) = (
+            section("logs/important.log"),
+            section("logs/debug.log"),
+            section("vendor/keep.rs"),
+            section("vendor/lib.rs"),
+            section(".git/hooks/pre-commit"),
+            section("src/main.rs"),
+        );
+        let input = format!("{important}{debug}{kept_vendor}{vendor}{hook}{main}");
+
+        let vcs_filter = || -> Result<Filter> {
+            Ok(Filter::new(temp_dir.path())?
+                .with_vcs_patterns(vec![".git/".to_string()])
+                .with_vcs_enabled(true))
+        };
+        assert_eq!(
+            vcs_filter()?.filter_bytes(input.as_bytes())?,
+            main.as_bytes()
+        );
+
+        let filter = vcs_filter()?.with_force_include(include.clone())?;
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            format!("{important}{kept_vendor}{hook}{main}")
+        );
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(report.filtered_by_diffignore(), 1);
+
+        // Single-path queries agree with the section decisions
+        let decision = |path: &str| {
+            report
+                .sections()
+                .iter()
+                .find(|section| section.path.as_deref() == Some(path))
+                .map(|section| section.decision)
+        };
+        for path in [
+            "logs/important.log",
+            "logs/debug.log",
+            "vendor/keep.rs",
+            ".git/hooks/pre-commit",
+        ] {
+            assert_eq!(
+                Some(filter.is_path_filtered(path, false)),
+                decision(path),
+                "{path}"
+            );
+        }
+        assert_eq!(
+            filter.is_path_filtered("logs/important.log", false),
+            FilterDecision::Keep
+        );
+
+        let filter = vcs_filter()?
+            .with_force_include(include)?
+            .with_annotate(true);
+        let output = String::from_utf8(filter.filter_bytes(input.as_bytes())?)?;
+        assert!(output.starts_with(&format!(
+            "# kept: force-included by logs/important.log\n{important}"
+        )));
+        assert!(output.contains("# kept: force-included by .git/hooks/\n"));
+
+        assert!(Filter::new(temp_dir.path())?
+            .with_force_include(temp_dir.path().join("missing.include"))
+            .is_err());
+        Ok(())
+    }
+
+    /// **What is tested:** Inline patterns added by `with_extra_ignore_patterns`
+    /// **Why it is tested:** Inline rules must combine with the .gitignore and be applied after its patterns
+    /// **Test conditions:** .gitignore with `*.log`; inline `*.snap`, `!keep.log` and an invalid `a/**b[` pattern; then a removed diffignore file
+    /// **Expectations:** `out.snap` and `debug.log` are dropped, `keep.log` is re-included, and the invalid pattern becomes a warning; a diffignore file removed before the patterns are added makes the setter fail
+    #[test]
+    fn test_extra_ignore_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let section = |path: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\n\
+                 index 1234567..abcdefg 100644\n\
+                 --- a/{path}\n\
+                 +++ b/{path}\n\
+                 @@ -1 +1 @@\n\
+                 -old\n\
+                 +new\n"
+            )
+        };
+        let (main, snap, log, keep) = (
+            section("src/main.rs"),
+            section("tests/out.snap"),
+            section("debug.log"),
+            section("keep.log"),
+        );
+        let input = format!("{main}{snap}{log}{keep}");
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{main}{snap}").as_bytes()
+        );
+
+        let filter = Filter::new(temp_dir.path())?.with_extra_ignore_patterns(vec![
+            "*.snap".to_owned(),
+            "!keep.log".to_owned(),
+            "a/**b[".to_owned(),
+        ])?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{main}{keep}").as_bytes()
+        );
+        assert_eq!(
+            filter.is_path_filtered("tests/out.snap", false),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(filter.gitignore_warnings().len(), 1);
+        assert!(filter.gitignore_warnings()[0].contains("a/**b["));
+
+        // Reloading reports a configured file that has gone missing
+        let diffignore = temp_dir.path().join("review.diffignore");
+        fs::write(&diffignore, "vendor/\n")?;
+        let filter = Filter::new(temp_dir.path())?.with_diffignore(diffignore.clone())?;
+        fs::remove_file(&diffignore)?;
+        assert!(filter
+            .with_extra_ignore_patterns(vec!["*.snap".to_owned()])
+            .is_err());
+        Ok(())
+    }
+
+    /// **What is tested:** A filter whose .gitignore comes from `with_gitignore_str`
+    /// **Why it is tested:** Diffs of virtual checkouts must be filterable without any .gitignore on disk
+    /// **Test conditions:** Nonexistent root; content with a comment, `*.log`, `!keep.log`, `build/` and an invalid line; a diff of paths that do not exist; case-insensitive matching enabled afterwards
+    /// **Expectations:** Ignored paths are dropped, `keep.log` is kept, the invalid line is a warning, and the content survives re-reading for case-insensitivity
+    #[test]
+    fn test_with_gitignore_str() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path().join("virtual");
+        let section = |path: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\n\
+                 new file mode 100644\n\
+                 --- /dev/null\n\
+                 +++ b/{path}\n\
+                 @@ -0,0 +1 @@\n\
+                 +content\n"
+            )
+        };
+        let (main, log, keep, build, upper) = (
+            section("src/main.rs"),
+            section("debug.log"),
+            section("keep.log"),
+            section("build/out.o"),
+            section("TRACE.LOG"),
+        );
+        let input = format!("{main}{log}{keep}{build}{upper}");
+
+        let filter = Filter::new(&root)?
+            .with_gitignore_str("# generated\n*.log\n!keep.log\nbuild/\na/**b[\n")?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{main}{keep}{upper}").as_bytes()
+        );
+        assert_eq!(filter.gitignore_warnings().len(), 1);
+        assert!(filter.gitignore_warnings()[0].contains("a/**b["));
+
+        let filter = filter.with_case_insensitive(true)?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{main}{keep}").as_bytes()
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** A filter whose .gitignore patterns are read by `with_gitignore_file`
+    /// **Why it is tested:** Patches must be filterable with a given ignore file, also without a repository
+    /// **Test conditions:** Repository .gitignore with `*.log`; a separate file with `*.tmp` and `/build/`; a missing file
+    /// **Expectations:** The file's patterns replace those of the root .gitignore, anchored at the root, and a missing file is an error
+    #[test]
+    fn test_with_gitignore_file() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let file = temp_dir.path().join("ci.gitignore");
+        fs::write(&file, "*.tmp\n/build/\n")?;
+        let section = |path: &str| format!("diff --git a/{path} b/{path}\n+change\n");
+        let (log, tmp, build, nested_build) = (
+            section("debug.log"),
+            section("cache.tmp"),
+            section("build/out.o"),
+            section("src/build/out.o"),
+        );
+        let input = format!("{log}{tmp}{build}{nested_build}");
+
+        let filter = Filter::new(temp_dir.path())?.with_gitignore_file(file)?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{log}{nested_build}").as_bytes()
+        );
+
+        let missing = Filter::new(temp_dir.path())?
+            .with_gitignore_file(temp_dir.path().join("missing.gitignore"));
+        assert!(missing.is_err());
+        Ok(())
+    }
+
+    /// **What is tested:** Global ignore patterns read by `with_global_excludes_file`
+    /// **Why it is tested:** Git applies the global ignore file beneath the repository's own patterns
+    /// **Test conditions:** Global file ignoring `*.md` and `*.bak`, repository .gitignore re-including `README.md`; a missing global file
+    /// **Expectations:** `notes.md` and `old.bak` are filtered, `README.md` is kept, and the missing file adds no patterns
+    #[test]
+    fn test_with_global_excludes_file() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n!README.md\n")?;
+        let global = temp_dir.path().join("global-ignore");
+        fs::write(&global, "*.md\n*.bak\n")?;
+
+        let filter = Filter::new(temp_dir.path())?.with_global_excludes_file(global)?;
+        assert_eq!(
+            filter.is_path_filtered("notes.md", false),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.is_path_filtered("old.bak", false),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.is_path_filtered("README.md", false),
+            FilterDecision::Keep
+        );
+        assert_eq!(
+            filter.is_path_filtered("debug.log", false),
+            FilterDecision::FilteredByGitignore
+        );
+
+        let filter = Filter::new(temp_dir.path())?
+            .with_global_excludes_file(temp_dir.path().join("missing-ignore"))?;
+        assert_eq!(
+            filter.is_path_filtered("notes.md", false),
+            FilterDecision::Keep
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** The per-section callback registered with `on_section`
+    /// **Why it is tested:** Progress displays rely on one call per section, in order, with its path and decision, also when piping downstream
+    /// **Test conditions:** Diff with a preamble, `src/main.rs`, `debug.log` and `keep.log` (ignored by `*.log`), processed directly and through a `cat` downstream
+    /// **Expectations:** Each run calls the callback three times in section order with the decisions of the report; the preamble is not reported
+    #[test]
+    fn test_on_section_callback() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let input = "preamble\n\
+                     diff --git a/src/main.rs b/src/main.rs\n\
+                     +fn main() {}\n\
+                     diff --git a/debug.log b/debug.log\n\
+                     +noise\n\
+                     diff --git a/keep.log b/keep.log\n\
+                     +noise\n";
+        let expected = vec![
+            ("src/main.rs".to_string(), FilterDecision::Keep),
+            ("debug.log".to_string(), FilterDecision::FilteredByGitignore),
+            ("keep.log".to_string(), FilterDecision::FilteredByGitignore),
+        ];
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collector = Arc::clone(&seen);
+        let filter = Filter::new(temp_dir.path())?.on_section(move |section| {
+            collector.lock().unwrap().push((
+                section.path.unwrap_or_default().to_string(),
+                section.decision,
+            ));
+        });
+
+        filter.filter_bytes(input.as_bytes())?;
+        assert_eq!(*seen.lock().unwrap(), expected);
+
+        seen.lock().unwrap().clear();
+        let filter = filter
+            .with_downstream_shell(true)
+            .with_downstream("cat > /dev/null".to_string());
+        let report = filter.process_diff_with_report(Cursor::new(input), io::sink())?;
+        assert_eq!(*seen.lock().unwrap(), expected);
+        assert_eq!(report.total(), 3);
+        Ok(())
+    }
+
+    /// **What is tested:** `# kept:` annotations written by `with_annotate`
+    /// **Why it is tested:** The annotation must name the negation that re-included a path, so rule interactions can be debugged
+    /// **Test conditions:** .gitignore with `*.log` and `!keep.log`; a diff of `src/main.rs`, `keep.log` and `debug.log`, with and without annotation
+    /// **Expectations:** `src/main.rs` gets `no matching ignore rule`, `keep.log` gets `re-included by !keep.log`, dropped sections get none, and output is unchanged without the option
+    #[test]
+    fn test_annotate_kept_sections() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n!keep.log\n")?;
+        let section = |path: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\n\
+                 index 1234567..abcdefg 100644\n\
+                 --- a/{path}\n\
+                 +++ b/{path}\n\
+                 @@ -1 +1 @@\n\
+                 -old\n\
+                 +new\n"
+            )
+        };
+        let (main, kept_log, debug_log) = (
+            section("src/main.rs"),
+            section("keep.log"),
+            section("debug.log"),
+        );
+        let input = format!("{main}{kept_log}{debug_log}");
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(
+            filter.filter_bytes(input.as_bytes())?,
+            format!("{main}{kept_log}").as_bytes()
+        );
+
+        let filter = Filter::new(temp_dir.path())?.with_annotate(true);
+        assert_eq!(
+            String::from_utf8(filter.filter_bytes(input.as_bytes())?)?,
+            format!(
+                "# kept: no matching ignore rule\n{main}\
+                 # kept: re-included by !keep.log\n{kept_log}"
+            )
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering a plain unified diff with `with_unified`
+    /// **Why it is tested:** `diff -u` output has no `diff --git` headers, yet ignored files in it must be dropped
+    /// **Test conditions:** Non-git unified diff of `debug.log` (ignored by `*.log`) and `src/main.rs`, with and without unified mode
+    /// **Expectations:** Without unified mode the input passes through unchanged; with it the `debug.log` section is dropped
+    #[test]
+    fn test_unified_diff() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let log = "--- debug.log\t2024-01-01 00:00:00.000000000 +0000\n\
+                   +++ debug.log\t2024-01-01 00:00:01.000000000 +0000\n\
+                   @@ -1 +1 @@\n\
+                   -old\n\
+                   +new\n";
+        let main = "--- src/main.rs\t2024-01-01 00:00:00.000000000 +0000\n\
+                    +++ src/main.rs\t2024-01-01 00:00:01.000000000 +0000\n\
+                    @@ -1 +1 @@\n\
+                    -fn main() {}\n\
+                    +fn main() { println!(\"hi\"); }\n";
+        let input = format!("{log}{main}");
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert_eq!(filter.filter_bytes(input.as_bytes())?, input.as_bytes());
+
+        let filter = Filter::new(temp_dir.path())?.with_unified(true);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+        assert_eq!(output, main.as_bytes());
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(report.kept_paths().collect::<Vec<_>>(), ["src/main.rs"]);
+        Ok(())
+    }
+
+    /// **What is tested:** Assignment of `\ No newline at end of file` markers to their section
+    /// **Why it is tested:** The marker follows the last line of a file, right before the next header, so a wrong section boundary would keep it with a dropped file or leak it into the next one
+    /// **Test conditions:** Git and plain unified diffs of a kept and an ignored file, each ending without a newline on both sides, in both orders and with the ignored file last without a final line ending; filtered serially, on 4 threads, with a small section size limit and through `Filter::writer`
+    /// **Expectations:** The output is exactly the kept section with both of its markers; no marker of the dropped file appears
+    #[test]
+    fn test_no_newline_marker_stays_with_its_section(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let marker = "\\ No newline at end of file\n";
+        let hunk = format!("@@ -1 +1 @@\n-old\n{marker}+new\n{marker}");
+        let git = |path: &str| {
+            format!(
+                "diff --git a/{path} b/{path}\nindex 1111111..2222222 100644\n\
+                 --- a/{path}\n+++ b/{path}\n{hunk}"
+            )
+        };
+        let unified =
+            |path: &str| format!("--- {path}\t2024-01-01\n+++ {path}\t2024-01-02\n{hunk}");
+        let filter = || -> Result<Filter> { Ok(Filter::new(temp_dir.path())?.with_unified(true)) };
+
+        let cases = [
+            (git("src/main.rs"), git("debug.log")),
+            (unified("src/main.rs"), unified("debug.log")),
+        ];
+        for (kept, dropped) in cases {
+            let unterminated = dropped.strip_suffix('\n').unwrap_or(&dropped);
+            let inputs = [
+                format!("{dropped}{kept}"),
+                format!("{kept}{dropped}"),
+                format!("{kept}{unterminated}"),
+            ];
+
+            let filters = [
+                filter()?,
+                filter()?.with_parallelism(4),
+                filter()?.with_max_section_bytes(16),
+            ];
+            for filter in &filters {
+                for input in &inputs {
+                    let output = filter.filter_bytes(input.as_bytes())?;
+                    assert_eq!(String::from_utf8(output)?, kept);
+
+                    let mut output = Vec::new();
+                    io::copy(&mut input.as_bytes(), &mut filter.writer(&mut output))?;
+                    assert_eq!(String::from_utf8(output)?, kept);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering a diff into a string with `process_to_string`
+    /// **Why it is tested:** The convenience must produce the same output as the direct path, without running downstream commands
+    /// **Test conditions:** A diff with an ignored and a kept section, processed by a filter with a failing downstream command; then a diff with invalid UTF-8
+    /// **Expectations:** Only the kept section is returned, and invalid bytes are replaced instead of failing
+    #[test]
+    fn test_process_to_string() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?.with_downstream("false".to_string());
+
+        let kept = "diff --git a/src/main.rs b/src/main.rs\n\
+                    --- a/src/main.rs\n\
+                    +++ b/src/main.rs\n";
+        let input = format!("diff --git a/debug.log b/debug.log\n{kept}");
+        assert_eq!(filter.process_to_string(input.as_bytes())?, kept);
+
+        let binary = b"diff --git a/data.bin b/data.bin\n+\xff\n";
+        assert_eq!(
+            filter.process_to_string(&binary[..])?,
+            "diff --git a/data.bin b/data.bin\n+\u{fffd}\n"
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Building a filter through `FilterBuilder`
+    /// **Why it is tested:** The builder must apply all options at once and reject invalid ones in `build()`
+    /// **Test conditions:** `.gitignore` with `*.LOG`; builders with VCS filtering and case-insensitive matching, with defaults, with an empty downstream command, and with a gitignore file, extra patterns, a diffignore and a force-include file, each also missing
+    /// **Expectations:** The configured builder drops `.git/config` and `debug.log`, the default one keeps both, and the empty command is rejected; the file options all apply, and a missing file fails `build()`
+    #[test]
+    fn test_filter_builder() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.LOG\n")?;
+
+        let filter = FilterBuilder::new(temp_dir.path())
+            .with_vcs_enabled(true)
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_case_insensitive(true)
+            .with_downstream("cat".to_string())
+            .build()?;
+        assert_eq!(
+            filter.decide_file(".git/config"),
+            FilterDecision::FilteredByVcs
+        );
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(filter.downstream_pipeline, vec!["cat".to_string()]);
+
+        let filter = FilterBuilder::new(temp_dir.path())
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .build()?;
+        assert!(filter.decide_file(".git/config").is_kept());
+        assert!(filter.decide_file("debug.log").is_kept());
+
+        assert!(FilterBuilder::new(temp_dir.path())
+            .with_downstream_pipeline(vec!["cat".to_string(), " ".to_string()])
+            .build()
+            .is_err());
+
+        // File options are read together in build()
+        let (ignore, diffignore, include) = (
+            temp_dir.path().join("review.ignore"),
+            temp_dir.path().join("review.diffignore"),
+            temp_dir.path().join("review.include"),
+        );
+        fs::write(&ignore, "*.tmp\n")?;
+        fs::write(&diffignore, "vendor/\n")?;
+        fs::write(&include, "keep.tmp\n")?;
+        let builder = FilterBuilder::new(temp_dir.path())
+            .with_gitignore_file(ignore)
+            .with_extra_ignore_patterns(vec!["*.snap".to_string()])
+            .with_diffignore(diffignore)
+            .with_force_include(include);
+        let filter = builder.clone().build()?;
+        assert_eq!(
+            filter.decide_file("cache.tmp"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("out.snap"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("vendor/lib.rs"),
+            FilterDecision::FilteredByDiffignore
+        );
+        assert!(filter.decide_file("keep.tmp").is_kept());
+        assert!(filter.decide_file("debug.log").is_kept());
+
+        for builder in [
+            builder
+                .clone()
+                .with_gitignore_file(temp_dir.path().join("missing")),
+            builder
+                .clone()
+                .with_diffignore(temp_dir.path().join("missing")),
+            builder.with_force_include(temp_dir.path().join("missing")),
+        ] {
+            assert!(builder.build().is_err());
+        }
+        Ok(())
+    }
+
+    /// **What is tested:** The `vcs_patterns`, `is_vcs_enabled` and `has_downstream` accessors
+    /// **Why it is tested:** Library users display the active configuration through them instead of tracking it themselves
+    /// **Test conditions:** A default filter, and one with VCS patterns, VCS filtering and a downstream command configured
+    /// **Expectations:** The accessors report nothing configured by default and the configured values otherwise
+    #[test]
+    fn test_configuration_accessors() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let filter = Filter::new(temp_dir.path())?;
+        assert!(filter.vcs_patterns().is_empty());
+        assert!(!filter.is_vcs_enabled());
+        assert!(!filter.has_downstream());
+
+        let filter = filter
+            .with_vcs_patterns(vec![".git/".to_string(), ".hg/".to_string()])
+            .with_vcs_enabled(true)
+            .with_downstream("cat".to_string());
+        assert_eq!(filter.vcs_patterns(), [".git/", ".hg/"]);
+        assert!(filter.is_vcs_enabled());
+        assert!(filter.has_downstream());
+        Ok(())
+    }
+
+    /// **What is tested:** Reading .gitignore files in subdirectories with `with_nested_gitignore`
+    /// **Why it is tested:** Git applies each .gitignore relative to its directory, with deeper files taking precedence
+    /// **Test conditions:** Root `.gitignore` with `*.log` and `vendor/`; `sub/.gitignore` with `*.tmp`, `!keep.log` and `/local/`; `vendor/.gitignore` with `!*.log`
+    /// **Expectations:** Nested patterns apply only below `sub/`, `sub/keep.log` is re-included, `vendor/` stays ignored, and nothing changes when disabled
+    #[test]
+    fn test_nested_gitignore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\nvendor/\n")?;
+        fs::create_dir_all(temp_dir.path().join("sub/local"))?;
+        fs::write(
+            temp_dir.path().join("sub/.gitignore"),
+            "*.tmp\n!keep.log\n/local/\n",
+        )?;
+        fs::create_dir_all(temp_dir.path().join("vendor"))?;
+        fs::write(temp_dir.path().join("vendor/.gitignore"), "!*.log\n")?;
 
-        // Check for any non-UTF-8 content that should be preserved
-        // This includes null bytes and other binary sequences
-        let has_binary_content = data.iter().any(|&b| {
-            // Check for null bytes or high-bit bytes that might be binary
-            b == 0 || (b >= 0x80 && !self.is_valid_utf8_sequence(data, b))
-        });
+        let filter = Filter::new(temp_dir.path())?.with_nested_gitignore(true)?;
+        for path in ["sub/cache.tmp", "sub/deep/cache.tmp", "sub/local/data.txt"] {
+            assert_eq!(
+                filter.decide_file(path),
+                FilterDecision::FilteredByGitignore,
+                "{path}"
+            );
+        }
+        for path in ["cache.tmp", "local/data.txt", "sub/keep.log", "sub/main.rs"] {
+            assert!(filter.decide_file(path).is_kept(), "{path}");
+        }
+        assert_eq!(
+            filter.decide_file("sub/debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("vendor/debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
 
-        // If it contains binary content, preserve it unchanged
-        has_binary_content
+        let filter = Filter::new(temp_dir.path())?;
+        assert!(filter.decide_file("sub/cache.tmp").is_kept());
+        Ok(())
     }
 
-    /// Helper to check if a byte sequence is valid UTF-8
-    fn is_valid_utf8_sequence(&self, data: &[u8], _byte: u8) -> bool {
-        // For simplicity, if String::from_utf8 would succeed, it's valid UTF-8
-        // Otherwise, we should preserve the binary content
-        std::str::from_utf8(data).is_ok()
-    }
-}
+    /// **What is tested:** Matching `git diff --relative` paths against a .gitignore in a parent directory
+    /// **Why it is tested:** Diff paths relative to a subdirectory must be matched as Git matches them from the repository root
+    /// **Test conditions:** Repository .gitignore with `*.log` and the anchored `/sub/build/`, filter rooted at `sub/` with the gitignore root set to the repository; then `/sub/lib/build/` with the path prefix `lib` set before or after the gitignore root
+    /// **Expectations:** `build/out.txt` and `debug.log` relative to `sub/` are dropped, `src/main.rs` is kept; with the prefix, `build/out.txt` relative to `sub/lib/` is dropped whichever setter ran last
+    #[test]
+    fn test_with_gitignore_root_relative_paths(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\n/sub/build/\n")?;
+        let sub_dir = temp_dir.path().join("sub");
+        fs::create_dir(&sub_dir)?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::Cursor;
-    use tempfile::TempDir;
+        // Without a separate gitignore root, sub/ has no .gitignore of its own
+        let filter = Filter::new(&sub_dir)?;
+        assert!(filter.decide_file("build/out.txt").is_kept());
 
-    fn create_test_repo() -> Result<TempDir> {
-        let temp_dir = TempDir::new().map_err(|e| Error::processing_error(e.to_string()))?;
+        let filter = Filter::new(&sub_dir)?.with_gitignore_root(temp_dir.path().to_path_buf())?;
+        assert_eq!(
+            filter.decide_file("build/out.txt"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
+        assert!(filter.decide_file("src/main.rs").is_kept());
 
-        // Create .git directory
-        fs::create_dir(temp_dir.path().join(".git"))
-            .map_err(|e| Error::processing_error(e.to_string()))?;
+        let diff = "diff --git a/build/out.txt b/build/out.txt\n\
+                    +built\n\
+                    diff --git a/src/main.rs b/src/main.rs\n\
+                    +fn main() {}\n";
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff), &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n"
+        );
 
-        // Create .gitignore
-        fs::write(temp_dir.path().join(".gitignore"), "*.log\n")
-            .map_err(|e| Error::processing_error(e.to_string()))?;
+        // A `--relative` prefix below the filter root is joined to its path, in either order
+        fs::write(temp_dir.path().join(".gitignore"), "/sub/lib/build/\n")?;
+        for filter in [
+            Filter::new(&sub_dir)?
+                .with_gitignore_root(temp_dir.path().to_path_buf())?
+                .with_path_prefix("lib".to_string()),
+            Filter::new(&sub_dir)?
+                .with_path_prefix("lib/".to_string())
+                .with_gitignore_root(temp_dir.path().to_path_buf())?,
+        ] {
+            assert_eq!(
+                filter.decide_file("build/out.txt"),
+                FilterDecision::FilteredByGitignore
+            );
+            assert!(filter.decide_file("lib/build/out.txt").is_kept());
+        }
+        Ok(())
+    }
 
-        Ok(temp_dir)
+    /// **What is tested:** Single-path queries through `is_path_filtered`
+    /// **Why it is tested:** Integrations ask about individual paths without a diff and must get the same decision `process_diff` makes
+    /// **Test conditions:** Repository ignoring `*.log` and `target/`, VCS patterns enabled, files and directories queried
+    /// **Expectations:** Gitignored and VCS paths report their filter kind, normal files are kept, and `target` matches only as a directory
+    #[test]
+    fn test_is_path_filtered() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\ntarget/\n")?;
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true);
+
+        assert_eq!(
+            filter.is_path_filtered("debug.log", false),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.is_path_filtered(".git/config", false),
+            FilterDecision::FilteredByVcs
+        );
+        assert_eq!(
+            filter.is_path_filtered(".git", true),
+            FilterDecision::FilteredByVcs
+        );
+        assert_eq!(
+            filter.is_path_filtered("src/main.rs", false),
+            FilterDecision::Keep
+        );
+        assert_eq!(
+            filter.is_path_filtered("target", true),
+            FilterDecision::FilteredByGitignore
+        );
+        assert_eq!(
+            filter.is_path_filtered("target", false),
+            FilterDecision::Keep
+        );
+        Ok(())
     }
 
-    /// **What is tested:** Basic filter creation with default settings
-    /// **Why it is tested:** Ensures that a filter can be created successfully with proper default values and gitignore loading
-    /// **Test conditions:** Creates a temporary repository with .git directory and .gitignore file
-    /// **Expectations:** Filter should have gitignore loaded, empty VCS patterns, VCS filtering disabled, and no downstream command
+    /// **What is tested:** Path extraction used for the filtering decisions
+    /// **Why it is tested:** Ensures that file paths are correctly extracted from diff headers for pattern matching
+    /// **Test conditions:** Sections with a simple header, a header with spaces, and text that is not a diff header
+    /// **Expectations:** Should extract the correct file paths from valid headers, and no path from text before the first header
     #[test]
-    fn test_filter_creation() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    fn test_extract_file_path() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let filter = Filter::new(temp_dir.path())?;
+        let path = |line: &str| {
+            filter
+                .sections(Cursor::new(format!("{line}\n")))
+                .next()
+                .and_then(|section| section.ok()?.path().map(ToOwned::to_owned))
+        };
 
-        assert!(filter.gitignore.is_some());
-        assert!(filter.vcs_patterns.is_empty());
-        assert!(!filter.vcs_filtering_enabled); // Default: VCS filtering disabled
-        assert!(filter.downstream_command.is_none());
+        assert_eq!(
+            path("diff --git a/src/main.rs b/src/main.rs"),
+            Some("src/main.rs".to_string())
+        );
+        assert_eq!(
+            path("diff --git a/test file.txt b/test file.txt"),
+            Some("test file.txt".to_string())
+        );
+        assert_eq!(path("not a diff line"), None);
         Ok(())
     }
 
-    /// **What is tested:** Filter configuration with VCS patterns
-    /// **Why it is tested:** Verifies that VCS patterns are properly stored and VCS filtering is automatically enabled when patterns are added
-    /// **Test conditions:** Creates filter and adds VCS patterns using with_vcs_patterns method
-    /// **Expectations:** VCS patterns should be stored correctly and VCS filtering should be enabled
+    /// **What is tested:** Filtering a diff with the prefixes resolved from `diff.srcPrefix` and `diff.dstPrefix`
+    /// **Why it is tested:** Users with custom prefixes in git config must get the same filtering without passing them on the command line
+    /// **Test conditions:** Mock git config with `old/` and `new/` prefixes, an anchored `/target/` pattern, and a diff generated with those prefixes
+    /// **Expectations:** Paths are extracted without the prefixes, the root is found, and only the section under `target/` is removed
     #[test]
-    fn test_filter_with_vcs_patterns() -> std::result::Result<(), Box<dyn std::error::Error>> {
-        let temp_dir = create_test_repo()?;
-        let patterns = vec![".git/".to_string(), ".svn/".to_string()];
-        let filter = Filter::new(temp_dir.path())?.with_vcs_patterns(patterns.clone());
+    fn test_diff_prefixes_from_git_config() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::config::{AppConfig, CliArgs, MockGitConfigReader};
+        use crate::root_finder::RootFinder;
 
-        assert_eq!(filter.vcs_patterns, patterns);
-        assert!(filter.vcs_filtering_enabled); // VCS filtering should be enabled when patterns are added
+        let temp_dir = TempDir::new()?;
+        gix::init(temp_dir.path())?;
+        fs::write(temp_dir.path().join(".gitignore"), "/target/\n")?;
+        let reader = MockGitConfigReader::new()
+            .with_config("diff.srcPrefix", "old/")
+            .with_config("diff.dstPrefix", "new/");
+        let config = AppConfig::from_cli_with_reader(CliArgs::default(), &reader)?;
+        let diff = "diff --git old/src/main.rs new/src/main.rs\n+fn main() {}\n\
+                    diff --git old/target/out.o new/target/out.o\n+binary\n";
+
+        let root = RootFinder::find_root_with_prefixes(
+            temp_dir.path().to_path_buf(),
+            Cursor::new(diff),
+            config.diff_prefixes(),
+        )?;
+        assert_eq!(root, temp_dir.path());
+
+        let filter = Filter::new(&root)?.with_diff_prefixes(config.diff_prefixes().clone());
+        let paths = filter
+            .sections(Cursor::new(diff))
+            .map(|section| Ok(section?.path().map(ToOwned::to_owned)))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            paths,
+            [
+                Some("src/main.rs".to_owned()),
+                Some("target/out.o".to_owned())
+            ]
+        );
+        assert_eq!(
+            filter.process_to_string(Cursor::new(diff))?,
+            "diff --git old/src/main.rs new/src/main.rs\n+fn main() {}\n"
+        );
         Ok(())
     }
 
-    /// **What is tested:** Filter configuration with downstream command
-    /// **Why it is tested:** Ensures that downstream commands can be properly configured for piping filtered output
-    /// **Test conditions:** Creates filter and adds downstream command using with_downstream method
-    /// **Expectations:** Downstream command should be stored correctly in the filter
+    /// **What is tested:** Filtering of sections whose headers use backslash-separated paths
+    /// **Why it is tested:** Diffs from Windows tools must be filtered with the usual forward-slash .gitignore patterns
+    /// **Test conditions:** `.gitignore` with a `target/` directory pattern, a diff with backslash headers for a file under `target` and one under `src`
+    /// **Expectations:** The section under `target` is removed, the other is kept byte for byte
     #[test]
-    fn test_filter_with_downstream() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    fn test_process_diff_backslash_paths() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
-        let command = "cat".to_string();
-        let filter = Filter::new(temp_dir.path())?.with_downstream(command.clone());
+        fs::write(temp_dir.path().join(".gitignore"), "target/\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+        let kept = "diff --git a\\src\\main.rs b\\src\\main.rs\n+fn main() {}\n";
+        let diff = format!("{kept}diff --git a\\target\\out.o b\\target\\out.o\n+binary\n");
 
-        assert_eq!(filter.downstream_command, Some(command));
+        let report = filter.process_diff_with_report(Cursor::new(diff.as_str()), Vec::new())?;
+        assert_eq!(report.kept_paths().collect::<Vec<_>>(), vec!["src/main.rs"]);
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(filter.process_to_string(Cursor::new(diff))?, kept);
         Ok(())
     }
 
-    /// **What is tested:** File path extraction from git diff headers
-    /// **Why it is tested:** Critical for identifying which files are being modified to apply filtering rules correctly
-    /// **Test conditions:** Tests various diff header formats including normal paths and paths with spaces
-    /// **Expectations:** Should correctly extract file paths from valid diff headers and return None for invalid formats
+    /// **What is tested:** Decisions for renames between C-style quoted paths
+    /// **Why it is tested:** Quoted, renamed paths must be decoded before matching, or ignored files would always be kept; the VCS check that overrides binary pass-through must agree
+    /// **Test conditions:** Quoted rename of an ignored `.log` file with UTF-8 octal escapes and a kept quoted file; then a diff with invalid UTF-8 whose ambiguous header names a rename into `.git/` only in its `rename to` line, with VCS filtering enabled
+    /// **Expectations:** The ignored rename is dropped and the kept file written unchanged; the VCS rename is dropped instead of the binary input being passed through
     #[test]
-    fn test_extract_file_path() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    fn test_process_diff_quoted_renames() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let filter = Filter::new(temp_dir.path())?;
 
-        let line = "diff --git a/src/main.rs b/src/main.rs";
-        let path = filter.extract_file_path(line);
-        assert_eq!(path, Some("src/main.rs".to_string()));
-
-        let line = "diff --git a/test file.txt b/test file.txt";
-        let path = filter.extract_file_path(line);
-        assert_eq!(path, Some("test file.txt".to_string()));
+        let renamed = r#"diff --git "a/old dir/caf\303\251.log" "b/new dir/caf\303\251.log"
+similarity index 100%
+rename from "old dir/caf\303\251.log"
+rename to "new dir/caf\303\251.log"
+"#;
+        let kept = r#"diff --git "a/src/sp ace.rs" "b/src/sp ace.rs"
+index 1234567..abcdefg 100644
+--- "a/src/sp ace.rs"
++++ "b/src/sp ace.rs"
+@@ -1 +1 @@
+-old
++new
+"#;
+        assert_eq!(
+            filter.filter_bytes(format!("{renamed}{kept}").as_bytes())?,
+            kept.as_bytes()
+        );
 
-        let invalid_line = "not a diff line";
-        let path = filter.extract_file_path(invalid_line);
-        assert_eq!(path, None);
+        let filter = filter
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true);
+        let vcs_rename =
+            b"diff --git a/hook b/.git/hooks/pre commit b/hook b/.git/hooks/pre commit\n\
+                           similarity index 100%\n\
+                           rename from hook b/hook\n\
+                           rename to .git/hooks/pre commit\n";
+        let binary = b"diff --git a/image.bin b/image.bin\n\
+                       index 1234567..abcdefg 100644\n\
+                       +\xff\xfe\n";
+        assert_eq!(
+            filter.filter_bytes(&[vcs_rename.as_slice(), binary].concat())?,
+            binary
+        );
         Ok(())
     }
 
@@ -466,29 +5127,39 @@ mod tests {
     fn test_is_vcs_file() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let patterns = vec![".git/".to_string(), ".svn/".to_string()];
-        let filter = Filter::new(temp_dir.path())?.with_vcs_patterns(patterns);
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(patterns)
+            .with_vcs_enabled(true);
 
-        assert!(filter.is_vcs_file(".git/config"));
-        assert!(filter.is_vcs_file(".svn/entries"));
-        assert!(!filter.is_vcs_file("src/main.rs"));
-        assert!(!filter.is_vcs_file("README.md"));
+        assert!(filter.is_vcs_path(".git/config", false));
+        assert!(filter.is_vcs_path(".svn/entries", false));
+        assert!(!filter.is_vcs_path("src/main.rs", false));
+        assert!(!filter.is_vcs_path("README.md", false));
         Ok(())
     }
 
     /// **What is tested:** File inclusion logic when VCS filtering is enabled
     /// **Why it is tested:** Verifies that VCS files are properly excluded when VCS filtering is active
     /// **Test conditions:** Creates filter with VCS patterns enabled and tests file inclusion decisions
-    /// **Expectations:** VCS files should be excluded, regular files should be included
+    /// **Expectations:** VCS files should be excluded as VCS, ignored files as gitignore, regular files should be included
     #[test]
-    fn test_should_include_file_vcs_enabled() -> std::result::Result<(), Box<dyn std::error::Error>>
-    {
+    fn test_decide_file_vcs_enabled() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let patterns = vec![".git/".to_string()];
-        let filter = Filter::new(temp_dir.path())?.with_vcs_patterns(patterns);
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(patterns)
+            .with_vcs_enabled(true);
 
         // When VCS filtering is enabled, VCS files should be excluded
-        assert!(!filter.should_include_file(".git/config"));
-        assert!(filter.should_include_file("src/main.rs"));
+        assert_eq!(
+            filter.decide_file(".git/config"),
+            FilterDecision::FilteredByVcs
+        );
+        assert_eq!(filter.decide_file("src/main.rs"), FilterDecision::Keep);
+        assert_eq!(
+            filter.decide_file("debug.log"),
+            FilterDecision::FilteredByGitignore
+        );
         Ok(())
     }
 
@@ -497,14 +5168,13 @@ mod tests {
     /// **Test conditions:** Creates filter without VCS patterns and tests file inclusion decisions
     /// **Expectations:** Both VCS files and regular files should be included
     #[test]
-    fn test_should_include_file_vcs_disabled() -> std::result::Result<(), Box<dyn std::error::Error>>
-    {
+    fn test_decide_file_vcs_disabled() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let filter = Filter::new(temp_dir.path())?;
 
         // When VCS filtering is disabled (default), VCS files should be included
-        assert!(filter.should_include_file(".git/config"));
-        assert!(filter.should_include_file("src/main.rs"));
+        assert_eq!(filter.decide_file(".git/config"), FilterDecision::Keep);
+        assert_eq!(filter.decide_file("src/main.rs"), FilterDecision::Keep);
         Ok(())
     }
 
@@ -546,7 +5216,9 @@ index 1234567..abcdefg 100644
     ) -> std::result::Result<(), Box<dyn std::error::Error>> {
         let temp_dir = create_test_repo()?;
         let patterns = vec![".git/".to_string()];
-        let filter = Filter::new(temp_dir.path())?.with_vcs_patterns(patterns);
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(patterns)
+            .with_vcs_enabled(true);
 
         let diff_content = r"diff --git a/.git/config b/.git/config
 index 1234567..abcdefg 100644
@@ -634,4 +5306,560 @@ index 1234567..abcdefg 100644
         assert!(output.is_empty());
         Ok(())
     }
+
+    /// **What is tested:** Include decision for renamed files where only the source is ignored
+    /// **Why it is tested:** The destination of a rename is the path that exists after the change and must drive the decision
+    /// **Test conditions:** Diff renaming an ignored `debug.log` to a tracked `debug.txt`
+    /// **Expectations:** The rename section should be kept including its rename lines
+    #[test]
+    fn test_process_diff_rename_from_ignored_source(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff_content = r"diff --git a/debug.log b/debug.txt
+similarity index 100%
+rename from debug.log
+rename to debug.txt
+";
+
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff_content), &mut output)?;
+
+        let output_str = String::from_utf8(output)?;
+        assert_eq!(output_str, diff_content);
+        Ok(())
+    }
+
+    /// **What is tested:** Include decision for renamed and copied files where the destination is ignored
+    /// **Why it is tested:** Ensures rename/copy destinations matching gitignore patterns are filtered consistently
+    /// **Test conditions:** Diff renaming `notes.txt` to `notes.log` and copying `src/a.rs` to `out.log`, followed by a normal file
+    /// **Expectations:** Both rename and copy sections should be dropped, the normal file should be kept
+    #[test]
+    fn test_process_diff_rename_and_copy_to_ignored_destination(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff_content = r"diff --git a/notes.txt b/notes.log
+similarity index 90%
+rename from notes.txt
+rename to notes.log
+index 1234567..abcdefg 100644
+--- a/notes.txt
++++ b/notes.log
+@@ -1 +1 @@
+-old
++new
+diff --git a/src/a.rs b/out.log
+similarity index 100%
+copy from src/a.rs
+copy to out.log
+diff --git a/test.txt b/test.txt
+index 1234567..abcdefg 100644
+--- a/test.txt
++++ b/test.txt
+@@ -1 +1,2 @@
+ hello
++world
+";
+
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff_content), &mut output)?;
+
+        let output_str = String::from_utf8(output)?;
+        assert!(!output_str.contains("notes.log"));
+        assert!(!output_str.contains("out.log"));
+        assert!(output_str.contains("diff --git a/test.txt b/test.txt"));
+        assert!(output_str.contains("+world"));
+        Ok(())
+    }
+
+    /// **What is tested:** Filtering of sections whose header paths are C-style quoted
+    /// **Why it is tested:** Git quotes paths with special or non-ASCII characters, which must still match gitignore patterns
+    /// **Test conditions:** Diff with a quoted ignored path containing spaces, and a quoted kept path with UTF-8 octal escapes
+    /// **Expectations:** The ignored quoted section should be dropped, the other one kept unchanged
+    #[test]
+    fn test_process_diff_quoted_paths() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff_content = r#"diff --git "a/build output\tnotes.log" "b/build output\tnotes.log"
+index 1234567..abcdefg 100644
+--- "a/build output\tnotes.log"
++++ "b/build output\tnotes.log"
+@@ -1 +1 @@
+-old
++new
+diff --git "a/caf\303\251.txt" "b/caf\303\251.txt"
+index 1234567..abcdefg 100644
+--- "a/caf\303\251.txt"
++++ "b/caf\303\251.txt"
+@@ -1 +1 @@
+-old
++new
+"#;
+
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff_content), &mut output)?;
+
+        let output_str = String::from_utf8(output)?;
+        assert!(!output_str.contains("notes.log"));
+        assert!(output_str.contains(r#"diff --git "a/caf\303\251.txt" "b/caf\303\251.txt""#));
+        Ok(())
+    }
+
+    /// **What is tested:** Section handling for combined diffs (`diff --cc` / `diff --combined`) of merge commits
+    /// **Why it is tested:** Combined headers carry a single path and must start new sections, or merge diffs would be mis-split
+    /// **Test conditions:** Combined diff mixing an ignored `.log` file with a tracked file, in both header forms
+    /// **Expectations:** Ignored sections should be dropped and tracked sections kept unchanged
+    #[test]
+    fn test_process_diff_combined_headers() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff_content = r"diff --cc debug.log
+index a2d82e8,037f036..3b1d3f3
+--- a/debug.log
++++ b/debug.log
+@@@ -1,1 -1,1 +1,2 @@@
+  started
+++merged
+diff --combined test.txt
+index 3ec5e3d,880c545..d1cb8a5
+--- a/test.txt
++++ b/test.txt
+@@@ -1,1 -1,1 +1,1 @@@
+- ours
+ -theirs
+++resolved
+";
+
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff_content), &mut output)?;
+
+        let output_str = String::from_utf8(output)?;
+        assert!(!output_str.contains("debug.log"));
+        assert!(output_str.starts_with("diff --combined test.txt\n"));
+        assert!(output_str.contains("++resolved"));
+        Ok(())
+    }
+
+    /// **What is tested:** Stripping a configured path prefix before gitignore matching
+    /// **Why it is tested:** Diffs from a superproject carry submodule-prefixed paths that root-level patterns would not match
+    /// **Test conditions:** Root-anchored `/build/` pattern, diff paths prefixed with `submodule/`, and a path outside the prefix
+    /// **Expectations:** The prefixed build path should be filtered; paths are otherwise matched and emitted unchanged
+    #[test]
+    fn test_process_diff_strip_path_prefix() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "/build/\n")?;
+
+        let diff_content = r"diff --git a/submodule/build/out.txt b/submodule/build/out.txt
+index 1234567..abcdefg 100644
+--- a/submodule/build/out.txt
++++ b/submodule/build/out.txt
+@@ -1 +1 @@
+-old
++new
+diff --git a/submodule/src/lib.rs b/submodule/src/lib.rs
+index 1234567..abcdefg 100644
+--- a/submodule/src/lib.rs
++++ b/submodule/src/lib.rs
+@@ -1 +1 @@
+-old
++new
+diff --git a/other/build/out.txt b/other/build/out.txt
+index 1234567..abcdefg 100644
+--- a/other/build/out.txt
++++ b/other/build/out.txt
+@@ -1 +1 @@
+-old
++new
+";
+
+        let mut output = Vec::new();
+        Filter::new(temp_dir.path())?.process_diff(Cursor::new(diff_content), &mut output)?;
+        assert!(String::from_utf8(output)?.contains("submodule/build/out.txt"));
+
+        let filter = Filter::new(temp_dir.path())?.with_strip_path_prefix("submodule/".to_string());
+        let mut output = Vec::new();
+        filter.process_diff(Cursor::new(diff_content), &mut output)?;
+
+        let output_str = String::from_utf8(output)?;
+        assert!(!output_str.contains("submodule/build/out.txt"));
+        assert!(output_str.contains("diff --git a/submodule/src/lib.rs b/submodule/src/lib.rs"));
+        assert!(output_str.contains("diff --git a/other/build/out.txt b/other/build/out.txt"));
+        Ok(())
+    }
+
+    /// **What is tested:** Handling of `GIT binary patch` sections produced by `git diff --binary`
+    /// **Why it is tested:** Base85 hunks and their blank separator lines must stay attached to their section
+    /// **Test conditions:** Binary patch for an ignored `.dat` file followed by one for a tracked `.bin` file
+    /// **Expectations:** The ignored section should be dropped whole; the tracked one should be kept byte-for-byte
+    #[test]
+    fn test_process_diff_binary_patch_sections(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.dat\n")?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let ignored_section = "diff --git a/cache.dat b/cache.dat
+index 738e930d3814bb7e448a3e01ce5f39918ecdbf9b..012144a234d4b352f271d70f5f8831447b0066b4 100644
+GIT binary patch
+literal 9
+QcmZSJOioPBNHzKg01IpbE&u=k
+
+literal 9
+QcmZSJ<Va3T&Pe?S00~S31ONa4
+
+";
+        let tracked_section = "diff --git a/image.bin b/image.bin
+index b43761b27df02a0c6c305120d37445368d1ac5e1..0e2b3610699e573a46848077d9032816e40d3907 100644
+GIT binary patch
+literal 11
+ScmZQzWKPP=ODw81`VRmPE(5y&
+
+literal 10
+RcmZQzWJ=1+ODwAV4*(1}1Bd_s
+
+";
+
+        let mut output = Vec::new();
+        filter.process_diff(
+            Cursor::new(format!("{ignored_section}{tracked_section}")),
+            &mut output,
+        )?;
+        assert_eq!(String::from_utf8(output)?, tracked_section);
+
+        let mut output = Vec::new();
+        filter.process_diff(
+            Cursor::new(format!("{tracked_section}{ignored_section}")),
+            &mut output,
+        )?;
+        assert_eq!(String::from_utf8(output)?, tracked_section);
+        Ok(())
+    }
+
+    /// **What is tested:** Accounting of section decisions in the returned FilterReport
+    /// **Why it is tested:** `--stats` relies on accurate counts, also when output is piped to a downstream command
+    /// **Test conditions:** Diff with kept, gitignored and VCS sections, processed directly and through `cat > /dev/null`
+    /// **Expectations:** Both runs should report the same per-reason counts and the number of bytes written
+    #[test]
+    fn test_process_diff_with_report() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let diff_content = r"diff --git a/.git/config b/.git/config
+index 1234567..abcdefg 100644
+--- a/.git/config
++++ b/.git/config
+@@ -1 +1 @@
+-old
++new
+diff --git a/debug.log b/debug.log
+index 1234567..abcdefg 100644
+--- a/debug.log
++++ b/debug.log
+@@ -1 +1 @@
+-old
++new
+diff --git a/src/main.rs b/src/main.rs
+index 1234567..abcdefg 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1 +1 @@
+-old
++new
+diff --git a/src/lib.rs b/src/lib.rs
+index 1234567..abcdefg 100644
+";
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true);
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(Cursor::new(diff_content), &mut output)?;
+        assert_eq!(
+            (
+                report.kept(),
+                report.filtered_by_gitignore(),
+                report.filtered_by_vcs()
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            report.kept_paths().collect::<Vec<_>>(),
+            vec!["src/main.rs", "src/lib.rs"]
+        );
+        assert_eq!(report.output_bytes(), output.len() as u64);
+
+        let filter = filter
+            .with_downstream("cat > /dev/null".to_string())
+            .with_downstream_shell(true);
+        let downstream_report =
+            filter.process_diff_with_report(Cursor::new(diff_content), Vec::new())?;
+        assert_eq!(downstream_report, report);
+        Ok(())
+    }
+
+    /// **What is tested:** Output size reported for a diff whose sections are all ignored
+    /// **Why it is tested:** Library users detect a diff emptied by filtering from the report, like `--fail-if-empty`
+    /// **Test conditions:** Diff with two `.log` sections written directly and piped to a downstream command, and the same diff with a kept section added
+    /// **Expectations:** `output_bytes` is 0 with no sections kept for the ignored diff in both modes, and non-zero once a section is kept
+    #[test]
+    fn test_report_empty_output() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let ignored = "diff --git a/debug.log b/debug.log\n+line\n\
+                       diff --git a/logs/run.log b/logs/run.log\n+line\n";
+        let filter = Filter::new(temp_dir.path())?;
+
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(Cursor::new(ignored), &mut output)?;
+        assert!(output.is_empty());
+        assert_eq!(report.output_bytes(), 0);
+        assert_eq!((report.kept(), report.filtered()), (0, 2));
+
+        let report = Filter::new(temp_dir.path())?
+            .with_downstream("cat".to_string())
+            .process_diff_with_report(Cursor::new(ignored), Vec::new())?;
+        assert_eq!(report.output_bytes(), 0);
+
+        let kept = format!("{ignored}diff --git a/src/main.rs b/src/main.rs\n+line\n");
+        let report = filter.process_diff_with_report(Cursor::new(kept), Vec::new())?;
+        assert_eq!(report.output_bytes(), 45);
+        assert_eq!((report.kept(), report.filtered()), (1, 2));
+        Ok(())
+    }
+
+    /// **What is tested:** Collection of top-level directories touched by kept sections
+    /// **Why it is tested:** Tooling uses the set to trigger per-area CI, so ignored areas must not appear
+    /// **Test conditions:** Diff touching `src/`, `docs/`, an ignored `logs/` file, a VCS file, a rename into `lib/` and a root-level file
+    /// **Expectations:** Only the distinct, sorted top-level directories of non-ignored sections should be returned
+    #[test]
+    fn test_kept_top_dirs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true)
+            .with_downstream("false".to_string());
+
+        let diff_content = r"diff --git a/src/main.rs b/src/main.rs
+index 1234567..abcdefg 100644
+diff --git a/logs/debug.log b/logs/debug.log
+index 1234567..abcdefg 100644
+diff --git a/docs/guide.md b/docs/guide.md
+index 1234567..abcdefg 100644
+diff --git a/.git/config b/.git/config
+index 1234567..abcdefg 100644
+diff --git a/src/lib.rs b/src/lib.rs
+index 1234567..abcdefg 100644
+diff --git a/old/util.rs b/lib/util.rs
+similarity index 100%
+rename from old/util.rs
+rename to lib/util.rs
+diff --git a/README.md b/README.md
+index 1234567..abcdefg 100644
+";
+
+        let dirs = filter.kept_top_dirs(diff_content)?;
+        assert_eq!(
+            dirs.into_iter().collect::<Vec<_>>(),
+            vec!["docs", "lib", "src"]
+        );
+        Ok(())
+    }
+
+    /// **What is tested:** Splitting a list of changed paths with `partition_paths`
+    /// **Why it is tested:** Pre-commit hooks only have the file list and must get the same decisions as for a diff
+    /// **Test conditions:** Paths for a source file, a gitignored log, a VCS file, a file in a gitignored directory and a nested source file, with VCS filtering enabled
+    /// **Expectations:** Source files are kept and the others filtered, both in input order, matching the diff decisions
+    #[test]
+    fn test_partition_paths() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        fs::write(temp_dir.path().join(".gitignore"), "*.log\nbuild/\n")?;
+        let filter = Filter::new(temp_dir.path())?
+            .with_vcs_patterns(vec![".git/".to_string()])
+            .with_vcs_enabled(true);
+        let paths = [
+            "src/main.rs",
+            "debug.log",
+            ".git/config",
+            "build/out.o",
+            "docs/guide.md",
+        ]
+        .map(String::from);
+
+        let (kept, filtered) = filter.partition_paths(&paths);
+        assert_eq!(kept, ["src/main.rs", "docs/guide.md"]);
+        assert_eq!(filtered, ["debug.log", ".git/config", "build/out.o"]);
+
+        let diff: String = paths
+            .iter()
+            .map(|path| format!("diff --git a/{path} b/{path}\n"))
+            .collect();
+        let report = filter.process_diff_with_report(Cursor::new(diff), io::sink())?;
+        assert_eq!(report.kept_paths().collect::<Vec<_>>(), kept);
+        Ok(())
+    }
+
+    /// **What is tested:** Iteration over the sections of a three-file diff with [`Filter::sections`]
+    /// **Why it is tested:** Custom tooling makes its own keep/drop decisions on top of the shared section parsing
+    /// **Test conditions:** Diff touching `src/main.rs`, an ignored `debug.log` and a rename into `docs/guide.md`
+    /// **Expectations:** Three sections with headers, paths and bodies; only the log is ignored, and writing the kept sections matches `process_diff`
+    #[test]
+    fn test_sections_three_file_diff() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let diff_content = "diff --git a/src/main.rs b/src/main.rs
+index 1234567..abcdefg 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1 +1 @@
+-fn main() {}
++fn main() { run() }
+diff --git a/debug.log b/debug.log
+index 1111111..2222222 100644
+--- a/debug.log
++++ b/debug.log
+@@ -1 +1,2 @@
+ log entry
++another log entry
+diff --git a/docs/old.md b/docs/guide.md
+similarity index 100%
+rename from docs/old.md
+rename to docs/guide.md
+";
+
+        let sections = filter
+            .sections(diff_content.as_bytes())
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].header, "diff --git a/src/main.rs b/src/main.rs");
+        assert_eq!(sections[0].old_path.as_deref(), Some("src/main.rs"));
+        assert_eq!(sections[0].new_path.as_deref(), Some("src/main.rs"));
+        assert!(sections[0]
+            .body
+            .starts_with(b"index 1234567..abcdefg 100644\n"));
+        assert!(sections[0].body.ends_with(b"+fn main() { run() }\n"));
+        assert_eq!(sections[1].new_path.as_deref(), Some("debug.log"));
+        assert_eq!(sections[2].old_path.as_deref(), Some("docs/old.md"));
+        assert_eq!(sections[2].new_path.as_deref(), Some("docs/guide.md"));
+
+        let ignored: Vec<bool> = sections
+            .iter()
+            .map(|section| section.is_ignored(&filter))
+            .collect();
+        assert_eq!(ignored, vec![false, true, false]);
+
+        // Reassembling the kept sections reproduces the filtered diff
+        let mut reassembled = Vec::new();
+        for section in sections
+            .iter()
+            .filter(|section| !section.is_ignored(&filter))
+        {
+            section.write_to(&mut reassembled)?;
+        }
+        let mut output = Vec::new();
+        filter.process_diff(diff_content.as_bytes(), &mut output)?;
+        assert_eq!(reassembled, output);
+        Ok(())
+    }
+
+    /// **What is tested:** Byte-exact preservation of mixed LF and CRLF line endings within one stream
+    /// **Why it is tested:** Concatenated diffs from different platforms mix endings, which must neither break header detection nor be normalized
+    /// **Test conditions:** LF section followed by an ignored CRLF section and a kept CRLF section without a final newline
+    /// **Expectations:** The ignored section is dropped and both kept sections are emitted byte-for-byte with their original endings
+    #[test]
+    fn test_process_diff_mixed_line_endings() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = create_test_repo()?;
+        let filter = Filter::new(temp_dir.path())?;
+
+        let lf_section = "diff --git a/src/unix.rs b/src/unix.rs\n\
+                          index 1234567..abcdefg 100644\n\
+                          --- a/src/unix.rs\n\
+                          +++ b/src/unix.rs\n\
+                          @@ -1 +1 @@\n\
+                          -old\n\
+                          +new\n";
+        let ignored_crlf_section = "diff --git a/debug.log b/debug.log\r\n\
+                                    index 1234567..abcdefg 100644\r\n\
+                                    @@ -1 +1 @@\r\n\
+                                    +entry\r\n";
+        let crlf_section = "diff --git a/src/windows.rs b/src/windows.rs\r\n\
+                            index 1234567..abcdefg 100644\r\n\
+                            --- a/src/windows.rs\r\n\
+                            +++ b/src/windows.rs\r\n\
+                            @@ -1 +1 @@\r\n\
+                            -old\r\n\
+                            +new";
+
+        let mut output = Vec::new();
+        let report = filter.process_diff_with_report(
+            Cursor::new(format!("{lf_section}{ignored_crlf_section}{crlf_section}")),
+            &mut output,
+        )?;
+
+        assert_eq!(report.filtered_by_gitignore(), 1);
+        assert_eq!(output, format!("{lf_section}{crlf_section}").into_bytes());
+        Ok(())
+    }
+
+    /// **What is tested:** Sections whose header lines end in LF while their content lines end in CRLF
+    /// **Why it is tested:** Lines are split at `\n` only; a `\r` is content, so endings that change within a section must neither move its boundaries nor be normalized
+    /// **Test conditions:** A kept section with CRLF hunk lines, a bare `\r` inside a line and an empty CRLF context line; an ignored section and a mode-only section with CRLF content under LF headers; filtered serially, on 4 threads, with a small size limit, in unified mode and with header markers
+    /// **Expectations:** The kept section is written byte for byte, the other two are dropped for their own reasons, and markers use the LF ending of their header
+    #[test]
+    fn test_process_diff_crlf_content_under_lf_header(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = create_test_repo()?;
+
+        let kept = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                    index 1234567..abcdefg 100644\n\
+                    --- a/src/lib.rs\n\
+                    +++ b/src/lib.rs\n\
+                    @@ -1,3 +1,3 @@\n \
+                    first\r\n\
+                    -old\r\n\
+                    +new\rstill new\r\n\
+                    \r\n";
+        let ignored = "diff --git a/debug.log b/debug.log\n\
+                       index 1234567..abcdefg 100644\n\
+                       @@ -1 +1 @@\n\
+                       -started\r\n\
+                       +finished\r\n";
+        let mode_only = "diff --git a/run.sh b/run.sh\n\
+                         old mode 100644\r\n\
+                         new mode 100755\r\n";
+        let input = format!("{ignored}{kept}{mode_only}");
+
+        let filter =
+            || -> Result<Filter> { Ok(Filter::new(temp_dir.path())?.with_drop_mode_only(true)) };
+        let filters = [
+            filter()?,
+            filter()?.with_parallelism(4),
+            filter()?.with_max_section_bytes(16),
+            filter()?.with_unified(true),
+        ];
+        for filter in filters {
+            let mut output = Vec::new();
+            let report = filter.process_diff_with_report(input.as_bytes(), &mut output)?;
+            assert_eq!(output, kept.as_bytes());
+            assert_eq!(report.filtered_by_gitignore(), 1);
+            assert_eq!(report.filtered_by_mode_only(), 1);
+        }
+
+        let output = filter()?
+            .with_keep_empty_diff_headers(true)
+            .filter_bytes(input.as_bytes())?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            format!(
+                "diff --git a/debug.log b/debug.log\n# filtered by gitignore\n\
+                 {kept}\
+                 diff --git a/run.sh b/run.sh\n# filtered by mode-only\n"
+            )
+        );
+        Ok(())
+    }
 }
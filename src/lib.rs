@@ -17,15 +17,41 @@
 //! filter.process_diff(Cursor::new(input), &mut output)?;
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
+//!
+//! Custom keep/drop decisions on parsed sections:
+//!
+//! ```rust
+//! use diff_gitignore_filter::Filter;
+//! use std::io::Cursor;
+//!
+//! let filter = Filter::new(".")?;
+//! let input = "diff --git a/src/lib.rs b/src/lib.rs\n";
+//! let mut output = Vec::new();
+//!
+//! for section in filter.sections(Cursor::new(input)) {
+//!     let section = section?;
+//!     if !section.is_ignored(&filter) && section.path() != Some("CHANGELOG.md") {
+//!         section.write_to(&mut output)?;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
 
 pub mod config;
+pub mod diagnostics;
+pub mod diff;
 pub mod error;
 pub mod filter;
+pub mod matcher;
+pub mod report;
 pub mod root_finder;
 
-pub use config::{AppConfig, ConfigError, GitConfig, GitConfigReader, SystemGitConfigReader};
+pub use config::{
+    AppConfig, ConfigError, EnvConfig, GitConfig, GitConfigReader, SystemGitConfigReader,
+};
 pub use error::{Error, Result};
-pub use filter::Filter;
+pub use filter::{Filter, FilterBuilder, FilterWriter, OutputFormat, UnparsableAction};
+pub use report::{FilterDecision, FilterReport, SectionDecision, SectionInfo};
 pub use root_finder::RootFinder;
 
 #[cfg(test)]
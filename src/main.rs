@@ -3,22 +3,146 @@
 //! A pure stream-filter for Git diffs that respects .gitignore patterns
 //! and supports optional downstream filtering.
 
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, ValueEnum};
 use std::env;
-use std::io::{self, BufReader, Seek, SeekFrom};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 use tempfile::tempfile;
 
-use diff_gitignore_filter::{AppConfig, ConfigError, Filter, Result, RootFinder};
+use diff_gitignore_filter::config::ConfigKeys;
+use diff_gitignore_filter::diagnostics::{self, diag};
+use diff_gitignore_filter::diff::StripAnsi;
+use diff_gitignore_filter::{
+    AppConfig, ConfigError, Filter, FilterBuilder, FilterReport, OutputFormat, Result, RootFinder,
+    UnparsableAction,
+};
+
+/// Output format selectable with `--format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// The filtered diff
+    #[default]
+    Diff,
+    /// A JSON array describing each file section
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Diff => OutputFormat::Diff,
+            Format::Json => OutputFormat::Json,
+        }
+    }
+}
+
+/// Handling of unparsable file sections selectable with `--on-unparsable`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OnUnparsable {
+    /// Keep the section
+    #[default]
+    Keep,
+    /// Drop the section
+    Drop,
+    /// Fail with an error naming the header
+    Error,
+}
+
+impl From<OnUnparsable> for UnparsableAction {
+    fn from(action: OnUnparsable) -> Self {
+        match action {
+            OnUnparsable::Keep => UnparsableAction::Keep,
+            OnUnparsable::Drop => UnparsableAction::Drop,
+            OnUnparsable::Error => UnparsableAction::Error,
+        }
+    }
+}
 
 /// Pure stream-filter for Git diffs that respects .gitignore patterns
 #[derive(Parser)]
 #[command(name = "diff-gitignore-filter")]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Pipe filtered output to downstream command
-    #[arg(short, long, value_name = "COMMAND")]
-    downstream: Option<String>,
+    /// Pipe filtered output to downstream command (repeat to chain stages)
+    #[arg(
+        short,
+        long,
+        value_name = "COMMAND",
+        long_help = "Pipe filtered output to a downstream command. Repeat the option to \
+                     build a pipeline: each stage's stdout is connected to the next stage's \
+                     stdin, e.g. '-d cat -d \"delta\" -d less'. Commands are split into program and \
+                     arguments with shell quoting rules and spawned without a shell (see \
+                     --downstream-shell). Overrides git config 'diff-gitignore-filter.downstream-filter'; \
+                     a blank COMMAND disables downstream filtering."
+    )]
+    downstream: Vec<String>,
+
+    /// Run downstream commands through a shell
+    #[arg(
+        long,
+        long_help = "Run each downstream command through 'sh -c' so that pipes, redirections \
+                     and variable expansion are interpreted. By default the command is split \
+                     into program and arguments using shell quoting rules and spawned \
+                     directly, without a shell."
+    )]
+    downstream_shell: bool,
+
+    /// Kill the downstream pipeline after this many seconds
+    #[arg(
+        long,
+        value_name = "SECS",
+        long_help = "Kill the downstream commands and fail with a DownstreamTimeout error if \
+                     writing the filtered diff to them and waiting for them to exit takes \
+                     longer than SECS seconds. Guards CI runs against commands that hang, \
+                     e.g. while waiting for a terminal. A value of 0 means no timeout."
+    )]
+    downstream_timeout: Option<u64>,
+
+    /// Re-run a failed downstream pipeline up to N times
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        long_help = "Re-run the downstream commands up to N times if one of them cannot be \
+                     spawned or exits with a non-zero status, e.g. for network-backed tools \
+                     that fail transiently. The input is replayed on every attempt; output \
+                     a failed attempt already wrote is not taken back. Timeouts are not \
+                     retried."
+    )]
+    downstream_retries: u32,
+
+    /// Bypass the downstream commands for filtered diffs longer than N lines
+    #[arg(
+        long,
+        value_name = "N",
+        long_help = "Write the filtered diff directly to stdout instead of piping it to the \
+                     downstream commands when it has more than N lines, e.g. to page or \
+                     highlight ordinary diffs but not huge ones. The whole filtered diff is \
+                     held in memory to count its lines before the downstream commands are \
+                     started. A value of 0 means no limit."
+    )]
+    downstream_max_lines: Option<usize>,
+
+    /// Working directory of the downstream commands
+    #[arg(
+        long,
+        value_name = "DIR",
+        long_help = "Run the downstream commands in DIR instead of the current directory, \
+                     e.g. so that tools like delta find their configuration. Relative paths \
+                     are resolved against the current directory."
+    )]
+    downstream_cwd: Option<PathBuf>,
+
+    /// Set an environment variable for the downstream commands (repeatable)
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        long_help = "Set the environment variable NAME to VALUE for the downstream commands. \
+                     Repeat the option to set several variables. The downstream commands \
+                     inherit all other variables of the environment."
+    )]
+    downstream_env: Vec<String>,
 
     /// Enable VCS ignore filtering (overrides git config)
     #[arg(long, overrides_with = "no_vcs", action = ArgAction::SetTrue)]
@@ -35,9 +159,482 @@ struct Args {
         help = "Comma-separated VCS patterns (e.g., '.git/,.svn/')",
         long_help = "Specify custom VCS ignore patterns as comma-separated list. \
                      Overrides git config 'diff-gitignore-filter.vcs-ignore.patterns'. \
-                     Patterns are trimmed and empty patterns are filtered out."
+                     Patterns are trimmed and empty patterns are filtered out. Patterns \
+                     matching every path ('*', '**/') or containing a '..' component are \
+                     rejected."
     )]
     vcs_pattern: Option<String>,
+
+    /// Additional VCS patterns appended to the default or configured ones
+    #[arg(
+        long,
+        value_name = "PATTERNS",
+        help = "Comma-separated VCS patterns added to the effective ones (e.g., '.jj/')",
+        long_help = "Append comma-separated VCS ignore patterns to the effective patterns \
+                     instead of replacing them: the --vcs-pattern list if given, otherwise \
+                     the patterns from the environment, git config or the defaults. For \
+                     example '--vcs-pattern-add .jj/' filters .jj/ in addition to .git/ and \
+                     the other defaults."
+    )]
+    vcs_pattern_add: Option<String>,
+
+    /// Accept an empty VCS pattern list instead of failing
+    #[arg(
+        long,
+        long_help = "Accept a --vcs-pattern list that is empty after splitting and trimming \
+                     (e.g. '' or ' , '). VCS filtering then stays enabled but matches nothing. \
+                     Without this flag such a list is rejected as an invalid argument."
+    )]
+    allow_empty_vcs: bool,
+
+    /// Namespace of the filter's git config keys
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        long_help = "Read the filter's git config settings under PREFIX instead of the \
+                     default keys: '<PREFIX>.vcs-ignore.enabled', \
+                     '<PREFIX>.vcs-ignore.patterns' and '<PREFIX>.downstream-filter'. \
+                     Without this option (or DIFF_GITIGNORE_FILTER_CONFIG_KEY_PREFIX) the \
+                     'diff-gitignore-filter.*' keys are read, with the deprecated \
+                     'gitignore-diff.downstream-filter' as fallback. Git's own settings such as \
+                     core.ignorecase are not affected."
+    )]
+    config_key_prefix: Option<String>,
+
+    /// Read review-only ignore patterns from FILE instead of .diffignore
+    #[arg(
+        long,
+        value_name = "FILE",
+        long_help = "Drop file sections matching the patterns in FILE, in addition to \
+                     .gitignore and VCS patterns. FILE uses .gitignore syntax with patterns \
+                     relative to the repository root; unlike .gitignore it also hides tracked \
+                     files, e.g. committed vendored code. Without this option, .diffignore in \
+                     the repository root is read if it exists. Filtered sections are reported \
+                     with reason 'diffignore'."
+    )]
+    diffignore: Option<PathBuf>,
+
+    /// Always keep files matching the patterns in FILE, even if they are ignored
+    #[arg(
+        long,
+        value_name = "FILE",
+        long_help = "Keep file sections matching the patterns in FILE even if .gitignore, \
+                     .diffignore, VCS or extension filters would drop them, e.g. a committed \
+                     list of ignored files that should still be reviewed. FILE uses \
+                     .gitignore syntax with patterns relative to the repository root; a \
+                     match has the highest priority. Sections dropped by --no-mode-only or \
+                     --no-deletions stay dropped."
+    )]
+    force_include: Option<PathBuf>,
+
+    /// Read .gitignore patterns from FILE, also outside a repository
+    #[arg(
+        long,
+        value_name = "FILE",
+        long_help = "Read the .gitignore patterns from FILE instead of the .gitignore in the \
+                     repository root. The patterns are relative to the root, or to the \
+                     current directory when no repository is found, so diffs can be filtered \
+                     outside a repository, e.g. when linting patches in CI. Patterns of \
+                     --ignore follow those of FILE."
+    )]
+    gitignore_file: Option<PathBuf>,
+
+    /// Ignore paths matching PATTERN in addition to .gitignore (repeatable)
+    #[arg(
+        long = "ignore",
+        value_name = "PATTERN",
+        long_help = "Add PATTERN to the patterns of the root .gitignore, e.g. '*.snap' or \
+                     'generated/'. PATTERN uses .gitignore syntax and is relative to the \
+                     repository root. Repeat the option to add several patterns; they are \
+                     applied in order after the .gitignore's own patterns, so '!PATTERN' can \
+                     re-include a path the .gitignore ignores. Filtered sections are reported \
+                     with reason 'gitignore'."
+    )]
+    ignore: Vec<String>,
+
+    /// Strip a leading path prefix from diff paths before matching
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        long_help = "Remove a leading path prefix (e.g. 'submodule/') from each diff path before \
+                     matching it against .gitignore and VCS patterns. Useful for diffs generated \
+                     from a superproject; complements 'git diff --relative'. Paths outside the \
+                     prefix are matched unchanged and output is never rewritten."
+    )]
+    strip_path_prefix: Option<String>,
+
+    /// Directory the diff paths are relative to (for 'git diff --relative')
+    #[arg(
+        long,
+        value_name = "DIR",
+        long_help = "Directory, relative to the repository root, that the diff paths are \
+                     relative to, e.g. 'subdir/' for the output of 'git -C subdir diff \
+                     --relative'. It is prepended to each diff path before matching against \
+                     .gitignore patterns, so root patterns such as '/subdir/build/' apply. \
+                     Output is never rewritten."
+    )]
+    path_prefix: Option<String>,
+
+    /// Source path prefix of the diff headers (default: 'a/' or git config)
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        long_help = "Source path prefix used in the 'diff --git' headers, as passed to \
+                     'git diff --src-prefix'. Defaults to diff.srcPrefix (ignored when \
+                     diff.mnemonicPrefix is set, as in git), or '' when diff.noprefix is \
+                     set, or 'a/'. Headers that do not match are still \
+                     parsed with the default, mnemonic and empty prefixes."
+    )]
+    src_prefix: Option<String>,
+
+    /// Destination path prefix of the diff headers (default: 'b/' or git config)
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        long_help = "Destination path prefix used in the 'diff --git' headers, as passed to \
+                     'git diff --dst-prefix'. Defaults to diff.dstPrefix, or '' when \
+                     diff.noprefix is set, or 'b/'."
+    )]
+    dst_prefix: Option<String>,
+
+    /// Also filter plain unified diffs without 'diff --git' headers
+    #[arg(
+        long,
+        long_help = "Also recognize file sections of plain unified diffs, such as 'diff -u' \
+                     or 'diff -ruN' output, which have no 'diff --git' headers: a '--- ' line \
+                     directly followed by a '+++ ' line starts a file section, unless it is \
+                     inside a 'diff --git' section or a hunk. Paths are taken from these lines \
+                     with the --src-prefix/--dst-prefix stripped, e.g. '--dst-prefix new/' for \
+                     'diff -ruN old new'. Off by default, so that git diffs are never split at \
+                     such lines. Without it, input without 'diff --git' headers is passed \
+                     through unfiltered."
+    )]
+    unified: bool,
+
+    /// Remove ANSI color codes from the input, e.g. of 'git diff --color'
+    #[arg(
+        long,
+        long_help = "Remove ANSI color sequences (SGR escapes such as ESC[32m) from the input \
+                     before it is parsed, so that the output of 'git diff --color' is filtered \
+                     like a plain diff. The filtered output is written without colors. Input \
+                     without color sequences is not changed."
+    )]
+    strip_ansi: bool,
+
+    /// Validate the resolved configuration and exit without reading a diff
+    #[arg(
+        long,
+        long_help = "Resolve the full configuration (CLI, git config, defaults), validate all \
+                     VCS patterns and check that the downstream command exists, then exit \
+                     without reading stdin. Exits with code 0 if the configuration is valid \
+                     and 1 with details otherwise."
+    )]
+    dry_run_config: bool,
+
+    /// Print the resolved configuration as TOML and exit without reading a diff
+    #[arg(
+        long,
+        conflicts_with = "dry_run_config",
+        long_help = "Resolve the configuration from the command line, the environment, git \
+                     config and the defaults, print the VCS and downstream settings as TOML \
+                     to stdout and exit without reading stdin. A [sources] table names the \
+                     layer each setting was taken from: 'cli', 'env', 'git-config' or \
+                     'default'."
+    )]
+    print_config: bool,
+
+    /// Print the keep/drop decision for each file instead of the filtered diff
+    #[arg(
+        long,
+        conflicts_with = "list_dirs",
+        long_help = "Instead of the filtered diff, print one line per file section with the \
+                     decision made for it, e.g. 'KEEP src/main.rs' or \
+                     'DROP debug.log (gitignore)'. Useful to find out why a path is or is \
+                     not filtered. Downstream commands are not run in this mode."
+    )]
+    dry_run: bool,
+
+    /// Output format: the filtered diff or a JSON description of each file
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = Format::Diff,
+        conflicts_with_all = ["list_dirs", "dry_run", "split_on"],
+        long_help = "Output format. 'diff' (the default) writes the filtered diff unchanged. \
+                     'json' writes a JSON array with one object per file section instead, \
+                     e.g. {\"path\": \"debug.log\", \"status\": \"filtered\", \
+                     \"reason\": \"gitignore\", \"header\": \"diff --git ...\", \
+                     \"body_bytes\": 120}. Status is 'kept' or 'filtered', reason is \
+                     'gitignore', 'vcs', 'diffignore', 'unparsable', 'mode-only', 'deletion', \
+                     'extension', 'only-vcs', 'rename' or 'none'. \
+                     Downstream commands are not run in JSON mode."
+    )]
+    format: Format,
+
+    /// Replace filtered files by their header and a comment instead of removing them
+    #[arg(
+        long,
+        long_help = "Instead of removing a filtered file section entirely, write its \
+                     'diff --git' line followed by a comment line such as \
+                     '# filtered by gitignore' (or 'vcs', 'diffignore', 'unparsable', \
+                     'mode-only', 'deletion', 'extension', 'only-vcs', 'rename'). For \
+                     downstream tools that expect a header for every file of the diff."
+    )]
+    keep_empty_diff_headers: bool,
+
+    /// What to do with file sections whose path cannot be determined
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value_t = OnUnparsable::Keep,
+        long_help = "What to do with a file section whose path cannot be determined, e.g. \
+                     because its 'diff --git' header is malformed or uses unknown prefixes. \
+                     'keep' (the default) writes it unchanged; 'drop' removes it like an \
+                     ignored file, with reason 'unparsable'; 'error' stops processing with \
+                     an error naming the header."
+    )]
+    on_unparsable: OnUnparsable,
+
+    /// Precede kept files with a comment saying why they were kept
+    #[arg(
+        long,
+        long_help = "Write a comment line before each kept file section saying why it was \
+                     kept, e.g. '# kept: no matching ignore rule' or \
+                     '# kept: re-included by !important.log'. For debugging how patterns \
+                     interact; the annotated output is not meant for 'git apply'."
+    )]
+    annotate: bool,
+
+    /// Drop files whose only change is the file mode
+    #[arg(
+        long,
+        long_help = "Drop file sections that only change the file mode ('old mode'/'new mode' \
+                     lines without content changes), e.g. after 'chmod +x'. Applies \
+                     in addition to .gitignore and VCS patterns."
+    )]
+    no_mode_only: bool,
+
+    /// Drop files that are deleted
+    #[arg(
+        long,
+        long_help = "Drop file sections that delete a file ('deleted file mode' line). \
+                     Applies in addition to .gitignore and VCS patterns."
+    )]
+    no_deletions: bool,
+
+    /// Apply .gitignore files added in the diff to the files after them
+    #[arg(
+        long,
+        long_help = "When the diff adds or modifies a .gitignore, also drop the file \
+                     sections after it that its added lines ignore. Sections before the \
+                     .gitignore's section are not affected, so the result depends on the \
+                     order of the sections. Removed lines of a modified .gitignore stay \
+                     in effect if the .gitignore on disk still has them."
+    )]
+    apply_diff_gitignore: bool,
+
+    /// Keep or drop a rename shown as a deletion and an addition as one unit
+    #[arg(
+        long,
+        long_help = "Pair the deletion of a file with the addition of a file with the same \
+                     content, as in 'git diff --no-renames' output, and drop the deletion \
+                     with reason 'rename' if the addition is dropped, so both are dropped \
+                     together like a rename. A deletion dropped by its own path stays \
+                     dropped, and a force-included deletion or one dropped by --no-deletions \
+                     or --no-mode-only keeps its decision. Heuristic: sections are paired only if the object names on \
+                     their 'index' lines are equal (unchanged content) and they belong to \
+                     the same diff or commit; empty files are never paired. The whole diff \
+                     is buffered and --max-section-bytes does not apply."
+    )]
+    pair_renames: bool,
+
+    /// Keep only the sections of VCS metadata files and drop all others
+    #[arg(
+        long,
+        visible_alias = "invert-vcs",
+        long_help = "Invert the VCS filter: keep exactly the file sections matching the VCS \
+                     patterns (see --vcs-pattern) and drop all others with reason 'only-vcs', \
+                     e.g. to inspect changes to .git/ hooks. The patterns apply with or \
+                     without --vcs; .gitignore, .diffignore and extension filters are not \
+                     applied."
+    )]
+    only_vcs: bool,
+
+    /// Keep only files with one of these extensions (comma-separated)
+    #[arg(
+        long,
+        value_name = "EXTS",
+        long_help = "Keep only file sections whose path has one of the comma-separated \
+                     extensions, e.g. 'rs,toml'. The extension is the part of the file name \
+                     after its last dot, compared case-sensitively. Files without an extension \
+                     (e.g. 'Makefile') and dotfiles (e.g. '.env') are dropped. Applies after \
+                     .gitignore and VCS patterns; dropped sections are reported with reason \
+                     'extension'."
+    )]
+    only_ext: Option<String>,
+
+    /// Drop files with one of these extensions (comma-separated)
+    #[arg(
+        long,
+        value_name = "EXTS",
+        long_help = "Drop file sections whose path has one of the comma-separated extensions, \
+                     e.g. 'lock,snap'. Files without an extension and dotfiles (e.g. '.env') \
+                     are never dropped by this option. Applies after .gitignore and VCS \
+                     patterns, together with --only-ext; dropped sections are reported with \
+                     reason 'extension'."
+    )]
+    exclude_ext: Option<String>,
+
+    /// Number of threads deciding which files to keep
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        long_help = "Decide which file sections to keep on N threads. The diff is split into \
+                     sections first and the kept ones are written in their original order, so \
+                     the output is identical to the default serial mode. Only worthwhile for \
+                     very large diffs."
+    )]
+    jobs: u16,
+
+    /// Stream file sections larger than this many bytes instead of buffering them
+    #[arg(
+        long,
+        value_name = "BYTES",
+        long_help = "Stop buffering a file section once its body exceeds BYTES bytes, to \
+                     bound memory use for huge sections such as generated files. The \
+                     decision for such a section is made from its header path; the rest \
+                     of it is streamed to the output if it is kept and skipped otherwise. \
+                     Sections are then decided serially, regardless of --jobs. A value of \
+                     0 means no limit."
+    )]
+    max_section_bytes: Option<usize>,
+
+    /// Exit with status 1 if the filtered diff is empty
+    #[arg(
+        long,
+        conflicts_with = "list_dirs",
+        long_help = "Exit with status 1 if nothing is left of the diff after filtering, and \
+                     with status 0 if any output was produced. The filtered output is written \
+                     as usual, also to downstream commands. With --split-on, the marker lines \
+                     do not count as output."
+    )]
+    fail_if_empty: bool,
+
+    /// Terminate report lines with NUL instead of newline
+    #[arg(
+        short = 'z',
+        long = "null",
+        long_help = "Terminate the lines of --dry-run, --list-dirs and --stats output with NUL \
+                     instead of a newline, like 'git diff --name-only -z'. Paths containing \
+                     newlines or other unusual characters can then be parsed safely. The \
+                     filtered diff itself is not affected."
+    )]
+    null: bool,
+
+    /// Print a summary of kept and filtered files to stderr
+    #[arg(
+        long,
+        long_help = "After processing, write a one-line summary such as \
+                     'kept 12 files, filtered 5 (.gitignore: 4, vcs: 1)' to stderr. \
+                     Stdout is left untouched, so the flag is safe to combine with pipes \
+                     and downstream commands."
+    )]
+    stats: bool,
+
+    /// Write diagnostics to stderr (-v: root selection, -vv: also each file's decision)
+    #[arg(
+        short,
+        long,
+        action = ArgAction::Count,
+        long_help = "Write diagnostic lines to stderr. With -v, how the repository root was \
+                     selected and which downstream commands were spawned; with -vv, also the \
+                     decision made for each file section, e.g. 'DROP debug.log (gitignore)'. \
+                     Stdout is left untouched."
+    )]
+    verbose: u8,
+
+    /// List the top-level directories touched by kept files instead of the diff
+    #[arg(
+        long,
+        long_help = "Instead of the filtered diff, print the distinct top-level directories \
+                     touched by the files that are kept after filtering, one per line and \
+                     sorted. Files at the repository root are not listed. The downstream \
+                     command is not run in this mode."
+    )]
+    list_dirs: bool,
+
+    /// Write the sections dropped by the filter to FILE
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["list_dirs", "dry_run", "format", "split_on"],
+        long_help = "Write every section removed from the diff to FILE, byte for byte, while \
+                     the kept sections are written to stdout as usual. Together the two \
+                     outputs hold every section of the input, e.g. to audit what was \
+                     filtered. FILE is created or truncated. Cannot be combined with a \
+                     downstream command."
+    )]
+    filtered_out: Option<PathBuf>,
+
+    /// Print the repository root the .gitignore is read from to stderr
+    #[arg(
+        long,
+        conflicts_with = "split_on",
+        long_help = "Before processing, write the root directory selected for the input to \
+                     stderr: the directory whose .gitignore is used and that diff paths are \
+                     matched against. Useful to debug why the wrong .gitignore applies."
+    )]
+    print_root: bool,
+
+    /// Print the selected repository root to stderr and exit without filtering
+    #[arg(
+        long,
+        conflicts_with = "split_on",
+        long_help = "Like --print-root, but exit with status 0 after printing the root \
+                     instead of processing the diff."
+    )]
+    print_root_only: bool,
+
+    /// Split the input into independent diffs at lines equal to MARKER
+    #[arg(
+        long,
+        value_name = "MARKER",
+        value_parser = clap::builder::NonEmptyStringValueParser::new(),
+        conflicts_with = "list_dirs",
+        long_help = "Treat the input as several independent diffs separated by lines equal to \
+                     MARKER (e.g. '=== DIFF BOUNDARY ==='). Each diff gets its own root \
+                     resolution and filtering, and the marker lines are kept in the output \
+                     between the filtered diffs. With a downstream command, it is run once \
+                     per diff."
+    )]
+    split_on: Option<String>,
+
+    /// Run 'git diff REVS' and filter its output instead of reading stdin
+    #[arg(
+        long = "diff",
+        value_name = "REVS",
+        conflicts_with = "input",
+        long_help = "Run 'git diff REVS' in the current directory (e.g. 'HEAD~3..HEAD' or \
+                     'main...feature') and filter its output instead of reading stdin. The \
+                     repository is the one git discovers from the current directory, and \
+                     git's diff.noprefix/diff.srcPrefix/diff.dstPrefix settings apply to the \
+                     generated headers as usual. Fails if git diff fails, e.g. for an \
+                     unknown revision."
+    )]
+    diff: Option<String>,
+
+    /// Diff file to read instead of stdin ('-' reads stdin)
+    #[arg(
+        value_name = "INPUT",
+        long_help = "Read the diff from this file instead of stdin. Without INPUT, or when \
+                     INPUT is '-', the diff is read from stdin."
+    )]
+    input: Option<PathBuf>,
 }
 
 /// Convert CLI args to CliArgs struct for AppConfig
@@ -47,49 +644,164 @@ impl From<Args> for diff_gitignore_filter::config::CliArgs {
             vcs: args.vcs,
             no_vcs: args.no_vcs,
             downstream: args.downstream,
+            downstream_shell: args.downstream_shell,
+            downstream_timeout: args.downstream_timeout,
+            downstream_retries: args.downstream_retries,
+            downstream_max_lines: args.downstream_max_lines,
+            downstream_cwd: args.downstream_cwd,
+            downstream_env: args.downstream_env,
             vcs_pattern: args.vcs_pattern,
+            vcs_pattern_add: args.vcs_pattern_add,
+            allow_empty_vcs: args.allow_empty_vcs,
+            config_key_prefix: args.config_key_prefix,
+            diffignore: args.diffignore,
+            force_include: args.force_include,
+            gitignore_file: args.gitignore_file,
+            ignore: args.ignore,
+            strip_path_prefix: args.strip_path_prefix,
+            path_prefix: args.path_prefix,
+            src_prefix: args.src_prefix,
+            dst_prefix: args.dst_prefix,
+            unified: args.unified,
+            strip_ansi: args.strip_ansi,
+            dry_run: args.dry_run,
+            null_terminated: args.null,
+            output_format: args.format.into(),
+            on_unparsable: args.on_unparsable.into(),
+            jobs: args.jobs.into(),
+            max_section_bytes: args.max_section_bytes,
+            keep_empty_diff_headers: args.keep_empty_diff_headers,
+            annotate: args.annotate,
+            no_mode_only: args.no_mode_only,
+            no_deletions: args.no_deletions,
+            apply_diff_gitignore: args.apply_diff_gitignore,
+            only_vcs: args.only_vcs,
+            pair_renames: args.pair_renames,
+            verbose: args.verbose,
+            only_ext: args.only_ext,
+            exclude_ext: args.exclude_ext,
         }
     }
 }
 
-/// Process diff with temporary file using AppConfig with functional composition
-fn process_diff_with_config<W: io::Write>(
-    mut temp_file: std::fs::File,
-    mut output: W,
-    config: &AppConfig,
-) -> Result<()> {
+/// Find the root for the diff in the temporary file, falling back to the current directory
+fn find_root(mut temp_file: &std::fs::File, config: &AppConfig) -> Result<PathBuf> {
     // Phase 1: Root-Finding with functional error handling
     temp_file.seek(SeekFrom::Start(0)).map_err(|e| {
         diff_gitignore_filter::Error::processing_error(format!("Failed to seek to start: {e}"))
     })?;
 
     let root_result = {
-        let root_reader = BufReader::new(&temp_file);
-        RootFinder::find_root(env::current_dir()?, root_reader)
+        let root_reader = BufReader::new(temp_file);
+        RootFinder::find_root_with_prefixes(
+            env::current_dir()?,
+            root_reader,
+            config.diff_prefixes(),
+        )
     }; // root_reader is automatically dropped here
 
+    root_result
+        .or_else(|e| {
+            diag!(1, "no root found ({e}), using the current directory");
+            env::current_dir()
+        })
+        .map_err(|e| {
+            diff_gitignore_filter::Error::processing_error(format!(
+                "Failed to determine root directory: {e}"
+            ))
+        })
+}
+
+/// Find the root for the diff in the temporary file and build the filter from AppConfig
+fn build_filter(temp_file: &std::fs::File, config: &AppConfig) -> Result<Filter> {
     // Phase 2: Filter-Pipeline with functional composition and improved fallback logic
-    let root = root_result.or_else(|_| env::current_dir()).map_err(|e| {
-        diff_gitignore_filter::Error::processing_error(format!(
-            "Failed to determine root directory: {e}"
-        ))
-    })?;
+    let root = find_root(temp_file, config)?;
 
-    let filter = Filter::new(root)?;
+    let builder = FilterBuilder::new(root)
+        .with_vcs_enabled(config.vcs_enabled())
+        .with_vcs_patterns(config.vcs_patterns().to_vec())
+        .with_downstream_pipeline(config.downstream_pipeline().to_vec())
+        // Follow Git's core.ignorecase for .gitignore matching
+        .with_case_insensitive(config.ignore_case())
+        .with_extra_ignore_patterns(config.extra_ignore_patterns().to_vec());
 
-    // Functional composition for VCS patterns with proper ownership handling
-    let filter = if config.vcs_enabled() {
-        filter.with_vcs_patterns(config.vcs_patterns().to_vec())
-    } else {
-        filter
+    // Git's global ignore file, read beneath the repository's patterns
+    let builder = match config.global_excludes_file() {
+        Some(file) => builder.with_global_excludes_file(file.to_path_buf()),
+        None => builder,
+    };
+
+    let builder = match config.gitignore_file() {
+        Some(file) => builder.with_gitignore_file(file.to_path_buf()),
+        None => builder,
+    };
+
+    let builder = match config.diffignore() {
+        Some(file) => builder.with_diffignore(file.to_path_buf()),
+        None => builder,
+    };
+
+    let builder = match config.force_include() {
+        Some(file) => builder.with_force_include(file.to_path_buf()),
+        None => builder,
     };
 
-    // Functional composition for downstream filter with proper ownership handling
-    let filter = match config.downstream_filter() {
-        Some(command) => filter.with_downstream(command.to_string()),
+    let filter = builder
+        .build()?
+        .with_downstream_shell(config.downstream_shell())
+        .with_downstream_timeout(config.downstream_timeout().unwrap_or_default())
+        .with_downstream_retries(config.downstream_retries())
+        .with_downstream_max_lines(config.downstream_max_lines().unwrap_or_default())
+        .with_downstream_env(config.downstream_env().to_vec())
+        .with_diff_prefixes(config.diff_prefixes().clone())
+        .with_unified(config.unified())
+        .with_dry_run(config.dry_run())
+        .with_null_terminated(config.null_terminated())
+        .with_parallelism(config.jobs())
+        .with_max_section_bytes(config.max_section_bytes().unwrap_or_default())
+        .with_keep_empty_diff_headers(config.keep_empty_diff_headers())
+        .with_annotate(config.annotate())
+        .with_drop_mode_only(config.no_mode_only())
+        .with_drop_deletions(config.no_deletions())
+        .with_apply_diff_gitignore(config.apply_diff_gitignore())
+        .with_only_vcs(config.only_vcs())
+        .with_pair_renames(config.pair_renames())
+        .with_only_extensions(config.only_extensions().to_vec())
+        .with_excluded_extensions(config.excluded_extensions().to_vec())
+        .with_output_format(config.output_format())
+        .with_on_unparsable(config.on_unparsable());
+
+    let filter = match config.downstream_cwd() {
+        Some(dir) => filter.with_downstream_cwd(dir.to_path_buf()),
         None => filter,
     };
 
+    let filter = match config.strip_path_prefix() {
+        Some(prefix) => filter.with_strip_path_prefix(prefix.to_string()),
+        None => filter,
+    };
+
+    let filter = match config.path_prefix() {
+        Some(prefix) => filter.with_path_prefix(prefix.to_string()),
+        None => filter,
+    };
+
+    // Invalid .gitignore lines are skipped; report them without failing
+    for warning in filter.gitignore_warnings() {
+        eprintln!("diff-gitignore-filter: warning: {warning}");
+    }
+
+    Ok(filter)
+}
+
+/// Process diff with temporary file using AppConfig with functional composition
+fn process_diff_with_config<W: io::Write>(
+    mut temp_file: std::fs::File,
+    mut output: W,
+    config: &AppConfig,
+) -> Result<FilterReport> {
+    let filter = build_filter(&temp_file, config)?;
+
     // Phase 3: Filter-Processing with functional error handling
     temp_file.seek(SeekFrom::Start(0)).map_err(|e| {
         diff_gitignore_filter::Error::processing_error(format!(
@@ -98,35 +810,310 @@ fn process_diff_with_config<W: io::Write>(
     })?;
 
     let filter_reader = BufReader::new(&temp_file);
-    filter.process_diff(filter_reader, &mut output)
+    filter.process_diff_with_report(filter_reader, &mut output)
+}
+
+/// Process the diff, writing kept sections to `output` and dropped ones to `filtered_out`
+fn process_diff_split_with_config<W: io::Write>(
+    mut temp_file: std::fs::File,
+    output: W,
+    config: &AppConfig,
+    filtered_out: &Path,
+) -> Result<FilterReport> {
+    let filter = build_filter(&temp_file, config)?;
+    if filter.has_downstream() {
+        return Err(diff_gitignore_filter::Error::processing_error(
+            "--filtered-out cannot be combined with a downstream command".to_string(),
+        ));
+    }
+
+    let file = std::fs::File::create(filtered_out).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!(
+            "Failed to create '{}': {e}",
+            filtered_out.display()
+        ))
+    })?;
+
+    temp_file.seek(SeekFrom::Start(0)).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!(
+            "Failed to seek to start for filter: {e}"
+        ))
+    })?;
+
+    let mut filtered_writer = io::BufWriter::new(file);
+    let report =
+        filter.process_diff_split(BufReader::new(&temp_file), output, &mut filtered_writer)?;
+    filtered_writer.flush().map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!(
+            "Failed to write '{}': {e}",
+            filtered_out.display()
+        ))
+    })?;
+    Ok(report)
+}
+
+/// Process input made of several diffs separated by marker lines
+///
+/// Each diff between two markers is processed like a standalone input, including root
+/// resolution. Marker lines are written through unchanged, with their original line ending.
+fn process_split_diff_with_config<W: io::Write>(
+    mut temp_file: std::fs::File,
+    mut output: W,
+    config: &AppConfig,
+    marker: &str,
+) -> Result<FilterReport> {
+    temp_file.seek(SeekFrom::Start(0)).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to seek to start: {e}"))
+    })?;
+
+    let mut input = Vec::new();
+    temp_file.read_to_end(&mut input).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to read diff: {e}"))
+    })?;
+
+    let mut report = FilterReport::default();
+    let mut chunk = Vec::new();
+
+    for line in input.split_inclusive(|&byte| byte == b'\n') {
+        if !is_marker_line(line, marker) {
+            chunk.extend_from_slice(line);
+            continue;
+        }
+
+        report.merge(process_diff_chunk(&chunk, &mut output, config)?);
+        chunk.clear();
+
+        // Downstream commands write to stdout directly, so keep the marker in order
+        output
+            .write_all(line)
+            .and_then(|()| output.flush())
+            .map_err(|e| write_error("marker", e))?;
+    }
+
+    report.merge(process_diff_chunk(&chunk, &mut output, config)?);
+    Ok(report)
+}
+
+/// Process one diff of a split input; empty chunks produce no output
+fn process_diff_chunk<W: io::Write>(
+    chunk: &[u8],
+    output: W,
+    config: &AppConfig,
+) -> Result<FilterReport> {
+    if chunk.is_empty() {
+        return Ok(FilterReport::default());
+    }
+
+    process_diff_with_config(create_temp_file_with(chunk)?, output, config)
+}
+
+/// Check if a line, without its line ending, is exactly the split marker
+fn is_marker_line(line: &[u8], marker: &str) -> bool {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    line == marker.as_bytes()
+}
+
+/// Write the top-level directories touched by kept sections, one per line
+fn list_dirs_with_config<W: io::Write>(
+    mut temp_file: std::fs::File,
+    mut output: W,
+    config: &AppConfig,
+) -> Result<()> {
+    let filter = build_filter(&temp_file, config)?;
+
+    temp_file.seek(SeekFrom::Start(0)).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!(
+            "Failed to seek to start for filter: {e}"
+        ))
+    })?;
+
+    let mut diff = Vec::new();
+    temp_file.read_to_end(&mut diff).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to read diff: {e}"))
+    })?;
+
+    let terminator = if config.null_terminated() { '\0' } else { '\n' };
+    filter
+        .kept_top_dirs(&String::from_utf8_lossy(&diff))?
+        .iter()
+        .try_for_each(|dir| write!(output, "{dir}{terminator}"))
+        .map_err(|e| write_error("directories", e))
+}
+
+/// Error for a failed write of `what` to the output
+///
+/// A broken pipe is kept as an IO error, so [`main`] can tell that the reader of stdout
+/// exited early.
+fn write_error(what: &str, error: io::Error) -> diff_gitignore_filter::Error {
+    if error.kind() == io::ErrorKind::BrokenPipe {
+        diff_gitignore_filter::Error::Io(error)
+    } else {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to write {what}: {error}"))
+    }
 }
 
 /// Handle configuration errors with user-friendly messages using functional pattern matching
 fn handle_config_error(error: ConfigError) -> ! {
     let error_message = match error {
-        ConfigError::GitCommandFailed { .. } => "Git command failed",
-        ConfigError::InvalidGitConfig { .. } => "Invalid git config",
-        ConfigError::NotInGitRepository { .. } => "Not in git repository",
-        ConfigError::IoError { .. } => "Configuration error",
-        ConfigError::InvalidCliArgument { .. } => "Invalid CLI argument",
+        ConfigError::GitCommandFailed {
+            command,
+            exit_code,
+            stderr,
+        } => format!(
+            "Git command '{command}' failed with exit code {exit_code}: {}",
+            stderr.trim_end()
+        ),
+        ConfigError::InvalidGitConfig {
+            key,
+            value,
+            expected,
+        } => format!("Invalid git config '{key}' = '{value}' (expected {expected})"),
+        ConfigError::NotInGitRepository { path } => {
+            format!("Not in git repository: {}", path.display())
+        }
+        ConfigError::IoError { source } => format!("Configuration error: {source}"),
+        ConfigError::InvalidCliArgument {
+            argument,
+            value,
+            expected,
+        } => format!("Invalid CLI argument {argument} '{value}' (expected {expected})"),
+        ConfigError::ValidationFailed {
+            setting,
+            value,
+            reason,
+        } => format!("Invalid configuration: {setting} '{value}': {reason}"),
+        ConfigError::InvalidEnvValue {
+            variable,
+            value,
+            expected,
+        } => format!("Invalid environment variable {variable} = '{value}' (expected {expected})"),
     };
 
     eprintln!("{error_message}");
     process::exit(1);
 }
 
-fn main() -> Result<()> {
+/// Validate the configuration for `--dry-run-config` and exit with the result
+fn check_config(config_result: std::result::Result<AppConfig, ConfigError>) -> ! {
+    match config_result.and_then(|config| config.validate()) {
+        Ok(()) => {
+            println!("Configuration is valid");
+            process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("Configuration is invalid: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    // Errors are reported with their Display message, e.g.
+    // "Error: DownstreamProcessFailed: Downstream command 'false' failed with exit code: Some(1)"
+    match run() {
+        Ok(()) => {}
+        // The reader of stdout (e.g. `head` or a closed pager) exited early; like other
+        // Unix tools, stop without an error
+        Err(error) if error.is_broken_pipe() => {}
+        Err(error) => {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Tell users to migrate when the downstream filter came from the deprecated git config key
+fn warn_legacy_downstream_key(config: &AppConfig) {
+    if !config.sources().legacy_downstream_filter {
+        return;
+    }
+    let keys = ConfigKeys::default();
+    if let Some(legacy_key) = keys.legacy_downstream_filter() {
+        eprintln!(
+            "diff-gitignore-filter: git config key '{legacy_key}' is deprecated, use '{}' instead",
+            keys.downstream_filter()
+        );
+    }
+}
+
+/// Parse the arguments, process the diff and report the result
+fn run() -> Result<()> {
+    let args = Args::parse();
+    let dry_run_config = args.dry_run_config;
+    let print_config = args.print_config;
+    let stats = args.stats;
+    let list_dirs = args.list_dirs;
+    let print_root = args.print_root || args.print_root_only;
+    let print_root_only = args.print_root_only;
+    let split_on = args.split_on.clone();
+    let filtered_out = args.filtered_out.clone();
+    let fail_if_empty = args.fail_if_empty;
+    let input = args.input.clone();
+    let diff_revs = args.diff.clone();
+
     // Functional pipeline with Result monad composition
-    let config = Args::parse()
+    let config_result = args
         .pipe(diff_gitignore_filter::config::CliArgs::from)
-        .pipe(AppConfig::from_cli)
-        .unwrap_or_else(|error| handle_config_error(error));
+        .pipe(AppConfig::from_cli);
 
-    // Functional composition for file operations
-    let temp_file = create_temp_file_with_stdin()?;
+    if dry_run_config {
+        check_config(config_result);
+    }
+
+    let config = config_result.unwrap_or_else(|error| handle_config_error(error));
+    diagnostics::set_verbosity(config.verbosity());
+    warn_legacy_downstream_key(&config);
+
+    if print_config {
+        return io::stdout()
+            .write_all(config.to_toml().as_bytes())
+            .map_err(|e| write_error("configuration", e));
+    }
+
+    // Both the root finder and the filter read the input, so it must be seekable
+    let temp_file = match diff_revs.as_deref() {
+        Some(revs) => run_git_diff(revs)?,
+        None => open_input(input.as_deref())?,
+    };
+    let temp_file = if config.strip_ansi() {
+        strip_ansi_input(temp_file)?
+    } else {
+        temp_file
+    };
+
+    if print_root {
+        eprintln!("{}", find_root(&temp_file, &config)?.display());
+        if print_root_only {
+            return Ok(());
+        }
+    }
+
+    if list_dirs {
+        return list_dirs_with_config(temp_file, io::stdout(), &config);
+    }
 
     // Process the diff with functional error propagation
-    process_diff_with_config(temp_file, io::stdout(), &config)
+    let report = match (split_on.as_deref(), filtered_out.as_deref()) {
+        (Some(marker), _) => {
+            process_split_diff_with_config(temp_file, io::stdout(), &config, marker)?
+        }
+        (None, Some(path)) => {
+            process_diff_split_with_config(temp_file, io::stdout(), &config, path)?
+        }
+        (None, None) => process_diff_with_config(temp_file, io::stdout(), &config)?,
+    };
+
+    if stats {
+        let terminator = if config.null_terminated() { '\0' } else { '\n' };
+        eprint!("{report}{terminator}");
+    }
+
+    if fail_if_empty && report.output_bytes() == 0 {
+        process::exit(1);
+    }
+
+    Ok(())
 }
 
 /// Helper trait for functional pipeline composition
@@ -146,6 +1133,68 @@ impl<T> Pipe<T> for T {
     }
 }
 
+/// Open the diff input: the given file, or a temporary copy of stdin for `-` or no file
+fn open_input(input: Option<&Path>) -> Result<std::fs::File> {
+    match input {
+        Some(path) if path != Path::new("-") => std::fs::File::open(path).map_err(|e| {
+            diff_gitignore_filter::Error::processing_error(format!(
+                "Failed to open input file '{}': {e}",
+                path.display()
+            ))
+        }),
+        _ => create_temp_file_with_stdin(),
+    }
+}
+
+/// Run `git diff <revs>` in the current directory and capture its output in a temporary file
+///
+/// Color and external diff drivers are disabled so the output can be parsed.
+fn run_git_diff(revs: &str) -> Result<std::fs::File> {
+    let temp_file = tempfile().map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to create temp file: {e}"))
+    })?;
+    let stdout = temp_file.try_clone().map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to create temp file: {e}"))
+    })?;
+
+    let output = process::Command::new("git")
+        .args(["diff", "--no-color", "--no-ext-diff", revs])
+        .stdin(process::Stdio::null())
+        .stdout(stdout)
+        .output()
+        .map_err(|e| {
+            diff_gitignore_filter::Error::processing_error(format!("Failed to run git diff: {e}"))
+        })?;
+
+    if !output.status.success() {
+        return Err(diff_gitignore_filter::Error::processing_error(format!(
+            "git diff {revs} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(temp_file)
+}
+
+/// Copy the input to a temporary file without its ANSI color sequences
+///
+/// Stripping the input once lets the root finder and the filter read the plain diff.
+fn strip_ansi_input(mut input: std::fs::File) -> Result<std::fs::File> {
+    input.seek(SeekFrom::Start(0)).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to seek to start: {e}"))
+    })?;
+
+    let mut temp_file = tempfile().map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to create temp file: {e}"))
+    })?;
+
+    io::copy(&mut StripAnsi::new(BufReader::new(input)), &mut temp_file).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to strip colors: {e}"))
+    })?;
+
+    Ok(temp_file)
+}
+
 /// Create temporary file and copy stdin with functional error handling
 fn create_temp_file_with_stdin() -> Result<std::fs::File> {
     let mut temp_file = tempfile().map_err(|e| {
@@ -159,6 +1208,19 @@ fn create_temp_file_with_stdin() -> Result<std::fs::File> {
     Ok(temp_file)
 }
 
+/// Create temporary file holding the given content
+fn create_temp_file_with(content: &[u8]) -> Result<std::fs::File> {
+    let mut temp_file = tempfile().map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to create temp file: {e}"))
+    })?;
+
+    io::Write::write_all(&mut temp_file, content).map_err(|e| {
+        diff_gitignore_filter::Error::processing_error(format!("Failed to write temp file: {e}"))
+    })?;
+
+    Ok(temp_file)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,17 +1235,101 @@ mod tests {
     #[test]
     fn test_cli_args_conversion() {
         let args = Args {
-            downstream: Some("test-command".to_string()),
+            downstream: vec!["test-command".to_string()],
+            downstream_shell: true,
+            downstream_timeout: Some(3),
+            downstream_retries: 2,
+            downstream_max_lines: Some(5000),
+            downstream_cwd: Some(PathBuf::from("/tmp")),
+            downstream_env: vec!["PAGER=cat".to_string()],
             vcs: true,
             no_vcs: false,
             vcs_pattern: None,
+            vcs_pattern_add: Some(".jj/".to_string()),
+            allow_empty_vcs: true,
+            config_key_prefix: Some("acme".to_string()),
+            diffignore: Some(PathBuf::from("review.ignore")),
+            force_include: Some(PathBuf::from("review.include")),
+            gitignore_file: Some(PathBuf::from("ci.gitignore")),
+            ignore: vec!["*.snap".to_string()],
+            strip_path_prefix: Some("submodule/".to_string()),
+            path_prefix: Some("subdir/".to_string()),
+            src_prefix: Some("i/".to_string()),
+            dst_prefix: None,
+            unified: true,
+            strip_ansi: true,
+            dry_run_config: false,
+            print_config: false,
+            dry_run: true,
+            format: Format::Json,
+            keep_empty_diff_headers: true,
+            on_unparsable: OnUnparsable::Error,
+            annotate: true,
+            no_mode_only: true,
+            no_deletions: false,
+            apply_diff_gitignore: true,
+            only_vcs: true,
+            pair_renames: true,
+            verbose: 2,
+            only_ext: Some("rs,toml".to_string()),
+            exclude_ext: None,
+            jobs: 4,
+            max_section_bytes: Some(1024),
+            fail_if_empty: false,
+            null: true,
+            stats: false,
+            list_dirs: false,
+            filtered_out: None,
+            print_root: false,
+            print_root_only: false,
+            split_on: None,
+            diff: None,
+            input: None,
         };
 
         let cli_args = CliArgs::from(args);
-        assert_eq!(cli_args.downstream, Some("test-command".to_string()));
+        assert_eq!(cli_args.downstream, vec!["test-command".to_string()]);
+        assert!(cli_args.downstream_shell);
+        assert_eq!(cli_args.downstream_timeout, Some(3));
+        assert_eq!(cli_args.downstream_retries, 2);
+        assert_eq!(cli_args.downstream_max_lines, Some(5000));
+        assert_eq!(cli_args.downstream_cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(cli_args.downstream_env, vec!["PAGER=cat".to_string()]);
         assert!(cli_args.vcs);
         assert!(!cli_args.no_vcs);
         assert_eq!(cli_args.vcs_pattern, None);
+        assert_eq!(cli_args.vcs_pattern_add, Some(".jj/".to_string()));
+        assert!(cli_args.allow_empty_vcs);
+        assert_eq!(cli_args.config_key_prefix, Some("acme".to_string()));
+        assert_eq!(cli_args.diffignore, Some(PathBuf::from("review.ignore")));
+        assert_eq!(
+            cli_args.force_include,
+            Some(PathBuf::from("review.include"))
+        );
+        assert_eq!(cli_args.gitignore_file, Some(PathBuf::from("ci.gitignore")));
+        assert_eq!(cli_args.ignore, vec!["*.snap".to_string()]);
+        assert_eq!(cli_args.strip_path_prefix, Some("submodule/".to_string()));
+        assert_eq!(cli_args.path_prefix, Some("subdir/".to_string()));
+        assert_eq!(cli_args.src_prefix, Some("i/".to_string()));
+        assert_eq!(cli_args.dst_prefix, None);
+        assert!(cli_args.unified);
+        assert!(cli_args.strip_ansi);
+        assert!(cli_args.dry_run);
+        assert!(cli_args.null_terminated);
+        assert_eq!(cli_args.output_format, OutputFormat::Json);
+        assert_eq!(cli_args.on_unparsable, UnparsableAction::Error);
+        assert_eq!(cli_args.jobs, 4);
+        assert_eq!(cli_args.max_section_bytes, Some(1024));
+        assert!(cli_args.keep_empty_diff_headers);
+        assert!(cli_args.annotate);
+        assert!(cli_args.no_mode_only);
+        assert!(!cli_args.no_deletions);
+        assert!(cli_args.apply_diff_gitignore);
+        assert!(cli_args.only_vcs);
+        assert!(cli_args.pair_renames);
+        assert_eq!(cli_args.verbose, 2);
+        assert_eq!(cli_args.only_ext, Some("rs,toml".to_string()));
+        assert_eq!(cli_args.exclude_ext, None);
     }
 
     /// **What is tested:** Basic diff processing with AppConfig integration
@@ -212,8 +1358,9 @@ index 1234567..abcdefg 100644
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // This might fail if not in a git repo, but should not panic
@@ -275,8 +1422,9 @@ index 1234567..abcdefg 100644
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: Some("cat".to_string()),
+            downstream: vec!["cat".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // This might fail if not in a git repo, but should not panic
@@ -328,8 +1476,9 @@ index 1234567..abcdefg 100644
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         let config_result = AppConfig::from_cli(cli_args);
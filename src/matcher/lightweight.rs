@@ -0,0 +1,511 @@
+//! Dependency-free .gitignore matcher
+//!
+//! Supports the common subset of the .gitignore syntax: `*` and `?` within a path
+//! component, `**` as a whole component (`**/name`, `dir/**`, `a/**/b`), a trailing `/`
+//! for directories, a leading or inner `/` anchoring the pattern at the root, a leading
+//! `!` re-including paths, `#` comments and `\` escapes. Patterns with character
+//! classes such as `[a-z]` are not supported and rejected as invalid, so they are
+//! skipped with a warning. The API mirrors the part of `ignore::gitignore` this crate
+//! uses.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Error reading ignore patterns
+#[derive(Debug)]
+pub enum Error {
+    /// Several errors, e.g. for different lines of one file
+    Partial(Vec<Error>),
+    /// An ignore file could not be read
+    Io(io::Error),
+    /// A pattern uses syntax this matcher does not support
+    Glob {
+        /// Pattern line as given
+        glob: Option<String>,
+        /// Why the pattern was rejected
+        err: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Partial(errors) => {
+                let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                f.write_str(&messages.join("\n"))
+            }
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Glob {
+                glob: Some(glob),
+                err,
+            } => write!(f, "error parsing glob '{glob}': {err}"),
+            Error::Glob { glob: None, err } => f.write_str(err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Outcome of matching a path, with the glob that decided it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match<T> {
+    /// No pattern matched
+    None,
+    /// The last matching pattern ignores the path
+    Ignore(T),
+    /// The last matching pattern is a `!pattern` re-including the path
+    Whitelist(T),
+}
+
+impl<T> Match<T> {
+    /// Check if no pattern matched
+    pub fn is_none(&self) -> bool {
+        matches!(self, Match::None)
+    }
+
+    /// Check if the path is ignored
+    pub fn is_ignore(&self) -> bool {
+        matches!(self, Match::Ignore(_))
+    }
+
+    /// Check if the path is re-included
+    pub fn is_whitelist(&self) -> bool {
+        matches!(self, Match::Whitelist(_))
+    }
+}
+
+/// Element of a compiled pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// A character matching itself
+    Literal(char),
+    /// `?`: any character except `/`
+    AnyChar,
+    /// `*`: any run of characters without `/`
+    Star,
+    /// Leading `**/`: nothing or any run of components ending in `/`
+    RecursivePrefix,
+    /// Inner `/**/`: a `/`, optionally followed by any run of components ending in `/`
+    RecursiveZeroOrMore,
+    /// Trailing `/**`: a `/` followed by at least one character
+    RecursiveSuffix,
+}
+
+/// A single pattern line
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// Pattern line as given
+    original: String,
+    /// Pattern compiled against paths relative to the matcher's root
+    tokens: Vec<Token>,
+    /// Whether the pattern starts with `!`
+    is_whitelist: bool,
+    /// Whether the pattern ends with `/` and only matches directories
+    is_only_dir: bool,
+}
+
+impl Glob {
+    /// Pattern line as given
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// Check if the pattern re-includes paths (`!pattern`)
+    pub fn is_whitelist(&self) -> bool {
+        self.is_whitelist
+    }
+
+    /// Check if the pattern only matches directories (`pattern/`)
+    pub fn is_only_dir(&self) -> bool {
+        self.is_only_dir
+    }
+
+    /// Compile a pattern line; None for blank lines and comments
+    fn parse(line: &str) -> Result<Option<Glob>, Error> {
+        if line.starts_with('#') {
+            return Ok(None);
+        }
+
+        // Trailing spaces are ignored unless escaped with a backslash
+        let mut pattern = line.trim_end_matches(['\r', '\n']);
+        while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+
+        let (is_whitelist, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let (is_only_dir, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+
+        // A slash other than a trailing one anchors the pattern at the root
+        let anchored = pattern.contains('/');
+        let mut tokens =
+            Self::tokenize(pattern.strip_prefix('/').unwrap_or(pattern)).ok_or_else(|| {
+                Error::Glob {
+                    glob: Some(line.to_owned()),
+                    err: "character classes are not supported".to_owned(),
+                }
+            })?;
+        if !anchored && tokens.first() != Some(&Token::RecursivePrefix) {
+            tokens.insert(0, Token::RecursivePrefix);
+        }
+
+        Ok(Some(Glob {
+            original: line.to_owned(),
+            tokens,
+            is_whitelist,
+            is_only_dir,
+        }))
+    }
+
+    /// Split a pattern without its `!`, trailing `/` and leading `/` into tokens
+    ///
+    /// None if the pattern has an unescaped `[`.
+    fn tokenize(pattern: &str) -> Option<Vec<Token>> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut index = 0;
+
+        while index < chars.len() {
+            match chars[index] {
+                '\\' if index + 1 < chars.len() => {
+                    tokens.push(Token::Literal(chars[index + 1]));
+                    index += 2;
+                }
+                '[' => return None,
+                '?' => {
+                    tokens.push(Token::AnyChar);
+                    index += 1;
+                }
+                '*' => {
+                    let start = index;
+                    while index < chars.len() && chars[index] == '*' {
+                        index += 1;
+                    }
+                    let at_start = start == 0 || chars[start - 1] == '/';
+                    let at_end = index == chars.len() || chars[index] == '/';
+
+                    // Other runs of asterisks act like a single one
+                    if index - start < 2 || !at_start || !at_end {
+                        tokens.push(Token::Star);
+                    } else if start == 0 && index == chars.len() {
+                        tokens.extend([Token::RecursivePrefix, Token::Star]);
+                    } else if start == 0 {
+                        tokens.push(Token::RecursivePrefix);
+                        index += 1; // The `/` after `**`
+                    } else if index == chars.len() {
+                        tokens.pop(); // The `/` before `**`
+                        tokens.push(Token::RecursiveSuffix);
+                    } else {
+                        tokens.pop();
+                        tokens.push(Token::RecursiveZeroOrMore);
+                        index += 1;
+                    }
+                }
+                c => {
+                    tokens.push(Token::Literal(c));
+                    index += 1;
+                }
+            }
+        }
+
+        Some(tokens)
+    }
+
+    /// Check if the compiled pattern matches a path relative to the root
+    fn is_match(tokens: &[Token], text: &[char]) -> bool {
+        let Some((token, rest)) = tokens.split_first() else {
+            return text.is_empty();
+        };
+
+        match token {
+            Token::Literal(c) => text.first() == Some(c) && Self::is_match(rest, &text[1..]),
+            Token::AnyChar => {
+                text.first().is_some_and(|c| *c != '/') && Self::is_match(rest, &text[1..])
+            }
+            Token::Star => (0..=text.len())
+                .take_while(|&end| end == 0 || text[end - 1] != '/')
+                .any(|end| Self::is_match(rest, &text[end..])),
+            Token::RecursivePrefix => (0..=text.len())
+                .filter(|&end| end == 0 || text[end - 1] == '/')
+                .any(|end| Self::is_match(rest, &text[end..])),
+            Token::RecursiveZeroOrMore => {
+                text.first() == Some(&'/')
+                    && (1..=text.len())
+                        .filter(|&end| text[end - 1] == '/')
+                        .any(|end| Self::is_match(rest, &text[end..]))
+            }
+            Token::RecursiveSuffix => text.first() == Some(&'/') && text.len() > 1,
+        }
+    }
+}
+
+/// Matcher for the patterns of one or more ignore files, anchored at a root directory
+#[derive(Debug, Clone)]
+pub struct Gitignore {
+    /// Directory the patterns are relative to
+    root: PathBuf,
+    /// Patterns in the order they were added; the last matching one decides
+    globs: Vec<Glob>,
+    /// Whether paths are matched case-insensitively
+    case_insensitive: bool,
+}
+
+impl Gitignore {
+    /// Read the ignore file at `path`, with its patterns anchored at its directory
+    ///
+    /// An unreadable file gives an empty matcher along with the error.
+    pub fn new<P: AsRef<Path>>(path: P) -> (Gitignore, Option<Error>) {
+        let path = path.as_ref();
+        let mut builder = GitignoreBuilder::new(path.parent().unwrap_or(Path::new("")));
+        let error = builder.add(path);
+        (builder.compile(), error)
+    }
+
+    /// Create a matcher without patterns
+    pub fn empty() -> Gitignore {
+        GitignoreBuilder::new("").compile()
+    }
+
+    /// Directory the patterns are relative to
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Check if the matcher has no patterns
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    /// Number of patterns ignoring paths
+    pub fn num_ignores(&self) -> u64 {
+        self.globs.iter().filter(|glob| !glob.is_whitelist).count() as u64
+    }
+
+    /// Number of `!pattern` lines re-including paths
+    pub fn num_whitelists(&self) -> u64 {
+        self.globs.iter().filter(|glob| glob.is_whitelist).count() as u64
+    }
+
+    /// Match a single path, without considering its parent directories
+    ///
+    /// The path is taken relative to the root if it lies below it. Set `is_dir` when it
+    /// names a directory, so that patterns ending in `/` can match it.
+    pub fn matched<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> Match<&Glob> {
+        let path = path.as_ref();
+        let path = path.strip_prefix(&self.root).unwrap_or(path);
+        let path = path.strip_prefix(".").unwrap_or(path);
+        let text = path.to_string_lossy();
+        let text: Vec<char> = if self.case_insensitive {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        };
+
+        self.globs
+            .iter()
+            .rev()
+            .find(|glob| (is_dir || !glob.is_only_dir) && Glob::is_match(&glob.tokens, &text))
+            .map_or(Match::None, |glob| match glob.is_whitelist {
+                true => Match::Whitelist(glob),
+                false => Match::Ignore(glob),
+            })
+    }
+}
+
+/// Builder collecting the patterns of a [`Gitignore`]
+#[derive(Debug, Clone)]
+pub struct GitignoreBuilder {
+    /// Directory the patterns are relative to
+    root: PathBuf,
+    /// Patterns added so far
+    globs: Vec<Glob>,
+    /// Whether paths are matched case-insensitively
+    case_insensitive: bool,
+}
+
+impl GitignoreBuilder {
+    /// Create a builder for patterns relative to `root`
+    pub fn new<P: AsRef<Path>>(root: P) -> GitignoreBuilder {
+        let root = root.as_ref();
+        GitignoreBuilder {
+            root: root.strip_prefix(".").unwrap_or(root).to_path_buf(),
+            globs: Vec::new(),
+            case_insensitive: false,
+        }
+    }
+
+    /// Set whether paths are matched case-insensitively
+    pub fn case_insensitive(&mut self, yes: bool) -> Result<&mut GitignoreBuilder, Error> {
+        self.case_insensitive = yes;
+        Ok(self)
+    }
+
+    /// Add the patterns of an ignore file
+    ///
+    /// Unsupported patterns are skipped and reported together as [`Error::Partial`].
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Option<Error> {
+        let content = match fs::read_to_string(path.as_ref()) {
+            Ok(content) => content,
+            Err(e) => return Some(Error::Io(e)),
+        };
+
+        let errors: Vec<Error> = content
+            .lines()
+            .filter_map(|line| self.add_line(None, line).err())
+            .collect();
+        (!errors.is_empty()).then_some(Error::Partial(errors))
+    }
+
+    /// Add a single pattern line; blank lines and comments are skipped
+    pub fn add_line(
+        &mut self,
+        _from: Option<PathBuf>,
+        line: &str,
+    ) -> Result<&mut GitignoreBuilder, Error> {
+        self.globs.extend(Glob::parse(line)?);
+        Ok(self)
+    }
+
+    /// Build the matcher from the patterns added so far
+    pub fn build(&self) -> Result<Gitignore, Error> {
+        Ok(self.compile())
+    }
+
+    /// Build the matcher, lowercasing the patterns for case-insensitive matching
+    fn compile(&self) -> Gitignore {
+        let mut globs = self.globs.clone();
+        if self.case_insensitive {
+            for token in globs.iter_mut().flat_map(|glob| glob.tokens.iter_mut()) {
+                if let Token::Literal(c) = token {
+                    *c = c.to_lowercase().next().unwrap_or(*c);
+                }
+            }
+        }
+
+        Gitignore {
+            root: self.root.clone(),
+            globs,
+            case_insensitive: self.case_insensitive,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Patterns, path, whether it is a directory, and the expected outcome
+    /// (`"ignore"`, `"whitelist"` or `"none"`) for the supported subset
+    const CASES: &[(&[&str], &str, bool, &str)] = &[
+        (&["*.log"], "debug.log", false, "ignore"),
+        (&["*.log"], "src/debug.log", false, "ignore"),
+        (&["*.log"], "debug.txt", false, "none"),
+        (&["/root.txt"], "root.txt", false, "ignore"),
+        (&["/root.txt"], "sub/root.txt", false, "none"),
+        (&["build/"], "build", true, "ignore"),
+        (&["build/"], "build", false, "none"),
+        (&["build/"], "src/build", true, "ignore"),
+        (&["doc/*.md"], "doc/a.md", false, "ignore"),
+        (&["doc/*.md"], "doc/sub/a.md", false, "none"),
+        (&["doc/*.md"], "x/doc/a.md", false, "none"),
+        (&["**/cache"], "cache", true, "ignore"),
+        (&["**/cache"], "a/b/cache", false, "ignore"),
+        (&["logs/**"], "logs/a", false, "ignore"),
+        (&["logs/**"], "logs/a/b", false, "ignore"),
+        (&["logs/**"], "logs", true, "none"),
+        (&["a/**/b"], "a/b", false, "ignore"),
+        (&["a/**/b"], "a/x/y/b", false, "ignore"),
+        (&["a/**/b"], "c/a/b", false, "none"),
+        (&["file?.txt"], "file1.txt", false, "ignore"),
+        (&["file?.txt"], "file12.txt", false, "none"),
+        (&["a*b"], "a/b", false, "none"),
+        (&["*"], "a/b", false, "ignore"),
+        (&["*.log", "!keep.log"], "keep.log", false, "whitelist"),
+        (&["*.log", "!keep.log"], "other.log", false, "ignore"),
+        (&["!keep.log", "*.log"], "keep.log", false, "ignore"),
+        (&["# comment"], "# comment", false, "none"),
+        (&["\\#hash"], "#hash", false, "ignore"),
+        (&["\\!bang"], "!bang", false, "ignore"),
+        (&["trailing   "], "trailing", false, "ignore"),
+        (&["", "*.tmp"], "x.tmp", false, "ignore"),
+        (&["\\[literal\\]"], "[literal]", false, "ignore"),
+    ];
+
+    /// Outcome of a match as named in [`CASES`]
+    fn outcome(is_none: bool, is_ignore: bool) -> &'static str {
+        match (is_none, is_ignore) {
+            (true, _) => "none",
+            (false, true) => "ignore",
+            (false, false) => "whitelist",
+        }
+    }
+
+    /// **What is tested:** The built-in matcher against the supported pattern subset
+    /// **Why it is tested:** It must decide like Git for `*`, `**`, `?`, trailing `/`, anchoring, `!` and escapes
+    /// **Test conditions:** The pattern suite of [`CASES`], plus case-insensitive matching, a path given below the root and an unsupported character class
+    /// **Expectations:** Each path gets the expected outcome; the character class is rejected with an error naming the pattern
+    #[test]
+    fn test_lightweight_matcher() {
+        for (patterns, path, is_dir, expected) in CASES {
+            let mut builder = GitignoreBuilder::new("/repo");
+            for pattern in *patterns {
+                builder.add_line(None, pattern).unwrap();
+            }
+            let gitignore = builder.build().unwrap();
+            let matched = gitignore.matched(path, *is_dir);
+            assert_eq!(
+                outcome(matched.is_none(), matched.is_ignore()),
+                *expected,
+                "{patterns:?} on {path}"
+            );
+        }
+
+        let mut builder = GitignoreBuilder::new("/repo");
+        builder.case_insensitive(true).unwrap();
+        builder.add_line(None, "*.LOG").unwrap();
+        let gitignore = builder.build().unwrap();
+        assert!(gitignore.matched("Debug.log", false).is_ignore());
+        assert!(gitignore.matched("/repo/src/debug.log", false).is_ignore());
+        assert_eq!(
+            (gitignore.num_ignores(), gitignore.num_whitelists()),
+            (1, 0)
+        );
+
+        let error = GitignoreBuilder::new("/repo")
+            .add_line(None, "file[0-9]")
+            .unwrap_err();
+        assert!(error.to_string().contains("file[0-9]"));
+    }
+
+    /// **What is tested:** The built-in matcher against the `ignore` crate
+    /// **Why it is tested:** The `lightweight` feature must not change decisions for the supported subset
+    /// **Test conditions:** The pattern suite of [`CASES`] matched by both backends
+    /// **Expectations:** The `ignore` crate gives the same expected outcome for every case
+    #[cfg(feature = "ignore")]
+    #[test]
+    fn test_matches_ignore_crate() {
+        for (patterns, path, is_dir, expected) in CASES {
+            let mut builder = ignore::gitignore::GitignoreBuilder::new("/repo");
+            for pattern in *patterns {
+                builder.add_line(None, pattern).unwrap();
+            }
+            let gitignore = builder.build().unwrap();
+            let matched = gitignore.matched(path, *is_dir);
+            assert_eq!(
+                outcome(matched.is_none(), matched.is_ignore()),
+                *expected,
+                "{patterns:?} on {path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! .gitignore matcher backends
+//!
+//! By default .gitignore patterns are matched with the `ignore` crate, whose types are
+//! re-exported here. With the `lightweight` feature the dependency-free matcher of
+//! [`lightweight`] is used instead; build with `default-features = false` to drop the
+//! `ignore` crate from the dependency tree. Both backends expose the same API, so the
+//! rest of the crate uses [`Gitignore`], [`GitignoreBuilder`], [`Glob`], [`Match`] and
+//! [`Error`] from this module regardless of the backend.
+
+pub mod lightweight;
+
+#[cfg(not(any(feature = "ignore", feature = "lightweight")))]
+compile_error!("enable the `ignore` or the `lightweight` feature to match .gitignore patterns");
+
+#[cfg(not(feature = "lightweight"))]
+pub use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+#[cfg(not(feature = "lightweight"))]
+pub use ignore::{Error, Match};
+
+#[cfg(feature = "lightweight")]
+pub use lightweight::{Error, Gitignore, GitignoreBuilder, Glob, Match};
@@ -0,0 +1,330 @@
+//! Filter report module
+//!
+//! This module provides the accounting of the per-section decisions the filter makes
+//! while processing a diff, independent of where the filtered output is written to.
+
+use std::fmt;
+
+/// Decision made for a single diff section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// The section is kept in the output
+    Keep,
+    /// The section matched a .gitignore pattern
+    FilteredByGitignore,
+    /// The section matched a VCS pattern
+    FilteredByVcs,
+    /// The section matched a .diffignore pattern
+    FilteredByDiffignore,
+    /// The section was dropped because its path could not be parsed
+    Unparsable,
+    /// The section only changes the file mode and mode-only changes are dropped
+    FilteredByModeOnly,
+    /// The section deletes the file and deletions are dropped
+    FilteredByDeletion,
+    /// The section's file extension is not allowed or is denied
+    FilteredByExtension,
+    /// The section does not match a VCS pattern and only VCS sections are kept
+    FilteredByOnlyVcs,
+    /// The section deletes a file whose content was added under a dropped path
+    FilteredByRename,
+}
+
+impl FilterDecision {
+    /// Check if the section is kept in the output
+    pub fn is_kept(self) -> bool {
+        self == Self::Keep
+    }
+
+    /// Why the section was dropped: `gitignore`, `vcs`, `diffignore`, `unparsable`,
+    /// `mode-only`, `deletion`, `extension`, `only-vcs`, `rename`, or `none` for kept sections
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Keep => "none",
+            Self::FilteredByGitignore => "gitignore",
+            Self::FilteredByVcs => "vcs",
+            Self::FilteredByDiffignore => "diffignore",
+            Self::Unparsable => "unparsable",
+            Self::FilteredByModeOnly => "mode-only",
+            Self::FilteredByDeletion => "deletion",
+            Self::FilteredByExtension => "extension",
+            Self::FilteredByOnlyVcs => "only-vcs",
+            Self::FilteredByRename => "rename",
+        }
+    }
+}
+
+/// Decision made for one diff section together with the path it was based on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionDecision {
+    /// Path the decision was based on (rename/copy destination or header path)
+    pub path: Option<String>,
+    /// Decision made for the section
+    pub decision: FilterDecision,
+}
+
+/// Section passed to the callback registered with [`crate::Filter::on_section`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionInfo<'a> {
+    /// Path the decision was based on (rename/copy destination or header path)
+    pub path: Option<&'a str>,
+    /// Decision made for the section
+    pub decision: FilterDecision,
+}
+
+/// Summary of the decisions made while processing a diff
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterReport {
+    /// Decisions in the order the sections appeared in the diff
+    sections: Vec<SectionDecision>,
+    /// Bytes of filtered diff output produced
+    output_bytes: u64,
+}
+
+impl FilterReport {
+    /// Record the decision made for one section
+    pub fn record(&mut self, path: Option<&str>, decision: FilterDecision) {
+        self.sections.push(SectionDecision {
+            path: path.map(ToOwned::to_owned),
+            decision,
+        });
+    }
+
+    /// Record bytes of filtered diff output produced
+    pub fn record_output(&mut self, bytes: u64) {
+        self.output_bytes += bytes;
+    }
+
+    /// Append the decisions of a report for a diff that followed this one
+    pub fn merge(&mut self, other: FilterReport) {
+        self.sections.extend(other.sections);
+        self.output_bytes += other.output_bytes;
+    }
+
+    /// Bytes of filtered diff output produced, also when written to a downstream command
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    /// Decisions in the order the sections appeared in the diff
+    pub fn sections(&self) -> &[SectionDecision] {
+        &self.sections
+    }
+
+    /// Number of sections kept in the output
+    pub fn kept(&self) -> usize {
+        self.count(FilterDecision::Keep)
+    }
+
+    /// Number of sections filtered out by .gitignore patterns
+    pub fn filtered_by_gitignore(&self) -> usize {
+        self.count(FilterDecision::FilteredByGitignore)
+    }
+
+    /// Number of sections filtered out by VCS patterns
+    pub fn filtered_by_vcs(&self) -> usize {
+        self.count(FilterDecision::FilteredByVcs)
+    }
+
+    /// Number of sections filtered out by .diffignore patterns
+    pub fn filtered_by_diffignore(&self) -> usize {
+        self.count(FilterDecision::FilteredByDiffignore)
+    }
+
+    /// Number of sections dropped because their path could not be parsed
+    pub fn unparsable(&self) -> usize {
+        self.count(FilterDecision::Unparsable)
+    }
+
+    /// Number of sections dropped because they only change the file mode
+    pub fn filtered_by_mode_only(&self) -> usize {
+        self.count(FilterDecision::FilteredByModeOnly)
+    }
+
+    /// Number of sections dropped because they delete the file
+    pub fn filtered_by_deletion(&self) -> usize {
+        self.count(FilterDecision::FilteredByDeletion)
+    }
+
+    /// Number of sections dropped because of their file extension
+    pub fn filtered_by_extension(&self) -> usize {
+        self.count(FilterDecision::FilteredByExtension)
+    }
+
+    /// Number of sections dropped because they do not match a VCS pattern
+    pub fn filtered_by_only_vcs(&self) -> usize {
+        self.count(FilterDecision::FilteredByOnlyVcs)
+    }
+
+    /// Number of deletions dropped together with the addition they were paired with
+    pub fn filtered_by_rename(&self) -> usize {
+        self.count(FilterDecision::FilteredByRename)
+    }
+
+    /// Number of sections that were not kept
+    pub fn filtered(&self) -> usize {
+        self.sections.len() - self.kept()
+    }
+
+    /// Total number of sections seen
+    pub fn total(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Paths of the kept sections, in diff order
+    pub fn kept_paths(&self) -> impl Iterator<Item = &str> {
+        self.sections
+            .iter()
+            .filter(|section| section.decision.is_kept())
+            .filter_map(|section| section.path.as_deref())
+    }
+
+    fn count(&self, decision: FilterDecision) -> usize {
+        self.sections
+            .iter()
+            .filter(|section| section.decision == decision)
+            .count()
+    }
+}
+
+impl fmt::Display for SectionDecision {
+    /// Decision line as printed by `--dry-run`, e.g. `KEEP src/main.rs` or `DROP debug.log (gitignore)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.as_deref().unwrap_or("<unknown>");
+        match self.decision {
+            FilterDecision::Keep => write!(f, "KEEP {path}"),
+            decision => write!(f, "DROP {path} ({})", decision.reason()),
+        }
+    }
+}
+
+impl fmt::Display for FilterReport {
+    /// Short one-line summary, e.g. `kept 12 files, filtered 5 (.gitignore: 4, vcs: 1)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kept = self.kept();
+        let noun = if kept == 1 { "file" } else { "files" };
+        write!(
+            f,
+            "kept {kept} {noun}, filtered {} (.gitignore: {}, vcs: {}",
+            self.filtered(),
+            self.filtered_by_gitignore(),
+            self.filtered_by_vcs()
+        )?;
+
+        let optional_counts = [
+            ("diffignore", self.filtered_by_diffignore()),
+            ("unparsable", self.unparsable()),
+            ("mode-only", self.filtered_by_mode_only()),
+            ("deletion", self.filtered_by_deletion()),
+            ("extension", self.filtered_by_extension()),
+            ("only-vcs", self.filtered_by_only_vcs()),
+            ("rename", self.filtered_by_rename()),
+        ];
+        for (label, count) in optional_counts {
+            if count > 0 {
+                write!(f, ", {label}: {count}")?;
+            }
+        }
+
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// **What is tested:** Recording decisions and rendering the one-line summary
+    /// **Why it is tested:** The summary is printed by `--stats` and its counts must add up
+    /// **Test conditions:** Reports with mixed decisions, a single kept file, unparsable sections merged from a second report, and mode-only, deletion and extension drops
+    /// **Expectations:** Counters should match the recorded decisions and the summary should be formatted accordingly
+    #[test]
+    fn test_filter_report_summary() {
+        let mut report = FilterReport::default();
+        assert_eq!(
+            report.to_string(),
+            "kept 0 files, filtered 0 (.gitignore: 0, vcs: 0)"
+        );
+
+        [
+            ("src/main.rs", FilterDecision::Keep),
+            ("debug.log", FilterDecision::FilteredByGitignore),
+            ("build/out.log", FilterDecision::FilteredByGitignore),
+            (".git/config", FilterDecision::FilteredByVcs),
+        ]
+        .into_iter()
+        .for_each(|(path, decision)| report.record(Some(path), decision));
+
+        assert_eq!(report.filtered(), 3);
+        assert_eq!(report.total(), 4);
+        assert_eq!(report.kept_paths().collect::<Vec<_>>(), vec!["src/main.rs"]);
+        assert_eq!(
+            report.to_string(),
+            "kept 1 file, filtered 3 (.gitignore: 2, vcs: 1)"
+        );
+
+        let mut later = FilterReport::default();
+        later.record(Some("README.md"), FilterDecision::Keep);
+        later.record(None, FilterDecision::Unparsable);
+        later.record_output(42);
+        report.record_output(100);
+        report.merge(later);
+        assert_eq!(report.output_bytes(), 142);
+        assert_eq!(
+            report.to_string(),
+            "kept 2 files, filtered 4 (.gitignore: 2, vcs: 1, unparsable: 1)"
+        );
+
+        report.record(Some("run.sh"), FilterDecision::FilteredByModeOnly);
+        report.record(Some("old.rs"), FilterDecision::FilteredByDeletion);
+        assert_eq!(report.filtered_by_mode_only(), 1);
+        assert_eq!(report.filtered_by_deletion(), 1);
+        assert_eq!(
+            report.to_string(),
+            "kept 2 files, filtered 6 (.gitignore: 2, vcs: 1, unparsable: 1, mode-only: 1, deletion: 1)"
+        );
+
+        report.record(Some("Cargo.lock"), FilterDecision::FilteredByExtension);
+        assert_eq!(report.filtered_by_extension(), 1);
+        assert_eq!(
+            report.to_string(),
+            "kept 2 files, filtered 7 (.gitignore: 2, vcs: 1, unparsable: 1, mode-only: 1, deletion: 1, extension: 1)"
+        );
+    }
+
+    /// **What is tested:** Rendering of single section decisions
+    /// **Why it is tested:** `--dry-run` prints one such line per section for debugging filter decisions
+    /// **Test conditions:** Kept, .gitignore-filtered, VCS-filtered, unparsable and mode-only sections
+    /// **Expectations:** Kept sections render as `KEEP <path>`, others as `DROP <path> (<reason>)`
+    #[test]
+    fn test_section_decision_display() {
+        let line = |path: Option<&str>, decision| {
+            SectionDecision {
+                path: path.map(ToOwned::to_owned),
+                decision,
+            }
+            .to_string()
+        };
+
+        assert_eq!(
+            line(Some("src/main.rs"), FilterDecision::Keep),
+            "KEEP src/main.rs"
+        );
+        assert_eq!(
+            line(Some("debug.log"), FilterDecision::FilteredByGitignore),
+            "DROP debug.log (gitignore)"
+        );
+        assert_eq!(
+            line(Some(".git/config"), FilterDecision::FilteredByVcs),
+            "DROP .git/config (vcs)"
+        );
+        assert_eq!(
+            line(None, FilterDecision::Unparsable),
+            "DROP <unknown> (unparsable)"
+        );
+        assert_eq!(
+            line(Some("run.sh"), FilterDecision::FilteredByModeOnly),
+            "DROP run.sh (mode-only)"
+        );
+    }
+}
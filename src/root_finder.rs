@@ -3,6 +3,10 @@
 //! This module provides functionality to find the root directory of a Git repository
 //! by analyzing diff content and filesystem structure.
 
+use crate::diagnostics::diag;
+use crate::diff::{
+    decode_path, is_diff_header, parse_diff_header_with_prefixes, parse_patch_paths, DiffPrefixes,
+};
 use crate::error::{Error, Result};
 use gix::discover;
 use relative_path::{RelativePath, RelativePathBuf};
@@ -87,6 +91,89 @@ struct PathAnalysis {
     is_relative: bool,
     /// Whether the path exists in the filesystem
     exists: bool,
+    /// Whether the path belongs to a rename or copy section
+    renamed: bool,
+}
+
+/// Source and destination path of one diff section
+#[derive(Debug, Default)]
+struct SectionPaths {
+    /// Source path (`rename from`/`copy from` or the header's old path)
+    old_path: Option<String>,
+    /// Destination path (`rename to`/`copy to` or the header's new path)
+    new_path: Option<String>,
+    /// Whether the section is a rename or copy
+    renamed: bool,
+    /// Paths of the `---`/`+++` lines, with `/dev/null` replaced by the real path
+    patch_paths: Option<(String, String)>,
+    /// Preceding `---` line, waiting for its `+++` line
+    old_patch_line: Option<String>,
+    /// Whether the first hunk has been reached
+    in_hunks: bool,
+}
+
+impl SectionPaths {
+    /// Start a section from its header line; the paths stay unset if it cannot be parsed
+    fn from_header(line: &str, prefixes: &DiffPrefixes) -> Self {
+        let (old_path, new_path) = RootFinder::parse_diff_header_line(line, prefixes)
+            .map_or((None, None), |(old_path, new_path)| {
+                (Some(old_path), Some(new_path))
+            });
+
+        Self {
+            old_path,
+            new_path,
+            ..Self::default()
+        }
+    }
+
+    /// Record rename/copy information and the `---`/`+++` lines before the first hunk
+    fn record_line(&mut self, line: &str, prefixes: &DiffPrefixes) {
+        if self.in_hunks {
+            return;
+        }
+        if line.starts_with("@@") {
+            self.in_hunks = true;
+            return;
+        }
+
+        self.record_extended_header(line);
+        if self.patch_paths.is_none() {
+            self.patch_paths = self
+                .old_patch_line
+                .take()
+                .and_then(|old_line| parse_patch_paths(&old_line, line, prefixes));
+        }
+        self.old_patch_line = line.starts_with("--- ").then(|| line.to_owned());
+    }
+
+    /// Record rename/copy information from a line of the section
+    fn record_extended_header(&mut self, line: &str) {
+        if let Some(path) = line
+            .strip_prefix("rename from ")
+            .or_else(|| line.strip_prefix("copy from "))
+        {
+            self.old_path = Some(decode_path(path));
+            self.renamed = true;
+        } else if let Some(path) = line
+            .strip_prefix("rename to ")
+            .or_else(|| line.strip_prefix("copy to "))
+        {
+            self.new_path = Some(decode_path(path));
+            self.renamed = true;
+        }
+    }
+
+    /// Source and destination path of the section, `None` if they cannot be determined
+    ///
+    /// If the header cannot be split into two paths, the paths of the `---`/`+++` lines
+    /// stand in for the ones not named by a rename or copy.
+    fn into_paths(self) -> Option<(String, String)> {
+        let (patch_old_path, patch_new_path) = self.patch_paths.unzip();
+        self.old_path
+            .or(patch_old_path)
+            .zip(self.new_path.or(patch_new_path))
+    }
 }
 
 /// Utility for finding Git repository root directories
@@ -103,48 +190,135 @@ impl RootFinder {
     ///
     /// Flow: AnalyzeDirectory → ExtractDiffPaths → AnalyzePaths → ClassifyContext → \[Workflow\] → RootSelection
     pub fn find_root<R: BufRead>(current_dir: PathBuf, diff_reader: R) -> Result<PathBuf> {
+        Self::find_root_with_prefixes(current_dir, diff_reader, &DiffPrefixes::default())
+    }
+
+    /// Find the root directory for a diff whose headers use the given path prefixes
+    ///
+    /// Same as [`RootFinder::find_root`], for diffs generated with `--no-prefix` or
+    /// custom `--src-prefix`/`--dst-prefix`.
+    pub fn find_root_with_prefixes<R: BufRead>(
+        current_dir: PathBuf,
+        diff_reader: R,
+        prefixes: &DiffPrefixes,
+    ) -> Result<PathBuf> {
         // 1. Extract and analyze diff paths
-        let path_analyses = Self::extract_and_analyze_diff_paths(diff_reader)?;
+        let path_analyses = Self::extract_and_analyze_diff_paths(diff_reader, prefixes)?;
+        Self::find_root_from_analyses(current_dir, path_analyses)
+    }
+
+    /// Find the root directory for a list of changed paths, without parsing a diff
+    ///
+    /// Runs the same context classification, worktree-aware scoring and root selection
+    /// as [`RootFinder::find_root`]. Each path stands for both sides of its change, as
+    /// the destination of a rename does, so outside a repository the root is chosen
+    /// among the paths' parent directories rather than by comparing old and new paths.
+    ///
+    /// ```rust,no_run
+    /// use diff_gitignore_filter::RootFinder;
+    /// use std::path::PathBuf;
+    ///
+    /// let paths = ["src/main.rs".to_string(), "Cargo.toml".to_string()];
+    /// let root = RootFinder::find_root_from_paths(PathBuf::from("."), &paths)?;
+    /// # Ok::<(), diff_gitignore_filter::Error>(())
+    /// ```
+    pub fn find_root_from_paths(current_dir: PathBuf, paths: &[String]) -> Result<PathBuf> {
+        let path_analyses = paths
+            .iter()
+            .flat_map(|path| [path, path])
+            .map(|path| Self::create_path_analysis(path.clone(), true))
+            .collect::<Result<Vec<_>>>()?;
+        Self::find_root_from_analyses(current_dir, path_analyses)
+    }
 
+    /// Select the root for analyzed paths, two per diff section
+    fn find_root_from_analyses(
+        current_dir: PathBuf,
+        path_analyses: Vec<PathAnalysis>,
+    ) -> Result<PathBuf> {
         // 2. Classify context
         let context = Self::classify_context(&current_dir, &path_analyses);
-
-        // Log context classification
+        diag!(
+            1,
+            "context {context:?} for {} paths from {}",
+            path_analyses.len(),
+            current_dir.display()
+        );
 
         // 3. Execute appropriate workflow based on context
-
-        // Log determined root after workflow
-
-        match context {
+        let root = match context {
             PathContext::InRepo => Self::process_in_repo_context(&current_dir, path_analyses),
             PathContext::OutsideRepo => Self::process_outside_repo_context(path_analyses),
             PathContext::Virtual => Self::process_virtual_context(&current_dir, path_analyses),
+        }?;
+        diag!(1, "selected root {}", root.display());
+        Ok(root)
+    }
+
+    /// Resolve the git directory of the repository or worktree rooted at `root`
+    ///
+    /// For a normal repository this is `root/.git`. A linked worktree has a `.git` file
+    /// instead, whose `gitdir:` line points into the main repository's
+    /// `.git/worktrees/<name>` directory; relative paths are taken relative to `root`.
+    /// Files such as `info/exclude` and `config` live in the resolved directory.
+    pub fn resolve_git_dir(root: &Path) -> Result<PathBuf> {
+        let dot_git = root.join(".git");
+        if dot_git.is_dir() {
+            return Ok(dot_git);
         }
+
+        let content = std::fs::read_to_string(&dot_git).map_err(|e| {
+            Error::processing_error(format!("Cannot read '{}': {e}", dot_git.display()))
+        })?;
+        let git_dir = content
+            .lines()
+            .find_map(|line| line.strip_prefix("gitdir:"))
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| {
+                Error::processing_error(format!("No 'gitdir:' line in '{}'", dot_git.display()))
+            })?;
+
+        Ok(root.join(git_dir))
     }
 
     /// Extract and analyze diff paths from diff reader
     ///
-    /// Reads the diff_reader and extracts paths from "diff --git" lines.
-    /// Analyzes each path with RelativePath and creates PathAnalysis objects.
-    fn extract_and_analyze_diff_paths<R: BufRead>(diff_reader: R) -> Result<Vec<PathAnalysis>> {
-        // Optimized functional approach: Single iterator chain without intermediate collection
-        diff_reader
-            .lines()
-            .filter_map(|line_result| {
-                match line_result {
-                    Ok(line) if line.starts_with("diff --git ") => Some(Ok(line)),
-                    Ok(_) => None, // Skip non-diff lines, continue iteration
-                    Err(e) => Some(Err(Error::from(e))),
-                }
-            })
-            .collect::<Result<Vec<String>>>()?
+    /// Reads the diff_reader and extracts paths from "diff --git" and "diff --cc" lines.
+    /// The `rename from`/`copy from` and `rename to`/`copy to` lines of a section are
+    /// authoritative over the header paths; headers that cannot be split fall back to
+    /// the `---`/`+++` lines, so `/dev/null` is never analyzed as a path. Analyzes each path
+    /// with RelativePath and creates PathAnalysis objects.
+    fn extract_and_analyze_diff_paths<R: BufRead>(
+        diff_reader: R,
+        prefixes: &DiffPrefixes,
+    ) -> Result<Vec<PathAnalysis>> {
+        let mut sections = Vec::new();
+        let mut current_section: Option<SectionPaths> = None;
+
+        for line in diff_reader.lines() {
+            let line = line.map_err(Error::from)?;
+
+            if is_diff_header(&line) {
+                sections.extend(current_section.take());
+                current_section = Some(SectionPaths::from_header(&line, prefixes));
+            } else if let Some(section) = current_section.as_mut() {
+                section.record_line(&line, prefixes);
+            }
+        }
+        sections.extend(current_section);
+
+        sections
             .into_iter()
-            .filter_map(|line| Self::parse_diff_header_line(&line))
-            .flat_map(|(left_path, right_path)| {
+            .filter_map(|section| {
+                let renamed = section.renamed;
+                section.into_paths().map(|paths| (paths, renamed))
+            })
+            .flat_map(|((old_path, new_path), renamed)| {
                 // Process both paths in a single iterator chain
-                [left_path, right_path]
+                [(old_path, renamed), (new_path, renamed)]
             })
-            .map(Self::create_path_analysis)
+            .map(|(path, renamed)| Self::create_path_analysis(path, renamed))
             .collect()
     }
 
@@ -152,13 +326,14 @@ impl RootFinder {
     ///
     /// Centralizes path analysis logic and provides consistent error handling
     /// for path normalization and filesystem operations.
-    fn create_path_analysis(path: String) -> Result<PathAnalysis> {
+    fn create_path_analysis(path: String, renamed: bool) -> Result<PathAnalysis> {
         let path_ref = Path::new(&path);
         let is_relative = !path_ref.is_absolute();
 
-        // Normalize path using RelativePath for consistent handling
+        // Normalize path using RelativePath for consistent handling; absolute paths stay
+        // anchored at their root
         let normalized = RelativePath::new(&path).normalize();
-        let normalized_path = normalized.to_path("").to_path_buf();
+        let normalized_path = normalized.to_path(Self::path_anchor(path_ref));
 
         // Check existence with error handling for filesystem operations
         let exists = match path_ref.try_exists() {
@@ -174,22 +349,18 @@ impl RootFinder {
             path: normalized_path,
             is_relative,
             exists,
+            renamed,
         })
     }
 
     /// Parse diff header line in "diff --git a/path1 b/path2" format
     ///
-    /// Extracts the two file paths from a git diff header line.
-    fn parse_diff_header_line(line: &str) -> Option<(String, String)> {
-        // Parse "diff --git a/path1 b/path2" format
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 4 && parts[0] == "diff" && parts[1] == "--git" {
-            let left_path = parts[2].strip_prefix("a/").unwrap_or(parts[2]);
-            let right_path = parts[3].strip_prefix("b/").unwrap_or(parts[3]);
-            Some((left_path.to_string(), right_path.to_string()))
-        } else {
-            None
-        }
+    /// Extracts the two file paths from a git diff header line using the shared
+    /// header parser, so trailing metadata never ends up in a path. Combined
+    /// headers ("diff --cc path") yield their single path twice.
+    fn parse_diff_header_line(line: &str, prefixes: &DiffPrefixes) -> Option<(String, String)> {
+        parse_diff_header_with_prefixes(line, prefixes)
+            .map(|header| (header.old_path, header.new_path))
     }
 
     /// Classify context as InRepo, OutsideRepo or Virtual
@@ -235,6 +406,11 @@ impl RootFinder {
             Ok(git_root)
         } else {
             // External paths present → delegate to OutsideRepo workflow
+            diag!(
+                1,
+                "paths outside {}, selecting the root as outside a repository",
+                git_root.display()
+            );
             Self::process_outside_repo_context(path_analyses)
         }
     }
@@ -271,13 +447,14 @@ impl RootFinder {
     /// Extracts path pairs, performs suffix-based analysis, uses fallback if no suffix found,
     /// and applies root selection to candidates.
     fn process_outside_repo_context(path_analyses: Vec<PathAnalysis>) -> Result<PathBuf> {
-        let path_pairs = Self::extract_path_pairs_from_analyses(&path_analyses);
+        let suffix_pairs = Self::extract_suffix_pairs_from_analyses(&path_analyses);
 
         // Suffix-based analysis
-        if let Some((left_root, right_root)) = Self::calculate_roots_by_suffix(&path_pairs) {
+        if let Some((left_root, right_root)) = Self::calculate_roots_by_suffix(&suffix_pairs) {
             Ok(Self::apply_root_selection(vec![left_root, right_root]))
         } else {
             // Fallback: Hierarchical search
+            let path_pairs = Self::extract_path_pairs_from_analyses(&path_analyses);
             let fallback_roots = Self::hierarchical_fallback_search(&path_pairs);
             Ok(Self::apply_root_selection(fallback_roots))
         }
@@ -285,23 +462,38 @@ impl RootFinder {
 
     /// Extract path pairs from path analyses
     ///
-    /// Groups paths pairwise (left, right) from consecutive PathAnalysis entries.
+    /// Groups paths pairwise (left, right) from consecutive PathAnalysis entries. The
+    /// destination of a rename or copy is authoritative, so it is used for both sides.
     fn extract_path_pairs_from_analyses(path_analyses: &[PathAnalysis]) -> Vec<(PathBuf, PathBuf)> {
         // Group paths pairwise (left, right) using functional iterator approach
         let pairs = path_analyses
             .chunks(2)
-            .filter_map(|chunk| {
-                if chunk.len() == 2 {
-                    Some((chunk[0].path.clone(), chunk[1].path.clone()))
-                } else {
-                    None
-                }
+            .filter_map(|chunk| match chunk {
+                [_, right] if right.renamed => Some((right.path.clone(), right.path.clone())),
+                [left, right] => Some((left.path.clone(), right.path.clone())),
+                _ => None,
             })
             .collect::<Vec<_>>();
 
         pairs
     }
 
+    /// Extract the path pairs usable for suffix analysis
+    ///
+    /// The two paths of a rename or copy legitimately differ and have no common suffix,
+    /// so such sections are left out; the remaining sections determine the roots.
+    fn extract_suffix_pairs_from_analyses(
+        path_analyses: &[PathAnalysis],
+    ) -> Vec<(PathBuf, PathBuf)> {
+        path_analyses
+            .chunks(2)
+            .filter_map(|chunk| match chunk {
+                [left, right] if !left.renamed => Some((left.path.clone(), right.path.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Calculate roots by suffix analysis
     ///
     /// Finds the longest common suffix of all path pairs and derives roots by suffix removal.
@@ -334,8 +526,8 @@ impl RootFinder {
                 Self::extract_root_by_suffix_removal(&right_rel, &common_suffix),
             ) {
                 Some((
-                    left_root.to_path("").to_path_buf(),
-                    right_root.to_path("").to_path_buf(),
+                    left_root.to_path(Self::path_anchor(&first_pair.0)),
+                    right_root.to_path(Self::path_anchor(&first_pair.1)),
                 ))
             } else {
                 None
@@ -481,10 +673,10 @@ impl RootFinder {
                 // Collect parent directories to avoid borrow checker issues
                 let mut parents = Vec::new();
                 if let Some(left_parent) = left_rel.parent() {
-                    parents.push(left_parent.to_path("").to_path_buf());
+                    parents.push(left_parent.to_path(Self::path_anchor(left_path)));
                 }
                 if let Some(right_parent) = right_rel.parent() {
-                    parents.push(right_parent.to_path("").to_path_buf());
+                    parents.push(right_parent.to_path(Self::path_anchor(right_path)));
                 }
                 parents
             })
@@ -501,8 +693,11 @@ impl RootFinder {
 
     /// Apply root selection logic to candidate paths
     ///
-    /// Evaluates all candidates with the Priority Score System.
-    /// Sorts descending by score and returns the candidate with the highest score.
+    /// Replaces each candidate inside a Git repository by that repository's root, then
+    /// evaluates all candidates with the Priority Score System.
+    /// Returns the candidate with the highest score; ties are broken by the fewest path
+    /// components, then by lexicographic path order, so the same candidates always
+    /// select the same root regardless of their order.
     /// Fallback: Returns current directory if no candidates.
     /// See docs/implementation_guide.md:357-371 and docs/flowchart_extended.md:119-187, 290-302
     fn apply_root_selection(candidates: Vec<PathBuf>) -> PathBuf {
@@ -510,15 +705,54 @@ impl RootFinder {
             return PathBuf::from(".");
         }
 
-        // Functional approach: Create, sort, and select in one iterator chain
+        // Functional approach: Create, order by (score desc, depth asc, path asc), and select
         candidates
             .into_iter()
+            .map(Self::discover_candidate_root)
             .map(RootCandidate::new)
-            .max_by_key(|candidate| candidate.priority_score)
+            .min_by(|left, right| {
+                right
+                    .priority_score
+                    .cmp(&left.priority_score)
+                    .then_with(|| {
+                        left.path
+                            .components()
+                            .count()
+                            .cmp(&right.path.components().count())
+                    })
+                    .then_with(|| left.path.cmp(&right.path))
+            })
             .map(|candidate| candidate.path)
             .unwrap_or_else(|| PathBuf::from("."))
     }
 
+    /// Root an absolute path is anchored at (e.g. `/`), or an empty path for relative paths
+    ///
+    /// `RelativePath` normalization drops the root of absolute paths; joining the
+    /// normalized path onto this anchor restores it.
+    fn path_anchor(path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.ancestors()
+                .last()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        } else {
+            PathBuf::new()
+        }
+    }
+
+    /// Resolve a candidate root to the working directory of the repository containing it
+    ///
+    /// Suffix analysis may yield a directory below the actual repository root, e.g. when
+    /// the diff compares two subdirectories of one repository. Candidates outside any
+    /// repository, and candidates in bare repositories, are returned unchanged.
+    fn discover_candidate_root(candidate: PathBuf) -> PathBuf {
+        discover(&candidate)
+            .ok()
+            .and_then(|repository| repository.workdir().map(Path::to_path_buf))
+            .unwrap_or(candidate)
+    }
+
     /// Process virtual context paths
     ///
     /// Follows documented virtual workflow logic exclusively:
@@ -529,13 +763,14 @@ impl RootFinder {
         path_analyses: Vec<PathAnalysis>,
     ) -> Result<PathBuf> {
         // Standard virtual path logic according to documentation
-        let virtual_pairs = Self::extract_path_pairs_from_analyses(&path_analyses);
+        let suffix_pairs = Self::extract_suffix_pairs_from_analyses(&path_analyses);
 
         if let Some((left_root, _right_root)) =
-            Self::calculate_virtual_roots_by_suffix(&virtual_pairs)
+            Self::calculate_virtual_roots_by_suffix(&suffix_pairs)
         {
             Ok(left_root) // Always use left root for virtual paths
         } else {
+            let virtual_pairs = Self::extract_path_pairs_from_analyses(&path_analyses);
             Ok(Self::virtual_heuristic_fallback(&virtual_pairs))
         }
     }
@@ -740,6 +975,39 @@ mod tests {
         Ok(())
     }
 
+    /// **What is tested:** Tie-breaking between candidates with equal priority scores
+    /// **Why it is tested:** Repeated runs over the same diff must select the same root, whatever the candidate order
+    /// **Test conditions:** Non-Git directories `c`, `a/x` and `b` without .gitignore (all score 1), in several orders; then `a/x` given a .gitignore
+    /// **Expectations:** The shallower directories win over `a/x` and `b` wins over `c` lexicographically; a higher score still wins over a shallower path
+    #[test]
+    fn test_apply_root_selection_tie_order() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = TempDir::new()?;
+        let [a_x, b, c] = ["a/x", "b", "c"].map(|dir| temp_dir.path().join(dir));
+        for dir in [&a_x, &b, &c] {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        for candidates in [
+            vec![c.clone(), a_x.clone(), b.clone()],
+            vec![b.clone(), c.clone(), a_x.clone()],
+            vec![a_x.clone(), c.clone(), b.clone()],
+        ] {
+            assert_eq!(RootFinder::apply_root_selection(candidates), b);
+        }
+        assert_eq!(
+            RootFinder::apply_root_selection(vec![c.clone(), a_x.clone()]),
+            c
+        );
+
+        std::fs::write(a_x.join(".gitignore"), "*.log\n")?;
+        assert_eq!(
+            RootFinder::apply_root_selection(vec![b.clone(), a_x.clone(), c]),
+            a_x
+        );
+        Ok(())
+    }
+
     /// **What is tested:** PathAnalysis struct creation and field validation
     /// **Why it is tested:** Ensures that path analysis correctly identifies path properties (existence, relativity)
     /// **Test conditions:** Creates PathAnalysis for both existing and non-existing paths
@@ -754,12 +1022,14 @@ mod tests {
             path: existing_path.clone(),
             is_relative: !existing_path.is_absolute(),
             exists: existing_path.exists(),
+            renamed: false,
         };
 
         let non_existing_analysis = PathAnalysis {
             path: non_existing_path.clone(),
             is_relative: !non_existing_path.is_absolute(),
             exists: non_existing_path.exists(),
+            renamed: false,
         };
 
         assert!(existing_analysis.exists);
@@ -794,7 +1064,7 @@ mod tests {
     fn test_parse_diff_header_line() {
         // Test valid diff header
         let line = "diff --git a/src/main.rs b/src/main.rs";
-        let result = RootFinder::parse_diff_header_line(line);
+        let result = RootFinder::parse_diff_header_line(line, &DiffPrefixes::default());
         assert_eq!(
             result,
             Some(("src/main.rs".to_string(), "src/main.rs".to_string()))
@@ -802,7 +1072,7 @@ mod tests {
 
         // Test with different paths
         let line2 = "diff --git a/old/file.txt b/new/file.txt";
-        let result2 = RootFinder::parse_diff_header_line(line2);
+        let result2 = RootFinder::parse_diff_header_line(line2, &DiffPrefixes::default());
         assert_eq!(
             result2,
             Some(("old/file.txt".to_string(), "new/file.txt".to_string()))
@@ -810,15 +1080,49 @@ mod tests {
 
         // Test invalid line
         let invalid_line = "not a diff line";
-        let result3 = RootFinder::parse_diff_header_line(invalid_line);
+        let result3 = RootFinder::parse_diff_header_line(invalid_line, &DiffPrefixes::default());
         assert_eq!(result3, None);
 
         // Test incomplete diff line
         let incomplete_line = "diff --git a/file.txt";
-        let result4 = RootFinder::parse_diff_header_line(incomplete_line);
+        let result4 = RootFinder::parse_diff_header_line(incomplete_line, &DiffPrefixes::default());
         assert_eq!(result4, None);
     }
 
+    /// **What is tested:** Parsing of diff header lines carrying trailing tab-separated metadata
+    /// **Why it is tested:** Metadata appended by tools must not corrupt path extraction, especially for paths with spaces
+    /// **Test conditions:** Header line with a path containing spaces followed by a tab and extra fields
+    /// **Expectations:** Should extract exactly the two paths and ignore the metadata
+    #[test]
+    fn test_parse_diff_header_line_with_trailing_metadata() {
+        let line = "diff --git a/src/my file.rs b/src/my file.rs\tsize=42 origin=review-tool";
+        let result = RootFinder::parse_diff_header_line(line, &DiffPrefixes::default());
+        assert_eq!(
+            result,
+            Some(("src/my file.rs".to_string(), "src/my file.rs".to_string()))
+        );
+    }
+
+    /// **What is tested:** Parsing of combined diff headers (`diff --cc` / `diff --combined`)
+    /// **Why it is tested:** Merge diffs use a single-path header form that must still contribute paths to root finding
+    /// **Test conditions:** Both combined header forms with a nested path
+    /// **Expectations:** Should return the single path as both the old and the new path
+    #[test]
+    fn test_parse_diff_header_line_combined() {
+        let expected = Some(("src/lib.rs".to_string(), "src/lib.rs".to_string()));
+        assert_eq!(
+            RootFinder::parse_diff_header_line("diff --cc src/lib.rs", &DiffPrefixes::default()),
+            expected
+        );
+        assert_eq!(
+            RootFinder::parse_diff_header_line(
+                "diff --combined src/lib.rs",
+                &DiffPrefixes::default()
+            ),
+            expected
+        );
+    }
+
     /// **What is tested:** Extraction and analysis of file paths from diff content
     /// **Why it is tested:** Validates the core functionality of parsing diff content to identify file paths for root finding
     /// **Test conditions:** Processes multi-file diff content with various file operations (modify, create)
@@ -844,7 +1148,7 @@ index 0000000..fedcba9
 "#;
 
         let cursor = Cursor::new(diff_content);
-        let result = RootFinder::extract_and_analyze_diff_paths(cursor);
+        let result = RootFinder::extract_and_analyze_diff_paths(cursor, &DiffPrefixes::default());
         assert!(result.is_ok());
 
         let path_analyses = result?;
@@ -861,6 +1165,47 @@ index 0000000..fedcba9
         Ok(())
     }
 
+    /// **What is tested:** Path extraction for added and deleted files whose header is ambiguous
+    /// **Why it is tested:** `/dev/null` on the `---`/`+++` lines must not be analyzed as a path and skew the suffix analysis
+    /// **Test conditions:** An added and a deleted file with headers naming only one path, followed by `---`/`+++` lines with `/dev/null`
+    /// **Expectations:** Both sides of each section resolve to the real file path and no analysis names `/dev/null`
+    #[test]
+    fn test_extract_paths_for_added_and_deleted_files(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let diff_content = "diff --git a/src/new.rs\n\
+                            new file mode 100644\n\
+                            --- /dev/null\n\
+                            +++ b/src/new.rs\n\
+                            @@ -0,0 +1 @@\n\
+                            +fn main() {}\n\
+                            diff --git a/docs/old.md\n\
+                            deleted file mode 100644\n\
+                            --- a/docs/old.md\n\
+                            +++ /dev/null\n\
+                            @@ -1 +0,0 @@\n\
+                            -# Old\n";
+
+        let path_analyses = RootFinder::extract_and_analyze_diff_paths(
+            Cursor::new(diff_content),
+            &DiffPrefixes::default(),
+        )?;
+        let paths: Vec<PathBuf> = path_analyses
+            .into_iter()
+            .map(|analysis| analysis.path)
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("src/new.rs"),
+                PathBuf::from("src/new.rs"),
+                PathBuf::from("docs/old.md"),
+                PathBuf::from("docs/old.md"),
+            ]
+        );
+        Ok(())
+    }
+
     /// **What is tested:** Context classification for directories within Git repositories
     /// **Why it is tested:** Ensures that the context classifier correctly identifies Git repository environments
     /// **Test conditions:** Creates a Git repository and tests context classification with empty path analyses
@@ -895,6 +1240,7 @@ index 0000000..fedcba9
             path: test_file,
             is_relative: true,
             exists: true,
+            renamed: false,
         }];
 
         let context = RootFinder::classify_context(temp_dir.path(), &path_analyses);
@@ -906,6 +1252,109 @@ index 0000000..fedcba9
         Ok(())
     }
 
+    /// **What is tested:** Root selection for absolute diff paths inside a repository, run from a sibling directory
+    /// **Why it is tested:** Suffix analysis yields subdirectories of the repository, whose .gitignore lives at the repository root
+    /// **Test conditions:** Git repository with `old/src/main.rs` and `new/src/main.rs`, diffed with absolute paths from a non-Git sibling directory
+    /// **Expectations:** The discovered repository root should be chosen instead of the suffix-derived `old` or `new` directory
+    #[test]
+    fn test_find_root_discovers_repo_above_candidates(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let repo_dir = temp_dir.path().join("repo");
+        let sibling_dir = temp_dir.path().join("elsewhere");
+        fs::create_dir_all(repo_dir.join("old/src"))?;
+        fs::create_dir_all(repo_dir.join("new/src"))?;
+        fs::create_dir(&sibling_dir)?;
+        gix::init(&repo_dir)?;
+
+        let old_file = repo_dir.join("old/src/main.rs");
+        let new_file = repo_dir.join("new/src/main.rs");
+        fs::write(&old_file, "fn main() {}\n")?;
+        fs::write(&new_file, "fn main() { run() }\n")?;
+
+        let diff = format!(
+            "diff --git a/{} b/{}\n",
+            old_file.display(),
+            new_file.display()
+        );
+        let root = RootFinder::find_root(sibling_dir, Cursor::new(diff))?;
+
+        assert_eq!(root.canonicalize()?, repo_dir.canonicalize()?);
+        Ok(())
+    }
+
+    /// **What is tested:** Root selection for a rename-heavy diff of two directories outside any Git repository
+    /// **Why it is tested:** The paths of a rename differ and have no common suffix, which must not break suffix analysis for the whole diff
+    /// **Test conditions:** `left/` and `right/` trees (only `right/` has a .gitignore) with one modified file and two renames, diffed with absolute paths
+    /// **Expectations:** The roots should be derived from the modified file, selecting the `right` directory with its .gitignore
+    #[test]
+    fn test_find_root_with_renames_outside_repo(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let left = temp_dir.path().join("left");
+        let right = temp_dir.path().join("right");
+        for dir in [&left, &right] {
+            fs::create_dir_all(dir.join("src"))?;
+            fs::create_dir_all(dir.join("docs"))?;
+            fs::write(dir.join("src/main.rs"), "fn main() {}\n")?;
+        }
+        fs::write(left.join("src/old.rs"), "")?;
+        fs::write(right.join("src/new.rs"), "")?;
+        fs::write(left.join("docs/a.md"), "")?;
+        fs::write(right.join("docs/b.md"), "")?;
+        fs::write(right.join(".gitignore"), "*.log\n")?;
+
+        let rename = |from: &str, to: &str| {
+            let (from, to) = (left.join(from), right.join(to));
+            format!(
+                "diff --git a/{0} b/{1}\nsimilarity index 100%\nrename from {0}\nrename to {1}\n",
+                from.display(),
+                to.display()
+            )
+        };
+        let diff = format!(
+            "{}diff --git a/{} b/{}\n--- a/x\n+++ b/x\n{}",
+            rename("src/old.rs", "src/new.rs"),
+            left.join("src/main.rs").display(),
+            right.join("src/main.rs").display(),
+            rename("docs/a.md", "docs/b.md")
+        );
+
+        let root = RootFinder::find_root(temp_dir.path().to_path_buf(), Cursor::new(diff))?;
+
+        assert_eq!(root, right);
+        Ok(())
+    }
+
+    /// **What is tested:** Root selection from a list of changed paths with `find_root_from_paths`
+    /// **Why it is tested:** Callers that already know the changed paths must get the same root selection without a diff
+    /// **Test conditions:** Absolute paths below a `project/` directory with a .gitignore, outside any repository; relative paths from a subdirectory of a Git repository
+    /// **Expectations:** The `project` directory is selected over `project/src`, and the repository root is found from its subdirectory
+    #[test]
+    fn test_find_root_from_paths() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let project = temp_dir.path().join("project");
+        fs::create_dir_all(project.join("src"))?;
+        fs::write(project.join("src/main.rs"), "fn main() {}\n")?;
+        fs::write(project.join("README.md"), "")?;
+        fs::write(project.join(".gitignore"), "*.log\n")?;
+
+        let paths = [
+            project.join("src/main.rs").display().to_string(),
+            project.join("README.md").display().to_string(),
+        ];
+        let root = RootFinder::find_root_from_paths(temp_dir.path().to_path_buf(), &paths)?;
+        assert_eq!(root, project);
+
+        let repo_dir = TempDir::new()?;
+        gix::init(repo_dir.path())?;
+        fs::create_dir(repo_dir.path().join("src"))?;
+        let paths = ["src/main.rs".to_string(), "Cargo.toml".to_string()];
+        let root = RootFinder::find_root_from_paths(repo_dir.path().join("src"), &paths)?;
+        assert_eq!(root.canonicalize()?, repo_dir.path().canonicalize()?);
+        Ok(())
+    }
+
     /// **What is tested:** Context classification for virtual paths (non-existing files)
     /// **Why it is tested:** Ensures proper handling of diff content referencing files that don't exist in filesystem
     /// **Test conditions:** Creates non-Git directory with path analyses for non-existing files
@@ -919,6 +1368,7 @@ index 0000000..fedcba9
             path: PathBuf::from("non/existing/file.txt"),
             is_relative: true,
             exists: false,
+            renamed: false,
         }];
 
         let context = RootFinder::classify_context(temp_dir.path(), &path_analyses);
@@ -11,6 +11,7 @@ use diff_gitignore_filter::config::CliArgs;
 use diff_gitignore_filter::{AppConfig, ConfigError};
 use predicates::prelude::*;
 use std::fs;
+use std::path::PathBuf;
 use std::process::Command as StdCommand;
 use tempfile::TempDir;
 
@@ -22,6 +23,12 @@ use common::{TestData, TestRepo};
 /// Path to the real-world diff fixture containing binary data
 const REAL_SAMPLE_DIFF_PATH: &str = "tests/fixtures/realsample_jira_cli.diff";
 
+/// Path to the combined diff (`git show --cc`) of a merge commit with conflicts
+const COMBINED_MERGE_DIFF_PATH: &str = "tests/fixtures/combined_merge.diff";
+
+/// Path to `git log -p` output of three commits adding ignored and kept files
+const GIT_LOG_PATCH_PATH: &str = "tests/fixtures/git_log_patch.diff";
+
 /// **What is tested:** Processing of real-world diff files containing binary data without UTF-8 errors
 /// **Why it is tested:** Ensures the tool can handle actual diff files from real repositories with mixed content types
 /// **Test conditions:** Real sample diff file with binary content, comprehensive gitignore patterns
@@ -125,6 +132,118 @@ fn test_real_sample_diff_with_gitignore_filtering() {
     assert!(!stdout.contains(".git/index"));
 }
 
+/// **What is tested:** Gitignore filtering of a combined diff produced by `git show --cc` for a merge commit
+/// **Why it is tested:** Merge diffs use `diff --cc <path>` headers, which must be recognized as section boundaries
+/// **Test conditions:** Real combined merge diff touching `build/output.log` and `src/lib.rs`, gitignore ignoring `build/`
+/// **Expectations:** The ignored build log section should be dropped while the tracked source section survives intact
+#[test]
+fn test_combined_merge_diff_filtering() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(["build/"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let combined_diff = fs::read(COMBINED_MERGE_DIFF_PATH).unwrap();
+
+    let mut cmd = Command::cargo_bin("diff-gitignore-filter").unwrap();
+    let output = cmd
+        .current_dir(temp_dir.path())
+        .write_stdin(combined_diff)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!stdout.contains("diff --cc build/output.log"));
+    assert!(!stdout.contains("feature build"));
+    assert!(stdout.starts_with("diff --cc src/lib.rs\n"));
+    assert!(stdout.contains("++    \"hello from main and feature\""));
+}
+
+/// **What is tested:** Filtering `git log -p` output spanning several commits
+/// **Why it is tested:** Commit metadata must survive even when the file section before it or all files of its commit are dropped
+/// **Test conditions:** Fixture of three commits; `debug.log` ends the first commit and `build.log` is the only file of the second, both ignored by `*.log`
+/// **Expectations:** Both log sections are dropped, all commit metadata and the other sections are written unchanged and in order
+#[test]
+fn test_git_log_patch_stream() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let log = fs::read_to_string(GIT_LOG_PATCH_PATH).unwrap();
+    let section = |path: &str| {
+        let start = log.find(&format!("diff --git a/{path} ")).unwrap();
+        let end = start + log[start..].find("commit ").unwrap();
+        &log[start..end]
+    };
+    let expected = log
+        .replace(section("debug.log"), "")
+        .replace(section("build.log"), "");
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(log.clone())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, expected);
+    assert!(!stdout.contains("run finished"));
+    assert!(!stdout.contains("build ok"));
+    assert!(stdout.contains(" }\ncommit 8e1b4d2c6a9f3e7b5d0c2a4f6e8b1d3c5a7e9f0b\n"));
+    assert!(stdout.contains("    Add build output\n\ncommit 0a1b2c3d"));
+}
+
+/// **What is tested:** Reading the diff from an INPUT file argument instead of stdin
+/// **Why it is tested:** Diffs saved to disk should be filterable without shell redirection, with `-` still meaning stdin
+/// **Test conditions:** The combined merge fixture passed by path, the same diff on stdin with `-`, and a missing input file
+/// **Expectations:** File and `-` input produce the same filtered output; a missing file fails with an error naming the path
+#[test]
+fn test_input_file_argument() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(["build/"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let fixture = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(COMBINED_MERGE_DIFF_PATH);
+
+    let from_file = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg(&fixture)
+        .output()
+        .unwrap();
+
+    assert!(from_file.status.success());
+    let stdout = String::from_utf8_lossy(&from_file.stdout);
+    assert!(!stdout.contains("diff --cc build/output.log"));
+    assert!(stdout.starts_with("diff --cc src/lib.rs\n"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("-")
+        .write_stdin(fs::read(&fixture).unwrap())
+        .assert()
+        .success()
+        .stdout(stdout.into_owned());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("missing.diff")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.diff"));
+}
+
 /// **What is tested:** Graceful handling of binary data within real diff files
 /// **Why it is tested:** Ensures binary content doesn't cause crashes or encoding errors
 /// **Test conditions:** Real sample diff with no filtering to test binary data handling
@@ -495,8 +614,9 @@ fn test_app_config_integration_with_cli_args() {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -504,7 +624,7 @@ fn test_app_config_integration_with_cli_args() {
                 // Should have default VCS enabled
                 assert!(config.vcs_enabled());
                 assert!(!config.vcs_patterns().is_empty());
-                assert!(config.downstream_filter().is_none());
+                assert!(config.downstream_pipeline().is_empty());
             }
             Err(ConfigError::NotInGitRepository { .. }) => {
                 // Acceptable in test environment
@@ -521,14 +641,15 @@ fn test_app_config_integration_with_cli_args() {
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: Some("cat".to_string()),
+            downstream: vec!["cat".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
             Ok(config) => {
                 assert!(config.vcs_enabled());
-                assert_eq!(config.downstream_filter(), Some("cat"));
+                assert_eq!(config.downstream_pipeline(), ["cat"]);
             }
             Err(_) => {
                 // Error is acceptable in test environment
@@ -557,8 +678,9 @@ fn test_app_config_error_handling_integration() {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         let result = AppConfig::from_cli(cli_args);
@@ -705,6 +827,40 @@ fn test_integration_git_config_empty_value() {
     test_repo.unset_git_config("gitignore-diff.downstream-filter");
 }
 
+/// **What is tested:** Blank and quote-only `--downstream` commands
+/// **Why it is tested:** A blank command must mean "no downstream filter" even over git config, while a command without a program must be reported clearly
+/// **Test conditions:** Git config downstream filter `false`; `--downstream` with a whitespace-only command, then with `""`
+/// **Expectations:** The whitespace-only command passes the filtered diff through; the quote-only command fails as an invalid CLI argument
+#[test]
+fn test_downstream_blank_and_quote_only_commands() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap();
+    test_repo
+        .set_git_config("diff-gitignore-filter.downstream-filter", "false")
+        .expect("Failed to set git config");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .args(["--downstream", "   "])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"))
+        .stdout(predicate::str::contains("debug.log").not());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .args(["--downstream", "\"\""])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid CLI argument"));
+}
+
 // ============================================================================
 // VCS CLI Parameter Tests
 // ============================================================================
@@ -880,6 +1036,51 @@ fn test_vcs_parameter_overrides_git_config_enabled() {
         .ok();
 }
 
+/// **What is tested:** Error messages for invalid configuration values
+/// **Why it is tested:** Users need the offending key, value and accepted values to fix their configuration
+/// **Test conditions:** Git config with a non-boolean `vcs-ignore.enabled`, then an empty `--only-ext` list
+/// **Expectations:** The command fails and stderr names the key or option, the value and what was expected
+#[test]
+fn test_config_errors_name_key_and_value() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    StdCommand::new("git")
+        .current_dir(temp_dir.path())
+        .args([
+            "config",
+            "diff-gitignore-filter.vcs-ignore.enabled",
+            "maybe",
+        ])
+        .output()
+        .expect("Failed to set git config");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(TestData::COMPLEX_VCS_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid git config 'diff-gitignore-filter.vcs-ignore.enabled' = 'maybe' \
+             (expected true, false, 1, 0, yes, no, on, or off)",
+        ));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--vcs", "--only-ext", ",,"])
+        .write_stdin(TestData::COMPLEX_VCS_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid CLI argument --only-ext ',,' (expected comma-separated file extensions",
+        ));
+}
+
 /// **What is tested:** CLI --no-vcs parameter overriding git config VCS filtering setting
 /// **Why it is tested:** Ensures CLI parameters take precedence over git configuration for VCS filtering
 /// **Test conditions:** Git config with VCS filtering enabled, CLI --no-vcs parameter
@@ -1787,6 +1988,53 @@ fn test_error_handling_downstream_pipe_broken() {
         .success(); // Should handle broken pipe gracefully
 }
 
+/// **What is tested:** Exit status when the reader of stdout exits before the output is written
+/// **Why it is tested:** Like other Unix tools, output piped to `head` or a closed pager must end quietly with status 0
+/// **Test conditions:** A large diff filtered with stdout closed before the first write, as diff, with `--list-dirs`, `--split-on` and `--print-config`, and piped to a downstream `head -c 1`
+/// **Expectations:** Every run exits with status 0 and writes no error
+#[test]
+fn test_broken_stdout_pipe_exits_successfully() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let input = format!("{}=== MARKER ===\n", TestData::SAMPLE_DIFF).repeat(2_000);
+    let input_path = temp_dir.path().join("input.diff");
+    fs::write(&input_path, &input).unwrap();
+
+    for args in [
+        &[][..],
+        &["--list-dirs"],
+        &["--split-on", "=== MARKER ==="],
+        &["--print-config"],
+    ] {
+        let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("diff-gitignore-filter"))
+            .current_dir(temp_dir.path())
+            .args(args)
+            .stdin(fs::File::open(&input_path).unwrap())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        drop(child.stdout.take());
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        assert!(output.stderr.is_empty(), "{args:?}: {output:?}");
+    }
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "head -c 1"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("d")
+        .stderr("");
+}
+
 /// **What is tested:** All temporary file operations including creation, copying, and seeking
 /// **Why it is tested:** Covers tempfile creation, io::copy from stdin, and both seek operations
 /// **Test conditions:** Large input with sample diff plus 10KB of data, git repository setup
@@ -2084,6 +2332,90 @@ fn test_error_handling_filter_creation_failure() {
         .stdout(predicate::str::contains("debug.log")); // No gitignore filtering without repo
 }
 
+/// **What is tested:** The `--gitignore-file` option outside a git repository
+/// **Why it is tested:** Patches must be filterable in CI without a checkout, where no .gitignore is found
+/// **Test conditions:** Temporary directory without git repository holding `ci.gitignore` with `*.log`; sample diff input; then a missing file
+/// **Expectations:** `debug.log` is dropped and `src/main.rs` kept; a missing file fails with its path in the message
+#[test]
+fn test_gitignore_file_outside_repository() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("ci.gitignore"), "*.log\n").unwrap();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--gitignore-file", "ci.gitignore"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"))
+        .stdout(predicate::str::contains("debug.log").not());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--gitignore-file", "missing.gitignore"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.gitignore"));
+}
+
+/// **What is tested:** Git's global ignore file from `$XDG_CONFIG_HOME/git/ignore` and `core.excludesFile`
+/// **Why it is tested:** Files ignored globally by Git must be filtered too, beneath the repository's own patterns
+/// **Test conditions:** Repository re-including `src/main.rs`, `$XDG_CONFIG_HOME/git/ignore` ignoring `*.md` and `*.rs`; then `core.excludesFile` naming a file that ignores `*.txt`
+/// **Expectations:** The XDG patterns filter `README.md` while `src/main.rs` stays; with `core.excludesFile` only its patterns apply
+#[test]
+fn test_global_excludes_file_from_xdg_config_home() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(vec!["!src/main.rs"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let config_home = TempDir::new().unwrap();
+    std::fs::create_dir(config_home.path().join("git")).unwrap();
+    std::fs::write(
+        config_home.path().join("git").join("ignore"),
+        "*.md\n*.rs\n",
+    )
+    .unwrap();
+    let diff = "diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n\
+                diff --git a/README.md b/README.md\n+docs\n\
+                diff --git a/notes.txt b/notes.txt\n+notes\n";
+
+    let run = || {
+        let output = Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(temp_dir.path())
+            .env("HOME", config_home.path())
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .write_stdin(diff)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let stdout = run();
+    assert!(stdout.contains("src/main.rs"));
+    assert!(!stdout.contains("README.md"));
+    assert!(stdout.contains("notes.txt"));
+
+    let excludes_file = config_home.path().join("excludes");
+    std::fs::write(&excludes_file, "*.txt\n").unwrap();
+    StdCommand::new("git")
+        .current_dir(temp_dir.path())
+        .args(["config", "core.excludesFile"])
+        .arg(&excludes_file)
+        .output()
+        .unwrap();
+
+    let stdout = run();
+    assert!(stdout.contains("src/main.rs"));
+    assert!(stdout.contains("README.md"));
+    assert!(!stdout.contains("notes.txt"));
+}
+
 /// **What is tested:** Process diff handling with binary data mixed with diff content
 /// **Why it is tested:** Verifies graceful handling of invalid UTF-8 bytes in diff input
 /// **Test conditions:** Mixed binary diff with invalid UTF-8 bytes and diff content
@@ -2335,6 +2667,38 @@ fn test_cli_vcs_pattern_invalid_format() {
         .stderr(predicate::str::contains("Invalid CLI argument"));
 }
 
+/// **What is tested:** `--allow-empty-vcs` with a VCS pattern list that is empty after trimming
+/// **Why it is tested:** The toggle decides whether an empty list is an error or makes VCS filtering a no-op
+/// **Test conditions:** VCS diff processed with `--vcs --vcs-pattern " , "`, with and without `--allow-empty-vcs`
+/// **Expectations:** Without the flag the command fails; with it VCS files pass through while .gitignore filtering still applies
+#[test]
+fn test_cli_allow_empty_vcs_patterns() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--vcs", "--vcs-pattern", " , "])
+        .write_stdin(TestData::VCS_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid CLI argument"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--vcs", "--vcs-pattern", " , ", "--allow-empty-vcs"])
+        .write_stdin(TestData::VCS_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"))
+        .stdout(predicate::str::contains(".git/config"));
+}
+
 /// **What is tested:** CLI --vcs-pattern parameter overriding git configuration
 /// **Why it is tested:** Verifies that CLI arguments take precedence over git config settings
 /// **Test conditions:** Git repository with git config VCS patterns, CLI override with different patterns
@@ -2744,3 +3108,1970 @@ fn test_vcs_pattern_multiple_custom_patterns() {
         .stdout(predicate::str::contains(".git/config"))
         .stdout(predicate::str::contains("src/main.rs"));
 }
+
+/// **What is tested:** `--dry-run-config` with an invalid VCS pattern
+/// **Why it is tested:** CI validation steps must detect misconfiguration before a real run
+/// **Test conditions:** Git repository, `--vcs-pattern` containing an unclosed character class
+/// **Expectations:** Command fails with exit code 1 and names the offending pattern on stderr
+#[test]
+fn test_dry_run_config_invalid_vcs_pattern() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--dry-run-config")
+        .arg("--vcs-pattern")
+        .arg(".git/,[.svn/")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("Invalid VCS pattern '[.svn/'"));
+}
+
+/// **What is tested:** `--dry-run-config` with a valid configuration
+/// **Why it is tested:** Verifies that a valid configuration exits successfully without processing any diff
+/// **Test conditions:** Git repository, valid VCS patterns and an existing downstream command, diff on stdin
+/// **Expectations:** Command succeeds and prints only the validation result, not the diff
+#[test]
+fn test_dry_run_config_valid() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--dry-run-config")
+        .arg("--vcs-pattern")
+        .arg(".git/,.custom/")
+        .arg("--downstream")
+        .arg("cat")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::eq("Configuration is valid\n"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--dry-run-config")
+        .arg("--downstream")
+        .arg("no-such-downstream-tool --color")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "executable 'no-such-downstream-tool' not found",
+        ));
+}
+
+/// **What is tested:** `--stats` summary written to stderr after processing
+/// **Why it is tested:** The summary is for interactive use and must not contaminate stdout, which feeds downstream tools
+/// **Test conditions:** Diff with two kept, one gitignored and one VCS section; run plain, with `--stats`, and with `--stats` plus a downstream command
+/// **Expectations:** No summary without the flag; with the flag stderr carries the exact counts and stdout still carries only the diff
+#[test]
+fn test_stats_summary_on_stderr() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let input = format!("{}{}", TestData::SAMPLE_DIFF, TestData::VCS_DIFF);
+    let summary = "kept 2 files, filtered 2 (.gitignore: 1, vcs: 1)\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--stats")
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "diff --git a/src/main.rs b/src/main.rs",
+        ))
+        .stdout(predicate::str::contains("kept").not())
+        .stderr(predicate::eq(summary));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--stats")
+        .arg("--downstream")
+        .arg("cat")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "diff --git a/src/main.rs b/src/main.rs",
+        ))
+        .stderr(predicate::eq(summary));
+}
+
+/// **What is tested:** `--list-dirs` output of top-level directories touched by kept files
+/// **Why it is tested:** Per-area CI triggers rely on ignored directories never being listed
+/// **Test conditions:** Diff touching `src/`, `docs/`, an ignored `target/` file and a root-level file
+/// **Expectations:** Only `docs` and `src` are printed, sorted and one per line, instead of the diff
+#[test]
+fn test_list_dirs_outputs_kept_top_level_directories() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let input = "diff --git a/src/main.rs b/src/main.rs
+index 1234567..abcdefg 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1 +1 @@
+-old
++new
+diff --git a/target/debug/build.txt b/target/debug/build.txt
+index 1234567..abcdefg 100644
+diff --git a/docs/guide.md b/docs/guide.md
+index 1234567..abcdefg 100644
+diff --git a/README.md b/README.md
+index 1234567..abcdefg 100644
+";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--list-dirs")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::eq("docs\nsrc\n"));
+}
+
+/// **What is tested:** Chaining several downstream commands with repeated `--downstream` flags
+/// **Why it is tested:** Users pipe filtered output through multiple stages, e.g. a filter, a pager and a counter
+/// **Test conditions:** SAMPLE_DIFF with `debug.log` ignored, piped through `cat` and then `wc -l`
+/// **Expectations:** Only the last stage writes to stdout, reporting the line count of the kept section
+#[test]
+fn test_downstream_pipeline_cat_wc() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--downstream")
+        .arg("cat")
+        .arg("--downstream")
+        .arg("wc -l")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "8");
+}
+
+/// **What is tested:** Error reporting for a failing stage in a downstream pipeline
+/// **Why it is tested:** With several stages the error must name which command failed
+/// **Test conditions:** Three-stage pipeline whose middle stage (`false`) exits with a non-zero status
+/// **Expectations:** Command fails with DownstreamProcessFailed naming the command and its stage
+#[test]
+fn test_downstream_pipeline_failing_stage() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["-d", "cat", "-d", "false", "-d", "cat"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DownstreamProcessFailed"))
+        .stderr(predicate::str::contains(
+            "Downstream command 'false' (stage 2 of 3) failed",
+        ));
+}
+
+/// **What is tested:** Broken pipe handling when the last pipeline stage stops reading early
+/// **Why it is tested:** Pagers and `head` exit early; earlier stages then die from SIGPIPE, which must not be an error
+/// **Test conditions:** Large diff piped through `cat` into `head -n 1`
+/// **Expectations:** Command succeeds and prints only the first line
+#[test]
+fn test_downstream_pipeline_broken_pipe_on_last_stage() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let input = TestData::SAMPLE_DIFF.repeat(20_000);
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["-d", "cat", "-d", "head -n 1"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::eq("diff --git a/src/main.rs b/src/main.rs\n"));
+}
+
+/// **What is tested:** Downstream commands spawned without a shell, with and without `--downstream-shell`
+/// **Why it is tested:** Quoted arguments must reach the program whole, while shell syntax is only interpreted on explicit opt-in
+/// **Test conditions:** SAMPLE_DIFF piped to a grep with a quoted argument, and to `cat | wc -l` with and without `--downstream-shell`
+/// **Expectations:** The quoted pattern is applied as one argument; the pipe fails as plain argv but counts the kept lines through the shell
+#[test]
+fn test_downstream_without_shell() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "grep -v 'fn main() {'"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"))
+        .stdout(predicate::str::contains("fn main() {").not());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "cat | wc -l"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DownstreamProcessFailed"));
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream-shell", "--downstream", "cat | wc -l"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "8");
+}
+
+/// **What is tested:** `--downstream-timeout` killing a downstream command that hangs
+/// **Why it is tested:** A misconfigured downstream command must not block CI forever
+/// **Test conditions:** `sleep 10` as downstream with a 1 second timeout and input larger than a pipe buffer, then `cat` with a timeout of 0
+/// **Expectations:** The hanging command fails with DownstreamTimeout within about 2 seconds; a timeout of 0 disables the deadline
+#[test]
+fn test_downstream_timeout_kills_hanging_command() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    // `sleep` never reads stdin, so writing the kept half of this (about 100 KB, just
+    // above a pipe buffer) blocks until the timeout kills it. Larger inputs make the
+    // run's time depend on how fast the binary filters them.
+    let input = TestData::SAMPLE_DIFF.repeat(600);
+    let started = std::time::Instant::now();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "sleep 10", "--downstream-timeout", "1"])
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DownstreamTimeout"))
+        .stderr(predicate::str::contains("sleep 10"));
+
+    assert!(
+        started.elapsed() < std::time::Duration::from_millis(2500),
+        "Timeout should end the run shortly after 1 second, took {:?}",
+        started.elapsed()
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "cat", "--downstream-timeout", "0"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"));
+}
+
+/// **What is tested:** `--split-on` processing concatenated diffs with their own roots
+/// **Why it is tested:** Pipelines concatenate independent diffs, and each must be filtered against its own .gitignore
+/// **Test conditions:** Outside a repository, two `old`/`new` directory comparisons separated by a marker; `first/new` ignores `*.log`, `second/new` ignores `*.rs`
+/// **Expectations:** Each diff is filtered by its own root's patterns and the marker line is preserved between them
+#[test]
+fn test_split_on_marker_filters_each_diff_against_its_root() {
+    let temp_dir = TempDir::new().unwrap();
+    for (dir, ignored) in [("first", "*.log\n"), ("second", "*.rs\n")] {
+        for side in ["old", "new"] {
+            let side_dir = temp_dir.path().join(dir).join(side);
+            fs::create_dir_all(&side_dir).unwrap();
+            fs::write(side_dir.join("app.log"), "log\n").unwrap();
+            fs::write(side_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        }
+        fs::write(temp_dir.path().join(dir).join("new/.gitignore"), ignored).unwrap();
+    }
+
+    let section = |dir: &str, file: &str| {
+        format!(
+            "diff --git a/{dir}/old/{file} b/{dir}/new/{file}\n\
+             index 1111111..2222222 100644\n\
+             --- a/{dir}/old/{file}\n\
+             +++ b/{dir}/new/{file}\n\
+             @@ -1 +1 @@\n\
+             -old\n\
+             +new\n"
+        )
+    };
+    let input = format!(
+        "{}{}=== DIFF BOUNDARY ===\n{}{}",
+        section("first", "app.log"),
+        section("first", "main.rs"),
+        section("second", "app.log"),
+        section("second", "main.rs"),
+    );
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--split-on", "=== DIFF BOUNDARY ==="])
+        .write_stdin(input)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}=== DIFF BOUNDARY ===\n{}",
+            section("first", "main.rs"),
+            section("second", "app.log")
+        )
+    );
+}
+
+/// **What is tested:** Git's `core.ignorecase` setting applied to .gitignore matching
+/// **Why it is tested:** On case-insensitive filesystems Git lets `*.LOG` match `debug.log`, and the filter must do the same
+/// **Test conditions:** Repository ignoring `*.LOG`, SAMPLE_DIFF touching `debug.log`, with `core.ignorecase` set to false and to true
+/// **Expectations:** `debug.log` is kept with case-sensitive matching and filtered once `core.ignorecase=true`
+#[test]
+fn test_core_ignorecase_matches_uppercase_pattern() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(["*.LOG"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    for (ignore_case, expect_log) in [("false", true), ("true", false)] {
+        StdCommand::new("git")
+            .current_dir(temp_dir.path())
+            .args(["config", "core.ignorecase", ignore_case])
+            .output()
+            .unwrap();
+
+        let output = Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(temp_dir.path())
+            .write_stdin(TestData::SAMPLE_DIFF)
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success());
+        assert!(stdout.contains("src/main.rs"));
+        assert_eq!(
+            stdout.contains("debug.log"),
+            expect_log,
+            "core.ignorecase={ignore_case}"
+        );
+    }
+}
+
+/// **What is tested:** Diffs generated with `--no-prefix` and with custom `--src-prefix`/`--dst-prefix`
+/// **Why it is tested:** Without the `a/` and `b/` prefixes, paths would keep the custom prefix and anchored patterns would stop matching
+/// **Test conditions:** Repository ignoring `/debug.log`, SAMPLE_DIFF rewritten without prefixes and with `old/`/`new/` prefixes, with and without the prefix flags
+/// **Expectations:** The no-prefix diff is filtered without flags; the custom-prefix diff is filtered only when the prefixes are passed
+#[test]
+fn test_no_prefix_and_custom_prefix_diffs() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(["/debug.log"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let with_prefixes = |src: &str, dst: &str| {
+        TestData::SAMPLE_DIFF
+            .replace(" a/", &format!(" {src}"))
+            .replace(" b/", &format!(" {dst}"))
+    };
+
+    let run = |diff: String, args: &[&str]| {
+        let output = Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(temp_dir.path())
+            .args(args)
+            .write_stdin(diff)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = run(with_prefixes("", ""), &[]);
+    assert!(stdout.contains("diff --git src/main.rs src/main.rs"));
+    assert!(!stdout.contains("debug.log"));
+
+    let stdout = run(with_prefixes("old/", "new/"), &[]);
+    assert!(stdout.contains("debug.log"));
+
+    let stdout = run(
+        with_prefixes("old/", "new/"),
+        &["--src-prefix", "old/", "--dst-prefix", "new/"],
+    );
+    assert!(stdout.contains("diff --git old/src/main.rs new/src/main.rs"));
+    assert!(!stdout.contains("debug.log"));
+}
+
+/// **What is tested:** The `--dry-run` flag reporting one decision line per file section
+/// **Why it is tested:** Dry-run output is used to debug why a path is or is not filtered, so every decision and its reason must be shown
+/// **Test conditions:** Mixed diff with a kept source file, a .gitignore-matched log, a VCS file and a rename into an ignored path, with a failing downstream command configured
+/// **Expectations:** Exactly the KEEP/DROP lines are printed in diff order, no diff content is written, and the downstream command is skipped
+#[test]
+fn test_dry_run_reports_decisions() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let diff = format!(
+        "{}{}",
+        TestData::SAMPLE_DIFF,
+        "diff --git a/.git/config b/.git/config
+index 2222222..3333333 100644
+--- a/.git/config
++++ b/.git/config
+@@ -1 +1,2 @@
+ [core]
++    bare = false
+diff --git a/notes.txt b/target/notes.txt
+similarity index 100%
+rename from notes.txt
+rename to target/notes.txt
+"
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--dry-run", "--downstream", "false"])
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout(
+            "KEEP src/main.rs\n\
+             DROP debug.log (gitignore)\n\
+             DROP .git/config (vcs)\n\
+             DROP target/notes.txt (gitignore)\n",
+        );
+}
+
+/// **What is tested:** NUL-terminated report output with `-z`/`--null`
+/// **Why it is tested:** Paths with spaces or newlines need a machine-safe separator, like `git diff --name-only -z`
+/// **Test conditions:** Diff touching `my docs/read me.txt` and the ignored `debug.log`, run with `--dry-run -z`, `--list-dirs --null` and `--stats -z`
+/// **Expectations:** Decision lines, directories and the stats summary end in NUL without newlines, and the filtered diff is unchanged
+#[test]
+fn test_null_terminated_report_output() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let kept = "diff --git a/my docs/read me.txt b/my docs/read me.txt\n\
+                index 1111111..2222222 100644\n\
+                --- a/my docs/read me.txt\n\
+                +++ b/my docs/read me.txt\n\
+                @@ -1 +1 @@\n\
+                -old\n\
+                +new\n";
+    let diff = format!(
+        "{kept}diff --git a/debug.log b/debug.log\n\
+         index 3333333..4444444 100644\n"
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--dry-run", "-z"])
+        .write_stdin(diff.clone())
+        .assert()
+        .success()
+        .stdout("KEEP my docs/read me.txt\0DROP debug.log (gitignore)\0");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--list-dirs", "--null"])
+        .write_stdin(diff.clone())
+        .assert()
+        .success()
+        .stdout("my docs\0");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--stats", "-z"])
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout(kept)
+        .stderr("kept 1 file, filtered 1 (.gitignore: 1, vcs: 0)\0");
+}
+
+/// **What is tested:** `--keep-empty-diff-headers` compared to the default removal of filtered files
+/// **Why it is tested:** Some downstream viewers expect a header for every file, while the default must keep removing filtered sections entirely
+/// **Test conditions:** SAMPLE_DIFF with a kept `src/main.rs` and an ignored `debug.log`, processed with and without the flag
+/// **Expectations:** Without the flag `debug.log` disappears; with it only its `diff --git` line and `# filtered by gitignore` remain, and `src/main.rs` is unchanged in both modes
+#[test]
+fn test_keep_empty_diff_headers() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let kept_section = TestData::SAMPLE_DIFF
+        .split_inclusive('\n')
+        .take_while(|line| !line.starts_with("diff --git a/debug.log"))
+        .collect::<String>();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(kept_section.clone());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--keep-empty-diff-headers")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{kept_section}diff --git a/debug.log b/debug.log\n# filtered by gitignore\n"
+        ));
+}
+
+/// **What is tested:** `--print-root` and `--print-root-only` reporting the root selected for the input
+/// **Why it is tested:** The chosen root decides which .gitignore applies, so it must be visible when debugging
+/// **Test conditions:** Git repository with nested directories `sub/deeper`; SAMPLE_DIFF processed from `sub/deeper` with each flag
+/// **Expectations:** Stderr holds the repository top-level; `--print-root` still writes the filtered diff, `--print-root-only` writes nothing to stdout
+#[test]
+fn test_print_root_in_nested_directory() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let nested = temp_dir.path().join("sub").join("deeper");
+    fs::create_dir_all(&nested).unwrap();
+    let top_level = temp_dir.path().canonicalize().unwrap();
+
+    let printed_root = |output: &[u8]| {
+        PathBuf::from(String::from_utf8_lossy(output).trim_end())
+            .canonicalize()
+            .unwrap()
+    };
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(&nested)
+        .arg("--print-root-only")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(printed_root(&output.stderr), top_level);
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(&nested)
+        .arg("--print-root")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(printed_root(&output.stderr), top_level);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("src/main.rs"));
+    assert!(!stdout.contains("debug.log"));
+}
+
+/// **What is tested:** The exit status of `--fail-if-empty`
+/// **Why it is tested:** Scripts use the status to tell a diff with remaining changes from one that was filtered away entirely
+/// **Test conditions:** A diff touching only an ignored log file, and SAMPLE_DIFF with one kept and one ignored file, both with `--fail-if-empty`
+/// **Expectations:** The all-ignored diff exits 1 without output; the partially kept diff exits 0 and still writes the kept section
+#[test]
+fn test_fail_if_empty_exit_status() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let ignored_only = "diff --git a/debug.log b/debug.log
+index 1111111..2222222 100644
+--- a/debug.log
++++ b/debug.log
+@@ -1 +1,2 @@
+ log entry
++another log entry
+";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--fail-if-empty")
+        .write_stdin(ignored_only)
+        .assert()
+        .code(1)
+        .stdout("");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--fail-if-empty")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "diff --git a/src/main.rs b/src/main.rs",
+        ))
+        .stdout(predicate::str::contains("debug.log").not());
+}
+
+/// **What is tested:** Byte-exact CRLF output through the binary, including stdin buffering and root finding
+/// **Why it is tested:** Diffs from Windows or `core.autocrlf` checkouts use `\r\n`, and tools checksumming the filtered diff need the original bytes
+/// **Test conditions:** CRLF diff with a kept source file, an ignored log file and a kept file without a final newline
+/// **Expectations:** The ignored section is dropped and stdout equals the kept sections' input bytes exactly
+#[test]
+fn test_crlf_diff_output_is_byte_exact() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let kept_section = "diff --git a/src/main.rs b/src/main.rs\r\n\
+                        index 1234567..abcdefg 100644\r\n\
+                        --- a/src/main.rs\r\n\
+                        +++ b/src/main.rs\r\n\
+                        @@ -1 +1 @@\r\n\
+                        -old\r\n\
+                        +new\r\n";
+    let ignored_section = "diff --git a/debug.log b/debug.log\r\n\
+                           index 1111111..2222222 100644\r\n\
+                           --- a/debug.log\r\n\
+                           +++ b/debug.log\r\n\
+                           @@ -1 +1 @@\r\n\
+                           +entry\r\n";
+    let unterminated_section = "diff --git a/README.md b/README.md\r\n\
+                                index 3333333..4444444 100644\r\n\
+                                --- a/README.md\r\n\
+                                +++ b/README.md\r\n\
+                                @@ -1 +1 @@\r\n\
+                                -Old\r\n\
+                                +New";
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(format!(
+            "{kept_section}{ignored_section}{unterminated_section}"
+        ))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        output.stdout,
+        format!("{kept_section}{unterminated_section}").into_bytes()
+    );
+}
+
+/// **What is tested:** The `--format json` description of kept and filtered sections
+/// **Why it is tested:** Review UIs consume the JSON instead of the diff and rely on its fields and their values
+/// **Test conditions:** The two-file sample diff (a kept source file and an ignored log file) processed with `--format json`
+/// **Expectations:** A JSON array with one object per file, holding path, status, reason, header and body size
+#[test]
+fn test_json_format_describes_sections() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--format", "json"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let sections = json.as_array().unwrap();
+    assert_eq!(sections.len(), 2);
+
+    let main_header = "diff --git a/src/main.rs b/src/main.rs";
+    let main_body = TestData::SAMPLE_DIFF
+        .split_once(&format!("{main_header}\n"))
+        .and_then(|(_, rest)| rest.split_once("diff --git"))
+        .map(|(body, _)| body.len())
+        .unwrap();
+    assert_eq!(
+        sections[0],
+        serde_json::json!({
+            "path": "src/main.rs",
+            "status": "kept",
+            "reason": "none",
+            "header": main_header,
+            "body_bytes": main_body,
+        })
+    );
+    assert_eq!(sections[1]["path"], "debug.log");
+    assert_eq!(sections[1]["status"], "filtered");
+    assert_eq!(sections[1]["reason"], "gitignore");
+    assert_eq!(sections[1]["header"], "diff --git a/debug.log b/debug.log");
+    assert!(sections[1]["body_bytes"].as_u64().unwrap() > 0);
+}
+
+/// **What is tested:** `--path-prefix` for diffs generated with `git -C subdir diff --relative`
+/// **Why it is tested:** Relative diff paths must be matched against the root .gitignore as if they carried the subdirectory
+/// **Test conditions:** Root .gitignore with `*.log` and the subdirectory-scoped `/subdir/build/`, diff paths relative to `subdir/`
+/// **Expectations:** `foo.log` and `build/out.txt` are dropped with the prefix; without it the scoped pattern does not apply
+#[test]
+fn test_path_prefix_for_relative_diffs() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(["*.log", "/subdir/build/"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    fs::create_dir_all(temp_dir.path().join("subdir/build")).unwrap();
+
+    let relative_diff = "diff --git a/foo.log b/foo.log\n\
+                         +entry\n\
+                         diff --git a/build/out.txt b/build/out.txt\n\
+                         +built\n\
+                         diff --git a/src/lib.rs b/src/lib.rs\n\
+                         +pub fn lib() {}\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--path-prefix", "subdir/"])
+        .write_stdin(relative_diff)
+        .assert()
+        .success()
+        .stdout("diff --git a/src/lib.rs b/src/lib.rs\n+pub fn lib() {}\n");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(relative_diff)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("diff --git a/build/out.txt"))
+        .stdout(predicate::str::contains("diff --git a/foo.log").not());
+}
+
+/// **What is tested:** VCS patterns configured as several values with `git config --add`
+/// **Why it is tested:** Reading only the last value would silently drop the earlier patterns
+/// **Test conditions:** Repository with `.svn/` and `.hg/` added as separate values of the patterns key, diff touching both
+/// **Expectations:** Sections under both directories are filtered and the source file is kept
+#[test]
+fn test_multi_valued_git_config_vcs_patterns() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    for pattern in [".svn/", ".hg/"] {
+        let status = StdCommand::new("git")
+            .current_dir(temp_dir.path())
+            .args([
+                "config",
+                "--add",
+                "diff-gitignore-filter.vcs-ignore.patterns",
+                pattern,
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    let diff = "diff --git a/.svn/entries b/.svn/entries\n\
+                +svn\n\
+                diff --git a/.hg/store b/.hg/store\n\
+                +hg\n\
+                diff --git a/src/main.rs b/src/main.rs\n\
+                +fn main() {}\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout("diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n");
+}
+
+/// **What is tested:** Environment variable overrides between CLI arguments and git config
+/// **Why it is tested:** CI containers configure the filter through the environment, which must beat git config but not explicit flags
+/// **Test conditions:** Git config enabling VCS filtering for `.svn/`, then `DIFF_GITIGNORE_FILTER_VCS`/`_VCS_PATTERNS` set, then `--vcs` on top
+/// **Expectations:** The environment disables or redirects VCS filtering over git config, `--vcs` wins over the environment, and a malformed boolean fails
+#[test]
+fn test_env_overrides_git_config_but_not_cli() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    for (key, value) in [
+        ("diff-gitignore-filter.vcs-ignore.enabled", "true"),
+        ("diff-gitignore-filter.vcs-ignore.patterns", ".svn/"),
+    ] {
+        let status = StdCommand::new("git")
+            .current_dir(temp_dir.path())
+            .args(["config", key, value])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    let diff = "diff --git a/.svn/entries b/.svn/entries\n\
+                +svn\n\
+                diff --git a/.hg/store b/.hg/store\n\
+                +hg\n";
+    let run = |env: &[(&str, &str)], args: &[&str]| {
+        let mut cmd = Command::cargo_bin("diff-gitignore-filter").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .env_remove("DIFF_GITIGNORE_FILTER_VCS")
+            .env_remove("DIFF_GITIGNORE_FILTER_VCS_PATTERNS")
+            .env_remove("DIFF_GITIGNORE_FILTER_DOWNSTREAM")
+            .envs(env.iter().copied())
+            .args(args)
+            .write_stdin(diff);
+        cmd.assert()
+    };
+
+    // Git config alone filters .svn/
+    run(&[], &[])
+        .success()
+        .stdout("diff --git a/.hg/store b/.hg/store\n+hg\n");
+
+    // The environment disables VCS filtering configured in git config
+    run(&[("DIFF_GITIGNORE_FILTER_VCS", "0")], &[])
+        .success()
+        .stdout(diff);
+
+    // The environment replaces the git config patterns
+    run(&[("DIFF_GITIGNORE_FILTER_VCS_PATTERNS", ".hg/")], &[])
+        .success()
+        .stdout("diff --git a/.svn/entries b/.svn/entries\n+svn\n");
+
+    // CLI flags win over the environment
+    run(&[("DIFF_GITIGNORE_FILTER_VCS", "0")], &["--vcs"])
+        .success()
+        .stdout("diff --git a/.hg/store b/.hg/store\n+hg\n");
+    run(
+        &[("DIFF_GITIGNORE_FILTER_VCS_PATTERNS", ".hg/")],
+        &["--vcs-pattern", ".svn/"],
+    )
+    .success()
+    .stdout("diff --git a/.hg/store b/.hg/store\n+hg\n");
+
+    run(&[("DIFF_GITIGNORE_FILTER_VCS", "maybe")], &[])
+        .failure()
+        .stderr(predicate::str::contains("Invalid environment variable"));
+}
+
+/// **What is tested:** `DIFF_GITIGNORE_FILTER_DOWNSTREAM` as downstream command source
+/// **Why it is tested:** The downstream command must be configurable without git config, while `--downstream` still takes priority
+/// **Test conditions:** Environment downstream `sed s/^/env:/`, with and without `--downstream "sed s/^/cli:/"`
+/// **Expectations:** Output is piped through the environment command, or through the CLI command when both are given
+#[test]
+fn test_env_downstream_override() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .env("DIFF_GITIGNORE_FILTER_DOWNSTREAM", "sed s/^/env:/")
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "env:diff --git a/src/main.rs b/src/main.rs\n",
+        ));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .env("DIFF_GITIGNORE_FILTER_DOWNSTREAM", "sed s/^/env:/")
+        .args(["--downstream", "sed s/^/cli:/"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "cli:diff --git a/src/main.rs b/src/main.rs\n",
+        ));
+}
+
+/// **What is tested:** `--vcs-pattern-add` extending the default VCS patterns
+/// **Why it is tested:** Users adding `.pijul/` must keep `.git/` filtered without re-specifying every default
+/// **Test conditions:** Diff touching `.git/`, `.pijul/` and a source file, run with `--vcs-pattern-add .pijul/`
+/// **Expectations:** Both `.git/` and `.pijul/` sections are filtered and the source file is kept
+#[test]
+fn test_vcs_pattern_add_keeps_defaults() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let diff = "diff --git a/.git/config b/.git/config\n\
+                +git\n\
+                diff --git a/.pijul/pristine/db b/.pijul/pristine/db\n\
+                +pijul\n\
+                diff --git a/src/main.rs b/src/main.rs\n\
+                +fn main() {}\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .env_remove("DIFF_GITIGNORE_FILTER_VCS_PATTERNS")
+        .args(["--vcs-pattern-add", ".pijul/"])
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout("diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n");
+}
+
+/// **What is tested:** Jujutsu and Fossil metadata in the default VCS patterns
+/// **Why it is tested:** Colocated Jujutsu repositories and Fossil checkouts produce `.jj/`, `.fslckout` and `_FOSSIL_` churn in diffs
+/// **Test conditions:** Diff touching `.jj/`, `.fslckout`, a nested `_FOSSIL_` and a source file, no VCS configuration
+/// **Expectations:** Only the source file is kept
+#[test]
+fn test_default_vcs_patterns_cover_jujutsu_and_fossil() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let diff = "diff --git a/.jj/repo/op_heads b/.jj/repo/op_heads\n\
+                +jj\n\
+                diff --git a/.fslckout b/.fslckout\n\
+                +fossil\n\
+                diff --git a/vendor/_FOSSIL_ b/vendor/_FOSSIL_\n\
+                +fossil\n\
+                diff --git a/src/main.rs b/src/main.rs\n\
+                +fn main() {}\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .env_remove("DIFF_GITIGNORE_FILTER_VCS")
+        .env_remove("DIFF_GITIGNORE_FILTER_VCS_PATTERNS")
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout("diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n");
+}
+
+/// **What is tested:** The `--no-mode-only` and `--no-deletions` options
+/// **Why it is tested:** Mode-only changes and deletions are dropped on request, independent of `.gitignore`
+/// **Test conditions:** A diff with a mode-only change, a deletion and a normal edit, filtered with each option
+/// **Expectations:** Each option drops only its kind of section and keeps the normal edit
+#[test]
+fn test_no_mode_only_and_no_deletions() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let mode_only = "diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n";
+    let deletion = "diff --git a/old.rs b/old.rs\n\
+                    deleted file mode 100644\n\
+                    index 1234567..0000000\n\
+                    --- a/old.rs\n\
+                    +++ /dev/null\n\
+                    @@ -1 +0,0 @@\n\
+                    -old\n";
+    let edit = "diff --git a/src/main.rs b/src/main.rs\n\
+                index 1234567..abcdefg 100644\n\
+                --- a/src/main.rs\n\
+                +++ b/src/main.rs\n\
+                @@ -1 +1 @@\n\
+                -old\n\
+                +new\n";
+    let input = format!("{mode_only}{deletion}{edit}");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--no-mode-only")
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(format!("{deletion}{edit}"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--no-deletions")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(format!("{mode_only}{edit}"));
+}
+
+/// **What is tested:** `--pair-renames` together with `--no-deletions` and `--force-include`
+/// **Why it is tested:** Pairing may only drop a deletion kept by its own path; it must not bring back a dropped deletion or drop a force-included one
+/// **Test conditions:** `old.rs` deleted and added as `src/new.rs` (kept) or `new.log` (ignored) with the same blob; `--dry-run --pair-renames` with `--no-deletions`, with a force-include file listing `old.rs`, and alone
+/// **Expectations:** `old.rs` stays dropped by `deletion`, stays kept when force-included, and is dropped with reason `rename` otherwise
+#[test]
+fn test_pair_renames_respects_other_filters() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    std::fs::write(temp_dir.path().join("force-include"), "old.rs\n").unwrap();
+
+    let deletion = "diff --git a/old.rs b/old.rs\n\
+                    deleted file mode 100644\n\
+                    index 1234567..0000000\n\
+                    --- a/old.rs\n\
+                    +++ /dev/null\n\
+                    @@ -1 +0,0 @@\n\
+                    -old\n";
+    let addition = |path: &str| {
+        format!(
+            "diff --git a/{path} b/{path}\nnew file mode 100644\nindex 0000000..1234567\n\
+             --- /dev/null\n+++ b/{path}\n@@ -0,0 +1 @@\n+old\n"
+        )
+    };
+    let dry_run = |args: &[&str], added: &str| {
+        Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(temp_dir.path())
+            .args(["--dry-run", "--pair-renames"])
+            .args(args)
+            .write_stdin(format!("{deletion}{}", addition(added)))
+            .assert()
+            .success()
+    };
+
+    dry_run(&["--no-deletions"], "src/new.rs").stdout("DROP old.rs (deletion)\nKEEP src/new.rs\n");
+    dry_run(&["--force-include", "force-include"], "new.log")
+        .stdout("KEEP old.rs\nDROP new.log (gitignore)\n");
+    dry_run(&[], "new.log").stdout("DROP old.rs (rename)\nDROP new.log (gitignore)\n");
+}
+
+/// **What is tested:** The `--apply-diff-gitignore` option
+/// **Why it is tested:** A .gitignore added by the diff should drop the later files it ignores when requested
+/// **Test conditions:** A diff adding `foo.gen`, then a `.gitignore` with `*.gen`, then `bar.gen` and `src/main.rs`, filtered with and without the option
+/// **Expectations:** With the option only the `.gen` file after the `.gitignore` is dropped; without it all files are kept
+#[test]
+fn test_apply_diff_gitignore() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let before = "diff --git a/foo.gen b/foo.gen\n+before\n";
+    let gitignore = "diff --git a/.gitignore b/.gitignore\n\
+                     new file mode 100644\n\
+                     --- /dev/null\n\
+                     +++ b/.gitignore\n\
+                     @@ -0,0 +1 @@\n\
+                     +*.gen\n";
+    let after = "diff --git a/bar.gen b/bar.gen\n+after\n";
+    let edit = "diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n";
+    let input = format!("{before}{gitignore}{after}{edit}");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--apply-diff-gitignore")
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(format!("{before}{gitignore}{edit}"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(input);
+}
+
+/// **What is tested:** The `--print-config` option
+/// **Why it is tested:** Users need to see the effective configuration and which layer each setting came from to understand precedence
+/// **Test conditions:** Git config disabling VCS filtering and setting a downstream filter; printed without options and with `--vcs` and `--downstream` overriding them
+/// **Expectations:** Without overrides the git config values are printed from `git-config`; the CLI overrides are printed from `cli`, and no diff is read
+#[test]
+fn test_print_config_reflects_cli_override() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap();
+    test_repo
+        .set_git_config("diff-gitignore-filter.vcs-ignore.enabled", "false")
+        .unwrap();
+    test_repo
+        .set_git_config("diff-gitignore-filter.downstream-filter", "less -R")
+        .unwrap();
+
+    let print_config = |args: &[&str]| {
+        let output = Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(test_repo.path())
+            .env_remove("DIFF_GITIGNORE_FILTER_VCS")
+            .env_remove("DIFF_GITIGNORE_FILTER_VCS_PATTERNS")
+            .env_remove("DIFF_GITIGNORE_FILTER_DOWNSTREAM")
+            .arg("--print-config")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let config = print_config(&[]);
+    assert!(config.starts_with("vcs_enabled = false\n"));
+    assert!(config.contains("\ndownstream_filter = [\"less -R\"]\n"));
+    assert!(config.ends_with(
+        "[sources]\n\
+         vcs_enabled = \"git-config\"\n\
+         vcs_patterns = \"default\"\n\
+         downstream_filter = \"git-config\"\n"
+    ));
+
+    let config = print_config(&["--vcs", "--downstream", "cat"]);
+    assert!(config.starts_with("vcs_enabled = true\n"));
+    assert!(config.contains("\ndownstream_filter = [\"cat\"]\n"));
+    assert!(config.contains("\nvcs_enabled = \"cli\"\n"));
+    assert!(config.ends_with("\ndownstream_filter = \"cli\"\n"));
+}
+
+/// **What is tested:** The `--on-unparsable` option
+/// **Why it is tested:** Sections with a malformed header must be dropped, kept or rejected as configured
+/// **Test conditions:** A diff of `src/main.rs`, a section whose `diff --git` line names no paths and an ignored `debug.log`, filtered without the option and with `keep`, `drop` and `error`
+/// **Expectations:** Without the option and with `keep` the malformed section is written unchanged, `drop` removes it, `error` fails naming the header; an unknown action is rejected
+#[test]
+fn test_on_unparsable() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let kept = "diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n";
+    let malformed = "diff --git not-a-path\n+hidden\n";
+    let ignored = "diff --git a/debug.log b/debug.log\n+log\n";
+    let input = format!("{kept}{malformed}{ignored}");
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("diff-gitignore-filter").unwrap();
+        cmd.current_dir(temp_dir.path())
+            .args(args)
+            .write_stdin(input.clone())
+            .assert()
+    };
+
+    run(&[]).success().stdout(format!("{kept}{malformed}"));
+    run(&["--on-unparsable", "keep"])
+        .success()
+        .stdout(format!("{kept}{malformed}"));
+    run(&["--on-unparsable", "drop"]).success().stdout(kept);
+    run(&["--on-unparsable", "error"])
+        .failure()
+        .stderr(predicate::str::contains("'diff --git not-a-path'"));
+    run(&["--on-unparsable", "ignore"]).failure();
+}
+
+/// **What is tested:** The `--filtered-out` option
+/// **Why it is tested:** Dropped sections must be available for auditing without changing the filtered diff on stdout
+/// **Test conditions:** A diff of a kept file and two ignored files, filtered with `--filtered-out` and, separately, together with a downstream command
+/// **Expectations:** Stdout holds the kept section, the file holds the ignored sections byte for byte; a downstream command is rejected
+#[test]
+fn test_filtered_out() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let kept = "diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n";
+    let log = "diff --git a/debug.log b/debug.log\r\n+log\r\n";
+    let target = "diff --git a/target/app b/target/app\n+binary\n";
+    let input = format!("{log}{kept}{target}");
+    let filtered_out = temp_dir.path().join("filtered.diff");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--filtered-out")
+        .arg(&filtered_out)
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(kept);
+    assert_eq!(
+        std::fs::read_to_string(&filtered_out).unwrap(),
+        format!("{log}{target}")
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--filtered-out")
+        .arg(&filtered_out)
+        .args(["--downstream", "cat"])
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--filtered-out"));
+}
+
+/// **What is tested:** The `--only-ext` and `--exclude-ext` options
+/// **Why it is tested:** Reviews can be narrowed to some file types, and files without an extension need a defined outcome
+/// **Test conditions:** A diff of `src/main.rs`, `Cargo.lock`, the dotfile `.env` and an ignored `debug.log`, filtered with each option and with `--dry-run`
+/// **Expectations:** `--only-ext rs` keeps only `src/main.rs`, `--exclude-ext lock` also keeps the dotfile, and dropped files are reported with reason `extension`
+#[test]
+fn test_only_ext_and_exclude_ext() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let section = |path: &str| format!("diff --git a/{path} b/{path}\n+change\n");
+    let (main, lock, env, log) = (
+        section("src/main.rs"),
+        section("Cargo.lock"),
+        section(".env"),
+        section("debug.log"),
+    );
+    let input = format!("{main}{lock}{env}{log}");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--only-ext", "rs"])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(main.clone());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--exclude-ext", ".lock"])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(format!("{main}{env}"));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--only-ext", "rs,toml", "--dry-run"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "KEEP src/main.rs\n\
+             DROP Cargo.lock (extension)\n\
+             DROP .env (extension)\n\
+             DROP debug.log (gitignore)\n",
+        );
+}
+
+/// **What is tested:** `--downstream-cwd` and `--downstream-env` for the downstream commands
+/// **Why it is tested:** Tools like delta resolve their configuration from the working directory and environment
+/// **Test conditions:** Filter run in a nested directory with `pwd` as downstream command and the repository root as `--downstream-cwd`, a shell command printing a variable set with `--downstream-env`, and a missing directory
+/// **Expectations:** `pwd` prints the configured directory, the variable reaches the command, and the missing directory fails with DownstreamSpawnFailed
+#[test]
+fn test_downstream_cwd_and_env() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let nested = temp_dir.path().join("nested");
+    std::fs::create_dir(&nested).unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(&nested)
+        .arg("--downstream-cwd")
+        .arg(&root)
+        .args(["--downstream", "pwd"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()),
+        root
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream-env", "FILTER_TEST_VALUE=from option"])
+        .args([
+            "--downstream-shell",
+            "--downstream",
+            "echo \"$FILTER_TEST_VALUE\"",
+        ])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout("from option\n");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream-cwd", "missing", "--downstream", "cat"])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DownstreamSpawnFailed"));
+}
+
+/// **What is tested:** The `-v` and `-vv` diagnostics
+/// **Why it is tested:** Diagnostics must explain root selection and decisions on stderr without changing the filtered diff on stdout
+/// **Test conditions:** The sample diff with an ignored `debug.log`, filtered without `-v`, with `-v` and with `-vv`
+/// **Expectations:** Stdout is identical in all runs; `-v` reports the selected root, `-vv` also one decision line per file, and without `-v` stderr is empty
+#[test]
+fn test_verbose_diagnostics() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let run = |args: &[&str]| {
+        Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(temp_dir.path())
+            .args(args)
+            .write_stdin(TestData::SAMPLE_DIFF)
+            .output()
+            .unwrap()
+    };
+
+    let quiet = run(&[]);
+    let verbose = run(&["-v"]);
+    let very_verbose = run(&["-vv"]);
+    for output in [&quiet, &verbose, &very_verbose] {
+        assert!(output.status.success());
+        assert_eq!(output.stdout, quiet.stdout);
+    }
+    assert!(String::from_utf8_lossy(&quiet.stdout).contains("src/main.rs"));
+    assert!(quiet.stderr.is_empty());
+
+    let stderr = String::from_utf8_lossy(&verbose.stderr);
+    assert!(stderr.contains("selected root"));
+    assert!(!stderr.contains("KEEP"));
+
+    let stderr = String::from_utf8_lossy(&very_verbose.stderr);
+    assert!(stderr.contains("selected root"));
+    assert!(stderr.contains("diff-gitignore-filter: KEEP src/main.rs\n"));
+    assert!(stderr.contains("diff-gitignore-filter: DROP debug.log (gitignore)\n"));
+}
+
+/// **What is tested:** Piping a large diff through a downstream `cat`
+/// **Why it is tested:** Filtering and feeding the downstream command run on separate threads; a large output must neither deadlock nor lose data
+/// **Test conditions:** About 8 MB of diff with every third file ignored, piped through `cat` whose output is captured
+/// **Expectations:** The run succeeds and `cat` prints exactly the kept sections, in order
+#[test]
+fn test_downstream_large_diff_through_cat() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+
+    let body = format!("+{}\n", "x".repeat(99)).repeat(20);
+    let sections: Vec<String> = (0..4000)
+        .map(|index| {
+            let path = match index % 3 {
+                0 => format!("logs/{index}.log"),
+                _ => format!("src/file{index}.rs"),
+            };
+            format!("diff --git a/{path} b/{path}\n@@ -0,0 +1,20 @@\n{body}")
+        })
+        .collect();
+    let expected: String = sections
+        .iter()
+        .filter(|section| !section.contains(".log"))
+        .map(String::as_str)
+        .collect();
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream", "cat"])
+        .write_stdin(sections.concat())
+        .timeout(std::time::Duration::from_secs(60))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), expected.len());
+    assert!(output.stdout == expected.as_bytes());
+}
+
+/// **What is tested:** `--downstream-retries` re-running a downstream command that fails once
+/// **Why it is tested:** A transient failure of the downstream tool must not abort the whole run
+/// **Test conditions:** Shell downstream command counting its runs in a file and failing on the first run, with `--downstream-retries 1` and without retries
+/// **Expectations:** With the retry the run succeeds and prints the filtered diff from the second run; without it the run fails
+#[test]
+fn test_downstream_retries() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let flaky = "runs=$(cat runs 2>/dev/null || echo 0); echo $((runs + 1)) > runs; \
+                 [ \"$runs\" -ge 1 ] && cat";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream-shell", "--downstream", flaky])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DownstreamProcessFailed"));
+    std::fs::remove_file(temp_dir.path().join("runs")).unwrap();
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--downstream-retries", "1", "--downstream-shell"])
+        .args(["--downstream", flaky])
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("diff --git"));
+    assert!(!stdout.contains(".log"));
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("runs")).unwrap(),
+        "2\n"
+    );
+}
+
+/// **What is tested:** `--downstream-max-lines` bypassing the downstream filter for long diffs
+/// **Why it is tested:** A pager or highlighter set in the Git config should handle ordinary diffs while huge ones are written plainly
+/// **Test conditions:** Downstream filter `sed s/^/>/` from `diff-gitignore-filter.downstream-filter` with a limit of 20 lines; a small diff, and a diff whose kept section has 50 lines next to an ignored section of 50 lines
+/// **Expectations:** The small diff is prefixed by the downstream filter although the input exceeds the limit; the large one is written unprefixed but still filtered
+#[test]
+fn test_downstream_max_lines() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap();
+    test_repo
+        .set_git_config("diff-gitignore-filter.downstream-filter", "sed s/^/>/")
+        .expect("Failed to set git config");
+
+    let section = |path: &str, lines: usize| {
+        format!("diff --git a/{path} b/{path}\n{}", "+line\n".repeat(lines))
+    };
+    let run = |input: String| {
+        Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(test_repo.path())
+            .args(["--downstream-max-lines", "20"])
+            .write_stdin(input)
+            .output()
+            .unwrap()
+    };
+
+    let kept = section("src/main.rs", 10);
+    let output = run(format!("{}{kept}", section("debug.log", 50)));
+    assert!(output.status.success());
+    let expected: String = kept.lines().map(|line| format!(">{line}\n")).collect();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+    let kept = section("src/main.rs", 50);
+    let output = run(format!("{}{kept}", section("debug.log", 50)));
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), kept);
+}
+
+/// **What is tested:** Filtering a diff with invalid UTF-8 and NUL bytes with and without `--downstream`
+/// **Why it is tested:** Plain output and a downstream pipeline must apply the same policy to binary content instead of one passing the diff through unfiltered
+/// **Test conditions:** A diff with an ignored `debug.log` section and a kept section containing `\xff` and NUL bytes, filtered once to stdout and once through `--downstream cat`
+/// **Expectations:** Both outputs are identical and byte-exact: the ignored section is dropped and the kept section is unchanged
+#[test]
+fn test_binary_content_filtered_with_and_without_downstream() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap();
+
+    let ignored = b"diff --git a/debug.log b/debug.log\n+\xff\x00log\n".to_vec();
+    let kept = b"diff --git a/src/data.bin b/src/data.bin\n+\x00\xfe\xff\n+text\n".to_vec();
+    let input = [ignored, kept.clone()].concat();
+    let run = |args: &[&str]| {
+        Command::cargo_bin("diff-gitignore-filter")
+            .unwrap()
+            .current_dir(test_repo.path())
+            .args(args)
+            .write_stdin(input.clone())
+            .output()
+            .unwrap()
+    };
+
+    let plain = run(&[]);
+    assert!(plain.status.success());
+    assert_eq!(plain.stdout, kept);
+
+    let downstream = run(&["--downstream", "cat"]);
+    assert!(downstream.status.success());
+    assert_eq!(downstream.stdout, plain.stdout);
+}
+
+/// **What is tested:** Downstream filter from `diff-gitignore-filter.downstream-filter` and the deprecated `gitignore-diff.downstream-filter`
+/// **Why it is tested:** The legacy key must keep working but tell users to migrate, while the new key must win silently
+/// **Test conditions:** Repository with only the legacy key set, then with both keys set to different commands
+/// **Expectations:** The legacy key is used with a deprecation notice on stderr; with both keys the new one is used without a notice
+#[test]
+fn test_downstream_filter_legacy_key_deprecation() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .build()
+        .unwrap();
+    test_repo
+        .set_git_config("gitignore-diff.downstream-filter", "grep -c diff")
+        .expect("Failed to set git config");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs").not())
+        .stderr(predicate::str::contains(
+            "'gitignore-diff.downstream-filter' is deprecated",
+        ));
+
+    test_repo
+        .set_git_config("diff-gitignore-filter.downstream-filter", "cat")
+        .expect("Failed to set git config");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .write_stdin(TestData::SAMPLE_DIFF)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("src/main.rs"))
+        .stderr(predicate::str::contains("deprecated").not());
+}
+
+/// **What is tested:** Hiding committed files with `.diffignore` and `--diffignore`
+/// **Why it is tested:** Review-time rules must drop tracked files that .gitignore cannot match, such as vendored code
+/// **Test conditions:** Repository with a committed `vendor/` directory listed only in `.diffignore`, then a `--diffignore` file listing `src/`, then a missing file
+/// **Expectations:** `vendor/` is dropped with reason diffignore, the explicit file replaces `.diffignore`, and a missing file fails
+#[test]
+fn test_diffignore_hides_committed_vendor() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let vendor = temp_dir.path().join("vendor");
+    std::fs::create_dir(&vendor).unwrap();
+    std::fs::write(vendor.join("lib.rs"), "pub fn vendored() {}\n").unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    };
+    git(&["add", "vendor/lib.rs"]);
+    git(&[
+        "-c",
+        "user.name=Test",
+        "-c",
+        "user.email=test@example.com",
+        "commit",
+        "-q",
+        "-m",
+        "Add vendored code",
+    ]);
+
+    let diff = "diff --git a/vendor/lib.rs b/vendor/lib.rs\n\
+                index 1234567..abcdefg 100644\n\
+                --- a/vendor/lib.rs\n\
+                +++ b/vendor/lib.rs\n\
+                @@ -1 +1 @@\n\
+                -pub fn vendored() {}\n\
+                +pub fn vendored() -> bool { true }\n\
+                diff --git a/src/main.rs b/src/main.rs\n\
+                index 1234567..abcdefg 100644\n\
+                --- a/src/main.rs\n\
+                +++ b/src/main.rs\n\
+                @@ -1 +1 @@\n\
+                -fn main() {}\n\
+                +fn main() { println!(\"hi\"); }\n";
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("vendor/lib.rs"));
+
+    std::fs::write(temp_dir.path().join(".diffignore"), "vendor/\n").unwrap();
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--dry-run")
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout("DROP vendor/lib.rs (diffignore)\nKEEP src/main.rs\n");
+
+    std::fs::write(temp_dir.path().join("review.ignore"), "src/\n").unwrap();
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--dry-run", "--diffignore", "review.ignore"])
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout("KEEP vendor/lib.rs\nDROP src/main.rs (diffignore)\n");
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--diffignore", "missing.ignore"])
+        .write_stdin(diff)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Diffignore file not found"));
+}
+
+/// **What is tested:** `--annotate` comment lines before kept sections
+/// **Why it is tested:** The option must reach the filter and name the negation pattern that re-included a file
+/// **Test conditions:** Repository ignoring `*.log` except `!keep.log`, a diff of `keep.log`, `debug.log` and `src/main.rs`
+/// **Expectations:** `keep.log` and `src/main.rs` are preceded by their `# kept:` lines, `debug.log` is dropped
+#[test]
+fn test_annotate_kept_sections() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(vec!["*.log", "!keep.log"])
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let diff = "diff --git a/keep.log b/keep.log\n\
+                --- a/keep.log\n\
+                +++ b/keep.log\n\
+                @@ -1 +1 @@\n\
+                -old\n\
+                +new\n\
+                diff --git a/debug.log b/debug.log\n\
+                --- a/debug.log\n\
+                +++ b/debug.log\n\
+                @@ -1 +1 @@\n\
+                -old\n\
+                +new\n\
+                diff --git a/src/main.rs b/src/main.rs\n\
+                --- a/src/main.rs\n\
+                +++ b/src/main.rs\n\
+                @@ -1 +1 @@\n\
+                -old\n\
+                +new\n";
+
+    let output = Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("--annotate")
+        .write_stdin(diff)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("# kept: re-included by !keep.log\ndiff --git a/keep.log b/keep.log\n")
+    );
+    assert!(stdout
+        .contains("# kept: no matching ignore rule\ndiff --git a/src/main.rs b/src/main.rs\n"));
+    assert!(!stdout.contains("debug.log"));
+}
+
+/// **What is tested:** Filtering a non-git unified diff with `--unified`
+/// **Why it is tested:** Output of `diff -ruN` has no `diff --git` headers and used to pass through unfiltered
+/// **Test conditions:** `diff -ruN old new` style diff of an ignored `debug.log` and `src/main.rs`, with `--src-prefix old/ --dst-prefix new/`
+/// **Expectations:** Without `--unified` the input is unchanged; with it `debug.log` and its command line are dropped and `src/main.rs` is kept
+#[test]
+fn test_unified_diff_filters_ignored_paths() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let log = "diff -ruN old/debug.log new/debug.log\n\
+               --- old/debug.log\t2024-01-01 00:00:00.000000000 +0000\n\
+               +++ new/debug.log\t2024-01-01 00:00:01.000000000 +0000\n\
+               @@ -1 +1 @@\n\
+               -old\n\
+               +new\n";
+    let main = "diff -ruN old/src/main.rs new/src/main.rs\n\
+                --- old/src/main.rs\t2024-01-01 00:00:00.000000000 +0000\n\
+                +++ new/src/main.rs\t2024-01-01 00:00:01.000000000 +0000\n\
+                @@ -1 +1 @@\n\
+                -fn main() {}\n\
+                +fn main() { println!(\"hi\"); }\n";
+    let diff = format!("{log}{main}");
+    let prefixes = ["--src-prefix", "old/", "--dst-prefix", "new/"];
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(prefixes)
+        .write_stdin(diff.clone())
+        .assert()
+        .success()
+        .stdout(diff.clone());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(prefixes)
+        .arg("--unified")
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout(main);
+}
+
+/// **What is tested:** Inline ignore patterns given with repeated `--ignore` options
+/// **Why it is tested:** Inline rules must drop paths the repository .gitignore does not cover without replacing it
+/// **Test conditions:** Repository ignoring `*.log`; diff of `src/main.rs`, `tests/out.snap` and `debug.log` with `--ignore '*.snap'`, then with an invalid pattern
+/// **Expectations:** Only `src/main.rs` is kept; the invalid pattern is skipped with a warning and fails `--dry-run-config`
+#[test]
+fn test_ignore_option_adds_inline_patterns() {
+    let temp_dir = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS.to_vec())
+        .build()
+        .unwrap()
+        .into_temp_dir();
+    let section = |path: &str| {
+        format!(
+            "diff --git a/{path} b/{path}\n\
+             index 1234567..abcdefg 100644\n\
+             --- a/{path}\n\
+             +++ b/{path}\n\
+             @@ -1 +1 @@\n\
+             -old\n\
+             +new\n"
+        )
+    };
+    let main = section("src/main.rs");
+    let diff = format!(
+        "{main}{}{}",
+        section("tests/out.snap"),
+        section("debug.log")
+    );
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--ignore", "*.snap"])
+        .write_stdin(diff.clone())
+        .assert()
+        .success()
+        .stdout(main.clone());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--ignore", "*.snap", "--ignore", "a/**b["])
+        .write_stdin(diff)
+        .assert()
+        .success()
+        .stdout(main)
+        .stderr(predicate::str::contains(
+            "diff-gitignore-filter: warning: Ignoring pattern 'a/**b['",
+        ));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args(["--ignore", "a/**b[", "--dry-run-config"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("ignore pattern"));
+}
+
+/// **What is tested:** `--diff` running `git diff` on a revision range itself
+/// **Why it is tested:** The wrapper must filter the generated diff like piped input, without reading stdin
+/// **Test conditions:** Repository ignoring `*.log` with two commits; the second changes `src/main.rs` and force-adds `debug.log`; run from a subdirectory, then with an unknown revision
+/// **Expectations:** The diff of `HEAD~1..HEAD` keeps `src/main.rs` and drops `debug.log`; the unknown revision fails with git's error
+#[test]
+fn test_diff_option_runs_git_diff() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .with_static_files([("src/main.rs", Some("fn main() {}\n"))])
+        .build()
+        .unwrap();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .current_dir(test_repo.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+    };
+
+    git(&["add", "-A"]);
+    git(&["commit", "-m", "initial"]);
+    std::fs::write(
+        test_repo.path().join("src/main.rs"),
+        "fn main() { println!(\"hi\"); }\n",
+    )
+    .unwrap();
+    std::fs::write(test_repo.path().join("debug.log"), "noise\n").unwrap();
+    git(&["add", "-A"]);
+    git(&["add", "-f", "debug.log"]);
+    git(&["commit", "-m", "change"]);
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path().join("src"))
+        .args(["--diff", "HEAD~1..HEAD"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "diff --git a/src/main.rs b/src/main.rs",
+        ))
+        .stdout(predicate::str::contains("println!"))
+        .stdout(predicate::str::contains("debug.log").not());
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .args(["--diff", "no-such-revision"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("git diff no-such-revision failed"));
+}
+
+/// **What is tested:** The `--strip-ansi` option on the output of `git diff --color`
+/// **Why it is tested:** Color codes hide the `diff --git` headers, so a colored diff would not be filtered
+/// **Test conditions:** A repository with a changed `src/main.rs` and a tracked, ignored `debug.log`; its `git diff --color=always` output filtered with `--strip-ansi`
+/// **Expectations:** The output equals the uncolored `git diff` without the `debug.log` section
+#[test]
+fn test_strip_ansi_filters_colored_diff() {
+    let test_repo = TestRepo::builder()
+        .with_patterns(TestData::SIMPLE_PATTERNS)
+        .with_static_files([
+            ("src/main.rs", Some("fn main() {}\n")),
+            ("debug.log", Some("started\n")),
+        ])
+        .build()
+        .unwrap();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .current_dir(test_repo.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    git(&["add", "-A"]);
+    git(&["add", "-f", "debug.log"]);
+    git(&["commit", "-m", "initial"]);
+    std::fs::write(
+        test_repo.path().join("src/main.rs"),
+        "fn main() { println!(\"hi\"); }\n",
+    )
+    .unwrap();
+    std::fs::write(test_repo.path().join("debug.log"), "started\nfinished\n").unwrap();
+
+    let colored = git(&["diff", "--color=always"]);
+    let plain = git(&["diff", "--no-color", "--", "src/main.rs"]);
+    assert!(colored.contains('\x1b'));
+
+    Command::cargo_bin("diff-gitignore-filter")
+        .unwrap()
+        .current_dir(test_repo.path())
+        .arg("--strip-ansi")
+        .write_stdin(colored)
+        .assert()
+        .success()
+        .stdout(plain);
+}
@@ -48,7 +48,8 @@ fn test_downstream_vcs_integration_fix() -> Result<(), Box<dyn std::error::Error
 
     // Test 2: VCS Filter Only (should exclude VCS files and .gitignore files)
     let vcs_filter = Filter::new(repo.path())?
-        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()]);
+        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()])
+        .with_vcs_enabled(true);
     let mut vcs_output = Vec::new();
     vcs_filter.process_diff(Cursor::new(input), &mut vcs_output)?;
     let vcs_result = String::from_utf8(vcs_output)?;
@@ -74,6 +75,7 @@ fn test_downstream_vcs_integration_fix() -> Result<(), Box<dyn std::error::Error
     // This tests that the downstream filter now uses the full filter stack
     let _vcs_downstream_filter = Filter::new(repo.path())?
         .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true)
         .with_downstream("cat".to_string());
 
     // We can't easily test the downstream output, but we can verify the filter was created
@@ -84,8 +86,9 @@ fn test_downstream_vcs_integration_fix() -> Result<(), Box<dyn std::error::Error
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: Some("cat".to_string()),
+            downstream: vec!["cat".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -95,8 +98,8 @@ fn test_downstream_vcs_integration_fix() -> Result<(), Box<dyn std::error::Error
                     "AppConfig should enable VCS filtering"
                 );
                 assert_eq!(
-                    config.downstream_filter(),
-                    Some("cat"),
+                    config.downstream_pipeline(),
+                    ["cat"],
                     "AppConfig should set downstream filter"
                 );
             }
@@ -131,6 +134,7 @@ fn test_filter_chain_order_independence() -> Result<(), Box<dyn std::error::Erro
     // Test that VCS -> Downstream and Base -> VCS -> Downstream work the same
     let _filter1 = Filter::new(repo.path())?
         .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true)
         .with_downstream("cat".to_string());
 
     let _filter2 = Filter::new(repo.path())?.with_downstream("cat".to_string());
@@ -144,16 +148,18 @@ fn test_filter_chain_order_independence() -> Result<(), Box<dyn std::error::Erro
         let cli_args1 = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: Some("cat".to_string()),
+            downstream: vec!["cat".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // Test configuration with VCS disabled and downstream
         let cli_args2 = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: Some("cat".to_string()),
+            downstream: vec!["cat".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match (
@@ -163,8 +169,8 @@ fn test_filter_chain_order_independence() -> Result<(), Box<dyn std::error::Erro
             (Ok(config1), Ok(config2)) => {
                 assert!(config1.vcs_enabled());
                 assert!(!config2.vcs_enabled());
-                assert_eq!(config1.downstream_filter(), Some("cat"));
-                assert_eq!(config2.downstream_filter(), Some("cat"));
+                assert_eq!(config1.downstream_pipeline(), ["cat"]);
+                assert_eq!(config2.downstream_pipeline(), ["cat"]);
                 println!("✅ AppConfig creation successful with different VCS settings");
             }
             _ => {
@@ -210,7 +216,8 @@ fn test_regression_downstream_vcs_integration_bug() -> Result<(), Box<dyn std::e
 
     // Step 1: Verify VCS-only filter works correctly (baseline)
     let vcs_only_filter = Filter::new(repo.path())?
-        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()]);
+        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()])
+        .with_vcs_enabled(true);
     let mut vcs_only_output = Vec::new();
     vcs_only_filter.process_diff(
         std::io::Cursor::new(input_with_vcs_files),
@@ -244,6 +251,7 @@ fn test_regression_downstream_vcs_integration_bug() -> Result<(), Box<dyn std::e
     // Create VCS + Downstream filter (this was the broken combination)
     let _vcs_downstream_filter = Filter::new(repo.path())?
         .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true)
         .with_downstream("cat".to_string());
 
     // Step 3: Verify the filter structure is correct
@@ -424,8 +424,9 @@ fn test_app_config_integration_basic() {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -433,7 +434,7 @@ fn test_app_config_integration_basic() {
                 // Should have default behavior
                 assert!(config.vcs_enabled());
                 assert!(!config.vcs_patterns().is_empty());
-                assert!(config.downstream_filter().is_none());
+                assert!(config.downstream_pipeline().is_empty());
             }
             Err(_) => {
                 // Error is acceptable in test environment
@@ -444,8 +445,9 @@ fn test_app_config_integration_basic() {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -479,8 +481,9 @@ fn test_app_config_error_scenarios() {
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: Some("test-command".to_string()),
+            downstream: vec!["test-command".to_string()],
             vcs_pattern: None,
+            ..Default::default()
         };
 
         let result = AppConfig::from_cli(cli_args);
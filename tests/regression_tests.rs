@@ -3,10 +3,13 @@
 //! These tests ensure that previously identified bugs do not reoccur.
 
 use diff_gitignore_filter::Filter;
-use std::io::Cursor;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
 
 mod common;
 use common::test_utilities::AdvancedTestRepo;
+use common::TestRepo;
 
 /// **Regression Test for Bug #1: Architecture Bug - VCS filtering before binary detection**
 ///
@@ -22,7 +25,8 @@ fn test_regression_vcs_filtering_before_binary_detection() -> Result<(), Box<dyn
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
     let filter = Filter::new(test_repo.path())?
-        .with_vcs_patterns(vec![".git/".to_string(), ".svn/".to_string()]);
+        .with_vcs_patterns(vec![".git/".to_string(), ".svn/".to_string()])
+        .with_vcs_enabled(true);
 
     // Create a diff with VCS files that have binary content markers
     let binary_vcs_diff = r#"diff --git a/.git/objects/12/34567890abcdef b/.git/objects/12/34567890abcdef
@@ -82,11 +86,13 @@ Binary files /dev/null and b/.svn/wc.db differ
 fn test_regression_nested_vcs_path_matching() -> Result<(), Box<dyn std::error::Error>> {
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![
-        ".git/".to_string(),
-        ".hg/".to_string(),
-        "CVS/".to_string(),
-    ]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![
+            ".git/".to_string(),
+            ".hg/".to_string(),
+            "CVS/".to_string(),
+        ])
+        .with_vcs_enabled(true);
 
     // Create a diff with nested VCS paths that should be filtered
     let nested_vcs_diff = r#"diff --git a/jira-timesheet-cli/.git/COMMIT_EDITMSG b/jira-timesheet-cli/.git/COMMIT_EDITMSG
@@ -173,7 +179,8 @@ fn test_regression_combined_vcs_and_binary_handling() -> Result<(), Box<dyn std:
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
     let filter = Filter::new(test_repo.path())?
-        .with_vcs_patterns(vec![".git/".to_string(), ".svn/".to_string()]);
+        .with_vcs_patterns(vec![".git/".to_string(), ".svn/".to_string()])
+        .with_vcs_enabled(true);
 
     // Create a diff with nested VCS binary files and normal files
     let combined_diff = r#"diff --git a/project-x/.git/objects/ab/cdef1234567890 b/project-x/.git/objects/ab/cdef1234567890
@@ -240,7 +247,9 @@ Binary files /dev/null and b/binary_file.dat differ
 fn test_regression_vcs_pattern_specificity() -> Result<(), Box<dyn std::error::Error>> {
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![".git/".to_string()]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true);
 
     // Create a diff with files that should NOT be filtered despite having similar names
     let specificity_diff = r#"diff --git a/.gitignore b/.gitignore
@@ -303,3 +312,92 @@ index 666666..777777 100644
 
     Ok(())
 }
+
+/// **Regression Test: Kept sections must be emitted byte-identical**
+///
+/// **Problem:** Any rewriting of whitespace, line endings or `\ No newline at end of file`
+/// markers in kept sections makes `git apply` reject the filtered diff as a corrupt patch.
+///
+/// **Guarantee:** Kept sections are written from the raw input bytes; only the decisions
+/// are made on decoded text.
+///
+/// **Test:** Filters a real `git diff` with CRLF lines, trailing whitespace and a missing
+/// final newline, compares the output with the input minus the ignored section, and applies
+/// it to a clean checkout.
+#[test]
+fn test_regression_kept_sections_apply_cleanly() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = TestRepo::builder()
+        .with_patterns(["*.log"])
+        .with_static_files([
+            ("src/main.rs", Some("fn main() {}")),
+            ("src/crlf.txt", Some("first\r\nsecond\r\n")),
+            ("src/spaces.rs", Some("let x = 1;   \n\tlet y = 2;\n")),
+            ("debug.log", Some("started\n")),
+        ])
+        .build()?;
+    let git = |args: &[&str], stdin: Option<&[u8]>| -> std::io::Result<Vec<u8>> {
+        let mut child = Command::new("git")
+            .current_dir(test_repo.path())
+            .args(["-c", "core.autocrlf=false"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.unwrap_or_default())?;
+        let output = child.wait_with_output()?;
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(output.stdout)
+    };
+
+    git(&["add", "-A"], None)?;
+    git(&["add", "-f", "debug.log"], None)?;
+    git(&["commit", "-m", "initial"], None)?;
+
+    let changed = [
+        ("src/main.rs", "fn main() {\n    run();\n}"),
+        ("src/crlf.txt", "first\r\nchanged\r\n"),
+        ("src/spaces.rs", "let x = 1;   \n\tlet y = 3;  \t\n"),
+        ("debug.log", "started\nfinished\n"),
+    ];
+    for (path, content) in changed {
+        fs::write(test_repo.path().join(path), content)?;
+    }
+    let diff = git(&["diff"], None)?;
+
+    let filter = Filter::new(test_repo.path())?;
+    let mut output = Vec::new();
+    filter.process_diff(Cursor::new(&diff), &mut output)?;
+
+    // The output is the input with the ignored section cut out, byte for byte
+    let diff_text = String::from_utf8(diff.clone())?;
+    let log_start = diff_text.find("diff --git a/debug.log").unwrap();
+    let log_end = diff_text[log_start + 1..]
+        .find("diff --git ")
+        .map_or(diff_text.len(), |end| log_start + 1 + end);
+    let expected = [&diff[..log_start], &diff[log_end..]].concat();
+    assert_eq!(output, expected);
+    assert!(diff_text.contains("\\ No newline at end of file"));
+
+    git(&["checkout", "--", "."], None)?;
+    git(&["apply", "--check"], Some(&output))?;
+    git(&["apply"], Some(&output))?;
+
+    for (path, content) in &changed[..3] {
+        assert_eq!(fs::read_to_string(test_repo.path().join(path))?, *content);
+    }
+    assert_eq!(
+        fs::read_to_string(test_repo.path().join("debug.log"))?,
+        "started\n"
+    );
+
+    Ok(())
+}
@@ -20,8 +20,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             match AppConfig::from_cli(cli_args) {
@@ -29,7 +30,7 @@ mod config_tests {
                     // Should have default VCS enabled
                     assert!(config.vcs_enabled());
                     assert!(!config.vcs_patterns().is_empty());
-                    assert!(config.downstream_filter().is_none());
+                    assert!(config.downstream_pipeline().is_empty());
                 }
                 Err(ConfigError::NotInGitRepository { .. }) => {
                     // Acceptable in test environment
@@ -53,8 +54,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: true,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             match AppConfig::from_cli(cli_args) {
@@ -84,8 +86,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: true,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             match AppConfig::from_cli(cli_args) {
@@ -114,13 +117,14 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: Some("cat".to_string()),
+                downstream: vec!["cat".to_string()],
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             match AppConfig::from_cli(cli_args) {
                 Ok(config) => {
-                    assert_eq!(config.downstream_filter(), Some("cat"));
+                    assert_eq!(config.downstream_pipeline(), ["cat"]);
                 }
                 Err(ConfigError::NotInGitRepository { .. }) => {
                     // Acceptable in test environment
@@ -144,8 +148,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: true,
                 no_vcs: true,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             match AppConfig::from_cli(cli_args) {
@@ -174,8 +179,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             let result = AppConfig::from_cli(cli_args);
@@ -211,26 +217,30 @@ mod config_tests {
                 CliArgs {
                     vcs: true,
                     no_vcs: false,
-                    downstream: None,
+                    downstream: Vec::new(),
                     vcs_pattern: None,
+                    ..Default::default()
                 },
                 CliArgs {
                     vcs: false,
                     no_vcs: true,
-                    downstream: None,
+                    downstream: Vec::new(),
                     vcs_pattern: None,
+                    ..Default::default()
                 },
                 CliArgs {
                     vcs: false,
                     no_vcs: false,
-                    downstream: Some("echo test".to_string()),
+                    downstream: vec!["echo test".to_string()],
                     vcs_pattern: None,
+                    ..Default::default()
                 },
                 CliArgs {
                     vcs: true,
                     no_vcs: true,
-                    downstream: Some("cat".to_string()),
+                    downstream: vec!["cat".to_string()],
                     vcs_pattern: None,
+                    ..Default::default()
                 },
             ];
 
@@ -268,8 +278,9 @@ mod config_tests {
                     let cli_args = CliArgs {
                         vcs: false,
                         no_vcs: false,
-                        downstream: None,
+                        downstream: Vec::new(),
                         vcs_pattern: None,
+                        ..Default::default()
                     };
 
                     let result = AppConfig::from_cli(cli_args);
@@ -305,8 +316,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             let result = AppConfig::from_cli(cli_args);
@@ -341,8 +353,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             let result = AppConfig::from_cli(cli_args);
@@ -377,8 +390,9 @@ mod config_tests {
             let cli_args = CliArgs {
                 vcs: false,
                 no_vcs: false,
-                downstream: None,
+                downstream: Vec::new(),
                 vcs_pattern: None,
+                ..Default::default()
             };
 
             // Test multiple times to catch different error scenarios
@@ -412,6 +426,12 @@ mod config_tests {
                     Err(ConfigError::InvalidCliArgument { .. }) => {
                         // Verify error structure
                     }
+                    Err(ConfigError::ValidationFailed { .. }) => {
+                        // Verify error structure
+                    }
+                    Err(ConfigError::InvalidEnvValue { .. }) => {
+                        // Verify error structure
+                    }
                 }
             }
         }
@@ -432,8 +452,9 @@ mod config_tests {
                     let cli_args = CliArgs {
                         vcs: false,
                         no_vcs: false,
-                        downstream: None,
+                        downstream: Vec::new(),
                         vcs_pattern: None,
+                        ..Default::default()
                     };
 
                     let result = AppConfig::from_cli(cli_args);
@@ -445,7 +466,9 @@ mod config_tests {
                         | Err(ConfigError::GitCommandFailed { .. })
                         | Err(ConfigError::InvalidGitConfig { .. })
                         | Err(ConfigError::IoError { .. })
-                        | Err(ConfigError::InvalidCliArgument { .. }) => {
+                        | Err(ConfigError::InvalidCliArgument { .. })
+                        | Err(ConfigError::ValidationFailed { .. })
+                        | Err(ConfigError::InvalidEnvValue { .. }) => {
                             // All these results are acceptable
                         }
                     }
@@ -469,8 +492,9 @@ fn test_basic_functionality() {
     let cli_args = CliArgs {
         vcs: false,
         no_vcs: false,
-        downstream: None,
+        downstream: Vec::new(),
         vcs_pattern: None,
+        ..Default::default()
     };
 
     let result = AppConfig::from_cli(cli_args);
@@ -484,7 +508,9 @@ fn test_basic_functionality() {
         | Err(ConfigError::GitCommandFailed { .. })
         | Err(ConfigError::InvalidGitConfig { .. })
         | Err(ConfigError::IoError { .. })
-        | Err(ConfigError::InvalidCliArgument { .. }) => {
+        | Err(ConfigError::InvalidCliArgument { .. })
+        | Err(ConfigError::ValidationFailed { .. })
+        | Err(ConfigError::InvalidEnvValue { .. }) => {
             // All these error types are acceptable in test environment
         }
     }
@@ -4,7 +4,7 @@
 //! conditional application, and complex diff scenarios with mixed VCS and normal files.
 
 use diff_gitignore_filter::config::CliArgs;
-use diff_gitignore_filter::{AppConfig, Filter, GitConfig};
+use diff_gitignore_filter::{AppConfig, Filter, FilterDecision, GitConfig};
 use std::io::Cursor;
 
 mod common;
@@ -21,15 +21,17 @@ fn test_vcs_filter_enabled_default_behavior() -> Result<(), Box<dyn std::error::
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
     // Test default behavior (VCS filtering should be enabled by default)
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![
-        ".git/*".to_string(),
-        ".svn/*".to_string(),
-        "_svn/*".to_string(),
-        ".hg/*".to_string(),
-        "CVS/*".to_string(),
-        "CVSROOT/*".to_string(),
-        ".bzr/*".to_string(),
-    ]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![
+            ".git/*".to_string(),
+            ".svn/*".to_string(),
+            "_svn/*".to_string(),
+            ".hg/*".to_string(),
+            "CVS/*".to_string(),
+            "CVSROOT/*".to_string(),
+            ".bzr/*".to_string(),
+        ])
+        .with_vcs_enabled(true);
 
     let mut output = Vec::new();
     filter.process_diff(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
@@ -175,7 +177,9 @@ fn test_custom_vcs_patterns() -> Result<(), Box<dyn std::error::Error>> {
     // Create a filter with custom patterns by manually creating VcsIgnoreFilter
     // Since we can't easily inject custom patterns into the Filter::with_vcs_ignore method,
     // we test the pattern logic directly through the GitConfig
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![".git/".to_string()]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true);
 
     let mut output = Vec::new();
     filter.process_diff(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
@@ -194,6 +198,38 @@ fn test_custom_vcs_patterns() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// **What is tested:** VCS patterns supplied while VCS filtering stays disabled
+/// **Why it is tested:** Patterns can be configured ahead of time without activating VCS filtering
+/// **Test conditions:** Filter with `.git/` and `.svn/` patterns, processed with VCS filtering disabled and then enabled
+/// **Expectations:** VCS files pass through while disabled and are dropped once `with_vcs_enabled(true)` is set
+#[test]
+fn test_vcs_patterns_without_vcs_filtering() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
+
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![".git/".to_string(), ".svn/".to_string()])
+        .with_vcs_enabled(false);
+
+    let mut output = Vec::new();
+    filter.process_diff(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
+    let result = String::from_utf8(output)?;
+    assert!(result.contains(".git/config"), "Should include Git files");
+    assert!(result.contains(".svn/entries"), "Should include SVN files");
+
+    let filter = filter.with_vcs_enabled(true);
+    let mut output = Vec::new();
+    filter.process_diff(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
+    let result = String::from_utf8(output)?;
+    assert!(!result.contains(".git/config"), "Should exclude Git files");
+    assert!(!result.contains(".svn/entries"), "Should exclude SVN files");
+    assert!(
+        result.contains("src/main.rs"),
+        "Should include normal files"
+    );
+
+    Ok(())
+}
+
 /// **What is tested:** Recognition and filtering of all major VCS systems individually
 /// **Why it is tested:** Ensures comprehensive VCS support covering Git, SVN, Mercurial, CVS, and Bazaar
 /// **Test conditions:** Individual diffs for each VCS system (Git, SVN, _svn, Mercurial, CVS, CVSROOT, Bazaar)
@@ -202,15 +238,17 @@ fn test_custom_vcs_patterns() -> Result<(), Box<dyn std::error::Error>> {
 fn test_all_vcs_systems_recognition() -> Result<(), Box<dyn std::error::Error>> {
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![
-        ".git/*".to_string(),
-        ".svn/*".to_string(),
-        "_svn/*".to_string(),
-        ".hg/*".to_string(),
-        "CVS/*".to_string(),
-        "CVSROOT/*".to_string(),
-        ".bzr/*".to_string(),
-    ]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![
+            ".git/*".to_string(),
+            ".svn/*".to_string(),
+            "_svn/*".to_string(),
+            ".hg/*".to_string(),
+            "CVS/*".to_string(),
+            "CVSROOT/*".to_string(),
+            ".bzr/*".to_string(),
+        ])
+        .with_vcs_enabled(true);
 
     // Test each VCS system individually
     let git_diff = "diff --git a/.git/index b/.git/index\nindex abc..def 100644\n";
@@ -298,7 +336,8 @@ fn test_vcs_filter_with_gitignore_combination() -> Result<(), Box<dyn std::error
     let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
 
     let filter = Filter::new(test_repo.path())?
-        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()]);
+        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()])
+        .with_vcs_enabled(true);
 
     let mut output = Vec::new();
     filter.process_diff(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
@@ -345,6 +384,7 @@ fn test_vcs_filter_with_downstream_integration() -> Result<(), Box<dyn std::erro
     // Test that VCS filter works correctly when combined with downstream filter
     let _filter = Filter::new(test_repo.path())?
         .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true)
         .with_downstream("cat".to_string());
 
     // We can't easily test the actual downstream output in a unit test,
@@ -368,7 +408,8 @@ fn test_complex_diff_scenarios() -> Result<(), Box<dyn std::error::Error>> {
     let nested_vcs_diff = TestData::NESTED_VCS_DIFF;
 
     let filter = Filter::new(test_repo.path())?
-        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()]);
+        .with_vcs_patterns(vec![".git/*".to_string(), ".svn/*".to_string()])
+        .with_vcs_enabled(true);
 
     let mut output = Vec::new();
     filter.process_diff(Cursor::new(nested_vcs_diff), &mut output)?;
@@ -397,6 +438,58 @@ fn test_complex_diff_scenarios() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// **What is tested:** Depth of VCS pattern matches, for unanchored, anchored and `**` patterns
+/// **Why it is tested:** VCS directories of nested checkouts must be filtered wherever they are, while a leading `/` restricts a pattern to the root
+/// **Test conditions:** The default-style patterns `.git/` and `.svn/`, the anchored `/.hg/` and `vendor/**/.bzr/` with a `**` spanning directories, checked on a diff and on single paths
+/// **Expectations:** `project/.git/config` and `deep/nested/.svn/entries` are filtered, `my.git.txt` is kept; `/.hg/` matches only at the root; `**` matches zero or more directories below any `vendor/`
+#[test]
+fn test_vcs_patterns_match_at_any_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = AdvancedTestRepo::vcs_test_repo().build().unwrap();
+
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![
+            ".git/".to_string(),
+            ".svn/".to_string(),
+            "/.hg/".to_string(),
+            "vendor/**/.bzr/".to_string(),
+        ])
+        .with_vcs_enabled(true);
+
+    let diff = "diff --git a/project/.git/config b/project/.git/config\n\
+                +[core]\n\
+                diff --git a/deep/nested/.svn/entries b/deep/nested/.svn/entries\n\
+                +12\n\
+                diff --git a/my.git.txt b/my.git.txt\n\
+                +notes\n";
+    let mut output = Vec::new();
+    filter.process_diff(Cursor::new(diff), &mut output)?;
+    assert_eq!(
+        String::from_utf8(output)?,
+        "diff --git a/my.git.txt b/my.git.txt\n+notes\n"
+    );
+
+    for (path, filtered) in [
+        (".git/config", true),
+        ("a/b/.git/config", true),
+        ("a/.gitignore", false),
+        ("my.git.txt", false),
+        (".hg/hgrc", true),
+        ("sub/.hg/hgrc", false),
+        ("vendor/.bzr/branch", true),
+        ("vendor/x/y/.bzr/branch", true),
+        ("lib/vendor/x/.bzr/branch", true),
+        ("other/x/.bzr/branch", false),
+    ] {
+        assert_eq!(
+            filter.is_path_filtered(path, false) == FilterDecision::FilteredByVcs,
+            filtered,
+            "{path}"
+        );
+    }
+
+    Ok(())
+}
+
 /// **What is tested:** Edge cases and prevention of false positive filtering for VCS-like filenames
 /// **Why it is tested:** Ensures VCS filtering only targets actual VCS metadata directories, not files with VCS-like names
 /// **Test conditions:** Files with VCS-like names that are not actual VCS directories
@@ -407,7 +500,9 @@ fn test_edge_cases_and_false_positives() -> Result<(), Box<dyn std::error::Error
 
     let edge_case_diff = TestData::VCS_LIKE_FILENAMES_DIFF;
 
-    let filter = Filter::new(test_repo.path())?.with_vcs_patterns(vec![".git/".to_string()]);
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![".git/".to_string()])
+        .with_vcs_enabled(true);
 
     let mut output = Vec::new();
     filter.process_diff(Cursor::new(edge_case_diff), &mut output)?;
@@ -539,8 +634,9 @@ fn test_main_rs_get_vcs_enabled_function_logic() -> Result<(), Box<dyn std::erro
         let cli_args = CliArgs {
             vcs: true,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         // Test with AppConfig::from_cli() - the new API
@@ -560,8 +656,9 @@ fn test_main_rs_get_vcs_enabled_function_logic() -> Result<(), Box<dyn std::erro
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -580,8 +677,9 @@ fn test_main_rs_get_vcs_enabled_function_logic() -> Result<(), Box<dyn std::erro
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         println!(
@@ -635,8 +733,9 @@ fn test_comprehensive_priority_scenarios() -> Result<(), Box<dyn std::error::Err
     let cli_args = CliArgs {
         vcs: true,
         no_vcs: false,
-        downstream: None,
+        downstream: Vec::new(),
         vcs_pattern: None,
+        ..Default::default()
     };
 
     // Change to the test directory to read git config using thread-safe approach
@@ -659,8 +758,9 @@ fn test_comprehensive_priority_scenarios() -> Result<(), Box<dyn std::error::Err
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: true,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -702,8 +802,9 @@ fn test_fallback_behavior_missing_config() -> Result<(), Box<dyn std::error::Err
         let cli_args = CliArgs {
             vcs: false,
             no_vcs: false,
-            downstream: None,
+            downstream: Vec::new(),
             vcs_pattern: None,
+            ..Default::default()
         };
 
         match AppConfig::from_cli(cli_args) {
@@ -748,6 +849,18 @@ fn test_fallback_behavior_missing_config() -> Result<(), Box<dyn std::error::Err
                     patterns.contains(&".bzr/".to_string()),
                     "Should include default .bzr/ pattern"
                 );
+                assert!(
+                    patterns.contains(&".jj/".to_string()),
+                    "Should include default .jj/ pattern"
+                );
+                assert!(
+                    patterns.contains(&".fslckout".to_string()),
+                    "Should include default .fslckout pattern"
+                );
+                assert!(
+                    patterns.contains(&"_FOSSIL_".to_string()),
+                    "Should include default _FOSSIL_ pattern"
+                );
             }
             Err(_) => {
                 // Error is acceptable in test environment
@@ -757,3 +870,150 @@ fn test_fallback_behavior_missing_config() -> Result<(), Box<dyn std::error::Err
         Ok(())
     })
 }
+
+/// **What is tested:** VCS patterns matching VCS directories nested below the repository root
+/// **Why it is tested:** Diffs of superprojects or vendored checkouts contain paths like `project/.git/config`, which must be filtered like top-level ones
+/// **Test conditions:** Default-style VCS patterns and a diff touching nested `.git/`, `.svn/` and `CVS/` files next to look-alike names
+/// **Expectations:** Nested VCS metadata is filtered; files that only resemble VCS names (`.gitignore`, `git/notes.md`, `CVSfile`) are kept
+#[test]
+fn test_vcs_filter_nested_vcs_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = TestRepo::builder().build()?;
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(vec![
+            ".git/".to_string(),
+            ".svn/".to_string(),
+            "CVS/*".to_string(),
+        ])
+        .with_vcs_enabled(true);
+
+    let section = |path: &str| {
+        format!("diff --git a/{path} b/{path}\nindex 1111111..2222222 100644\n--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-old\n+new\n")
+    };
+    let diff: String = [
+        "project/.git/config",
+        "vendor/lib/.svn/entries",
+        "third_party/CVS/Entries",
+        "project/.gitignore",
+        "git/notes.md",
+        "CVSfile",
+    ]
+    .iter()
+    .map(|path| section(path))
+    .collect();
+
+    let mut output = Vec::new();
+    filter.process_diff(Cursor::new(diff), &mut output)?;
+    let result = String::from_utf8(output)?;
+
+    assert!(!result.contains("project/.git/config"));
+    assert!(!result.contains("vendor/lib/.svn/entries"));
+    assert!(!result.contains("third_party/CVS/Entries"));
+    assert!(result.contains("diff --git a/project/.gitignore b/project/.gitignore"));
+    assert!(result.contains("diff --git a/git/notes.md b/git/notes.md"));
+    assert!(result.contains("diff --git a/CVSfile b/CVSfile"));
+    Ok(())
+}
+
+/// **What is tested:** Throughput of VCS filtering on a large diff with many patterns
+/// **Why it is tested:** VCS patterns are compiled into one matcher so each section path is matched once instead of scanning every pattern per line
+/// **Test conditions:** 5,000 sections with 20 hunk lines each, every tenth section in a nested `.git/` directory, and 50 VCS patterns
+/// **Expectations:** All VCS sections are filtered, all others are kept, and processing finishes well within a generous time budget
+#[test]
+fn test_vcs_filter_large_diff_performance() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = TestRepo::builder().build()?;
+    let patterns = (0..49)
+        .map(|i| format!(".vcs{i}/"))
+        .chain([".git/".to_string()])
+        .collect();
+    let filter = Filter::new(test_repo.path())?
+        .with_vcs_patterns(patterns)
+        .with_vcs_enabled(true);
+
+    let hunk = "+line\n".repeat(20);
+    let diff: String = (0..5_000)
+        .map(|i| {
+            let path = if i % 10 == 0 {
+                format!("module{i}/.git/objects/{i}")
+            } else {
+                format!("module{i}/src/file{i}.rs")
+            };
+            format!("diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n@@ -0,0 +1,20 @@\n{hunk}")
+        })
+        .collect();
+
+    let started = std::time::Instant::now();
+    let mut output = Vec::new();
+    let report = filter.process_diff_with_report(Cursor::new(diff), &mut output)?;
+    let elapsed = started.elapsed();
+
+    assert_eq!(report.filtered_by_vcs(), 500);
+    assert_eq!(report.kept(), 4_500);
+    assert!(
+        elapsed < std::time::Duration::from_secs(10),
+        "VCS filtering took {elapsed:?}"
+    );
+    Ok(())
+}
+
+/// **What is tested:** Keeping only VCS sections with `with_only_vcs`
+/// **Why it is tested:** Changes to VCS metadata such as `.git/` hooks must be extractable from a diff, independent of .gitignore
+/// **Test conditions:** Complex VCS diff with default VCS patterns, VCS filtering disabled and enabled, and a .gitignore ignoring `*.md`
+/// **Expectations:** Only the `.git/`, `.svn/`, `.hg/`, `CVS/` and `.bzr/` sections survive; `src/main.rs`, `README.md` and VCS-like file names are dropped with reason `only-vcs`, and `is_path_filtered` reports the same
+#[test]
+fn test_only_vcs_keeps_only_vcs_sections() -> Result<(), Box<dyn std::error::Error>> {
+    let test_repo = TestRepo::builder().with_patterns(vec!["*.md"]).build()?;
+    let patterns = vec![
+        ".git/".to_string(),
+        ".svn/".to_string(),
+        ".hg/".to_string(),
+        "CVS/".to_string(),
+        ".bzr/".to_string(),
+    ];
+
+    for vcs_enabled in [false, true] {
+        let filter = Filter::new(test_repo.path())?
+            .with_vcs_patterns(patterns.clone())
+            .with_vcs_enabled(vcs_enabled)
+            .with_only_vcs(true);
+
+        let mut output = Vec::new();
+        let report = filter
+            .process_diff_with_report(Cursor::new(TestData::COMPLEX_VCS_DIFF), &mut output)?;
+        let result = String::from_utf8(output)?;
+
+        for path in [
+            "docs/.hg/store/data/readme.txt.i",
+            ".git/config",
+            ".svn/entries",
+            "deep/nested/path/.svn/entries",
+            ".hg/hgrc",
+            "CVS/Entries",
+            ".bzr/branch-format",
+        ] {
+            assert!(
+                result.contains(&format!("diff --git a/{path} b/{path}")),
+                "{path} should be kept"
+            );
+        }
+        for path in [
+            "src/main.rs",
+            "README.md",
+            "my.git.txt",
+            "src/vcs/git_parser.rs",
+        ] {
+            assert!(!result.contains(path), "{path} should be dropped");
+        }
+        assert_eq!(
+            filter.is_path_filtered("src/main.rs", false),
+            FilterDecision::FilteredByOnlyVcs
+        );
+        assert_eq!(
+            filter.is_path_filtered(".git/config", false),
+            FilterDecision::Keep
+        );
+        assert_eq!(report.kept(), 7);
+        assert_eq!(report.filtered_by_only_vcs(), 4);
+        assert_eq!(FilterDecision::FilteredByOnlyVcs.reason(), "only-vcs");
+    }
+    Ok(())
+}
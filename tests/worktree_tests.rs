@@ -267,9 +267,7 @@ fn test_worktree_with_external_paths() -> Result<()> {
     // Should delegate to outside repo workflow due to external paths
     // The result should be a valid path, current directory, or relative path
     assert!(
-        found_root.exists()
-            || found_root == std::path::PathBuf::from(".")
-            || found_root.is_relative()
+        found_root.exists() || found_root == std::path::Path::new(".") || found_root.is_relative()
     );
 
     Ok(())
@@ -352,3 +350,56 @@ fn test_worktree_integration_with_framework() -> Result<()> {
 
     Ok(())
 }
+
+/// Test git-dir resolution for linked worktrees
+/// **What is tested:** `RootFinder::resolve_git_dir` for a main repository and a linked worktree
+/// **Why it is tested:** Files like info/exclude live in the worktree's git dir, which the `.git` file only points to
+/// **Test conditions:** Main repository with a worktree created by `git worktree add`
+/// **Expectations:** The main repository resolves to its `.git` directory, the worktree to `.git/worktrees/<name>` of the main repository
+#[test]
+fn test_resolve_git_dir_in_worktree() -> Result<()> {
+    let main_repo = TestRepo::builder()
+        .with_patterns(["*.log"])
+        .with_static_files([("src/main.rs", Some("fn main() {}"))])
+        .build()?;
+
+    // Older git versions cannot add a worktree to a repository without commits
+    Command::new("git")
+        .current_dir(main_repo.path())
+        .args(["commit", "--allow-empty", "-m", "initial"])
+        .output()
+        .expect("Failed to commit");
+
+    let worktree_temp = TempDir::new()?;
+    let worktree_path = worktree_temp.path().join("feature-branch");
+
+    let output = Command::new("git")
+        .current_dir(main_repo.path())
+        .args([
+            "worktree",
+            "add",
+            worktree_path.to_str().unwrap(),
+            "-b",
+            "feature",
+        ])
+        .output()
+        .expect("Failed to create worktree");
+    assert!(output.status.success(), "git worktree add failed");
+
+    assert_eq!(
+        RootFinder::resolve_git_dir(main_repo.path())?.canonicalize()?,
+        main_repo.path().join(".git").canonicalize()?
+    );
+
+    let git_dir = RootFinder::resolve_git_dir(&worktree_path)?;
+    assert_eq!(
+        git_dir.canonicalize()?,
+        main_repo
+            .path()
+            .join(".git/worktrees/feature-branch")
+            .canonicalize()?
+    );
+    assert!(git_dir.join("HEAD").is_file());
+
+    Ok(())
+}